@@ -2,9 +2,10 @@
 #extension GL_ARB_separate_shader_objects : enable
 
 layout(binding = 0) uniform UniformBufferObject {
-    mat4 model;
     mat4 view;
     mat4 proj;
+    vec4 light_dir;
+    vec4 view_pos;
     float texture_weight;
 } ubo;
 
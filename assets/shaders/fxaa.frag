@@ -0,0 +1,56 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// Fast approximate anti-aliasing after Timothy Lottes' FXAA,
+// in the common form with one search step along the edge.
+
+layout(binding = 0) uniform sampler2D sceneSampler;
+
+layout(location = 0) in vec2 fragCoords;
+
+layout(location = 0) out vec4 outColor;
+
+const float FXAA_REDUCE_MIN = 1.0 / 128.0;
+const float FXAA_REDUCE_MUL = 1.0 / 8.0;
+const float FXAA_SPAN_MAX = 8.0;
+
+// the scene is sampled as linear colors, the square root approximates
+// the perceived brightness the edges are detected with
+float luma(vec3 color) {
+    return dot(sqrt(color), vec3(0.299, 0.587, 0.114));
+}
+
+void main() {
+    vec2 texel = 1.0 / vec2(textureSize(sceneSampler, 0));
+
+    vec4 center = texture(sceneSampler, fragCoords);
+    float lumaNW = luma(texture(sceneSampler, fragCoords + vec2(-1.0, -1.0) * texel).rgb);
+    float lumaNE = luma(texture(sceneSampler, fragCoords + vec2(1.0, -1.0) * texel).rgb);
+    float lumaSW = luma(texture(sceneSampler, fragCoords + vec2(-1.0, 1.0) * texel).rgb);
+    float lumaSE = luma(texture(sceneSampler, fragCoords + vec2(1.0, 1.0) * texel).rgb);
+    float lumaM = luma(center.rgb);
+    float lumaMin = min(lumaM, min(min(lumaNW, lumaNE), min(lumaSW, lumaSE)));
+    float lumaMax = max(lumaM, max(max(lumaNW, lumaNE), max(lumaSW, lumaSE)));
+
+    // direction along the edge, perpendicular to the luma gradient
+    vec2 dir = vec2(
+        -((lumaNW + lumaNE) - (lumaSW + lumaSE)),
+        (lumaNW + lumaSW) - (lumaNE + lumaSE)
+    );
+    float dirReduce = max((lumaNW + lumaNE + lumaSW + lumaSE) * 0.25 * FXAA_REDUCE_MUL, FXAA_REDUCE_MIN);
+    float dirScale = 1.0 / (min(abs(dir.x), abs(dir.y)) + dirReduce);
+    dir = clamp(dir * dirScale, vec2(-FXAA_SPAN_MAX), vec2(FXAA_SPAN_MAX)) * texel;
+
+    vec3 colorA = 0.5 * (
+        texture(sceneSampler, fragCoords + dir * (1.0 / 3.0 - 0.5)).rgb
+        + texture(sceneSampler, fragCoords + dir * (2.0 / 3.0 - 0.5)).rgb
+    );
+    vec3 colorB = colorA * 0.5 + 0.25 * (
+        texture(sceneSampler, fragCoords + dir * -0.5).rgb
+        + texture(sceneSampler, fragCoords + dir * 0.5).rgb
+    );
+    // the wider blend is only used if it does not sample across another edge
+    float lumaB = luma(colorB);
+    vec3 color = lumaB < lumaMin || lumaB > lumaMax ? colorA : colorB;
+    outColor = vec4(color, center.a);
+}
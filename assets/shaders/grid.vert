@@ -0,0 +1,18 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4 view;
+    mat4 proj;
+} ubo;
+
+layout(location = 0) in vec3 vPosition;
+layout(location = 1) in vec3 vColor;
+
+layout(location = 0) out vec3 fragColor;
+
+void main() {
+    // the grid is in world space and ignores the model matrix
+    gl_Position = ubo.proj * ubo.view * vec4(vPosition, 1.0);
+    fragColor = vColor;
+}
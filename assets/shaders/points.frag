@@ -0,0 +1,11 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec3 fragColor;
+
+layout(location = 0) out vec4 outColor;
+
+// the vertex colors of point clouds are already linear like the material colors of models
+void main() {
+    outColor = vec4(fragColor, 1.0);
+}
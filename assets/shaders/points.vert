@@ -0,0 +1,24 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4 view;
+    mat4 proj;
+} ubo;
+
+layout(push_constant) uniform PushConstants {
+    mat4 model;
+    mat4 normal_matrix;
+} pc;
+
+layout(location = 0) in vec3 vPosition;
+layout(location = 1) in vec3 vColor;
+
+layout(location = 0) out vec3 fragColor;
+
+void main() {
+    gl_Position = ubo.proj * ubo.view * pc.model * vec4(vPosition, 1.0);
+    // clamped to the point size range, which is only 1.0 without the `largePoints` feature
+    gl_PointSize = 3.0;
+    fragColor = vColor;
+}
@@ -1,17 +1,32 @@
 #version 450
 #extension GL_ARB_separate_shader_objects : enable
+#extension GL_GOOGLE_include_directive : require
 
 layout(binding = 0) uniform UniformBufferObject {
-    mat4 model;
     mat4 view;
     mat4 proj;
+    vec4 light_dir;
+    vec4 view_pos;
     float texture_weight;
+    float morph_weight;
+    uint morph_crossfade;
+    float reflectivity;
+    float near;
+    float far;
+    uint show_depth;
+    uint vertex_colors;
 } ubo;
 
 layout(location = 0) in vec3 fragColor;
 layout(location = 1) in vec2 fragCoords;
+layout(location = 2) flat in int fragInstance;
+layout(location = 3) in vec3 fragNormal;
+layout(location = 4) in vec3 fragPosition;
+layout(location = 5) in vec4 fragTangent;
 
 layout(binding = 1) uniform sampler2D texSampler;
+layout(binding = 2) uniform samplerCube cubeSampler;
+layout(binding = 3) uniform sampler2D normalSampler;
 
 layout(location = 0) out vec4 outColor;
 
@@ -24,13 +39,105 @@ float random(vec2 p) {
     return fract(cos(dot(p, K1)) * 12345.6789);
 }
 
+#include "srgb.glsl"
+
+// normal in world space facing the viewer
+vec3 surface_normal() {
+    vec3 normal = normalize(fragNormal);
+    // perturb the normal with the tangent-space normal map, without a normal map
+    // a flat one is bound and without texture coordinates the tangent is zero
+    vec3 tangent = fragTangent.xyz - normal * dot(normal, fragTangent.xyz);
+    if (dot(tangent, tangent) > 1e-12) {
+        tangent = normalize(tangent);
+        vec3 bitangent = cross(normal, tangent) * fragTangent.w;
+        vec3 mapped = texture(normalSampler, fragCoords).xyz * 2.0 - 1.0;
+        normal = normalize(mat3(tangent, bitangent, normal) * mapped);
+    }
+    // light both sides of faces as models are not always closed
+    if (!gl_FrontFacing) {
+        normal = -normal;
+    }
+    return normal;
+}
+
+// Blinn-Phong lighting of a directional light with white color
+vec3 lighting(vec3 normal, vec3 view_dir) {
+    const float ambient = 0.15;
+    const float specular_strength = 0.4;
+    const float shininess = 32.0;
+
+    vec3 light_dir = normalize(ubo.light_dir.xyz);
+    vec3 halfway = normalize(light_dir + view_dir);
+    float diffuse = max(dot(normal, light_dir), 0.0);
+    float specular = diffuse > 0.0 ? pow(max(dot(normal, halfway), 0.0), shininess) : 0.0;
+    return vec3(ambient + diffuse) + specular_strength * specular;
+}
+
+// ordered dithering threshold in (0, 1) from a 4x4 bayer matrix
+float bayer(vec2 p) {
+    const float m[16] = float[](
+         0.0,  8.0,  2.0, 10.0,
+        12.0,  4.0, 14.0,  6.0,
+         3.0, 11.0,  1.0,  9.0,
+        15.0,  7.0, 13.0,  5.0
+    );
+    ivec2 i = ivec2(p) % 4;
+    return (m[i.y * 4 + i.x] + 0.5) / 16.0;
+}
+
+// distance from the camera mapped from the near plane at 0 to the far plane at 1
+float linear_depth() {
+    float depth = gl_FragCoord.z;
+    // the depth of orthographic projections, whose last row is (0, 0, 0, 1), is already linear
+    if (ubo.proj[3][3] == 1.0) {
+        return depth;
+    }
+    float dist = ubo.near * ubo.far / (ubo.far - depth * (ubo.far - ubo.near));
+    return (dist - ubo.near) / (ubo.far - ubo.near);
+}
+
+// white at the near plane to black at the far plane, tinted close to the planes
+vec3 depth_color() {
+    const float edge = 0.01;
+    float t = linear_depth();
+    vec3 color = vec3(1.0 - t);
+    if (t < edge) {
+        color = mix(color, vec3(1.0, 0.0, 0.0), 0.7);
+    } else if (t > 1.0 - edge) {
+        color = mix(color, vec3(0.0, 0.0, 1.0), 0.7);
+    }
+    return color;
+}
+
 void main() {
-    vec4 color = vec4(
+    if (ubo.morph_crossfade != 0u) {
+        // instance 0 is the model and instance 1 the morph target
+        float alpha = fragInstance == 0 ? 1.0 - ubo.morph_weight : ubo.morph_weight;
+        if (alpha < bayer(gl_FragCoord.xy)) {
+            discard;
+        }
+    }
+
+    if (ubo.show_depth != 0u) {
+        outColor = vec4(depth_color(), 1.0);
+        return;
+    }
+
+    // models with vertex colors are shown with them instead of random colors per face
+    vec4 color = ubo.vertex_colors != 0u ? vec4(fragColor, 1.0) : vec4(srgb_to_linear(vec3(
         random(vec2(gl_PrimitiveID, 1.1)),
         random(vec2(gl_PrimitiveID, 2.2)),
-        random(vec2(gl_PrimitiveID, 3.3)),
-        1.0
-    );
-    vec4 tex = texture(texSampler, fragCoords);
-    outColor = mix(color, tex, ubo.texture_weight);
+        random(vec2(gl_PrimitiveID, 3.3))
+    )), 1.0);
+    // textures are sampled as linear colors, vertex color holds the diffuse color of the material
+    vec4 tex = texture(texSampler, fragCoords) * vec4(fragColor, 1.0);
+    vec4 albedo = mix(color, tex, ubo.texture_weight);
+
+    vec3 normal = surface_normal();
+    vec3 view_dir = normalize(ubo.view_pos.xyz - fragPosition);
+    vec3 lit = albedo.rgb * lighting(normal, view_dir);
+    // mirror x like the skybox shader to look up the reflected environment
+    vec3 reflected = reflect(-view_dir, normal);
+    vec3 environment = texture(cubeSampler, vec3(-reflected.x, reflected.yz)).rgb;
+    outColor = vec4(mix(lit, environment, ubo.reflectivity), albedo.a);
 }
@@ -2,21 +2,46 @@
 #extension GL_ARB_separate_shader_objects : enable
 
 layout(binding = 0) uniform UniformBufferObject {
-    mat4 model;
     mat4 view;
     mat4 proj;
+    vec4 light_dir;
+    vec4 view_pos;
     float texture_weight;
+    float morph_weight;
+    uint morph_crossfade;
+    float reflectivity;
 } ubo;
 
+layout(push_constant) uniform PushConstants {
+    mat4 model;
+    mat4 normal_matrix;
+} pc;
+
 layout(location = 0) in vec3 vPosition;
 layout(location = 1) in vec3 vColor;
 layout(location = 2) in vec2 vCoords;
+layout(location = 3) in vec3 vNormal;
+layout(location = 4) in vec3 vMorphPosition;
+layout(location = 5) in vec4 vTangent;
 
 layout(location = 0) out vec3 fragColor;
 layout(location = 1) out vec2 fragCoords;
+layout(location = 2) flat out int fragInstance;
+layout(location = 3) out vec3 fragNormal;
+layout(location = 4) out vec3 fragPosition;
+layout(location = 5) out vec4 fragTangent;
 
 void main() {
-    gl_Position = ubo.proj * ubo.view * ubo.model * vec4(vPosition, 1.0);
+    // when crossfading the morph target is drawn as its own instance instead
+    float morph = ubo.morph_crossfade == 0u ? ubo.morph_weight : 0.0;
+    vec3 position = mix(vPosition, vMorphPosition, morph);
+    vec4 world_position = pc.model * vec4(position, 1.0);
+    gl_Position = ubo.proj * ubo.view * world_position;
+    fragNormal = mat3(pc.normal_matrix) * vNormal;
+    // tangents lie in the surface and transform like positions
+    fragTangent = vec4(mat3(pc.model) * vTangent.xyz, vTangent.w);
+    fragPosition = world_position.xyz;
     fragColor = vColor;
     fragCoords = vCoords;
+    fragInstance = gl_InstanceIndex;
 }
@@ -0,0 +1,4 @@
+// converts a color picked in sRGB to the linear space the framebuffer and lighting expect
+vec3 srgb_to_linear(vec3 color) {
+    return mix(color / 12.92, pow((color + 0.055) / 1.055, vec3(2.4)), step(0.04045, color));
+}
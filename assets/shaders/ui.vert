@@ -0,0 +1,23 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+#extension GL_GOOGLE_include_directive : require
+
+layout(push_constant) uniform PushConstants {
+    vec2 screenSize;
+} pc;
+
+layout(location = 0) in vec2 vPos;
+layout(location = 1) in vec2 vCoords;
+layout(location = 2) in vec4 vColor;
+
+layout(location = 0) out vec4 fragColor;
+layout(location = 1) out vec2 fragCoords;
+
+#include "srgb.glsl"
+
+void main() {
+    // egui positions are in points with the origin at the top left like vulkan
+    gl_Position = vec4(2.0 * vPos / pc.screenSize - 1.0, 0.0, 1.0);
+    fragColor = vec4(srgb_to_linear(vColor.rgb), vColor.a);
+    fragCoords = vCoords;
+}
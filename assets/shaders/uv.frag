@@ -0,0 +1,13 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+#extension GL_GOOGLE_include_directive : require
+
+layout(location = 0) in vec3 fragColor;
+
+layout(location = 0) out vec4 outColor;
+
+#include "srgb.glsl"
+
+void main() {
+    outColor = vec4(srgb_to_linear(fragColor), 1.0);
+}
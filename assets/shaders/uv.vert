@@ -0,0 +1,15 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 1) in vec3 vColor;
+layout(location = 2) in vec2 vCoords;
+
+layout(location = 0) out vec3 fragColor;
+
+void main() {
+    // leave a margin to show coordinates slightly outside of [0, 1]
+    vec2 pos = (vCoords * 2.0 - 1.0) * 0.8;
+    // v points up while the y axis of vulkan points down
+    gl_Position = vec4(pos.x, -pos.y, 0.0, 1.0);
+    fragColor = vColor;
+}
@@ -7,6 +7,16 @@ fn main() {
         "shader.frag",
         "cubemap.vert",
         "cubemap.frag",
+        "uv.vert",
+        "uv.frag",
+        "grid.vert",
+        "normals.vert",
+        "points.vert",
+        "points.frag",
+        "fxaa.vert",
+        "fxaa.frag",
+        "ui.vert",
+        "ui.frag",
     ];
 
     let src_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets").join("shaders");
@@ -18,6 +28,7 @@ fn main() {
 
         let output = Command::new("glslangValidator")
             .arg("-V")
+            .arg(format!("-I{}", src_dir.to_str().unwrap()))
             .arg(input_path.to_str().unwrap())
             .arg("-o")
             .arg(output_path.to_str().unwrap())
@@ -34,4 +45,5 @@ fn main() {
 
         println!("cargo:rerun-if-changed={}", input_path.to_str().unwrap());
     }
+    println!("cargo:rerun-if-changed={}", src_dir.join("srgb.glsl").to_str().unwrap());
 }
@@ -0,0 +1,72 @@
+//! Renders a model surrounded by smaller copies of itself without opening a window,
+//! then again with every other copy removed and the rest moved closer.
+//!
+//! Usage: `cargo run --example instances -- <model> [copy texture] [output dir]`
+
+use scop_lib::fs;
+use scop_lib::math::{Deg, Matrix4, Vector3};
+use scop_lib::vulkan::{VkApp, VkAppConfig};
+
+use anyhow::Context;
+use std::path::PathBuf;
+
+const SIZE: u32 = 512;
+const COPIES: usize = 6;
+
+/// Places a copy at `distance` from the center, turned by `angle` around the y axis.
+fn ring_transform(angle: Deg<f32>, distance: f32) -> Matrix4 {
+    Matrix4::from_angle_y(angle)
+        * Matrix4::from_translation(Vector3::from([0., 0., distance]))
+        * Matrix4::from_scale(0.4)
+}
+
+fn main() -> Result<(), anyhow::Error> {
+    env_logger::init();
+
+    let mut args = std::env::args_os().skip(1);
+    let model = PathBuf::from(args.next().context("No model given")?);
+    let texture = args.next().map(PathBuf::from);
+    let output_dir = PathBuf::from(args.next().unwrap_or("instances".into()));
+    std::fs::create_dir_all(&output_dir)
+        .with_context(|| format!("Failed to create {}", output_dir.display()))?;
+
+    let config = VkAppConfig::default().size(SIZE, SIZE).model_path(&model);
+    let mut app = VkApp::new_headless(&config)?;
+    app.set_show_cubemap(false);
+    app.view_matrix = Matrix4::look_at_rh(
+        Vector3::from([0., 2., 5.]),
+        Vector3::from([0., 0., 0.]),
+        Vector3::from([0., 1., 0.]),
+    );
+
+    let nobj = fs::load_model(&model)?;
+    let angle = |i: usize| Deg(360. / COPIES as f32 * i as f32);
+    for i in 0..COPIES {
+        let id = app.add_model(nobj.clone(), ring_transform(angle(i), 1.5));
+        if let Some(texture) = &texture {
+            app.set_model_texture(id, texture)?;
+        }
+    }
+
+    let save = |app: &mut VkApp, name: &str| -> Result<(), anyhow::Error> {
+        let output = output_dir.join(name);
+        app.render_to_image()?.save(&output).with_context(|| format!("Failed to save {}", output.display()))?;
+        println!("{} -> {}", model.display(), output.display());
+        match app.pick(SIZE as f32 / 2., SIZE as f32 / 2.) {
+            Some(pick) => println!("  center shows triangle {} of {:?}", pick.triangle, pick.model),
+            None => println!("  center shows nothing"),
+        }
+        Ok(())
+    };
+    save(&mut app, "ring.png")?;
+
+    for (i, id) in app.model_ids().into_iter().enumerate() {
+        if i % 2 == 0 {
+            app.remove_model(id);
+        } else {
+            app.set_model_transform(id, ring_transform(angle(i), 1.));
+        }
+    }
+    save(&mut app, "half_ring.png")?;
+    Ok(())
+}
@@ -0,0 +1,54 @@
+//! Renders a thumbnail of each model in a directory without opening a window.
+//!
+//! Usage: `cargo run --example thumbnails -- <model dir> <output dir>`
+
+use scop_lib::fs;
+use scop_lib::math::{Deg, Matrix4};
+use scop_lib::vulkan::{VkApp, VkAppConfig};
+
+use anyhow::Context;
+use std::path::PathBuf;
+
+const SIZE: u32 = 256;
+
+fn main() -> Result<(), anyhow::Error> {
+    env_logger::init();
+
+    let mut args = std::env::args_os().skip(1);
+    let model_dir = PathBuf::from(args.next().unwrap_or("assets/models".into()));
+    let output_dir = PathBuf::from(args.next().unwrap_or("thumbnails".into()));
+    std::fs::create_dir_all(&output_dir)
+        .with_context(|| format!("Failed to create {}", output_dir.display()))?;
+
+    let mut paths = std::fs::read_dir(&model_dir)
+        .with_context(|| format!("Failed to read {}", model_dir.display()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file())
+        .collect::<Vec<_>>();
+    paths.sort();
+
+    let Some(first) = paths.first() else {
+        anyhow::bail!("No models found in {}", model_dir.display());
+    };
+    let config = VkAppConfig::default().size(SIZE, SIZE).model_path(first);
+    let mut app = VkApp::new_headless(&config)?;
+    app.set_show_cubemap(false);
+
+    for path in paths {
+        let nobj = match fs::load_model(&path) {
+            Ok(nobj) => nobj,
+            Err(err) => {
+                log::warn!("Skipping {}: {err:#}", path.display());
+                continue;
+            }
+        };
+        app.load_new_model(nobj);
+        app.model_matrix = Matrix4::from_angle_y(Deg(30.));
+
+        let image = app.render_to_image()?;
+        let output = output_dir.join(path.file_stem().unwrap_or_default()).with_extension("png");
+        image.save(&output).with_context(|| format!("Failed to save {}", output.display()))?;
+        println!("{} -> {}", path.display(), output.display());
+    }
+    Ok(())
+}
@@ -0,0 +1,31 @@
+//! Renders a model from evenly spaced angles without opening a window.
+//!
+//! Usage: `cargo run --example turntable -- <model> <output dir> [views]`
+
+use scop_lib::vulkan::{VkApp, VkAppConfig};
+
+use anyhow::Context;
+use std::path::PathBuf;
+
+const SIZE: u32 = 512;
+const VIEWS: u32 = 8;
+
+fn main() -> Result<(), anyhow::Error> {
+    env_logger::init();
+
+    let mut args = std::env::args_os().skip(1);
+    let model = PathBuf::from(args.next().context("No model given")?);
+    let output_dir = PathBuf::from(args.next().unwrap_or("turntable".into()));
+    let views = match args.next() {
+        Some(views) => views.to_string_lossy().parse().context("Invalid number of views")?,
+        None => VIEWS,
+    };
+
+    let config = VkAppConfig::default().size(SIZE, SIZE).model_path(&model);
+    let mut app = VkApp::new_headless(&config)?;
+    app.set_show_cubemap(false);
+    for path in app.render_turntable(views, &output_dir)? {
+        println!("{} -> {}", model.display(), path.display());
+    }
+    Ok(())
+}
@@ -0,0 +1,248 @@
+use crate::math::{self, Deg, Matrix4, Quaternion, Vector3};
+
+/// Maximum pitch in degrees, just under 90° so the view never flips over the poles.
+const MAX_PITCH: f32 = 89.9;
+const MIN_DISTANCE: f32 = 0.01;
+
+/// Camera that orbits around a target point on a sphere of radius `distance`.
+///
+/// A yaw and pitch of zero place the camera on the positive z axis of the target.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OrbitCamera {
+    pub target: Vector3,
+    pub distance: f32,
+    pub yaw: Deg<f32>,
+    pub pitch: Deg<f32>,
+}
+
+impl Default for OrbitCamera {
+    fn default() -> Self {
+        Self {
+            target: Vector3::default(),
+            distance: 3.,
+            yaw: Deg(0.),
+            pitch: Deg(0.),
+        }
+    }
+}
+
+impl OrbitCamera {
+    /// Rotates the camera around the target by the given angles, clamping the pitch.
+    pub fn orbit(&mut self, yaw: Deg<f32>, pitch: Deg<f32>) {
+        self.yaw = Deg((self.yaw.0 + yaw.0) % 360.);
+        self.pitch = Deg((self.pitch.0 + pitch.0).clamp(-MAX_PITCH, MAX_PITCH));
+    }
+
+    /// Multiplies the distance to the target by `factor`.
+    pub fn zoom(&mut self, factor: f32) {
+        self.distance = (self.distance * factor).max(MIN_DISTANCE);
+    }
+
+    /// Returns the position of the camera.
+    pub fn eye(&self) -> Vector3 {
+        // a positive pitch lifts the camera, which turns it to look down
+        let rotation = Matrix4::from_euler(Deg(-self.pitch.0), self.yaw, Deg(0.));
+        let dir = (rotation * Vector3::from([0., 0., 1.]).to_direction()).truncate();
+        self.target + dir * self.distance
+    }
+
+    pub fn view_matrix(&self) -> Matrix4 {
+        Matrix4::look_at_rh(self.eye(), self.target, Vector3::from([0., 1., 0.]))
+    }
+}
+
+/// A view of a `CameraPath` at a point in time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Keyframe {
+    pub position: Vector3,
+    /// Point the camera looks at.
+    pub target: Vector3,
+    /// Time in seconds since the start of the path.
+    pub time: f32,
+}
+
+/// Keyframes of a camera flight, which are interpolated with a Catmull-Rom spline.
+#[derive(Debug, Default, Clone)]
+pub struct CameraPath {
+    /// Sorted by time.
+    keyframes: Vec<Keyframe>,
+    /// Start again at the first keyframe after the last one. Add the first view
+    /// again at the end for a seamless loop.
+    pub looping: bool,
+}
+
+impl CameraPath {
+    pub fn keyframes(&self) -> &[Keyframe] {
+        &self.keyframes
+    }
+
+    /// Adds a keyframe, keeping them sorted by time.
+    pub fn push(&mut self, keyframe: Keyframe) {
+        let idx = self.keyframes.partition_point(|other| other.time <= keyframe.time);
+        self.keyframes.insert(idx, keyframe);
+    }
+
+    pub fn clear(&mut self) {
+        self.keyframes.clear();
+    }
+
+    /// Time of the last keyframe.
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0., |keyframe| keyframe.time)
+    }
+
+    /// Returns the interpolated camera position and target at `time`. Before the first
+    /// and after the last keyframe the path stays at them unless it is `looping`.
+    pub fn sample(&self, time: f32) -> Option<(Vector3, Vector3)> {
+        let first = self.keyframes.first()?;
+        let duration = self.duration();
+        let time = if self.looping && duration > 0. { time.rem_euclid(duration) } else { time };
+        let next = self.keyframes.partition_point(|keyframe| keyframe.time <= time);
+        if next == 0 {
+            return Some((first.position, first.target));
+        }
+        if next == self.keyframes.len() {
+            let last = self.keyframes[next - 1];
+            return Some((last.position, last.target));
+        }
+
+        let k1 = self.keyframes[next - 1];
+        let k2 = self.keyframes[next];
+        // outer control points beyond the ends are extrapolated linearly
+        let extrapolate = |from: Keyframe, to: Keyframe| Keyframe {
+            position: to.position * 2. - from.position,
+            target: to.target * 2. - from.target,
+            time: to.time,
+        };
+        let k0 = if next >= 2 { self.keyframes[next - 2] } else { extrapolate(k2, k1) };
+        let k3 = self.keyframes.get(next + 1).copied().unwrap_or_else(|| extrapolate(k1, k2));
+        let t = (time - k1.time) / (k2.time - k1.time);
+        Some((
+            Vector3::catmull_rom(k0.position, k1.position, k2.position, k3.position, t),
+            Vector3::catmull_rom(k0.target, k1.target, k2.target, k3.target, t),
+        ))
+    }
+
+    /// Returns the view matrix at `time` like `sample`.
+    pub fn view_matrix(&self, time: f32) -> Option<Matrix4> {
+        let (position, target) = self.sample(time)?;
+        Some(Matrix4::look_at_rh(position, target, Vector3::from([0., 1., 0.])))
+    }
+}
+
+/// Animates the view from one view matrix to another, eased in and out.
+///
+/// The camera turns around `pivot`, e.g. the model center, instead of moving
+/// straight through it, so the pivot stays in view the whole time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraTransition {
+    from: Matrix4,
+    /// View at the end, it can be changed while the transition runs.
+    pub to: Matrix4,
+    pivot: Vector3,
+    /// In seconds.
+    duration: f32,
+    elapsed: f32,
+}
+
+impl CameraTransition {
+    pub fn new(from: Matrix4, to: Matrix4, pivot: Vector3, duration: f32) -> Self {
+        Self { from, to, pivot, duration, elapsed: 0. }
+    }
+
+    /// Advances the transition by `delta` seconds.
+    pub fn advance(&mut self, delta: f32) {
+        self.elapsed = (self.elapsed + delta).min(self.duration);
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+
+    /// Returns the view at the current time, `to` once it is done.
+    pub fn view_matrix(&self) -> Matrix4 {
+        if self.is_done() {
+            return self.to;
+        }
+        let t = math::ease_in_out(self.elapsed / self.duration);
+        let rotation = Quaternion::from_matrix4(self.from)
+            .slerp(Quaternion::from_matrix4(self.to), t)
+            .to_matrix4();
+        // the pivot moves on a straight line in view space while the view rotates around it
+        let pivot_in_view = |view: Matrix4| (view * self.pivot.to_point()).truncate();
+        let pivot = Vector3::lerp(pivot_in_view(self.from), pivot_in_view(self.to), t);
+        let translation = pivot - (rotation * self.pivot.to_point()).truncate();
+        Matrix4::from_translation(translation) * rotation
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Vector3, b: [f32; 3]) {
+        assert!((a - Vector3::from(b)).magnitude() < 1e-5, "{a:?} != {b:?}");
+    }
+
+    #[test]
+    fn eye_position() {
+        let mut camera = OrbitCamera::default();
+        assert_close(camera.eye(), [0., 0., 3.]);
+        camera.orbit(Deg(90.), Deg(0.));
+        assert_close(camera.eye(), [3., 0., 0.]);
+        camera.target = Vector3::from([1., 1., 1.]);
+        camera.zoom(0.5);
+        assert_close(camera.eye(), [2.5, 1., 1.]);
+    }
+
+    #[test]
+    fn pitch_is_clamped() {
+        let mut camera = OrbitCamera::default();
+        camera.orbit(Deg(0.), Deg(120.));
+        assert_eq!(camera.pitch, Deg(MAX_PITCH));
+        camera.orbit(Deg(0.), Deg(-300.));
+        assert_eq!(camera.pitch, Deg(-MAX_PITCH));
+    }
+
+    fn keyframe(position: [f32; 3], time: f32) -> Keyframe {
+        Keyframe { position: Vector3::from(position), target: Vector3::default(), time }
+    }
+
+    #[test]
+    fn camera_path() {
+        let mut path = CameraPath::default();
+        assert_eq!(path.sample(0.), None);
+        path.push(keyframe([2., 0., 0.], 2.));
+        path.push(keyframe([0., 0., 0.], 0.));
+        path.push(keyframe([1., 0., 0.], 1.));
+        assert_eq!(path.duration(), 2.);
+        assert_close(path.sample(-1.).unwrap().0, [0., 0., 0.]);
+        assert_close(path.sample(0.5).unwrap().0, [0.5, 0., 0.]);
+        assert_close(path.sample(1.).unwrap().0, [1., 0., 0.]);
+        assert_close(path.sample(3.).unwrap().0, [2., 0., 0.]);
+        path.looping = true;
+        assert_close(path.sample(2.5).unwrap().0, [0.5, 0., 0.]);
+    }
+
+    #[test]
+    fn camera_transition() {
+        let up = Vector3::from([0., 1., 0.]);
+        let pivot = Vector3::from([1., 0., 0.]);
+        let from = Matrix4::look_at_rh(Vector3::from([1., 0., 3.]), pivot, up);
+        let to = Matrix4::look_at_rh(Vector3::from([3., 0., 0.]), pivot, up);
+        let eye = |view: Matrix4| view.inverse().unwrap()[3].truncate();
+
+        let mut transition = CameraTransition::new(from, to, pivot, 0.3);
+        assert!(transition.view_matrix().approx_eq(&from, 1e-5));
+        transition.advance(0.15);
+        // halfway the camera looks at the pivot from 45° at the average distance
+        let view = transition.view_matrix();
+        let offset = 2.5 * 0.5f32.sqrt();
+        assert_close(eye(view), [1. + offset, 0., offset]);
+        assert_close((view * pivot.to_point()).truncate(), [0., 0., -2.5]);
+        assert!(!transition.is_done());
+        transition.advance(1.);
+        assert!(transition.is_done());
+        assert_eq!(transition.view_matrix(), to);
+    }
+}
@@ -1,54 +1,342 @@
-use std::io::{self, Cursor};
+use crate::obj::NormalizedObj;
+
+use std::fs::File;
+use std::io::{self, Cursor, Read, Seek};
 use std::path::{Path, PathBuf};
+use zip::ZipArchive;
+
+/// Extensions of the supported model formats, the first ones are preferred.
+pub const MODEL_EXTENSIONS: [&str; 5] = ["obj", "gltf", "glb", "stl", "ply"];
 
-pub fn load<P: AsRef<Path>>(path: P) -> Result<Cursor<Vec<u8>>, io::Error> {
-    use std::fs::File;
-    use std::io::Read;
+/// Returns true if `path` has the extension `ext`, ignoring case.
+pub fn has_extension(path: &Path, ext: &str) -> bool {
+    path.extension().is_some_and(|path_ext| path_ext.eq_ignore_ascii_case(ext))
+}
 
+/// Reads the whole file at `path` into memory.
+pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Cursor<Vec<u8>>> {
     let mut buf = Vec::new();
     let mut file = File::open(path)?;
     file.read_to_end(&mut buf)?;
     Ok(Cursor::new(buf))
 }
 
+/// Loads a model, the format is chosen by the file extension (`gltf`, `glb`, `stl`, `ply` or else obj).
+///
+/// From a `zip` archive the first model in it is loaded, see `ArchiveSource::first_model`.
+pub fn load_model<P: AsRef<Path>>(path: P) -> Result<NormalizedObj, anyhow::Error> {
+    let path = path.as_ref();
+    let (reader, name) = if is_archive(path) {
+        let mut archive = ArchiveSource::open(path)?;
+        let name = archive.first_model()
+            .ok_or_else(|| anyhow::anyhow!("No model found in {}", path.display()))?;
+        (archive.load(&name)?, PathBuf::from(name))
+    } else {
+        (load(path)?, path.to_path_buf())
+    };
+    let ext = name.extension().and_then(|ext| ext.to_str()).map(str::to_ascii_lowercase);
+    let mut nobj = match ext.as_deref() {
+        Some("gltf" | "glb") => NormalizedObj::from_gltf(reader)?,
+        Some("stl") => NormalizedObj::from_stl(reader)?,
+        Some("ply") => NormalizedObj::from_ply(reader)?,
+        _ => NormalizedObj::from_reader(reader)?,
+    };
+    let before = nobj.vertices.len();
+    if nobj.deduplicate() > 0 {
+        log::info!("Deduplicated vertices of {}: {before} -> {}", path.display(), nobj.vertices.len());
+    }
+    Ok(nobj)
+}
+
+/// Returns true if `path` is a zip archive by its extension.
+pub fn is_archive(path: &Path) -> bool {
+    has_extension(path, "zip")
+}
+
+/// Reads the entries of a zip archive, e.g. a model with its material libraries and textures.
+///
+/// Entries are named by their path in the archive with `/` as separator.
+pub struct ArchiveSource<R = File> {
+    archive: ZipArchive<R>,
+}
+
+impl ArchiveSource {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::from_reader(File::open(path)?)
+    }
+}
+
+impl<R: Read + Seek> ArchiveSource<R> {
+    pub fn from_reader(reader: R) -> io::Result<Self> {
+        Ok(Self { archive: ZipArchive::new(reader)? })
+    }
+
+    /// Returns the names of the files in the archive, sorted.
+    pub fn names(&self) -> Vec<String> {
+        let mut names = self.archive.file_names()
+            .filter(|name| !name.ends_with('/'))
+            .map(String::from)
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    /// Returns the first obj file by name, or else the first file of the next format
+    /// in `MODEL_EXTENSIONS`.
+    pub fn first_model(&self) -> Option<String> {
+        let names = self.names();
+        MODEL_EXTENSIONS.iter()
+            .find_map(|ext| names.iter().find(|name| has_extension(Path::new(name), ext)))
+            .cloned()
+    }
+
+    /// Reads the whole entry `name` into memory.
+    pub fn load(&mut self, name: &str) -> io::Result<Cursor<Vec<u8>>> {
+        let mut entry = self.archive.by_name(name)?;
+        let mut buf = Vec::with_capacity(entry.size() as usize);
+        entry.read_to_end(&mut buf)?;
+        Ok(Cursor::new(buf))
+    }
+}
+
+/// Files of a scene, either in a directory or in a zip archive.
+pub enum SceneSource {
+    Dir(PathBuf),
+    Archive(ArchiveSource),
+}
+
+impl SceneSource {
+    /// Opens `path` as archive if it is a zip file, else as directory.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        if is_archive(path) {
+            ArchiveSource::open(path).map(Self::Archive)
+        } else {
+            Ok(Self::Dir(path.to_path_buf()))
+        }
+    }
+
+    /// Returns the names of the files, sorted. Only the top level of a directory is listed.
+    pub fn names(&self) -> io::Result<Vec<String>> {
+        match self {
+            Self::Dir(dir) => {
+                let mut names = std::fs::read_dir(dir)?
+                    .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+                    .collect::<Vec<_>>();
+                names.sort();
+                Ok(names)
+            }
+            Self::Archive(archive) => Ok(archive.names()),
+        }
+    }
+
+    /// Reads the whole file `name` into memory, `name` uses `/` as separator.
+    pub fn load(&mut self, name: &str) -> io::Result<Cursor<Vec<u8>>> {
+        match self {
+            Self::Dir(dir) => load(dir.join(name)),
+            Self::Archive(archive) => archive.load(name),
+        }
+    }
+}
+
+/// Returns the name of the entry at `relative` to the entry `base`, like a path relative
+/// to the directory of a file. Backslashes count as separators, `..` goes up a directory.
+pub fn resolve_entry(base: &str, relative: &str) -> String {
+    let mut parts = base.split('/').collect::<Vec<_>>();
+    parts.pop();
+    for part in relative.split(['/', '\\']) {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+/// Order in which a `Carousel` cycles through the files.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SortMode {
+    /// By path, directories before their subdirectories.
+    #[default]
+    Name,
+    /// Oldest first.
+    ModifiedTime,
+    /// Smallest first.
+    Size,
+}
+
 #[derive(Debug, Default, Clone)]
 pub struct Carousel {
     dir: &'static str,
+    recursive: bool,
+    sort_mode: SortMode,
     curr: usize,
+    /// The file returned last by `get_next`, used to keep the position when files are
+    /// added, removed or sorted differently.
+    curr_path: Option<PathBuf>,
 }
 
 impl Carousel {
     pub fn new(dir: &'static str) -> Self {
-        Self { dir, curr: 0 }
+        Self { dir, ..Default::default() }
+    }
+
+    pub fn sort_mode(&self) -> SortMode {
+        self.sort_mode
+    }
+
+    /// Changes the order of the files. The next call to `get_next` continues
+    /// from the file returned last, wherever it is in the new order.
+    pub fn sort_by(&mut self, mode: SortMode) {
+        self.sort_mode = mode;
     }
 
     pub fn set_dir(&mut self, dir: &'static str) {
         self.dir = dir;
+        self.recursive = false;
+    }
+
+    /// Like `set_dir` but files in subdirectories are included as well.
+    pub fn set_dir_recursive(&mut self, dir: &'static str) {
+        self.dir = dir;
+        self.recursive = true;
+    }
+
+    /// Makes `path` the current file, the next call to `get_next` continues from it.
+    pub fn set_current<P: Into<PathBuf>>(&mut self, path: P) {
+        self.curr_path = Some(path.into());
+    }
+
+    /// Returns the files matching `filter` in the current order.
+    pub fn files<F>(&self, filter: F) -> Result<Vec<PathBuf>, io::Error>
+    where
+        F: Fn(&Path) -> bool,
+    {
+        let mut files = Vec::new();
+        Self::collect_files(Path::new(self.dir), self.recursive, &filter, &mut files)?;
+        self.sort(&mut files);
+        Ok(files)
     }
 
     pub fn get_next<F>(&mut self, offset: isize, filter: F) -> Result<PathBuf, io::Error>
     where
         F: Fn(&Path) -> bool,
     {
-        let mut files = std::fs::read_dir(self.dir)?
-            .filter_map(|path| {
-                let path = path.ok()?;
-                if !path.file_type().ok()?.is_file() {
-                    return None;
-                }
-                let path = path.path();
-                if !filter(&path) {
-                    return None;
-                }
-                Some(path)
-            })
-            .collect::<Vec<_>>();
+        let files = self.files(filter)?;
         if files.is_empty() {
-            return Err(io::Error::new(io::ErrorKind::Other, "no matching file found"));
+            return Err(io::Error::other("no matching file found"));
+        }
+        if let Some(idx) = self.curr_path.as_ref().and_then(|curr| files.iter().position(|file| file == curr)) {
+            self.curr = idx;
         }
-        files.sort();
         // take euclidian remainder and not modulus to get a positive value
         self.curr = (self.curr as isize + offset).rem_euclid(files.len() as isize) as usize;
+        self.curr_path = Some(files[self.curr].clone());
         Ok(files[self.curr].clone())
     }
+
+    /// Sorts `files` by the sort mode, using the path to break ties so the order is stable.
+    fn sort(&self, files: &mut [PathBuf]) {
+        files.sort();
+        match self.sort_mode {
+            SortMode::Name => {}
+            SortMode::ModifiedTime => files.sort_by_cached_key(|file| {
+                std::fs::metadata(file).and_then(|meta| meta.modified()).ok()
+            }),
+            SortMode::Size => files.sort_by_cached_key(|file| {
+                std::fs::metadata(file).map(|meta| meta.len()).ok()
+            }),
+        }
+    }
+
+    /// Pushes the files in `dir` matching `filter` to `files`. Symlinks are not followed
+    /// and unreadable subdirectories are skipped.
+    fn collect_files<F>(dir: &Path, recursive: bool, filter: &F, files: &mut Vec<PathBuf>) -> Result<(), io::Error>
+    where
+        F: Fn(&Path) -> bool,
+    {
+        for entry in std::fs::read_dir(dir)? {
+            let Ok(entry) = entry else { continue };
+            let Ok(file_type) = entry.file_type() else { continue };
+            let path = entry.path();
+            if file_type.is_file() && filter(&path) {
+                files.push(path);
+            } else if file_type.is_dir() && recursive {
+                if let Err(err) = Self::collect_files(&path, recursive, filter, files) {
+                    log::warn!("Failed to read directory {}: {err}", path.display());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::{SimpleFileOptions, ZipWriter};
+
+    #[test]
+    fn archive() {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        writer.add_directory("cube/", options).unwrap();
+        for (name, content) in [("readme.txt", "cube"), ("cube/cube.OBJ", "v 0 0 0"), ("cube/a.stl", "")] {
+            writer.start_file(name, options).unwrap();
+            writer.write_all(content.as_bytes()).unwrap();
+        }
+        let mut archive = ArchiveSource::from_reader(writer.finish().unwrap()).unwrap();
+
+        assert_eq!(archive.names(), ["cube/a.stl", "cube/cube.OBJ", "readme.txt"]);
+        // obj files are preferred over files of other formats sorted before them
+        assert_eq!(archive.first_model().as_deref(), Some("cube/cube.OBJ"));
+        assert_eq!(archive.load("cube/cube.OBJ").unwrap().into_inner(), b"v 0 0 0");
+        assert!(archive.load("cube.OBJ").is_err());
+    }
+
+    #[test]
+    fn carousel() {
+        let dir = std::env::temp_dir().join(format!("scop_carousel_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        let now = std::time::SystemTime::now();
+        // (name, size, age in seconds)
+        for (name, size, age) in [("a.obj", 3, 20), ("b.obj", 1, 10), ("sub/c.obj", 2, 30), ("note.txt", 4, 0)] {
+            let file = File::create(dir.join(name)).unwrap();
+            file.set_len(size).unwrap();
+            file.set_modified(now - std::time::Duration::from_secs(age)).unwrap();
+        }
+        let is_obj = |path: &Path| has_extension(path, "obj");
+        let [a, b, c] = ["a.obj", "b.obj", "sub/c.obj"].map(|name| dir.join(name));
+
+        let mut carousel = Carousel::new(String::from(dir.to_str().unwrap()).leak());
+        assert_eq!(carousel.files(is_obj).unwrap(), [a.clone(), b.clone()]);
+        carousel.set_dir_recursive(String::from(dir.to_str().unwrap()).leak());
+        assert_eq!(carousel.files(is_obj).unwrap(), [a.clone(), b.clone(), c.clone()]);
+        carousel.sort_by(SortMode::Size);
+        assert_eq!(carousel.files(is_obj).unwrap(), [b.clone(), c.clone(), a.clone()]);
+        carousel.sort_by(SortMode::ModifiedTime);
+        assert_eq!(carousel.files(is_obj).unwrap(), [c.clone(), a.clone(), b.clone()]);
+
+        // the current file is kept when the order changes
+        carousel.sort_by(SortMode::Name);
+        assert_eq!(carousel.get_next(1, is_obj).unwrap(), b);
+        carousel.sort_by(SortMode::Size);
+        assert_eq!(carousel.get_next(0, is_obj).unwrap(), b);
+        assert_eq!(carousel.get_next(1, is_obj).unwrap(), c);
+        assert_eq!(carousel.get_next(-2, is_obj).unwrap(), a);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn resolve() {
+        assert_eq!(resolve_entry("cube.obj", "cube.mtl"), "cube.mtl");
+        assert_eq!(resolve_entry("models/cube.obj", "textures\\cube.png"), "models/textures/cube.png");
+        assert_eq!(resolve_entry("models/cube.obj", "../cube.png"), "cube.png");
+        assert_eq!(resolve_entry("models/cube.obj", "./cube.png"), "models/cube.png");
+    }
 }
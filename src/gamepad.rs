@@ -0,0 +1,113 @@
+use gilrs::{Axis, Button, EventType, Gilrs};
+
+/// Stick deflection below which the stick counts as centered, worn sticks
+/// rarely return exactly to zero.
+const DEADZONE: f32 = 0.15;
+
+/// Discrete actions triggered by the face buttons.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GamepadAction {
+    NextModel,
+    PrevModel,
+    NextTexture,
+    FrameModel,
+}
+
+/// State of the game controllers since the last `poll`.
+///
+/// Stick and trigger values of all connected controllers are added up,
+/// so any of them can be used.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GamepadInput {
+    /// Left stick, x to the right and y up, in -1..=1.
+    pub translation: [f32; 2],
+    /// Right stick, x to the right and y up, in -1..=1.
+    pub rotation: [f32; 2],
+    /// Right minus left trigger, in -1..=1.
+    pub zoom: f32,
+    /// Actions of the face buttons pressed since the last poll, in order.
+    pub actions: Vec<GamepadAction>,
+}
+
+/// Reads game controllers with gilrs, controllers can be connected at any time.
+pub struct Gamepads {
+    gilrs: Gilrs,
+}
+
+impl Gamepads {
+    pub fn new() -> Result<Self, anyhow::Error> {
+        // gilrs::Error carries a whole Gilrs instance, so it is not passed on as is
+        let gilrs = Gilrs::new().map_err(|err| anyhow::anyhow!("{err}"))?;
+        for (_, gamepad) in gilrs.gamepads() {
+            log::info!("Found gamepad {}", gamepad.name());
+        }
+        Ok(Self { gilrs })
+    }
+
+    /// Processes the pending events and returns the current input.
+    pub fn poll(&mut self) -> GamepadInput {
+        let mut input = GamepadInput::default();
+        while let Some(event) = self.gilrs.next_event() {
+            match event.event {
+                EventType::ButtonPressed(button, _) => {
+                    let action = match button {
+                        Button::South => GamepadAction::NextModel,
+                        Button::East => GamepadAction::PrevModel,
+                        Button::West => GamepadAction::NextTexture,
+                        Button::North => GamepadAction::FrameModel,
+                        _ => continue,
+                    };
+                    input.actions.push(action);
+                }
+                EventType::Connected => {
+                    log::info!("Connected gamepad {}", self.gilrs.gamepad(event.id).name());
+                }
+                EventType::Disconnected => {
+                    log::info!("Disconnected gamepad {}", self.gilrs.gamepad(event.id).name());
+                }
+                _ => {}
+            }
+        }
+
+        for (_, gamepad) in self.gilrs.gamepads() {
+            let axis = |axis| deadzone(gamepad.value(axis));
+            let trigger = |button| gamepad.button_data(button).map_or(0., |data| data.value());
+            input.translation[0] += axis(Axis::LeftStickX);
+            input.translation[1] += axis(Axis::LeftStickY);
+            input.rotation[0] += axis(Axis::RightStickX);
+            input.rotation[1] += axis(Axis::RightStickY);
+            input.zoom += trigger(Button::RightTrigger2) - trigger(Button::LeftTrigger2);
+        }
+        input.translation = input.translation.map(|value| value.clamp(-1., 1.));
+        input.rotation = input.rotation.map(|value| value.clamp(-1., 1.));
+        input.zoom = input.zoom.clamp(-1., 1.);
+        input
+    }
+}
+
+/// Maps values within the deadzone to 0 and rescales the rest to start at 0.
+fn deadzone(value: f32) -> f32 {
+    if value.abs() < DEADZONE {
+        0.
+    } else {
+        (value - DEADZONE.copysign(value)) / (1. - DEADZONE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deadzone_rescales() {
+        assert_eq!(deadzone(0.), 0.);
+        assert_eq!(deadzone(DEADZONE / 2.), 0.);
+        assert_eq!(deadzone(-DEADZONE / 2.), 0.);
+        // the output starts at 0 at the edge of the deadzone and still reaches full deflection
+        assert_eq!(deadzone(DEADZONE), 0.);
+        assert_eq!(deadzone(1.), 1.);
+        assert_eq!(deadzone(-1.), -1.);
+        assert!((deadzone(0.5 + DEADZONE / 2.) - 0.5).abs() < 1e-6);
+        assert!((deadzone(-0.5 - DEADZONE / 2.) + 0.5).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,182 @@
+use crate::obj::{NormalizedObj, Vertex};
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read};
+
+impl NormalizedObj {
+    /// Loads the first primitive of the first mesh of a glTF 2.0 file, either as
+    /// `.gltf` with embedded (data uri) buffers or as binary `.glb`.
+    /// Buffers in external files are not supported.
+    pub fn from_gltf<R: Read>(mut reader: R) -> Result<Self, GltfError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let ::gltf::Gltf { document, blob } = ::gltf::Gltf::from_slice(&bytes)?;
+        let buffers = ::gltf::import_buffers(&document, None, blob)?;
+
+        let primitive = document.meshes()
+            .next()
+            .and_then(|mesh| mesh.primitives().next())
+            .ok_or(GltfError::NoMesh)?;
+        if primitive.mode() != ::gltf::mesh::Mode::Triangles {
+            return Err(GltfError::UnsupportedMode(primitive.mode()));
+        }
+        let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+
+        let positions = reader.read_positions().ok_or(GltfError::MissingPositions)?;
+        let mut nobj = Self {
+            vertices: positions.map(|pos_coords| Vertex { pos_coords, ..Default::default() }).collect(),
+            ..Default::default()
+        };
+        if let Some(normals) = reader.read_normals() {
+            nobj.has_normals = true;
+            for (vertex, normal) in nobj.vertices.iter_mut().zip(normals) {
+                vertex.normal = normal;
+            }
+        }
+        if let Some(tex_coords) = reader.read_tex_coords(0) {
+            nobj.has_tex_coords = true;
+            for (vertex, [u, v]) in nobj.vertices.iter_mut().zip(tex_coords.into_f32()) {
+                // glTF has the origin of the texture at the top left, obj at the bottom left
+                vertex.tex_coords = [u, 1. - v];
+            }
+        }
+        nobj.indices = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect(),
+            None => (0..nobj.vertices.len() as u32).collect(),
+        };
+        Ok(nobj)
+    }
+}
+
+#[derive(Debug)]
+pub enum GltfError {
+    Gltf(::gltf::Error),
+    Io(io::Error),
+    MissingPositions,
+    NoMesh,
+    UnsupportedMode(::gltf::mesh::Mode),
+}
+
+impl fmt::Display for GltfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Gltf(err) => write!(f, "glTF error: {err}"),
+            Self::Io(err) => write!(f, "IO error: {err}"),
+            Self::MissingPositions => write!(f, "Mesh primitive has no positions"),
+            Self::NoMesh => write!(f, "File contains no mesh"),
+            Self::UnsupportedMode(mode) => write!(f, "Unsupported primitive mode: {mode:?}"),
+        }
+    }
+}
+
+impl Error for GltfError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Gltf(err) => Some(err),
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<::gltf::Error> for GltfError {
+    fn from(source: ::gltf::Error) -> Self {
+        Self::Gltf(source)
+    }
+}
+
+impl From<io::Error> for GltfError {
+    fn from(source: io::Error) -> Self {
+        Self::Io(source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn parse_embedded_triangle() {
+        let file = r#"{
+            "asset": { "version": "2.0" },
+            "buffers": [{
+                "byteLength": 68,
+                "uri": "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAAAAAAAAAAAAAAAIA/AAAAAAAAAAAAAIA/AAABAAIAAAA="
+            }],
+            "bufferViews": [
+                { "buffer": 0, "byteOffset": 0, "byteLength": 36 },
+                { "buffer": 0, "byteOffset": 36, "byteLength": 24 },
+                { "buffer": 0, "byteOffset": 60, "byteLength": 6 }
+            ],
+            "accessors": [
+                { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                  "min": [0, 0, 0], "max": [1, 1, 0] },
+                { "bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC2" },
+                { "bufferView": 2, "componentType": 5123, "count": 3, "type": "SCALAR" }
+            ],
+            "meshes": [{
+                "primitives": [{ "attributes": { "POSITION": 0, "TEXCOORD_0": 1 }, "indices": 2 }]
+            }]
+        }"#;
+        let nobj = NormalizedObj::from_gltf(Cursor::new(file.as_bytes())).expect("failed to parse");
+        assert!(nobj.has_tex_coords);
+        assert!(!nobj.has_normals);
+        assert_eq!(nobj.indices, [0, 1, 2]);
+        assert_eq!(nobj.vertices, [
+            Vertex { pos_coords: [0., 0., 0.], tex_coords: [0., 1.], normal: [0.; 3], ..Default::default() },
+            Vertex { pos_coords: [1., 0., 0.], tex_coords: [1., 1.], normal: [0.; 3], ..Default::default() },
+            Vertex { pos_coords: [0., 1., 0.], tex_coords: [0., 0.], normal: [0.; 3], ..Default::default() },
+        ]);
+    }
+
+    #[test]
+    fn parse_binary_triangle() {
+        let json = r#"{
+            "asset": { "version": "2.0" },
+            "buffers": [{ "byteLength": 42 }],
+            "bufferViews": [
+                { "buffer": 0, "byteOffset": 0, "byteLength": 36 },
+                { "buffer": 0, "byteOffset": 36, "byteLength": 6 }
+            ],
+            "accessors": [
+                { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                  "min": [0, 0, 0], "max": [1, 1, 0] },
+                { "bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR" }
+            ],
+            "meshes": [{
+                "primitives": [{ "attributes": { "POSITION": 0 }, "indices": 1 }]
+            }]
+        }"#;
+        let mut json = json.as_bytes().to_vec();
+        json.resize(json.len().next_multiple_of(4), b' ');
+        let mut bin = [0., 0., 0., 1., 0., 0., 0., 1., 0.].iter()
+            .flat_map(|coord: &f32| coord.to_le_bytes())
+            .chain([0u16, 2, 1].iter().flat_map(|index| index.to_le_bytes()))
+            .collect::<Vec<_>>();
+        bin.resize(bin.len().next_multiple_of(4), 0);
+
+        // 12 bytes header and a 8 bytes header per chunk
+        let mut file = b"glTF".to_vec();
+        file.extend(2u32.to_le_bytes());
+        file.extend((12 + 8 + json.len() as u32 + 8 + bin.len() as u32).to_le_bytes());
+        for (chunk_type, chunk) in [(b"JSON", json), (b"BIN\0", bin)] {
+            file.extend((chunk.len() as u32).to_le_bytes());
+            file.extend(chunk_type);
+            file.extend(chunk);
+        }
+
+        let nobj = NormalizedObj::from_gltf(Cursor::new(file)).expect("failed to parse");
+        assert!(!nobj.has_tex_coords && !nobj.has_normals);
+        assert_eq!(nobj.indices, [0, 2, 1]);
+        assert_eq!(nobj.vertices[1].pos_coords, [1., 0., 0.]);
+    }
+
+    #[test]
+    fn no_mesh() {
+        let file = r#"{ "asset": { "version": "2.0" } }"#;
+        let err = NormalizedObj::from_gltf(Cursor::new(file.as_bytes())).unwrap_err();
+        assert!(matches!(err, GltfError::NoMesh));
+    }
+}
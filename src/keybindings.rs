@@ -0,0 +1,506 @@
+use serde::de::{value::StrDeserializer, IntoDeserializer};
+use serde::Deserialize;
+use winit::keyboard::{Key, KeyCode, ModifiersState, NamedKey, SmolStr};
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Something the viewer does when a key is pressed.
+///
+/// The `Move*` actions last as long as their key is held, all others happen on press.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Quit,
+    MoveForward,
+    MoveBackward,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    PrevModel,
+    NextModel,
+    Export,
+    DumpState,
+    ToggleTextureFilter,
+    Reload,
+    ToggleFpsLimit,
+    ToggleInvertY,
+    AddCameraKeyframe,
+    ClearCameraPath,
+    PlayCameraPath,
+    LoopCameraPath,
+    ToggleRecording,
+    Screenshot,
+    FrameModel,
+    RotateLightLeft,
+    RotateLightRight,
+    RotateLightUp,
+    RotateLightDown,
+    ToggleTransparency,
+    Msaa1,
+    Msaa2,
+    Msaa4,
+    Msaa8,
+    ToggleDepthTest,
+    DecreaseSensitivity,
+    IncreaseSensitivity,
+    DecreaseDepthBiasConstant,
+    IncreaseDepthBiasConstant,
+    DecreaseDepthBiasSlope,
+    IncreaseDepthBiasSlope,
+    ToggleSkybox,
+    CycleCullMode,
+    CycleReflectivity,
+    ToggleFullscreen,
+    CycleSortMode,
+    ToggleHotReload,
+    NextTexture,
+    ToggleGrid,
+    ToggleLetterbox,
+    Reset,
+    Morph,
+    ToggleNormals,
+    ToggleCameraMode,
+    ToggleWireframe,
+    CycleUvMode,
+    ToggleRotate,
+    ToggleTexture,
+    ToggleUvLayout,
+    ToggleProjection,
+    ToggleBbox,
+    ToggleVsync,
+    CycleClearColor,
+    ViewFront,
+    ViewBack,
+    ViewRight,
+    ViewLeft,
+    ViewTop,
+    ViewBottom,
+    ToggleCheckerboard,
+    DecreaseRotationSpeed,
+    IncreaseRotationSpeed,
+    CycleRotationAxis,
+    Turntable,
+    ToggleFlatShading,
+    ToggleDepthView,
+    ToggleFxaa,
+}
+
+/// Keys of all actions when there is no key bindings file.
+const DEFAULT_BINDINGS: &[(Action, &[&str])] = &[
+    (Action::Quit, &["Escape"]),
+    (Action::MoveForward, &["KeyW"]),
+    (Action::MoveBackward, &["KeyS"]),
+    (Action::MoveLeft, &["KeyA"]),
+    (Action::MoveRight, &["KeyD"]),
+    (Action::MoveUp, &["Space"]),
+    (Action::MoveDown, &["ShiftLeft"]),
+    (Action::PrevModel, &["ArrowLeft"]),
+    (Action::NextModel, &["ArrowRight"]),
+    (Action::Export, &["F2"]),
+    (Action::DumpState, &["F3"]),
+    (Action::ToggleTextureFilter, &["F4"]),
+    (Action::Reload, &["F5"]),
+    (Action::ToggleFpsLimit, &["F6"]),
+    (Action::ToggleInvertY, &["F7"]),
+    (Action::AddCameraKeyframe, &["F8"]),
+    (Action::ClearCameraPath, &["Shift+F8"]),
+    (Action::PlayCameraPath, &["F9"]),
+    (Action::LoopCameraPath, &["Shift+F9"]),
+    (Action::ToggleRecording, &["F10"]),
+    (Action::Screenshot, &["F12"]),
+    (Action::Turntable, &["Shift+F12"]),
+    (Action::FrameModel, &["Home"]),
+    (Action::RotateLightLeft, &["Ctrl+ArrowLeft"]),
+    (Action::RotateLightRight, &["Ctrl+ArrowRight"]),
+    (Action::RotateLightUp, &["Ctrl+ArrowUp"]),
+    (Action::RotateLightDown, &["Ctrl+ArrowDown"]),
+    (Action::ToggleTransparency, &["0"]),
+    (Action::Msaa1, &["1"]),
+    (Action::Msaa2, &["2"]),
+    (Action::ToggleFxaa, &["3"]),
+    (Action::Msaa4, &["4"]),
+    (Action::Msaa8, &["8"]),
+    (Action::ToggleDepthTest, &["9"]),
+    (Action::ToggleDepthView, &["Ctrl+9"]),
+    (Action::DecreaseSensitivity, &["-"]),
+    (Action::IncreaseSensitivity, &["=", "+"]),
+    (Action::DecreaseDepthBiasConstant, &["["]),
+    (Action::IncreaseDepthBiasConstant, &["]"]),
+    (Action::DecreaseDepthBiasSlope, &["{"]),
+    (Action::IncreaseDepthBiasSlope, &["}"]),
+    (Action::ToggleSkybox, &["b"]),
+    (Action::CycleCullMode, &["c"]),
+    (Action::CycleReflectivity, &["e"]),
+    (Action::ToggleFullscreen, &["f"]),
+    (Action::CycleSortMode, &["g"]),
+    (Action::ToggleHotReload, &["h"]),
+    (Action::NextTexture, &["i"]),
+    (Action::ToggleGrid, &["j"]),
+    (Action::ToggleLetterbox, &["k"]),
+    (Action::Reset, &["l"]),
+    (Action::Morph, &["m"]),
+    (Action::ToggleNormals, &["n"]),
+    (Action::ToggleFlatShading, &["Ctrl+n"]),
+    (Action::ToggleCameraMode, &["o"]),
+    (Action::ToggleWireframe, &["p"]),
+    (Action::CycleUvMode, &["q"]),
+    (Action::ToggleRotate, &["r"]),
+    (Action::ToggleTexture, &["t"]),
+    (Action::ToggleUvLayout, &["u"]),
+    (Action::ToggleProjection, &["v"]),
+    (Action::ToggleBbox, &["x"]),
+    (Action::ToggleVsync, &["y"]),
+    (Action::CycleClearColor, &["z"]),
+    (Action::ViewFront, &["Numpad1"]),
+    (Action::ViewBack, &["Ctrl+Numpad1"]),
+    (Action::ViewRight, &["Numpad3"]),
+    (Action::ViewLeft, &["Ctrl+Numpad3"]),
+    (Action::ViewTop, &["Numpad7"]),
+    (Action::ViewBottom, &["Ctrl+Numpad7"]),
+    (Action::ToggleCheckerboard, &["F11"]),
+    (Action::DecreaseRotationSpeed, &["NumpadSubtract", "Ctrl+-"]),
+    (Action::IncreaseRotationSpeed, &["NumpadAdd", "Ctrl+=", "Ctrl++"]),
+    (Action::CycleRotationAxis, &["Ctrl+r"]),
+];
+
+impl Action {
+    /// Returns all actions.
+    pub fn all() -> impl Iterator<Item = Self> {
+        DEFAULT_BINDINGS.iter().map(|&(action, _)| action)
+    }
+
+    /// Returns the name used in key bindings files, the variant name in snake case,
+    /// e.g. `next_model` for `NextModel`.
+    pub fn name(self) -> String {
+        let mut name = String::new();
+        for c in format!("{self:?}").chars() {
+            if c.is_ascii_uppercase() && !name.is_empty() {
+                name.push('_');
+            }
+            name.push(c.to_ascii_lowercase());
+        }
+        name
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::all().find(|action| action.name() == name)
+    }
+
+    /// Whether the action lasts while the key is held instead of happening once on press.
+    pub fn is_held(self) -> bool {
+        matches!(
+            self,
+            Self::MoveForward
+                | Self::MoveBackward
+                | Self::MoveLeft
+                | Self::MoveRight
+                | Self::MoveUp
+                | Self::MoveDown,
+        )
+    }
+}
+
+/// A key that triggers an action.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BoundKey {
+    /// Key at a position on the keyboard, independent of the layout. Named like
+    /// the `KeyCode` variants, e.g. `KeyW` for the key labeled W on a QWERTY keyboard.
+    Code(KeyCode),
+    /// Key producing a character or named key with the current layout, e.g. `w`,
+    /// `+` or `F5`. Characters are matched as typed, so `W` needs Shift.
+    Key(Key),
+}
+
+/// A key and the modifiers that must be held with it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyBinding {
+    pub key: BoundKey,
+    pub modifiers: ModifiersState,
+}
+
+impl KeyBinding {
+    /// Parses bindings like `r`, `F5`, `KeyW` or `Ctrl+ArrowLeft`.
+    ///
+    /// Single characters are logical keys, then names of `NamedKey` and then names
+    /// of `KeyCode` are tried. The modifiers `Ctrl`, `Shift`, `Alt` and `Super` come first.
+    pub fn parse(binding: &str) -> Option<Self> {
+        let mut modifiers = ModifiersState::empty();
+        let mut rest = binding;
+        // the key itself may be a +
+        while rest.len() > 1 {
+            let Some((modifier, key)) = rest.split_once('+') else { break };
+            modifiers |= match modifier {
+                "Ctrl" => ModifiersState::CONTROL,
+                "Shift" => ModifiersState::SHIFT,
+                "Alt" => ModifiersState::ALT,
+                "Super" => ModifiersState::SUPER,
+                _ => return None,
+            };
+            rest = key;
+        }
+
+        let key = if rest.chars().count() == 1 {
+            BoundKey::Key(Key::Character(SmolStr::new(rest)))
+        } else if let Ok(named) = NamedKey::deserialize(Self::deserializer(rest)) {
+            BoundKey::Key(Key::Named(named))
+        } else {
+            BoundKey::Code(KeyCode::deserialize(Self::deserializer(rest)).ok()?)
+        };
+        Some(Self { key, modifiers })
+    }
+
+    fn deserializer(name: &str) -> StrDeserializer<'_, serde::de::value::Error> {
+        name.into_deserializer()
+    }
+
+    /// Whether the binding matches a key event while `modifiers` are held.
+    /// Additional modifiers are allowed, e.g. Shift is needed to type some characters.
+    pub fn matches(&self, code: KeyCode, key: &Key, modifiers: ModifiersState) -> bool {
+        let key_matches = match &self.key {
+            BoundKey::Code(bound) => *bound == code,
+            BoundKey::Key(bound) => bound == key,
+        };
+        key_matches && modifiers.contains(self.modifiers)
+    }
+}
+
+impl fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (modifier, name) in [
+            (ModifiersState::CONTROL, "Ctrl"),
+            (ModifiersState::SHIFT, "Shift"),
+            (ModifiersState::ALT, "Alt"),
+            (ModifiersState::SUPER, "Super"),
+        ] {
+            if self.modifiers.contains(modifier) {
+                write!(f, "{name}+")?;
+            }
+        }
+        match &self.key {
+            BoundKey::Code(code) => write!(f, "{code:?}"),
+            BoundKey::Key(Key::Character(c)) => write!(f, "{c}"),
+            BoundKey::Key(Key::Named(named)) => write!(f, "{named:?}"),
+            BoundKey::Key(key) => write!(f, "{key:?}"),
+        }
+    }
+}
+
+/// Keys of the actions of the viewer.
+///
+/// A key bindings file is a TOML table from action names to one key or a list of keys,
+/// see `Action::name` and `KeyBinding::parse`. Actions missing in the file keep their
+/// default keys and an empty list unbinds an action:
+///
+/// ```
+/// use scop_lib::keybindings::{Action, KeyBindings};
+///
+/// let bindings = KeyBindings::from_toml(r#"
+///     move_forward = "KeyZ"
+///     next_model = ["ArrowRight", "PageDown"]
+///     toggle_rotate = []
+/// "#).unwrap();
+/// assert_eq!(bindings.keys(Action::NextModel).count(), 2);
+/// assert_eq!(bindings.keys(Action::ToggleRotate).count(), 0);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyBindings {
+    bindings: Vec<(Action, KeyBinding)>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        let bindings = DEFAULT_BINDINGS.iter()
+            .flat_map(|&(action, keys)| {
+                keys.iter().map(move |key| (action, KeyBinding::parse(key).unwrap()))
+            })
+            .collect();
+        Self { bindings }
+    }
+}
+
+impl KeyBindings {
+    /// Loads the bindings from a file, the defaults are used if it does not exist.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, KeyBindingsError> {
+        match fs::read_to_string(path) {
+            Ok(text) => Self::from_toml(&text),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(KeyBindingsError::Io(err)),
+        }
+    }
+
+    /// Parses the bindings from the contents of a key bindings file.
+    pub fn from_toml(text: &str) -> Result<Self, KeyBindingsError> {
+        let table = text.parse::<toml::Table>().map_err(KeyBindingsError::Toml)?;
+        let mut bindings = Self::default();
+        for (name, value) in table {
+            let action = Action::from_name(&name)
+                .ok_or_else(|| KeyBindingsError::UnknownAction(name.clone()))?;
+            let keys = match value {
+                toml::Value::String(key) => vec![key],
+                toml::Value::Array(keys) => keys.into_iter()
+                    .map(|key| match key {
+                        toml::Value::String(key) => Ok(key),
+                        _ => Err(KeyBindingsError::InvalidValue(name.clone())),
+                    })
+                    .collect::<Result<_, _>>()?,
+                _ => return Err(KeyBindingsError::InvalidValue(name)),
+            };
+            bindings.bindings.retain(|&(bound, _)| bound != action);
+            for key in keys {
+                let binding = KeyBinding::parse(&key)
+                    .ok_or_else(|| KeyBindingsError::InvalidKey { action: name.clone(), key })?;
+                bindings.bindings.push((action, binding));
+            }
+        }
+        Ok(bindings)
+    }
+
+    /// Returns the actions of a key event while `modifiers` are held.
+    ///
+    /// Only the bindings requiring the most modifiers count, e.g. `Ctrl+ArrowLeft`
+    /// wins over `ArrowLeft`. Of those a physical binding wins over a logical one,
+    /// e.g. `KeyW` over `z` on an AZERTY keyboard or `Numpad1` over `1` with NumLock on.
+    pub fn actions(&self, code: KeyCode, key: &Key, modifiers: ModifiersState) -> Vec<Action> {
+        let priority = |binding: &KeyBinding| {
+            (binding.modifiers.bits().count_ones(), matches!(binding.key, BoundKey::Code(_)))
+        };
+        let matching = self.bindings.iter()
+            .filter(|(_, binding)| binding.matches(code, key, modifiers))
+            .collect::<Vec<_>>();
+        let max_priority = matching.iter().map(|(_, binding)| priority(binding)).max();
+        matching.into_iter()
+            .filter(|(_, binding)| Some(priority(binding)) == max_priority)
+            .map(|&(action, _)| action)
+            .collect()
+    }
+
+    /// Returns the keys bound to `action`.
+    pub fn keys(&self, action: Action) -> impl Iterator<Item = &KeyBinding> {
+        self.bindings.iter()
+            .filter(move |&&(bound, _)| bound == action)
+            .map(|(_, binding)| binding)
+    }
+}
+
+/// Error while loading key bindings.
+#[derive(Debug)]
+pub enum KeyBindingsError {
+    Io(io::Error),
+    Toml(toml::de::Error),
+    UnknownAction(String),
+    /// The value of an action is neither a string nor a list of strings.
+    InvalidValue(String),
+    InvalidKey { action: String, key: String },
+}
+
+impl fmt::Display for KeyBindingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "IO error: {err}"),
+            Self::Toml(err) => write!(f, "Invalid TOML: {err}"),
+            Self::UnknownAction(action) => write!(f, "Unknown action {action}"),
+            Self::InvalidValue(action) =>
+                write!(f, "Keys of {action} must be a string or a list of strings"),
+            Self::InvalidKey { action, key } => write!(f, "Invalid key {key} for {action}"),
+        }
+    }
+}
+
+impl Error for KeyBindingsError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            Self::Toml(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn char_key(c: &str) -> Key {
+        Key::Character(SmolStr::new(c))
+    }
+
+    #[test]
+    fn parse() {
+        let binding = KeyBinding::parse("Ctrl+ArrowLeft").unwrap();
+        assert_eq!(binding.key, BoundKey::Key(Key::Named(NamedKey::ArrowLeft)));
+        assert_eq!(binding.modifiers, ModifiersState::CONTROL);
+        assert_eq!(KeyBinding::parse("KeyW").unwrap().key, BoundKey::Code(KeyCode::KeyW));
+        assert_eq!(KeyBinding::parse("+").unwrap().key, BoundKey::Key(char_key("+")));
+        let binding = KeyBinding::parse("Shift+Alt++").unwrap();
+        assert_eq!(binding.key, BoundKey::Key(char_key("+")));
+        assert_eq!(binding.modifiers, ModifiersState::SHIFT | ModifiersState::ALT);
+        assert_eq!(binding.to_string(), "Shift+Alt++");
+        assert_eq!(KeyBinding::parse("Hyper+r"), None);
+        assert_eq!(KeyBinding::parse("NoSuchKey"), None);
+        assert_eq!(KeyBinding::parse(""), None);
+    }
+
+    #[test]
+    fn action_names() {
+        assert_eq!(Action::NextModel.name(), "next_model");
+        assert_eq!(Action::Msaa4.name(), "msaa4");
+        for action in Action::all() {
+            assert_eq!(Action::from_name(&action.name()), Some(action));
+        }
+    }
+
+    #[test]
+    fn defaults() {
+        let bindings = KeyBindings::default();
+        let actions = |code, key: Key, modifiers| bindings.actions(code, &key, modifiers);
+        let left = Key::Named(NamedKey::ArrowLeft);
+        assert_eq!(actions(KeyCode::ArrowLeft, left.clone(), ModifiersState::empty()), [Action::PrevModel]);
+        assert_eq!(actions(KeyCode::ArrowLeft, left, ModifiersState::CONTROL), [Action::RotateLightLeft]);
+        // Shift is held while typing +, the binding works anyway
+        assert_eq!(actions(KeyCode::Equal, char_key("+"), ModifiersState::SHIFT), [Action::IncreaseSensitivity]);
+        // on an AZERTY keyboard the key at the position of W types z, the position wins
+        assert!(actions(KeyCode::KeyZ, char_key("w"), ModifiersState::empty()).is_empty());
+        assert_eq!(actions(KeyCode::KeyW, char_key("z"), ModifiersState::empty()), [Action::MoveForward]);
+        assert_eq!(actions(KeyCode::Numpad1, char_key("1"), ModifiersState::empty()), [Action::ViewFront]);
+        assert_eq!(actions(KeyCode::Numpad1, char_key("1"), ModifiersState::CONTROL), [Action::ViewBack]);
+        assert_eq!(actions(KeyCode::Digit1, char_key("1"), ModifiersState::empty()), [Action::Msaa1]);
+        assert_eq!(actions(KeyCode::KeyR, char_key("r"), ModifiersState::CONTROL), [Action::CycleRotationAxis]);
+        assert_eq!(actions(KeyCode::Minus, char_key("-"), ModifiersState::CONTROL), [Action::DecreaseRotationSpeed]);
+        assert_eq!(actions(KeyCode::Digit9, char_key("9"), ModifiersState::CONTROL), [Action::ToggleDepthView]);
+        assert_eq!(actions(KeyCode::Digit3, char_key("3"), ModifiersState::empty()), [Action::ToggleFxaa]);
+
+        // no key is bound to two actions
+        for (i, (_, a)) in bindings.bindings.iter().enumerate() {
+            assert!(bindings.bindings[i + 1..].iter().all(|(_, b)| a != b), "{a} is bound twice");
+        }
+    }
+
+    #[test]
+    fn from_toml() {
+        let bindings = KeyBindings::from_toml(r#"
+            move_forward = "KeyZ"
+            toggle_rotate = ["Ctrl+r", "F1"]
+        "#).unwrap();
+        let keys = |action| bindings.keys(action).map(ToString::to_string).collect::<Vec<_>>();
+        assert_eq!(keys(Action::MoveForward), ["KeyZ"]);
+        assert_eq!(keys(Action::ToggleRotate), ["Ctrl+r", "F1"]);
+        assert_eq!(keys(Action::MoveBackward), ["KeyS"]);
+        let r = char_key("r");
+        assert!(bindings.actions(KeyCode::KeyR, &r, ModifiersState::empty()).is_empty());
+
+        assert!(matches!(
+            KeyBindings::from_toml("jump = \"Space\""),
+            Err(KeyBindingsError::UnknownAction(action)) if action == "jump",
+        ));
+        assert!(matches!(
+            KeyBindings::from_toml("reset = \"Ctrl+\""),
+            Err(KeyBindingsError::InvalidKey { .. }),
+        ));
+        assert!(matches!(KeyBindings::from_toml("reset = 1"), Err(KeyBindingsError::InvalidValue(_))));
+        assert!(matches!(KeyBindings::from_toml("reset ="), Err(KeyBindingsError::Toml(_))));
+    }
+}
@@ -1,4 +1,15 @@
+pub mod camera;
 pub mod fs;
+#[cfg(feature = "gamepad")]
+pub mod gamepad;
+pub mod gltf;
+pub mod keybindings;
 pub mod math;
+pub mod mtl;
 pub mod obj;
+pub mod ply;
+pub mod recorder;
+pub mod session;
+pub mod stl;
 pub mod vulkan;
+pub mod watcher;
@@ -1,66 +1,403 @@
-use scop_lib::fs::{self, Carousel};
+use scop_lib::camera::{CameraPath, CameraTransition, Keyframe, OrbitCamera};
+use scop_lib::fs::{self, Carousel, SortMode};
+#[cfg(feature = "gamepad")]
+use scop_lib::gamepad::{GamepadAction, Gamepads};
+use scop_lib::keybindings::{Action, KeyBindings};
 use scop_lib::math::{Deg, Matrix4, Vector3};
 use scop_lib::obj::NormalizedObj;
-use scop_lib::vulkan::{ShaderSpv, VkApp};
+use scop_lib::recorder::FrameRecorder;
+use scop_lib::session::Session;
+use scop_lib::vulkan::{
+    CubemapPaths, DrawError, LoadStatus, LoadTicket, ProjectionMode, RotationAxis, Shading, TextureFilter,
+    TextureSource, UvMode, VkApp, VkAppConfig,
+};
+use scop_lib::watcher::FileWatcher;
 
 use anyhow::Context;
-use ash::vk::CullModeFlags;
+use ash::vk::{CullModeFlags, PolygonMode, PresentModeKHR, SampleCountFlags};
 use winit::{
     application::ApplicationHandler,
     dpi::PhysicalSize,
     event::{ElementState, KeyEvent, MouseButton, MouseScrollDelta, WindowEvent},
     event_loop::{ActiveEventLoop, ControlFlow, EventLoop},
-    keyboard::{Key, KeyCode, NamedKey, PhysicalKey},
+    keyboard::{ModifiersState, PhysicalKey},
     window::{Fullscreen, Window, WindowId},
 };
-use std::path::Path;
-use std::time::Instant;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 const WIDTH: u32 = 800;
 const HEIGHT: u32 = 600;
 const TITLE: &str = "scop";
 const TEXTURE_WEIGHT_CHANGE_SPEED: f32 = 0.5; // change will take 2 secs from 0 to 1
+const MORPH_WEIGHT_CHANGE_SPEED: f32 = 0.5;
+const LETTERBOX_ASPECT: f32 = 16. / 9.;
+const DEPTH_BIAS_CONSTANT_STEP: f32 = 0.5;
+const DEPTH_BIAS_SLOPE_STEP: f32 = 0.25;
+const LIGHT_ROTATION_STEP: f32 = 15.; // in degrees
+const REFLECTIVITY_STEP: f32 = 0.25;
+/// Change of the auto-rotate speed per key press in degrees per second.
+const ROTATION_SPEED_STEP: f32 = 15.;
+/// Rotation in degrees when moving the cursor across the whole window at sensitivity 1.
+const MOUSE_ROTATION: f32 = 180.;
+/// Factor the mouse sensitivity changes by per key press.
+const MOUSE_SENSITIVITY_STEP: f32 = 1.25;
+const MOUSE_SENSITIVITY_RANGE: std::ops::RangeInclusive<f32> = 0.1..=10.;
+/// Rotation in degrees per second with the right stick fully deflected.
+const STICK_ROTATION_SPEED: f32 = 120.;
+/// Mouse wheel lines per second with a trigger fully pressed.
+const TRIGGER_ZOOM_SPEED: f32 = 4.;
+/// Optional file to change the keys listed below, see `KeyBindings` for the format.
+const KEY_BINDINGS_PATH: &str = "keybindings.toml";
+/// File the model, texture and camera are saved to on exit and restored from on start.
+const SESSION_PATH: &str = "session.toml";
+/// File the current model is exported to.
+const EXPORT_PATH: &str = "export.obj";
+/// Directory recorded frames are saved to.
+const RECORD_DIR: &str = "frames";
+/// Directory the turntable views are saved to and their number.
+const TURNTABLE_DIR: &str = "turntable";
+const TURNTABLE_VIEWS: u32 = 8;
+/// Seconds the camera takes to move to the view of `FrameModel` and the view snaps.
+const CAMERA_TRANSITION_TIME: f32 = 0.3;
+/// Seconds between the keyframes of the camera path.
+const CAMERA_KEYFRAME_INTERVAL: f32 = 2.;
+/// Frame rate limit used when it is enabled without setting SCOP_FPS.
+const DEFAULT_FPS_CAP: u32 = 60;
+/// Background colors to cycle through, black, dark gray, light gray and white.
+const CLEAR_COLORS: [[f32; 4]; 4] = [
+    [0., 0., 0., 1.],
+    [0.2, 0.2, 0.2, 1.],
+    [0.6, 0.6, 0.6, 1.],
+    [1., 1., 1., 1.],
+];
+/// Images with this suffix are the normal maps of the image without it.
+const NORMAL_MAP_SUFFIX: &str = "_normal";
 
-fn check_if_obj(path: &Path) -> bool {
-    path.extension().map(|ext| ext == "obj").unwrap_or_default()
+fn check_if_model(path: &Path) -> bool {
+    fs::MODEL_EXTENSIONS.iter().any(|ext| fs::has_extension(path, ext)) || fs::is_archive(path)
 }
 
 fn check_if_image(path: &Path) -> bool {
-    path.extension().map(|ext| ext == "jpg" || ext == "png").unwrap_or_default()
+    ["jpg", "jpeg", "png", "tga", "bmp"].iter().any(|ext| fs::has_extension(path, ext))
+        && !path.file_stem().is_some_and(|stem| stem.to_string_lossy().ends_with(NORMAL_MAP_SUFFIX))
+}
+
+/// Returns the normal map next to `image` if there is one, e.g. `brick_normal.png` for `brick.png`.
+fn normal_map_path(image: &Path) -> Option<PathBuf> {
+    let stem = image.file_stem()?.to_string_lossy();
+    let path = image.with_file_name(format!("{stem}{NORMAL_MAP_SUFFIX}")).with_extension(image.extension()?);
+    path.is_file().then_some(path)
+}
+
+/// Loads the normal map belonging to `image` in the background
+/// or removes the current one if there is none.
+fn update_normal_map(app: &mut VkApp, image: &Path) {
+    match normal_map_path(image) {
+        Some(path) => {
+            app.request_normal_map_load(path);
+        }
+        None => {
+            if let Err(err) = app.clear_normal_map() {
+                log::warn!("Failed to remove normal map: {err:#}");
+            }
+        }
+    }
+}
+
+/// Loads `image` and its normal map in the background, see `VkApp::request_texture_load`.
+fn load_texture(app: &mut VkApp, image: &Path) {
+    app.request_texture_load(image);
+    update_normal_map(app, image);
+}
+
+/// Rotates the direction `dir` around the y axis by `yaw` and up or down by `pitch` degrees.
+fn rotate_direction(dir: Vector3, yaw: f32, pitch: f32) -> Vector3 {
+    let dir = dir.normalize();
+    let yaw = dir.x().atan2(dir.z()) + yaw.to_radians();
+    let pitch = (dir.y().asin() + pitch.to_radians()).clamp(-89f32.to_radians(), 89f32.to_radians());
+    Vector3::from([yaw.sin() * pitch.cos(), pitch.sin(), yaw.cos() * pitch.cos()])
+}
+
+/// Animates the view from `from` to the current view of `vulkan` around the model center.
+fn camera_transition(from: Matrix4, vulkan: &VkApp) -> CameraTransition {
+    let center = vulkan.model_matrix[3].truncate();
+    CameraTransition::new(from, vulkan.view_matrix, center, CAMERA_TRANSITION_TIME)
+}
+
+/// Configuration of the renderer showing `model_path` with `image_path`, or a white texture without one.
+fn vulkan_config(model_path: &Path, image_path: Option<PathBuf>) -> VkAppConfig {
+    let config = VkAppConfig::default()
+        .model_path(model_path)
+        .cubemap_paths(CubemapPaths::from_dir("assets/cubemap"))
+        .device_index(parse_env_var("SCOP_GPU"));
+    match image_path {
+        Some(image_path) => config.image_path(image_path),
+        None => config.no_image(),
+    }
+}
+
+fn load_nobj(path: &Path) -> Result<NormalizedObj, anyhow::Error> {
+    fs::load_model(path)
+}
+
+/// Parses the environment variable `name`, invalid values are ignored with a warning.
+fn parse_env_var<T: FromStr>(name: &str) -> Option<T> {
+    let value = std::env::var(name).ok()?;
+    let parsed = value.parse().ok();
+    if parsed.is_none() {
+        log::warn!("Ignoring invalid {name} value {value:?}");
+    }
+    parsed
+}
+
+/// Returns what `action` does for the key help printed on start.
+fn action_help(action: Action) -> String {
+    match action {
+        Action::Quit => "quit".into(),
+        Action::MoveForward => "move forward (free-fly mode)".into(),
+        Action::MoveBackward => "move backward (free-fly mode)".into(),
+        Action::MoveLeft => "move left (free-fly mode)".into(),
+        Action::MoveRight => "move right (free-fly mode)".into(),
+        Action::MoveUp => "move up (free-fly mode)".into(),
+        Action::MoveDown => "move down (free-fly mode)".into(),
+        Action::PrevModel => "switch to the previous model".into(),
+        Action::NextModel => "switch to the next model".into(),
+        Action::Export => format!("export the current model as processed for display to {EXPORT_PATH}"),
+        Action::DumpState => "dump the current state to the log".into(),
+        Action::ToggleTextureFilter => "toggle the texture filter between linear and nearest".into(),
+        Action::Reload => "reload the current model and texture from disk".into(),
+        Action::ToggleFpsLimit => format!("toggle the frame rate limit, {DEFAULT_FPS_CAP} fps unless set with SCOP_FPS"),
+        Action::ToggleInvertY => "toggle inverted vertical mouse movement".into(),
+        Action::AddCameraKeyframe => "add the current view to the camera path".into(),
+        Action::ClearCameraPath => "clear the camera path".into(),
+        Action::PlayCameraPath => "play or stop the camera path".into(),
+        Action::LoopCameraPath => "play the camera path in a loop".into(),
+        Action::ToggleRecording => format!("start or stop recording every frame to {RECORD_DIR}/frame_<number>.png"),
+        Action::Screenshot => "save a screenshot to screenshot_<timestamp>.png".into(),
+        Action::Turntable => {
+            format!("save {TURNTABLE_VIEWS} views around the model to {TURNTABLE_DIR}/turntable_<index>.png")
+        }
+        Action::FrameModel => "move the camera so the whole model is visible".into(),
+        Action::RotateLightLeft => "rotate the light left".into(),
+        Action::RotateLightRight => "rotate the light right".into(),
+        Action::RotateLightUp => "rotate the light up".into(),
+        Action::RotateLightDown => "rotate the light down".into(),
+        Action::ToggleTransparency => "toggle transparency, the model is blended with the alpha of its texture".into(),
+        Action::Msaa1 => "disable MSAA".into(),
+        Action::Msaa2 => "use 2 MSAA samples".into(),
+        Action::Msaa4 => "use 4 MSAA samples".into(),
+        Action::Msaa8 => "use 8 MSAA samples".into(),
+        Action::ToggleFxaa => "toggle FXAA, a cheaper anti-aliasing than MSAA that disables MSAA".into(),
+        Action::ToggleDepthTest => "toggle the depth test of the model, without it later drawn triangles are in front".into(),
+        Action::ToggleDepthView => {
+            "toggle coloring the model by its depth between the clip planes, red near and blue far".into()
+        }
+        Action::DecreaseSensitivity => "decrease mouse sensitivity".into(),
+        Action::IncreaseSensitivity => "increase mouse sensitivity".into(),
+        Action::DecreaseDepthBiasConstant => "decrease constant depth bias".into(),
+        Action::IncreaseDepthBiasConstant => "increase constant depth bias".into(),
+        Action::DecreaseDepthBiasSlope => "decrease slope depth bias".into(),
+        Action::IncreaseDepthBiasSlope => "increase slope depth bias".into(),
+        Action::ToggleSkybox => "toggle skybox".into(),
+        Action::CycleCullMode => "switch cull modes between NONE, BACK and FRONT".into(),
+        Action::CycleReflectivity => format!("increase how much of the skybox the model reflects by {REFLECTIVITY_STEP}, after 1 back to 0"),
+        Action::ToggleFullscreen => "toggle fullscreen".into(),
+        Action::CycleSortMode => "switch the model order between name, modification time and size".into(),
+        Action::ToggleHotReload => "toggle hot reloading the model and texture when their files change".into(),
+        Action::NextTexture => "switch texture image, a normal map named <image>_normal.<ext> is loaded with it".into(),
+        Action::ToggleGrid => "toggle ground grid and world axes".into(),
+        Action::ToggleLetterbox => "toggle letterboxing to 16:9".into(),
+        Action::Reset => "reset camera and object".into(),
+        Action::Morph => "morph into the next model and back".into(),
+        Action::ToggleNormals => "toggle vertex normals".into(),
+        Action::ToggleFlatShading => "toggle between smooth and flat shading".into(),
+        Action::ToggleCameraMode => "toggle between orbit and free-fly camera".into(),
+        Action::ToggleWireframe => "toggle wireframe".into(),
+        Action::CycleUvMode => "switch texture coordinates between file, planar and spherical".into(),
+        Action::ToggleRotate => "toggle rotate".into(),
+        Action::CycleRotationAxis => "switch the rotation axis between x, y and z".into(),
+        Action::DecreaseRotationSpeed => "decrease the rotation speed, below 0 the model turns the other way".into(),
+        Action::IncreaseRotationSpeed => "increase the rotation speed".into(),
+        Action::ToggleTexture => "toggle between random colors and texture".into(),
+        Action::ToggleUvLayout => "toggle uv layout overlay".into(),
+        Action::ToggleProjection => "toggle between perspective and orthographic projection".into(),
+        Action::ToggleBbox => "toggle the bounding box of the model".into(),
+        Action::ToggleVsync => "toggle VSync (present mode FIFO or MAILBOX/IMMEDIATE)".into(),
+        Action::CycleClearColor => "switch the background color, visible when the skybox is hidden".into(),
+        Action::ViewFront => "snap to the framed orthographic front view".into(),
+        Action::ViewBack => "snap to the framed orthographic back view".into(),
+        Action::ViewRight => "snap to the framed orthographic right view".into(),
+        Action::ViewLeft => "snap to the framed orthographic left view".into(),
+        Action::ViewTop => "snap to the framed orthographic top view".into(),
+        Action::ViewBottom => "snap to the framed orthographic bottom view".into(),
+        Action::ToggleCheckerboard => "toggle a checkerboard texture to check the texture coordinates".into(),
+    }
 }
 
 fn main() {
-    println!("Usage:");
+    env_logger::init();
+    let key_bindings = KeyBindings::load(KEY_BINDINGS_PATH).unwrap_or_else(|err| {
+        log::warn!("Failed to load key bindings from {KEY_BINDINGS_PATH}, using the defaults: {err}");
+        KeyBindings::default()
+    });
+
+    println!("Usage: scop [SCENE_DIR]");
+    println!("SCENE_DIR: directory or zip archive with an obj file, its mtl files and textures to load as scene");
+    println!("Zip archives in assets/models are shown with the first model in them");
     println!("Run with RUST_LOG=debug to see logging output");
+    println!("Run with SCOP_GPU=<index> to choose the GPU, RUST_LOG=info lists the available ones");
+    println!("Run with SCOP_FPS=<fps> to limit the frame rate, independent of VSync");
+    println!("Run with SCOP_VALIDATION=1 to enable the Vulkan validation layers, if installed, or 0 to disable them");
+    println!("  their messages are logged with the target vulkan::validation, e.g. RUST_LOG=vulkan::validation=warn");
+    println!("Build with --features ui for an on-screen control panel");
+    println!("Build with --features gamepad to use a game controller:");
+    println!("  left stick moves like WASD, right stick rotates the camera, triggers zoom,");
+    println!("  A and B switch models, X switches the texture image, Y frames the model");
+    println!("The model, texture and camera are restored on start, delete {SESSION_PATH} to reset them");
+    println!("Keys can be changed in {KEY_BINDINGS_PATH}, e.g. `next_model = \"PageDown\"`");
     println!();
-    println!("Left-Click: rotate model with mouse");
-    println!("Right-Click: rotate camera with mouse");
-    println!("Mouse-Wheel: zoom image");
-    println!("WASD: move around");
-    println!("Space and Left-Shift: move up and down");
-    println!("← and →: switch models");
-    println!("B: toggle skybox");
-    println!("C: switch cull modes between NONE, BACK and FRONT");
-    println!("I: switch texture image");
-    println!("L: reset camera and object");
-    println!("R: toggle rotate");
-    println!("T: toggle between random colors and texture");
+    println!("Left-Click: orbit camera around the model (orbit mode) or rotate model (free-fly mode)");
+    println!("Left-Click without moving: log the triangle under the cursor");
+    println!("Right-Click: rotate model (orbit mode) or rotate camera (free-fly mode)");
+    println!("Mouse-Wheel: change orbit distance (orbit mode) or zoom image (free-fly mode)");
+    for action in Action::all() {
+        let keys = key_bindings.keys(action).map(ToString::to_string).collect::<Vec<_>>();
+        if !keys.is_empty() {
+            println!("{}: {}", keys.join(", "), action_help(action));
+        }
+    }
     println!();
 
-    env_logger::init();
-
     let event_loop = EventLoop::new().unwrap();
     event_loop.set_control_flow(ControlFlow::Poll);
 
+    let fps_cap = parse_env_var("SCOP_FPS").filter(|&fps| fps > 0);
     let mut app = App {
+        key_bindings,
         toggle_rotate: true,
+        scene_dir: std::env::args_os().nth(1).map(PathBuf::from),
+        mouse_sensitivity: 1.,
+        limit_fps: fps_cap.is_some(),
+        fps_cap: fps_cap.unwrap_or(DEFAULT_FPS_CAP),
         ..Default::default()
     };
-    app.model_carousel.set_dir("assets/models");
+    app.model_carousel.set_dir_recursive("assets/models");
     app.image_carousel.set_dir("assets/images");
     event_loop.run_app(&mut app).unwrap();
 }
 
+/// Settings of a `VkApp` that are not part of its config, kept when the renderer is recreated.
+struct RendererSettings {
+    msaa_samples: SampleCountFlags,
+    fxaa: bool,
+    generate_mips: bool,
+    texture_filter: TextureFilter,
+    anisotropy: f32,
+    shading: Shading,
+    uv_mode: UvMode,
+    projection_mode: ProjectionMode,
+    polygon_mode: PolygonMode,
+    cull_mode: CullModeFlags,
+    transparent: bool,
+    depth_test: bool,
+    depth_bias: (f32, f32),
+    custom_clip_planes: Option<(f32, f32)>,
+    letterbox: Option<f32>,
+    clear_color: [f32; 4],
+    show_cubemap: bool,
+    show_grid: bool,
+    show_normals: bool,
+    show_bbox: bool,
+    show_depth: bool,
+    show_uv_layout: bool,
+    light_dir: Vector3,
+    texture_weight: f32,
+    reflectivity: f32,
+    rotation_axis: RotationAxis,
+    rotation_speed: f32,
+    morph_weight: Option<f32>,
+}
+
+impl RendererSettings {
+    fn of(app: &VkApp) -> Self {
+        Self {
+            msaa_samples: app.msaa_samples(),
+            fxaa: app.fxaa(),
+            generate_mips: app.generate_mips,
+            texture_filter: app.texture_filter(),
+            anisotropy: app.anisotropy(),
+            shading: app.shading,
+            uv_mode: app.uv_mode,
+            projection_mode: app.projection_mode,
+            polygon_mode: app.polygon_mode(),
+            cull_mode: app.cull_mode,
+            transparent: app.transparent(),
+            depth_test: app.depth_test(),
+            depth_bias: (app.depth_bias_constant, app.depth_bias_slope),
+            custom_clip_planes: app.has_custom_clip_planes().then(|| app.clip_planes()),
+            letterbox: app.letterbox(),
+            clear_color: app.clear_color(),
+            show_cubemap: app.show_cubemap(),
+            show_grid: app.show_grid(),
+            show_normals: app.show_normals(),
+            show_bbox: app.show_bbox(),
+            show_depth: app.show_depth(),
+            show_uv_layout: app.show_uv_layout(),
+            light_dir: app.light_dir,
+            texture_weight: app.texture_weight,
+            reflectivity: app.reflectivity,
+            rotation_axis: app.rotation_axis,
+            rotation_speed: app.rotation_speed(),
+            morph_weight: app.has_morph_target().then_some(app.morph_weight),
+        }
+    }
+
+    /// Applies the settings created in the config of the new app.
+    fn config(&self, config: VkAppConfig) -> VkAppConfig {
+        config
+            .msaa_samples(Some(self.msaa_samples))
+            .generate_mips(self.generate_mips)
+            .anisotropy(self.anisotropy)
+            .clear_color(self.clear_color)
+    }
+
+    /// Applies the remaining settings, returns true if the model has to be reloaded
+    /// for the shading and texture coordinates to take effect.
+    fn apply(&self, app: &mut VkApp) -> bool {
+        app.set_fxaa(self.fxaa);
+        if let Err(err) = app.set_texture_filter(self.texture_filter) {
+            log::warn!("Failed to restore the texture filter: {err:#}");
+        }
+        app.projection_mode = self.projection_mode;
+        app.set_polygon_mode(self.polygon_mode);
+        app.set_cull_mode(self.cull_mode);
+        app.set_transparent(self.transparent);
+        app.set_depth_test(self.depth_test);
+        app.set_depth_bias(self.depth_bias.0, self.depth_bias.1);
+        if let Some((near, far)) = self.custom_clip_planes {
+            app.set_clip_planes(near, far);
+        }
+        app.set_letterbox(self.letterbox);
+        app.set_show_cubemap(self.show_cubemap);
+        app.set_show_grid(self.show_grid);
+        app.set_show_normals(self.show_normals);
+        app.set_show_bbox(self.show_bbox);
+        app.set_show_depth(self.show_depth);
+        app.set_show_uv_layout(self.show_uv_layout);
+        app.light_dir = self.light_dir;
+        app.texture_weight = self.texture_weight;
+        app.reflectivity = self.reflectivity;
+        app.rotation_axis = self.rotation_axis;
+        app.set_rotation_speed(self.rotation_speed);
+        let reload = app.shading != self.shading || app.uv_mode != self.uv_mode;
+        app.shading = self.shading;
+        app.uv_mode = self.uv_mode;
+        reload
+    }
+}
+
 #[derive(Default)]
 pub struct KeyStates {
     forward: bool,
@@ -78,22 +415,68 @@ struct App {
 
     fps: Option<(Instant, u32)>,
     last_frame: Option<Instant>,
+    /// Wait between frames to render at most `fps_cap` frames per second.
+    limit_fps: bool,
+    fps_cap: u32,
+    /// Nothing is rendered while the window is not focused, except after resizing it.
+    paused: bool,
 
+    key_bindings: KeyBindings,
     pressed: KeyStates,
+    modifiers: ModifiersState,
     toggle_rotate: bool,
+    orbit_camera: OrbitCamera,
+    camera_path: CameraPath,
+    /// Start of the playback of `camera_path` if it is playing.
+    camera_path_start: Option<Instant>,
+    /// Animation of the view to the one of the last `FrameModel` or view snap.
+    camera_transition: Option<CameraTransition>,
+    free_fly: bool,
+    model_path: PathBuf,
+    /// Model of the carousel loaded in the background, `model_path` once it is loaded.
+    pending_model: Option<(LoadTicket, PathBuf)>,
+    scene_dir: Option<PathBuf>,
     load_prev_model: bool,
     load_next_model: bool,
     load_next_image: bool,
+    /// Load the current file of the carousel, e.g. after selecting it in the ui.
+    load_selected_model: bool,
+    load_selected_image: bool,
+    load_morph_target: bool,
+    reload_model: bool,
+    reload_texture: bool,
+    watcher: Option<FileWatcher>,
+    /// Created when recording is started the first time.
+    recorder: Option<FrameRecorder>,
+    /// Where the next captured frame is saved after a screenshot was requested.
+    screenshot_path: Option<String>,
     is_left_clicked: bool,
     is_right_clicked: bool,
     cursor_position: Option<[i32; 2]>,
+    /// Cursor position when the left button was pressed, a release at the same position is a click.
+    left_press_position: Option<[i32; 2]>,
     cursor_delta: [i32; 2],
     wheel_delta: f32,
+    /// Deflection of the gamepad sticks, x to the right and y up.
+    left_stick: [f32; 2],
+    right_stick: [f32; 2],
+    /// Right minus left gamepad trigger.
+    trigger_zoom: f32,
+    /// Factor for the rotation by mouse movements.
+    mouse_sensitivity: f32,
+    /// Flip the vertical direction of rotations by mouse movements.
+    invert_y: bool,
     tex_weight_change: f32,
+    morph_weight_change: f32,
     is_fullscreen: bool,
 
     model_carousel: Carousel,
     image_carousel: Carousel,
+
+    #[cfg(feature = "ui")]
+    egui: Option<egui_winit::State>,
+    #[cfg(feature = "gamepad")]
+    gamepads: Option<Gamepads>,
 }
 
 impl App {
@@ -103,26 +486,736 @@ impl App {
             .with_inner_size(PhysicalSize::new(WIDTH, HEIGHT));
         let window = event_loop.create_window(window_attrs).context("Failed to create window")?;
 
-        let model_path = self.model_carousel.get_next(0, check_if_obj)
+        let session = Session::load(SESSION_PATH).unwrap_or_else(|err| {
+            log::warn!("Failed to load {SESSION_PATH}, starting from the first files: {err}");
+            None
+        });
+        // files deleted since are skipped, then the carousels start from their first file
+        if let Some(session) = &session {
+            if session.model_path.is_file() {
+                self.model_carousel.set_current(&session.model_path);
+            }
+            if session.image_path.is_file() {
+                self.image_carousel.set_current(&session.image_path);
+            }
+        }
+        let model_path = self.model_carousel.get_next(0, check_if_model)
             .context("Failed to find a model")?;
-        let nobj = NormalizedObj::from_reader(fs::load(model_path)?)?;
-
+        // without an image the model is shown with a white texture
         let image_path = self.image_carousel.get_next(0, check_if_image)
-            .context("Failed to find an image")?;
-        let shader_spv = ShaderSpv {
-            vert: include_bytes!(concat!(env!("OUT_DIR"), "/shader.vert.spv")),
-            frag: include_bytes!(concat!(env!("OUT_DIR"), "/shader.frag.spv")),
-        };
-        let cubemap_spv = ShaderSpv {
-            vert: include_bytes!(concat!(env!("OUT_DIR"), "/cubemap.vert.spv")),
-            frag: include_bytes!(concat!(env!("OUT_DIR"), "/cubemap.frag.spv")),
-        };
-        let vulkan = VkApp::new(&window, WIDTH, HEIGHT, &image_path, nobj, shader_spv, cubemap_spv)?;
+            .inspect_err(|err| log::warn!("Failed to find an image: {err}"))
+            .ok();
+        let config = vulkan_config(&model_path, image_path).size(WIDTH, HEIGHT);
+        let mut vulkan = VkApp::new(&window, &config)?;
+        log::info!("Loaded {}: {}", model_path.display(), vulkan.model_stats());
+        let texture_path = vulkan.texture_path().to_path_buf();
+        update_normal_map(&mut vulkan, &texture_path);
+        if let Some(dir) = &self.scene_dir {
+            vulkan.load_scene(dir).context("Failed to load scene")?;
+            log::info!("Loaded scene {}: {}", dir.display(), vulkan.model_stats());
+        }
+        // the transforms only make sense for the same model
+        if let Some(session) = session.filter(|session| session.model_path == model_path) {
+            vulkan.model_matrix = session.model_matrix;
+            vulkan.view_matrix = session.view_matrix;
+            self.orbit_camera = session.orbit_camera;
+            self.free_fly = session.free_fly;
+            self.toggle_rotate = session.rotate;
+        }
+
+        #[cfg(feature = "gamepad")]
+        {
+            self.gamepads = Gamepads::new()
+                .inspect_err(|err| log::warn!("Failed to initialize gamepads: {err}"))
+                .ok();
+        }
+
+        #[cfg(feature = "ui")]
+        {
+            self.egui = Some(egui_winit::State::new(
+                egui::Context::default(),
+                egui::ViewportId::ROOT,
+                &window,
+                Some(window.scale_factor() as f32),
+                None,
+                None,
+            ));
+        }
 
+        self.model_path = model_path;
         self.vulkan = Some(vulkan);
         self.window = Some(window);
         Ok(())
     }
+
+    /// Creates the renderer again after the device was lost, e.g. after a driver reset.
+    /// The model, texture, view, settings and morph target are kept, models added
+    /// with `VkApp::add_model` are lost.
+    fn recreate_vulkan(&mut self) -> Result<(), anyhow::Error> {
+        let window = self.window.as_ref().unwrap();
+        let old = self.vulkan.take().unwrap();
+        let (model_matrix, view_matrix) = (old.model_matrix, old.view_matrix);
+        let settings = RendererSettings::of(&old);
+        let has_scene = old.has_scene();
+        let instance_count = old.model_ids().len();
+        let texture_path = Some(old.texture_path().to_path_buf())
+            .filter(|path| !path.as_os_str().is_empty());
+        old.wait_gpu_idle();
+        drop(old);
+
+        let size = window.inner_size();
+        let config = vulkan_config(&self.model_path, texture_path.clone()).size(size.width, size.height);
+        let config = settings.config(config);
+        let mut vulkan = VkApp::new(window, &config).context("Failed to recreate the renderer")?;
+        self.reload_model = settings.apply(&mut vulkan);
+        if let Some(morph_weight) = settings.morph_weight {
+            // the target is the next model in the carousel like when it was loaded
+            self.load_morph_target = true;
+            self.morph_weight_change = 0.;
+            vulkan.morph_weight = morph_weight;
+        }
+        if instance_count > 0 {
+            log::warn!("{instance_count} models added with add_model were lost with the device");
+        }
+        if let Some(texture_path) = texture_path {
+            update_normal_map(&mut vulkan, &texture_path);
+        }
+        if let Some(dir) = self.scene_dir.as_ref().filter(|_| has_scene) {
+            vulkan.load_scene(dir).context("Failed to load scene")?;
+        }
+        vulkan.model_matrix = model_matrix;
+        vulkan.view_matrix = view_matrix;
+        self.vulkan = Some(vulkan);
+        self.pending_model = None;
+        self.camera_transition = None;
+
+        // a new context uploads the ui textures to the new renderer again
+        #[cfg(feature = "ui")]
+        {
+            self.egui = Some(egui_winit::State::new(
+                egui::Context::default(),
+                egui::ViewportId::ROOT,
+                window,
+                Some(window.scale_factor() as f32),
+                None,
+                None,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Reads the gamepad sticks and triggers and handles the face buttons.
+    #[cfg(feature = "gamepad")]
+    fn poll_gamepads(&mut self) {
+        let Some(gamepads) = self.gamepads.as_mut() else { return };
+        let input = gamepads.poll();
+        self.left_stick = input.translation;
+        self.right_stick = input.rotation;
+        self.trigger_zoom = input.zoom;
+        for action in input.actions {
+            match action {
+                GamepadAction::NextModel => self.load_next_model = true,
+                GamepadAction::PrevModel => self.load_prev_model = true,
+                GamepadAction::NextTexture => self.load_next_image = true,
+                GamepadAction::FrameModel => {
+                    let vulkan = self.vulkan.as_mut().unwrap();
+                    let from = vulkan.view_matrix;
+                    vulkan.frame_model();
+                    self.orbit_camera.distance = vulkan.framing_distance();
+                    self.camera_transition = Some(camera_transition(from, vulkan));
+                }
+            }
+        }
+    }
+
+    /// Runs the ui for this frame and passes its output to the renderer.
+    #[cfg(feature = "ui")]
+    fn run_ui(&mut self) {
+        let (Some(mut state), Some(window)) = (self.egui.take(), self.window.as_ref()) else { return };
+        let raw_input = state.take_egui_input(window);
+        let ctx = state.egui_ctx().clone();
+        let output = ctx.run(raw_input, |ctx| self.draw_ui(ctx));
+        state.handle_platform_output(self.window.as_ref().unwrap(), output.platform_output);
+        self.egui = Some(state);
+
+        let primitives = ctx.tessellate(output.shapes, output.pixels_per_point);
+        let vulkan = self.vulkan.as_mut().unwrap();
+        if let Err(err) = vulkan.set_ui(&output.textures_delta, primitives, output.pixels_per_point) {
+            log::warn!("Failed to update ui: {err:#}");
+        }
+    }
+
+    #[cfg(feature = "ui")]
+    fn draw_ui(&mut self, ctx: &egui::Context) {
+        let Some(vulkan) = self.vulkan.as_mut() else { return };
+        egui::Window::new("Controls").show(ctx, |ui| {
+            let slider = egui::Slider::new(&mut vulkan.texture_weight, 0.0..=1.0).text("Texture weight");
+            if ui.add(slider).changed() {
+                self.tex_weight_change = 0.;
+            }
+            ui.add(egui::Slider::new(&mut vulkan.reflectivity, 0.0..=1.0).text("Reflectivity"));
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.toggle_rotate, "Rotate");
+                let mut speed = vulkan.rotation_speed();
+                if ui.add(egui::DragValue::new(&mut speed).suffix("°/s")).changed() {
+                    vulkan.set_rotation_speed(speed);
+                }
+                egui::ComboBox::from_id_salt("rotation_axis")
+                    .selected_text(format!("{:?}", vulkan.rotation_axis))
+                    .show_ui(ui, |ui| {
+                        for axis in [RotationAxis::X, RotationAxis::Y, RotationAxis::Z] {
+                            ui.selectable_value(&mut vulkan.rotation_axis, axis, format!("{axis:?}"));
+                        }
+                    });
+            });
+            let scale = vulkan.model_matrix[0].truncate().magnitude();
+            let (pitch, yaw, roll) = (vulkan.model_matrix * Matrix4::from_scale(1. / scale)).to_euler();
+            let mut angles = [pitch, yaw, roll].map(|angle| Deg::from(angle).0);
+            let changed = ui.horizontal(|ui| {
+                ["Pitch", "Yaw", "Roll"].iter().zip(&mut angles).fold(false, |changed, (label, angle)| {
+                    ui.label(*label);
+                    let drag = egui::DragValue::new(angle).suffix("°");
+                    ui.add(drag).changed() || changed
+                })
+            }).inner;
+            if changed {
+                let [pitch, yaw, roll] = angles.map(Deg);
+                vulkan.model_matrix = Matrix4::from_euler(pitch, yaw, roll) * Matrix4::from_scale(scale);
+                self.toggle_rotate = false;
+            }
+            ui.add(egui::Slider::new(&mut self.mouse_sensitivity, MOUSE_SENSITIVITY_RANGE)
+                .logarithmic(true)
+                .text("Mouse sensitivity"));
+            ui.checkbox(&mut self.invert_y, "Invert y");
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.limit_fps, "FPS cap");
+                ui.add_enabled(self.limit_fps, egui::DragValue::new(&mut self.fps_cap).range(1..=1000));
+            });
+
+            let mut cull_mode = vulkan.cull_mode;
+            egui::ComboBox::from_label("Cull mode")
+                .selected_text(format!("{cull_mode:?}"))
+                .show_ui(ui, |ui| {
+                    for mode in [CullModeFlags::NONE, CullModeFlags::BACK, CullModeFlags::FRONT] {
+                        ui.selectable_value(&mut cull_mode, mode, format!("{mode:?}"));
+                    }
+                });
+            if cull_mode != vulkan.cull_mode {
+                vulkan.set_cull_mode(cull_mode);
+            }
+            let mut transparent = vulkan.transparent();
+            if ui.checkbox(&mut transparent, "Transparent").changed() {
+                vulkan.set_transparent(transparent);
+            }
+            let mut depth_test = vulkan.depth_test();
+            if ui.checkbox(&mut depth_test, "Depth test").changed() {
+                vulkan.set_depth_test(depth_test);
+            }
+            let mut fxaa = vulkan.fxaa();
+            if ui.checkbox(&mut fxaa, "FXAA").changed() {
+                vulkan.set_fxaa(fxaa);
+            }
+            let mut show_depth = vulkan.show_depth();
+            if ui.checkbox(&mut show_depth, "Depth view").changed() {
+                vulkan.set_show_depth(show_depth);
+            }
+            ui.horizontal(|ui| {
+                let (mut near, mut far) = vulkan.clip_planes();
+                // the ranges keep the near plane positive and in front of the far one
+                let near_drag = egui::DragValue::new(&mut near).speed(0.01).range(1e-4..=far * 0.99).prefix("Near ");
+                let near_changed = ui.add(near_drag).changed();
+                let far_drag = egui::DragValue::new(&mut far).speed(0.1).range(near * 1.01..=f32::MAX).prefix("Far ");
+                if ui.add(far_drag).changed() || near_changed {
+                    vulkan.set_clip_planes(near, far);
+                }
+                if ui.add_enabled(vulkan.has_custom_clip_planes(), egui::Button::new("Auto")).clicked() {
+                    vulkan.reset_clip_planes();
+                }
+            });
+
+            let mut filter = vulkan.texture_filter();
+            egui::ComboBox::from_label("Texture filter")
+                .selected_text(format!("{filter:?}"))
+                .show_ui(ui, |ui| {
+                    for option in [TextureFilter::Linear, TextureFilter::Nearest] {
+                        ui.selectable_value(&mut filter, option, format!("{option:?}"));
+                    }
+                });
+            if let Err(err) = vulkan.set_texture_filter(filter) {
+                log::warn!("Failed to change texture filter: {err:#}");
+            }
+            let mut anisotropy = vulkan.anisotropy();
+            ui.add(egui::Slider::new(&mut anisotropy, 0.0..=16.0).step_by(1.).text("Anisotropy"));
+            if let Err(err) = vulkan.set_anisotropy(anisotropy) {
+                log::warn!("Failed to change anisotropy: {err:#}");
+            }
+            // only applies to loaded textures, so the current one and the cubemap are loaded again
+            if ui.checkbox(&mut vulkan.generate_mips, "Mipmaps").changed() {
+                self.reload_texture = true;
+                if let Err(err) = vulkan.reload_cubemap() {
+                    log::warn!("Failed to reload cubemap: {err:#}");
+                }
+            }
+
+            // the directories are only read while a dropdown is open
+            egui::ComboBox::from_label("Model")
+                .selected_text(self.model_path.display().to_string())
+                .show_ui(ui, |ui| match self.model_carousel.files(check_if_model) {
+                    Ok(files) => for file in files {
+                        if ui.selectable_label(file == self.model_path, file.display().to_string()).clicked() {
+                            self.model_carousel.set_current(file);
+                            self.load_selected_model = true;
+                        }
+                    },
+                    Err(err) => { ui.label(format!("Failed to list models: {err}")); }
+                });
+            egui::ComboBox::from_label("Texture")
+                .selected_text(vulkan.texture_path().display().to_string())
+                .show_ui(ui, |ui| match self.image_carousel.files(check_if_image) {
+                    Ok(files) => for file in files {
+                        if ui.selectable_label(file == vulkan.texture_path(), file.display().to_string()).clicked() {
+                            self.image_carousel.set_current(file);
+                            self.load_selected_image = true;
+                        }
+                    },
+                    Err(err) => { ui.label(format!("Failed to list images: {err}")); }
+                });
+        });
+    }
+
+    /// Updates the state of an action that lasts while its key is held.
+    fn set_held(&mut self, action: Action, pressed: bool) {
+        match action {
+            Action::MoveForward => self.pressed.forward = pressed,
+            Action::MoveBackward => self.pressed.backward = pressed,
+            Action::MoveLeft => self.pressed.left = pressed,
+            Action::MoveRight => self.pressed.right = pressed,
+            Action::MoveUp => self.pressed.up = pressed,
+            Action::MoveDown => self.pressed.down = pressed,
+            _ => {}
+        }
+    }
+
+    /// Passes the frames captured by the GPU to the screenshot and the recorder.
+    fn save_captured_frames(&mut self) {
+        let Some(vulkan) = self.vulkan.as_mut() else { return };
+        while let Some(image) = vulkan.take_captured_frame() {
+            if let Some(path) = self.screenshot_path.take() {
+                match image.save(&path) {
+                    Ok(()) => log::info!("Saved screenshot to {path}"),
+                    Err(err) => log::warn!("Failed to save screenshot: {err}"),
+                }
+            }
+            if let Some(recorder) = self.recorder.as_mut().filter(|recorder| recorder.is_recording()) {
+                recorder.record(image);
+            }
+        }
+    }
+
+    /// Runs an action whose key was pressed.
+    fn run_action(&mut self, event_loop: &ActiveEventLoop, action: Action) {
+        match action {
+            Action::Quit => {
+                event_loop.exit();
+                return;
+            }
+            Action::PrevModel => self.load_prev_model = true,
+            Action::NextModel => self.load_next_model = true,
+            _ => {}
+        }
+
+        let Some(vulkan) = self.vulkan.as_mut() else { return };
+        match action {
+            Action::Reload => {
+                self.reload_model = true;
+                self.reload_texture = true;
+            }
+            Action::FrameModel => {
+                let from = vulkan.view_matrix;
+                vulkan.frame_model();
+                self.orbit_camera.distance = vulkan.framing_distance();
+                self.camera_transition = Some(camera_transition(from, vulkan));
+            }
+            Action::Export => {
+                if vulkan.has_scene() {
+                    log::warn!("Exporting scenes is not supported, switch to a model first");
+                } else {
+                    let result = std::fs::File::create(EXPORT_PATH)
+                        .and_then(|file| vulkan.write_model(std::io::BufWriter::new(file)));
+                    match result {
+                        Ok(()) => log::info!("Exported {} to {EXPORT_PATH}", self.model_path.display()),
+                        Err(err) => log::warn!("Failed to export model: {err:#}"),
+                    }
+                }
+            }
+            Action::ToggleRecording => {
+                if self.recorder.as_ref().is_some_and(|recorder| recorder.is_recording()) {
+                    // the last frames are still being rendered
+                    vulkan.finish_captures();
+                    self.save_captured_frames();
+                }
+                let recorder = self.recorder.get_or_insert_with(|| FrameRecorder::new(RECORD_DIR));
+                if recorder.is_recording() {
+                    recorder.stop();
+                    log::info!("Stopped recording");
+                } else {
+                    match recorder.start() {
+                        Ok(()) => log::warn!(
+                            "Recording frames to {}, this lowers the frame rate \
+                            since each frame is copied from the GPU and saved",
+                            recorder.dir().display(),
+                        ),
+                        Err(err) => log::warn!("Failed to start recording: {err}"),
+                    }
+                }
+            }
+            Action::Screenshot => {
+                let timestamp = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_millis();
+                // the frame is saved once the GPU finished it, see `save_captured_frames`
+                match vulkan.request_capture() {
+                    Ok(()) => self.screenshot_path = Some(format!("screenshot_{timestamp}.png")),
+                    Err(err) => log::warn!("Failed to save screenshot: {err:#}"),
+                }
+            }
+            Action::Turntable => {
+                match vulkan.render_turntable(TURNTABLE_VIEWS, TURNTABLE_DIR) {
+                    Ok(paths) => log::info!("Saved {} turntable views to {TURNTABLE_DIR}", paths.len()),
+                    Err(err) => log::warn!("Failed to render turntable: {err:#}"),
+                }
+            }
+            Action::RotateLightLeft
+            | Action::RotateLightRight
+            | Action::RotateLightUp
+            | Action::RotateLightDown => {
+                let (yaw, pitch) = match action {
+                    Action::RotateLightLeft => (-LIGHT_ROTATION_STEP, 0.),
+                    Action::RotateLightRight => (LIGHT_ROTATION_STEP, 0.),
+                    Action::RotateLightUp => (0., LIGHT_ROTATION_STEP),
+                    _ => (0., -LIGHT_ROTATION_STEP),
+                };
+                vulkan.light_dir = rotate_direction(vulkan.light_dir, yaw, pitch);
+            }
+            Action::ClearCameraPath => {
+                self.camera_path.clear();
+                self.camera_path_start = None;
+                log::info!("Cleared camera path");
+            }
+            Action::AddCameraKeyframe => {
+                // the free-fly camera looks at a point in front of it
+                let (position, target) = if self.free_fly {
+                    let inverse = vulkan.view_matrix.inverse().unwrap_or(Matrix4::unit());
+                    let position = (inverse * Vector3::default().to_point()).project();
+                    let target = (inverse * Vector3::from([0., 0., -1.]).to_point()).project();
+                    (position, target)
+                } else {
+                    (self.orbit_camera.eye(), self.orbit_camera.target)
+                };
+                let time = if self.camera_path.keyframes().is_empty() {
+                    0.
+                } else {
+                    self.camera_path.duration() + CAMERA_KEYFRAME_INTERVAL
+                };
+                self.camera_path.push(Keyframe { position, target, time });
+                log::info!("Added camera keyframe {}", self.camera_path.keyframes().len());
+            }
+            Action::PlayCameraPath | Action::LoopCameraPath => {
+                if self.camera_path_start.is_some() {
+                    self.camera_path_start = None;
+                } else if self.camera_path.keyframes().len() < 2 {
+                    log::warn!("The camera path needs at least two keyframes, add them with F8");
+                } else {
+                    self.camera_path.looping = action == Action::LoopCameraPath;
+                    self.camera_path_start = Some(Instant::now());
+                    self.camera_transition = None;
+                }
+            }
+            Action::ToggleFpsLimit => {
+                self.limit_fps = !self.limit_fps;
+                if self.limit_fps {
+                    log::info!("Limiting frame rate to {} fps", self.fps_cap);
+                } else {
+                    log::info!("Frame rate unlimited");
+                }
+            }
+            Action::ToggleInvertY => {
+                self.invert_y = !self.invert_y;
+                log::info!("Invert y: {}", self.invert_y);
+            }
+            Action::DecreaseSensitivity | Action::IncreaseSensitivity => {
+                let step = if action == Action::DecreaseSensitivity {
+                    1. / MOUSE_SENSITIVITY_STEP
+                } else {
+                    MOUSE_SENSITIVITY_STEP
+                };
+                let range = MOUSE_SENSITIVITY_RANGE;
+                self.mouse_sensitivity = (self.mouse_sensitivity * step).clamp(*range.start(), *range.end());
+                log::info!("Mouse sensitivity: {}", self.mouse_sensitivity);
+            }
+            Action::ToggleTextureFilter => {
+                let filter = vulkan.texture_filter().toggled();
+                match vulkan.set_texture_filter(filter) {
+                    Ok(()) => log::info!("Texture filter: {filter:?}"),
+                    Err(err) => log::warn!("Failed to change texture filter: {err:#}"),
+                }
+            }
+            Action::DumpState => vulkan.log_state(&[
+                ("Model path", self.model_path.display().to_string()),
+                ("Rotate", self.toggle_rotate.to_string()),
+                ("Fullscreen", self.is_fullscreen.to_string()),
+                ("Free-fly", self.free_fly.to_string()),
+                ("Orbit camera", format!("{:?}", self.orbit_camera)),
+            ]),
+            Action::Msaa1 | Action::Msaa2 | Action::Msaa4 | Action::Msaa8 => {
+                let samples = match action {
+                    Action::Msaa1 => SampleCountFlags::TYPE_1,
+                    Action::Msaa2 => SampleCountFlags::TYPE_2,
+                    Action::Msaa4 => SampleCountFlags::TYPE_4,
+                    _ => SampleCountFlags::TYPE_8,
+                };
+                match vulkan.set_msaa(samples) {
+                    Ok(()) => log::info!("MSAA: {samples:?}"),
+                    Err(err) => log::warn!("{err}"),
+                }
+            }
+            Action::ToggleFxaa => {
+                let fxaa = !vulkan.fxaa();
+                log::info!("FXAA: {fxaa}");
+                vulkan.set_fxaa(fxaa);
+            }
+            Action::ToggleTransparency => {
+                let transparent = !vulkan.transparent();
+                log::info!("Transparency: {transparent}");
+                vulkan.set_transparent(transparent);
+            }
+            Action::ToggleDepthTest => {
+                let depth_test = !vulkan.depth_test();
+                log::info!("Depth test: {depth_test}");
+                vulkan.set_depth_test(depth_test);
+            }
+            Action::ToggleDepthView => {
+                let show_depth = !vulkan.show_depth();
+                log::info!("Depth view: {show_depth}, clip planes: {:?}", vulkan.clip_planes());
+                vulkan.set_show_depth(show_depth);
+            }
+            Action::ToggleSkybox => vulkan.set_show_cubemap(!vulkan.show_cubemap()),
+            Action::CycleCullMode => {
+                let mode = match vulkan.cull_mode {
+                    CullModeFlags::NONE => CullModeFlags::BACK,
+                    CullModeFlags::BACK => CullModeFlags::FRONT,
+                    _ => CullModeFlags::NONE,
+                };
+                log::info!("Cull mode: {mode:?}");
+                vulkan.set_cull_mode(mode);
+            }
+            Action::DecreaseDepthBiasConstant
+            | Action::IncreaseDepthBiasConstant
+            | Action::DecreaseDepthBiasSlope
+            | Action::IncreaseDepthBiasSlope => {
+                let (constant, slope) = match action {
+                    Action::DecreaseDepthBiasConstant => (-DEPTH_BIAS_CONSTANT_STEP, 0.),
+                    Action::IncreaseDepthBiasConstant => (DEPTH_BIAS_CONSTANT_STEP, 0.),
+                    Action::DecreaseDepthBiasSlope => (0., -DEPTH_BIAS_SLOPE_STEP),
+                    _ => (0., DEPTH_BIAS_SLOPE_STEP),
+                };
+                vulkan.set_depth_bias(
+                    vulkan.depth_bias_constant + constant,
+                    vulkan.depth_bias_slope + slope,
+                );
+                log::info!(
+                    "Depth bias: {} constant, {} slope",
+                    vulkan.depth_bias_constant,
+                    vulkan.depth_bias_slope,
+                );
+            }
+            Action::CycleReflectivity => {
+                vulkan.reflectivity = if vulkan.reflectivity >= 1. {
+                    0.
+                } else {
+                    (vulkan.reflectivity + REFLECTIVITY_STEP).min(1.)
+                };
+                log::info!("Reflectivity: {}", vulkan.reflectivity);
+            }
+            Action::ToggleFullscreen => {
+                let fullscreen = if self.is_fullscreen {
+                    None
+                } else {
+                    Some(Fullscreen::Borderless(None))
+                };
+                self.window.as_mut().unwrap().set_fullscreen(fullscreen);
+                self.is_fullscreen = !self.is_fullscreen;
+            }
+            Action::CycleSortMode => {
+                let mode = match self.model_carousel.sort_mode() {
+                    SortMode::Name => SortMode::ModifiedTime,
+                    SortMode::ModifiedTime => SortMode::Size,
+                    SortMode::Size => SortMode::Name,
+                };
+                log::info!("Sorting models by {mode:?}");
+                self.model_carousel.sort_by(mode);
+            }
+            Action::ToggleHotReload => {
+                if self.watcher.take().is_some() {
+                    log::info!("Hot reloading disabled");
+                } else {
+                    match FileWatcher::new() {
+                        Ok(watcher) => {
+                            log::info!("Hot reloading enabled");
+                            self.watcher = Some(watcher);
+                        }
+                        Err(err) => log::warn!("Failed to start file watcher: {err}"),
+                    }
+                }
+            }
+            Action::NextTexture => {
+                self.load_next_image = true;
+                if vulkan.texture_weight == 0. || self.tex_weight_change < 0. {
+                    self.tex_weight_change = TEXTURE_WEIGHT_CHANGE_SPEED;
+                }
+            }
+            Action::Morph => {
+                if !vulkan.has_morph_target() {
+                    self.load_morph_target = true;
+                }
+                self.morph_weight_change = if vulkan.morph_weight == 0. {
+                    MORPH_WEIGHT_CHANGE_SPEED
+                } else if vulkan.morph_weight == 1. {
+                    -MORPH_WEIGHT_CHANGE_SPEED
+                } else {
+                    -self.morph_weight_change
+                };
+            }
+            Action::DecreaseRotationSpeed | Action::IncreaseRotationSpeed => {
+                let step = if action == Action::DecreaseRotationSpeed {
+                    -ROTATION_SPEED_STEP
+                } else {
+                    ROTATION_SPEED_STEP
+                };
+                vulkan.set_rotation_speed(vulkan.rotation_speed() + step);
+                log::info!("Rotation speed: {}°/s", vulkan.rotation_speed());
+            }
+            Action::CycleRotationAxis => {
+                vulkan.rotation_axis = vulkan.rotation_axis.next();
+                log::info!("Rotation axis: {:?}", vulkan.rotation_axis);
+            }
+            Action::ToggleFlatShading => {
+                vulkan.shading = vulkan.shading.toggled();
+                log::info!("Shading: {:?}", vulkan.shading);
+                self.reload_model = true;
+            }
+            Action::CycleUvMode => {
+                vulkan.uv_mode = vulkan.uv_mode.next();
+                log::info!("Texture coordinates: {:?}", vulkan.uv_mode);
+                self.reload_model = true;
+            }
+            Action::ToggleRotate => self.toggle_rotate = !self.toggle_rotate,
+            Action::ToggleGrid => vulkan.set_show_grid(!vulkan.show_grid()),
+            Action::ToggleLetterbox => {
+                let letterbox = match vulkan.letterbox() {
+                    Some(_) => None,
+                    None => Some(LETTERBOX_ASPECT),
+                };
+                vulkan.set_letterbox(letterbox);
+            }
+            Action::Reset => {
+                vulkan.reset_ubo();
+                self.orbit_camera = OrbitCamera::default();
+                self.camera_transition = None;
+            }
+            Action::ToggleNormals => vulkan.set_show_normals(!vulkan.show_normals()),
+            Action::ToggleBbox => vulkan.set_show_bbox(!vulkan.show_bbox()),
+            Action::CycleClearColor => {
+                let current = CLEAR_COLORS.iter().position(|&color| color == vulkan.clear_color());
+                let next = current.map_or(0, |i| (i + 1) % CLEAR_COLORS.len());
+                vulkan.set_clear_color(CLEAR_COLORS[next]);
+            }
+            Action::ToggleCameraMode => {
+                self.free_fly = !self.free_fly;
+                log::info!("Camera mode: {}", if self.free_fly { "free-fly" } else { "orbit" });
+            }
+            Action::ToggleWireframe => {
+                let mode = match vulkan.polygon_mode() {
+                    PolygonMode::FILL => PolygonMode::LINE,
+                    _ => PolygonMode::FILL,
+                };
+                vulkan.set_polygon_mode(mode);
+            }
+            Action::ToggleProjection => {
+                vulkan.projection_mode = match vulkan.projection_mode {
+                    ProjectionMode::Perspective => ProjectionMode::Orthographic,
+                    ProjectionMode::Orthographic => ProjectionMode::Perspective,
+                };
+                log::info!("Projection: {:?}", vulkan.projection_mode);
+            }
+            Action::ToggleVsync => {
+                let mode = if vulkan.present_mode() != PresentModeKHR::FIFO {
+                    PresentModeKHR::FIFO
+                } else if vulkan.supports_present_mode(PresentModeKHR::MAILBOX) {
+                    PresentModeKHR::MAILBOX
+                } else {
+                    PresentModeKHR::IMMEDIATE
+                };
+                log::info!("Requesting present mode {mode:?}");
+                vulkan.set_present_mode(mode);
+            }
+            Action::ToggleUvLayout => vulkan.set_show_uv_layout(!vulkan.show_uv_layout()),
+            Action::ToggleCheckerboard => {
+                if vulkan.texture_source() != TextureSource::Checkerboard {
+                    if let Err(err) = vulkan.load_checkerboard() {
+                        log::error!("Failed to create checkerboard texture: {err:#}");
+                    }
+                } else if vulkan.texture_path().as_os_str().is_empty() {
+                    // no image was loaded, go back to the white texture
+                    if let Err(err) = vulkan.load_white_texture() {
+                        log::error!("Failed to create white texture: {err:#}");
+                    }
+                } else {
+                    let texture_path = vulkan.texture_path().to_path_buf();
+                    load_texture(vulkan, &texture_path);
+                }
+            }
+            Action::ViewFront | Action::ViewBack | Action::ViewRight
+            | Action::ViewLeft | Action::ViewTop | Action::ViewBottom => {
+                let (dir, yaw, pitch) = match action {
+                    Action::ViewFront => ([0., 0., 1.], 0., 0.),
+                    Action::ViewBack => ([0., 0., -1.], 180., 0.),
+                    Action::ViewRight => ([1., 0., 0.], 90., 0.),
+                    Action::ViewLeft => ([-1., 0., 0.], -90., 0.),
+                    Action::ViewTop => ([0., 1., 0.], 0., 90.),
+                    _ => ([0., -1., 0.], 0., -90.),
+                };
+                let from = vulkan.view_matrix;
+                // keep the scale and position, but look at the unrotated model
+                let scale = vulkan.model_matrix[0].truncate().magnitude();
+                let center = vulkan.model_matrix[3].truncate();
+                vulkan.model_matrix = Matrix4::from_translation(center) * Matrix4::from_scale(scale);
+                vulkan.projection_mode = ProjectionMode::Orthographic;
+                self.toggle_rotate = false;
+                vulkan.view_model_from(Vector3::from(dir));
+                if !self.free_fly {
+                    // orbit clamps the pitch, so top and bottom are viewed from just off the axis
+                    self.orbit_camera = OrbitCamera {
+                        target: center,
+                        distance: vulkan.framing_distance(),
+                        ..OrbitCamera::default()
+                    };
+                    self.orbit_camera.orbit(Deg(yaw), Deg(pitch));
+                }
+                self.camera_transition = Some(camera_transition(from, vulkan));
+                log::info!("View: {action:?}");
+            }
+            Action::ToggleTexture => {
+                self.tex_weight_change = if self.tex_weight_change == 0. {
+                    TEXTURE_WEIGHT_CHANGE_SPEED
+                } else {
+                    -self.tex_weight_change
+                };
+            }
+            _ => {}
+        }
+    }
 }
 
 impl ApplicationHandler for App {
@@ -135,19 +1228,15 @@ impl ApplicationHandler for App {
     }
 
     fn window_event(&mut self, event_loop: &ActiveEventLoop, _: WindowId, event: WindowEvent) {
-        match event {
-            WindowEvent::CloseRequested
-            | WindowEvent::KeyboardInput {
-                event:
-                    KeyEvent {
-                        state: ElementState::Pressed,
-                        logical_key: Key::Named(NamedKey::Escape),
-                        ..
-                    },
-                ..
-            } => {
-                event_loop.exit();
+        #[cfg(feature = "ui")]
+        if let (Some(state), Some(window)) = (self.egui.as_mut(), self.window.as_ref()) {
+            if state.on_window_event(window, &event).consumed {
+                return;
             }
+        }
+
+        match event {
+            WindowEvent::CloseRequested => event_loop.exit(),
             WindowEvent::KeyboardInput {
                 event:
                     KeyEvent {
@@ -160,65 +1249,50 @@ impl ApplicationHandler for App {
                 ..
             } => {
                 let pressed = state.is_pressed();
-                match physical_key_code {
-                    KeyCode::KeyW => self.pressed.forward = pressed,
-                    KeyCode::KeyA => self.pressed.left = pressed,
-                    KeyCode::KeyS => self.pressed.backward = pressed,
-                    KeyCode::KeyD => self.pressed.right = pressed,
-                    KeyCode::Space => self.pressed.up = pressed,
-                    KeyCode::ShiftLeft => self.pressed.down = pressed,
-                    KeyCode::ArrowLeft if pressed => self.load_prev_model = true,
-                    KeyCode::ArrowRight if pressed => self.load_next_model = true,
-                    _ => {}
-                }
-
-                let Some(vulkan) = self.vulkan.as_mut() else { return };
-                match (logical_key.as_ref(), pressed) {
-                    (Key::Character("b"), true) => {
-                        vulkan.show_cubemap = !vulkan.show_cubemap;
-                        vulkan.dirty_swapchain = true;
-                    }
-                    (Key::Character("c"), true) => {
-                        vulkan.cull_mode = match vulkan.cull_mode {
-                            CullModeFlags::NONE => CullModeFlags::BACK,
-                            CullModeFlags::BACK => CullModeFlags::FRONT,
-                            CullModeFlags::FRONT => CullModeFlags::NONE,
-                            other => other,
-                        };
-                        vulkan.dirty_swapchain = true;
-                    }
-                    (Key::Character("f"), true) => {
-                        let fullscreen = if self.is_fullscreen {
-                            None
-                        } else {
-                            Some(Fullscreen::Borderless(None))
-                        };
-                        self.window.as_mut().unwrap().set_fullscreen(fullscreen);
-                        self.is_fullscreen = !self.is_fullscreen;
+                for action in self.key_bindings.actions(physical_key_code, &logical_key, self.modifiers) {
+                    if action.is_held() {
+                        self.set_held(action, pressed);
+                    } else if pressed {
+                        self.run_action(event_loop, action);
                     }
-                    (Key::Character("i"), true) => {
-                        self.load_next_image = true;
-                        if vulkan.texture_weight == 0. || self.tex_weight_change < 0. {
-                            self.tex_weight_change = TEXTURE_WEIGHT_CHANGE_SPEED;
-                        }
-                    }
-                    (Key::Character("r"), true) => self.toggle_rotate = !self.toggle_rotate,
-                    (Key::Character("l"), true) => vulkan.reset_ubo(),
-                    (Key::Character("t"), true) => {
-                        self.tex_weight_change = if self.tex_weight_change == 0. {
-                            TEXTURE_WEIGHT_CHANGE_SPEED
-                        } else {
-                            -self.tex_weight_change
-                        };
-                    }
-                    _ => {}
                 }
             }
+            WindowEvent::ModifiersChanged(modifiers) => self.modifiers = modifiers.state(),
+            WindowEvent::Focused(focused) => {
+                self.paused = !focused;
+                // release events are not received without focus, so nothing may stay pressed
+                self.pressed = KeyStates::default();
+                self.is_left_clicked = false;
+                self.is_right_clicked = false;
+                self.left_press_position = None;
+                self.cursor_delta = [0, 0];
+                // the time without rendering does not count as frame time
+                self.last_frame = None;
+                self.fps = None;
+            }
             WindowEvent::Resized { .. } => {
                 self.vulkan.as_mut().unwrap().dirty_swapchain = true;
             }
             WindowEvent::MouseInput { button: MouseButton::Left, state, .. } => {
                 self.is_left_clicked = state == ElementState::Pressed;
+                if self.is_left_clicked {
+                    self.left_press_position = self.cursor_position;
+                } else if let Some(pos) = self.left_press_position.take() {
+                    if Some(pos) == self.cursor_position {
+                        let vulkan = self.vulkan.as_ref().unwrap();
+                        match vulkan.pick(pos[0] as f32, pos[1] as f32) {
+                            Some(pick) => log::info!(
+                                "Picked triangle {} of {} with vertices {:?} at {:?}, barycentric {:?}",
+                                pick.triangle,
+                                pick.model.map_or("the model".to_owned(), |id| format!("{id:?}")),
+                                pick.vertices,
+                                <[f32; 3]>::from(pick.position),
+                                pick.barycentric,
+                            ),
+                            None => log::info!("Picked nothing"),
+                        }
+                    }
+                }
             }
             WindowEvent::MouseInput { button: MouseButton::Right, state, .. } => {
                 self.is_right_clicked = state == ElementState::Pressed;
@@ -248,13 +1322,31 @@ impl ApplicationHandler for App {
             return;
         }
 
+        if self.paused && !self.vulkan.as_ref().is_some_and(|vulkan| vulkan.dirty_swapchain) {
+            event_loop.set_control_flow(ControlFlow::Wait);
+            return;
+        }
+        if let (true, Some(last_frame)) = (self.limit_fps, self.last_frame) {
+            let next_frame = last_frame + Duration::from_secs_f64(1. / self.fps_cap.max(1) as f64);
+            if Instant::now() < next_frame {
+                // sleep until the next frame is due instead of polling
+                event_loop.set_control_flow(ControlFlow::WaitUntil(next_frame));
+                return;
+            }
+        }
+        event_loop.set_control_flow(ControlFlow::Poll);
+
         if let Some((start, count)) = self.fps.as_mut() {
             let time = start.elapsed();
             *count += 1;
             if time.as_millis() > 1000 {
                 use std::io::Write;
 
-                eprint!("fps: {}        \r", *count as f32 / time.as_secs_f32());
+                let fps = *count as f32 / time.as_secs_f32();
+                match self.vulkan.as_ref().and_then(VkApp::last_gpu_time) {
+                    Some(gpu_time) => eprint!("fps: {fps}, gpu: {gpu_time:.2} ms        \r"),
+                    None => eprint!("fps: {fps}        \r"),
+                }
                 std::io::stdout().flush().unwrap();
                 *start = Instant::now();
                 *count = 0;
@@ -263,15 +1355,24 @@ impl ApplicationHandler for App {
             self.fps = Some((Instant::now(), 0));
         }
 
+        #[cfg(feature = "ui")]
+        self.run_ui();
+        #[cfg(feature = "gamepad")]
+        self.poll_gamepads();
+
         let app = self.vulkan.as_mut().unwrap();
         let window = self.window.as_ref().unwrap();
 
         if app.dirty_swapchain {
             let size = window.inner_size();
-            if size.width > 0 && size.height > 0 {
-                app.recreate_swapchain(size.width, size.height);
-            } else {
-                return;
+            match app.recreate_swapchain(size.width, size.height) {
+                Ok(true) => {}
+                // minimized, try again once the window has a size
+                Ok(false) => return,
+                Err(err) => {
+                    log::error!("Failed to recreate swapchain: {err:#}");
+                    return;
+                }
             }
         }
 
@@ -279,70 +1380,206 @@ impl ApplicationHandler for App {
         let delta = elapsed.as_secs_f32();
         self.last_frame = Some(Instant::now());
 
-        let translation = Vector3::from([
-            (self.pressed.left    as i8 - self.pressed.right    as i8) as f32 * delta,
-            (self.pressed.down    as i8 - self.pressed.up       as i8) as f32 * delta,
-            (self.pressed.forward as i8 - self.pressed.backward as i8) as f32 * delta,
-        ]);
-        app.view_matrix = Matrix4::from_translation(translation) * app.view_matrix;
-
         let extent = app.get_extent();
-        let x_ratio = self.cursor_delta[0] as f32 / extent.width as f32;
-        let y_ratio = self.cursor_delta[1] as f32 / extent.height as f32;
-        if self.is_left_clicked {
-            app.model_matrix = Matrix4::from_angle_y(Deg(x_ratio * 180.)) * app.model_matrix;
-            app.model_matrix = Matrix4::from_angle_x(Deg(y_ratio * 180.)) * app.model_matrix;
+        // rotation in degrees, moving across the whole window rotates by MOUSE_ROTATION
+        let sensitivity = MOUSE_ROTATION * self.mouse_sensitivity;
+        let x_angle = self.cursor_delta[0] as f32 / extent.width as f32 * sensitivity;
+        let mut y_angle = self.cursor_delta[1] as f32 / extent.height as f32 * sensitivity;
+        // the right stick rotates the camera like dragging with the mouse, up moves the view up
+        let stick_x_angle = self.right_stick[0] * STICK_ROTATION_SPEED * delta;
+        let mut stick_y_angle = -self.right_stick[1] * STICK_ROTATION_SPEED * delta;
+        if self.invert_y {
+            y_angle = -y_angle;
+            stick_y_angle = -stick_y_angle;
+        }
+        self.wheel_delta += self.trigger_zoom * TRIGGER_ZOOM_SPEED * delta;
+        if let Some(transition) = &self.camera_transition {
+            // free-fly moves the camera from where the transition ends
+            app.view_matrix = transition.to;
+        }
+        let rotate_model = if self.free_fly { self.is_left_clicked } else { self.is_right_clicked };
+        if rotate_model {
+            app.model_matrix = Matrix4::from_angle_y(Deg(x_angle)) * app.model_matrix;
+            app.model_matrix = Matrix4::from_angle_x(Deg(y_angle)) * app.model_matrix;
         }
-        if self.is_right_clicked {
-            app.view_matrix = Matrix4::from_angle_y(Deg(x_ratio * 180.)) * app.view_matrix;
-            app.view_matrix = Matrix4::from_angle_x(Deg(y_ratio * 180.)) * app.view_matrix;
+        if self.free_fly {
+            let translation = Vector3::from([
+                ((self.pressed.left    as i8 - self.pressed.right    as i8) as f32 - self.left_stick[0]) * delta,
+                (self.pressed.down    as i8 - self.pressed.up       as i8) as f32 * delta,
+                ((self.pressed.forward as i8 - self.pressed.backward as i8) as f32 + self.left_stick[1]) * delta,
+            ]);
+            app.view_matrix = Matrix4::from_translation(translation) * app.view_matrix;
+            if self.is_right_clicked {
+                app.view_matrix = Matrix4::from_angle_y(Deg(x_angle)) * app.view_matrix;
+                app.view_matrix = Matrix4::from_angle_x(Deg(y_angle)) * app.view_matrix;
+            }
+            if self.right_stick != [0.; 2] {
+                app.view_matrix = Matrix4::from_angle_y(Deg(stick_x_angle)) * app.view_matrix;
+                app.view_matrix = Matrix4::from_angle_x(Deg(stick_y_angle)) * app.view_matrix;
+            }
+            app.model_matrix = Matrix4::from_scale(1. + self.wheel_delta * 0.3) * app.model_matrix;
+        } else {
+            // the model is centered at the origin, which is the default target
+            if self.is_left_clicked {
+                self.orbit_camera.orbit(Deg(-x_angle), Deg(y_angle));
+            }
+            if self.right_stick != [0.; 2] {
+                self.orbit_camera.orbit(Deg(-stick_x_angle), Deg(stick_y_angle));
+            }
+            self.orbit_camera.zoom(0.9f32.powf(self.wheel_delta));
+            app.view_matrix = self.orbit_camera.view_matrix();
+        }
+        if let Some(start) = self.camera_path_start {
+            let time = start.elapsed().as_secs_f32();
+            if !self.camera_path.looping && time > self.camera_path.duration() {
+                self.camera_path_start = None;
+            } else if let Some(view) = self.camera_path.view_matrix(time) {
+                app.view_matrix = view;
+            }
+        }
+        if let Some(transition) = self.camera_transition.as_mut() {
+            // moving the camera during the transition changes where it ends
+            transition.to = app.view_matrix;
+            transition.advance(delta);
+            app.view_matrix = transition.view_matrix();
+            if transition.is_done() {
+                self.camera_transition = None;
+            }
         }
         if self.toggle_rotate {
-            app.model_matrix = Matrix4::from_angle_y(Deg(delta * -90.)) * app.model_matrix;
+            app.auto_rotate(delta);
         }
         self.cursor_delta = [0, 0];
-
-        app.model_matrix = Matrix4::from_scale(1. + self.wheel_delta * 0.3) * app.model_matrix;
         self.wheel_delta = 0.;
 
-        if self.load_next_model || self.load_prev_model {
+        if self.load_next_model || self.load_prev_model || self.load_selected_model {
             let offset = self.load_next_model as isize - self.load_prev_model as isize;
-            match self.model_carousel.get_next(offset, check_if_obj) {
-                Ok(path) => {
-                    fn get_nobj(path: &Path) -> Result<NormalizedObj, anyhow::Error> {
-                        Ok(NormalizedObj::from_reader(fs::load(path)?)?)
-                    }
-                    match get_nobj(&path) {
-                        Ok(nobj) => app.load_new_model(nobj),
-                        Err(err) => log::warn!("Failed to load model {}: {err}", path.display()),
-                    }
-                }
+            match self.model_carousel.get_next(offset, check_if_model) {
+                // the current model stays visible until the new one is uploaded
+                Ok(path) => self.pending_model = Some((app.request_model_load(&path), path)),
                 Err(err) => log::warn!("Failed to find a model: {err}"),
             };
             self.load_next_model = false;
             self.load_prev_model = false;
+            self.load_selected_model = false;
+        }
+        if let Some((ticket, path)) = self.pending_model.take_if(|(ticket, _)| ticket.is_done()) {
+            match ticket.status() {
+                LoadStatus::Loaded => {
+                    log::info!("Loaded {}: {}", path.display(), app.model_stats());
+                    let from = app.view_matrix;
+                    app.frame_model();
+                    self.orbit_camera.distance = app.framing_distance();
+                    self.camera_transition = Some(camera_transition(from, app));
+                    self.model_path = path;
+                    self.morph_weight_change = 0.;
+                }
+                LoadStatus::Failed(err) => log::warn!("Failed to load model {}: {err:#}", path.display()),
+                LoadStatus::Pending => unreachable!(),
+            }
+        }
+        if let Some(watcher) = self.watcher.as_mut() {
+            // the texture path is empty until an image is loaded
+            let files = [self.model_path.as_path(), app.texture_path()].into_iter()
+                .filter(|path| !path.as_os_str().is_empty())
+                .collect::<Vec<_>>();
+            if watcher.files() != files {
+                if let Err(err) = watcher.set_files(&files) {
+                    log::warn!("Failed to watch files: {err}");
+                }
+            }
+            for path in watcher.poll() {
+                log::info!("{} changed on disk", path.display());
+                self.reload_model |= path == self.model_path;
+                // a generated texture is not replaced by the file it replaced
+                self.reload_texture |= path == app.texture_path() && app.texture_source() == TextureSource::File;
+            }
+        }
+        if self.reload_model {
+            match load_nobj(&self.model_path) {
+                Ok(nobj) => {
+                    log::info!("Reloaded {}: {}", self.model_path.display(), nobj.stats());
+                    app.load_new_model(nobj);
+                    self.orbit_camera.distance = app.framing_distance();
+                    self.morph_weight_change = 0.;
+                }
+                Err(err) => log::warn!(
+                    "Failed to reload model {}, keeping the current one: {err}",
+                    self.model_path.display(),
+                ),
+            }
+            self.reload_model = false;
         }
-        if self.load_next_image {
-            match self.image_carousel.get_next(1, check_if_image) {
+        if self.reload_texture {
+            // the current texture is kept if the reload fails
+            let texture_path = app.texture_path().to_path_buf();
+            load_texture(app, &texture_path);
+            self.reload_texture = false;
+        }
+        if self.load_morph_target {
+            match self.model_carousel.get_next(1, check_if_model) {
                 Ok(path) => {
-                    if let Err(err) = app.load_new_texture(&path) {
-                        log::warn!("Error while loading new image: {err}");
-                        log::warn!("{err:#?}");
+                    if let Err(err) = load_nobj(&path).and_then(|nobj| app.load_morph_target(nobj)) {
+                        log::warn!("Failed to load morph target {}: {err}", path.display());
                     }
                 }
+                Err(err) => log::warn!("Failed to find a model: {err}"),
+            };
+            self.load_morph_target = false;
+        }
+        if self.load_next_image || self.load_selected_image {
+            match self.image_carousel.get_next(self.load_next_image as isize, check_if_image) {
+                Ok(path) => load_texture(app, &path),
                 Err(err) => log::warn!("Failed to find an image: {err}"),
             };
             self.load_next_image = false;
+            self.load_selected_image = false;
         }
 
         app.texture_weight = (app.texture_weight + self.tex_weight_change * delta).clamp(0., 1.);
+        app.morph_weight = (app.morph_weight + self.morph_weight_change * delta).clamp(0., 1.);
+
+        if self.recorder.as_ref().is_some_and(|recorder| recorder.is_recording()) {
+            if let Err(err) = app.request_capture() {
+                log::warn!("Failed to capture frame, stopping recording: {err:#}");
+                self.recorder.as_mut().unwrap().stop();
+            }
+        }
+        match app.draw_frame() {
+            Ok(dirty) => app.dirty_swapchain = dirty,
+            Err(DrawError::DeviceLost) => {
+                log::error!("The GPU device was lost, e.g. after a driver reset, recreating the renderer");
+                if let Err(err) = self.recreate_vulkan() {
+                    log::error!("{err:#}, exiting");
+                    event_loop.exit();
+                }
+                return;
+            }
+            Err(err) => {
+                log::error!("Failed to draw a frame, exiting: {err}");
+                event_loop.exit();
+                return;
+            }
+        }
 
-        app.dirty_swapchain = app.draw_frame();
+        self.save_captured_frames();
     }
 
     fn exiting(&mut self, _: &ActiveEventLoop) {
         if let Some(vulkan) = self.vulkan.as_ref() {
             vulkan.wait_gpu_idle();
+            let session = Session {
+                model_path: self.model_path.clone(),
+                image_path: vulkan.texture_path().to_path_buf(),
+                model_matrix: vulkan.model_matrix,
+                view_matrix: vulkan.view_matrix,
+                orbit_camera: self.orbit_camera,
+                free_fly: self.free_fly,
+                rotate: self.toggle_rotate,
+            };
+            if let Err(err) = session.save(SESSION_PATH) {
+                log::warn!("Failed to save {SESSION_PATH}: {err}");
+            }
         }
     }
 }
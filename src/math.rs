@@ -1,8 +1,16 @@
+pub mod aabb;
 pub mod angle;
+pub mod frustum;
 pub mod matrix;
+pub mod quaternion;
+pub mod ray;
 pub mod vector;
 
+pub use aabb::Aabb;
 pub use angle::{Rad, Deg};
+pub use frustum::Frustum;
+pub use quaternion::Quaternion;
+pub use ray::{Ray, TriangleHit};
 
 pub type Vector2 = vector::Vector<f32, 2>;
 pub type Vector3 = vector::Vector<f32, 3>;
@@ -28,3 +36,81 @@ where
         Vector4::from([0., 0., -(far * near) / (far - near), 0.]),
     ])
 }
+
+/// Linear interpolation between `a` at `t` = 0 and `b` at `t` = 1.
+pub fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+/// Eases `t` from 0 to 1 in and out, so animations start and end slowly.
+/// Values outside 0..1 are clamped.
+pub fn ease_in_out(t: f32) -> f32 {
+    let t = t.clamp(0., 1.);
+    t * t * (3. - 2. * t)
+}
+
+/// Returns the center and radius of a sphere containing all `points` using Ritter's algorithm.
+///
+/// The sphere is at most about 5% larger than the smallest one, it is found in two passes
+/// over the points. Without points the sphere is at the origin with radius 0.
+pub fn bounding_sphere<I>(points: I) -> (Vector3, f32)
+where
+    I: IntoIterator<Item = Vector3>,
+    I::IntoIter: Clone,
+{
+    let points = points.into_iter();
+    let farthest_from = |from: Vector3| points.clone()
+        .max_by(|a, b| (*a - from).magnitude().total_cmp(&(*b - from).magnitude()));
+    let Some(first) = points.clone().next() else {
+        return (Vector3::default(), 0.);
+    };
+    let a = farthest_from(first).unwrap();
+    let b = farthest_from(a).unwrap();
+
+    let mut center = (a + b) / 2.;
+    let mut radius = (b - a).magnitude() / 2.;
+    for point in points {
+        let distance = (point - center).magnitude();
+        if distance > radius {
+            // grow the sphere just enough to touch the point on the opposite side
+            let new_radius = (radius + distance) / 2.;
+            center = center + (point - center) * ((new_radius - radius) / distance);
+            radius = new_radius;
+        }
+    }
+    (center, radius)
+}
+
+/// Orthographic projection matrix that is suitable for Vulkan.
+///
+/// Like `perspective` it inverts the projected y-axis and maps the
+/// view space depth range from -near..-far to 0..1.
+pub fn orthographic(left: f32, right: f32, bottom: f32, top: f32, near: f32, far: f32) -> Matrix4 {
+    Matrix4::from([
+        Vector4::from([2. / (right - left), 0., 0., 0.]),
+        Vector4::from([0., -2. / (top - bottom), 0., 0.]),
+        Vector4::from([0., 0., -1. / (far - near), 0.]),
+        Vector4::from([
+            -(right + left) / (right - left),
+            (top + bottom) / (top - bottom),
+            -near / (far - near),
+            1.,
+        ]),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orthographic_depth() {
+        let proj = orthographic(-2., 2., -1., 1., 0.5, 10.);
+        let depth = |z: f32| (proj * Vector4::from([0., 0., z, 1.])).z();
+        assert!(depth(-0.5).abs() < 1e-6);
+        assert!((depth(-10.) - 1.).abs() < 1e-6);
+        assert!((depth(-5.25) - 0.5).abs() < 1e-6);
+        // y is flipped like in `perspective`
+        assert_eq!((proj * Vector4::from([0., 1., -1., 1.])).y(), -1.);
+    }
+}
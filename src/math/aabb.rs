@@ -0,0 +1,181 @@
+use super::{Frustum, Matrix4, Vector3};
+
+/// Axis aligned bounding box.
+///
+/// An empty box has `min` above `max` and contains nothing, it is the
+/// neutral element of `union` and the result of `from_points` without points.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Aabb {
+    pub min: Vector3,
+    pub max: Vector3,
+}
+
+impl Default for Aabb {
+    fn default() -> Self {
+        Self::EMPTY
+    }
+}
+
+impl Aabb {
+    pub const EMPTY: Self = Self {
+        min: Vector3::new(f32::MAX),
+        max: Vector3::new(f32::MIN),
+    };
+
+    pub fn new(min: Vector3, max: Vector3) -> Self {
+        Self { min, max }
+    }
+
+    /// Returns the smallest box containing all `points`.
+    pub fn from_points<I: IntoIterator<Item = Vector3>>(points: I) -> Self {
+        points.into_iter().fold(Self::EMPTY, |aabb, point| aabb.union(&Self::new(point, point)))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        (0..3).any(|i| self.min[i] > self.max[i])
+    }
+
+    pub fn center(&self) -> Vector3 {
+        (self.min + self.max) / 2.
+    }
+
+    /// Returns the size of the box along each axis, which is zero for an empty box.
+    pub fn extents(&self) -> Vector3 {
+        if self.is_empty() {
+            Vector3::default()
+        } else {
+            self.max - self.min
+        }
+    }
+
+    /// Returns the largest size along the axes.
+    pub fn max_extent(&self) -> f32 {
+        let extents = self.extents();
+        extents.x().max(extents.y()).max(extents.z())
+    }
+
+    pub fn contains(&self, point: Vector3) -> bool {
+        (0..3).all(|i| self.min[i] <= point[i] && point[i] <= self.max[i])
+    }
+
+    /// Returns the 8 corners, the bits 0, 1 and 2 of the index select the max
+    /// instead of the min coordinate of x, y and z.
+    pub fn corners(&self) -> [Vector3; 8] {
+        std::array::from_fn(|i| Vector3::from([
+            if i & 1 == 0 { self.min.x() } else { self.max.x() },
+            if i & 2 == 0 { self.min.y() } else { self.max.y() },
+            if i & 4 == 0 { self.min.z() } else { self.max.z() },
+        ]))
+    }
+
+    /// Returns the box containing the corners transformed by `matrix`, which is larger
+    /// than the box itself if `matrix` rotates it.
+    pub fn transformed_by(&self, matrix: &Matrix4) -> Self {
+        if self.is_empty() {
+            return *self;
+        }
+        Self::from_points(self.corners().map(|corner| (*matrix * corner.to_point()).project()))
+    }
+
+    /// Returns false if the box is completely outside one of the planes of `frustum`.
+    ///
+    /// This is conservative, a box near a corner of the frustum may be outside
+    /// of it and still intersect.
+    pub fn intersects_frustum(&self, frustum: &Frustum) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+        frustum.planes.iter().all(|&plane| {
+            // the corner farthest along the plane normal is the last one to leave
+            let corner = Vector3::from(std::array::from_fn(|i| {
+                if plane[i] >= 0. { self.max[i] } else { self.min[i] }
+            }));
+            Frustum::distance(plane, corner) >= 0.
+        })
+    }
+
+    /// Returns the smallest box containing both boxes.
+    pub fn union(&self, other: &Self) -> Self {
+        let mut result = *self;
+        for i in 0..3 {
+            result.min[i] = self.min[i].min(other.min[i]);
+            result.max[i] = self.max[i].max(other.max[i]);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::Deg;
+
+    fn aabb(min: [f32; 3], max: [f32; 3]) -> Aabb {
+        Aabb::new(Vector3::from(min), Vector3::from(max))
+    }
+
+    fn assert_close(a: Aabb, b: Aabb) {
+        assert!(a.min.approx_eq(&b.min, 1e-5) && a.max.approx_eq(&b.max, 1e-5), "{a:?} != {b:?}");
+    }
+
+    #[test]
+    fn from_points() {
+        let points = [[1., -2., 0.5], [-1., 3., 0.], [0., 0., 2.]].map(Vector3::from);
+        let bbox = Aabb::from_points(points);
+        assert_eq!(bbox, aabb([-1., -2., 0.], [1., 3., 2.]));
+        assert_eq!(bbox.center(), Vector3::from([0., 0.5, 1.]));
+        assert_eq!(bbox.extents(), Vector3::from([2., 5., 2.]));
+        assert_eq!(bbox.max_extent(), 5.);
+        assert!(points.into_iter().all(|point| bbox.contains(point)));
+        assert!(!bbox.contains(Vector3::from([0., 0., 3.])));
+    }
+
+    #[test]
+    fn empty() {
+        let empty = Aabb::from_points([]);
+        assert!(empty.is_empty());
+        assert_eq!(empty.extents(), Vector3::default());
+        assert!(!empty.contains(Vector3::default()));
+        let bbox = aabb([0.; 3], [1.; 3]);
+        assert_eq!(empty.union(&bbox), bbox);
+        assert!(empty.transformed_by(&Matrix4::from_scale(2.)).is_empty());
+    }
+
+    #[test]
+    fn union() {
+        let a = aabb([0., 0., 0.], [1., 1., 1.]);
+        let b = aabb([-1., 0.5, 2.], [0.5, 0.6, 3.]);
+        assert_eq!(a.union(&b), aabb([-1., 0., 0.], [1., 1., 3.]));
+        assert_eq!(a.union(&b), b.union(&a));
+    }
+
+    #[test]
+    fn transformed_by() {
+        let bbox = aabb([-1., -1., -1.], [1., 1., 1.]);
+        let moved = Matrix4::from_translation(Vector3::from([1., 2., 3.])) * Matrix4::from_scale(2.);
+        assert_close(bbox.transformed_by(&moved), aabb([-1., 0., 1.], [3., 4., 5.]));
+
+        // rotating a cube by 45 degrees makes its box wider by the diagonal
+        let rotated = bbox.transformed_by(&Matrix4::from_angle_y(Deg(45.)));
+        let half_diagonal = 2f32.sqrt();
+        assert_close(rotated, aabb([-half_diagonal, -1., -half_diagonal], [half_diagonal, 1., half_diagonal]));
+    }
+
+    #[test]
+    fn intersects_frustum() {
+        let view = Matrix4::look_at_rh(
+            Vector3::from([0., 0., 5.]),
+            Vector3::default(),
+            Vector3::from([0., 1., 0.]),
+        );
+        let frustum = Frustum::from_matrix(crate::math::perspective(Deg(90.), 1., 1., 10.) * view);
+        assert!(aabb([-1.; 3], [1.; 3]).intersects_frustum(&frustum));
+        // partially visible boxes are not culled
+        assert!(aabb([4., -1., -1.], [6., 1., 1.]).intersects_frustum(&frustum));
+        assert!(aabb([-1., -1., 3.], [1., 1., 7.]).intersects_frustum(&frustum));
+        assert!(!aabb([7., -1., -1.], [8., 1., 1.]).intersects_frustum(&frustum));
+        assert!(!aabb([-1., -1., 5.], [1., 1., 7.]).intersects_frustum(&frustum));
+        assert!(!aabb([-1., -1., -9.], [1., 1., -6.]).intersects_frustum(&frustum));
+        assert!(!Aabb::EMPTY.intersects_frustum(&frustum));
+    }
+}
@@ -1,17 +1,56 @@
+/// Angle in radians, convert from `Deg` with `From` or `Deg::to_radians`.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub struct Rad<T>(pub T);
 
+impl Rad<f32> {
+    pub fn to_degrees(self) -> Deg<f32> {
+        self.into()
+    }
+
+    /// Returns true if the angles differ by at most `epsilon`, without wrapping around.
+    pub fn approx_eq(&self, other: &Self, epsilon: f32) -> bool {
+        (self.0 - other.0).abs() <= epsilon
+    }
+}
+
 impl From<Deg<f32>> for Rad<f32> {
     fn from(value: Deg<f32>) -> Self {
         Rad(value.0 / 180. * std::f32::consts::PI)
     }
 }
 
+/// Angle in degrees, convert from `Rad` with `From` or `Rad::to_degrees`.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub struct Deg<T>(pub T);
 
+impl Deg<f32> {
+    pub fn to_radians(self) -> Rad<f32> {
+        self.into()
+    }
+
+    /// Returns true if the angles differ by at most `epsilon`, without wrapping around.
+    pub fn approx_eq(&self, other: &Self, epsilon: f32) -> bool {
+        (self.0 - other.0).abs() <= epsilon
+    }
+}
+
 impl From<Rad<f32>> for Deg<f32> {
     fn from(value: Rad<f32>) -> Self {
         Deg(value.0 * 180. / std::f32::consts::PI)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    #[test]
+    fn conversions() {
+        assert_eq!(Deg(180.).to_radians(), Rad(PI));
+        assert_eq!(Rad(PI / 2.).to_degrees(), Deg(90.));
+        let deg = Deg(-37.5);
+        assert!(deg.to_radians().to_degrees().approx_eq(&deg, 1e-5));
+        assert!(!Deg(359.).approx_eq(&Deg(-1.), 1e-5));
+    }
+}
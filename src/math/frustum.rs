@@ -0,0 +1,60 @@
+use super::{Matrix4, Vector3, Vector4};
+
+/// The volume visible through a projection, bounded by six planes.
+///
+/// Each plane is stored as `[a, b, c, d]` with the normal `[a, b, c]` pointing
+/// inside, so a point `p` is on the inner side if `a * p.x + b * p.y + c * p.z + d >= 0`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Frustum {
+    /// Left, right, bottom, top, near and far plane.
+    pub planes: [Vector4; 6],
+}
+
+impl Frustum {
+    /// Extracts the planes from a view-projection matrix with the Gribb-Hartmann method.
+    /// The frustum is in the space `matrix` transforms from.
+    ///
+    /// Expects a depth range of 0..1 like `perspective` and `orthographic` produce.
+    pub fn from_matrix(matrix: Matrix4) -> Self {
+        let row = |i: usize| Vector4::from([matrix[0][i], matrix[1][i], matrix[2][i], matrix[3][i]]);
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+        Self {
+            planes: [w + x, w - x, w + y, w - y, z, w - z],
+        }
+    }
+
+    /// Returns the signed distance of `point` to `plane` in multiples of the length
+    /// of the plane normal, it is negative outside.
+    pub fn distance(plane: Vector4, point: Vector3) -> f32 {
+        plane.dot(point.to_point())
+    }
+
+    pub fn contains(&self, point: Vector3) -> bool {
+        self.planes.iter().all(|&plane| Self::distance(plane, point) >= 0.)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::{self, Deg};
+
+    #[test]
+    fn contains() {
+        let view = Matrix4::look_at_rh(
+            Vector3::from([0., 0., 5.]),
+            Vector3::default(),
+            Vector3::from([0., 1., 0.]),
+        );
+        let frustum = Frustum::from_matrix(math::perspective(Deg(90.), 1., 1., 10.) * view);
+        assert!(frustum.contains(Vector3::default()));
+        assert!(frustum.contains(Vector3::from([3.9, 0., 0.])));
+        // outside the left and right planes at 90 degrees field of view
+        assert!(!frustum.contains(Vector3::from([5.1, 0., 0.])));
+        assert!(!frustum.contains(Vector3::from([0., -5.1, 0.])));
+        // behind the camera, in front of the near and behind the far plane
+        assert!(!frustum.contains(Vector3::from([0., 0., 6.])));
+        assert!(!frustum.contains(Vector3::from([0., 0., 4.5])));
+        assert!(!frustum.contains(Vector3::from([0., 0., -5.5])));
+    }
+}
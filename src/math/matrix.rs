@@ -23,7 +23,7 @@ impl<T: Default + Copy, const M: usize> Matrix<T, M, M> {
     /// Returns a transposed matrix.
     pub fn transpose_sqr(mut self) -> Self {
         for i in 1..M {
-            for j in 0..i/2+1 {
+            for j in 0..i {
                 let tmp = self[i][j];
                 self[i][j] = self[j][i];
                 self[j][i] = tmp;
@@ -33,6 +33,26 @@ impl<T: Default + Copy, const M: usize> Matrix<T, M, M> {
     }
 }
 
+impl<T: Default + Copy, const M: usize, const N: usize> Matrix<T, M, N> {
+    /// Returns a transposed matrix, swapping the number of columns and rows.
+    pub fn transpose(self) -> Matrix<T, N, M> {
+        let mut out = Matrix::<T, N, M>::default();
+        for i in 0..M {
+            for j in 0..N {
+                out[j][i] = self[i][j];
+            }
+        }
+        out
+    }
+}
+
+impl<const M: usize, const N: usize> Matrix<f32, M, N> {
+    /// Returns true if no element differs from the one of `other` by more than `epsilon`.
+    pub fn approx_eq(&self, other: &Self, epsilon: f32) -> bool {
+        self.cols.iter().zip(&other.cols).all(|(a, b)| a.approx_eq(b, epsilon))
+    }
+}
+
 impl<T: Default + Copy + From<bool>, const M: usize> Matrix<T, M, M> {
     /// Creates a unit matrix where the unit is derived from `true`.
     pub fn unit() -> Self {
@@ -96,6 +116,63 @@ impl Matrix<f32, 4> {
         Self::look_to_rh(eye, center - eye, up)
     }
 
+    /// Calculates the inverse matrix using the adjugate.
+    ///
+    /// Returns `None` if the matrix is singular or so close to it that
+    /// the inverse would be dominated by rounding errors.
+    pub fn inverse(&self) -> Option<Self> {
+        let a = <[[f32; 4]; 4]>::from(*self);
+        // determinants of the 2x2 sub-matrices of the first and last two columns
+        let s0 = a[0][0] * a[1][1] - a[1][0] * a[0][1];
+        let s1 = a[0][0] * a[1][2] - a[1][0] * a[0][2];
+        let s2 = a[0][0] * a[1][3] - a[1][0] * a[0][3];
+        let s3 = a[0][1] * a[1][2] - a[1][1] * a[0][2];
+        let s4 = a[0][1] * a[1][3] - a[1][1] * a[0][3];
+        let s5 = a[0][2] * a[1][3] - a[1][2] * a[0][3];
+        let c5 = a[2][2] * a[3][3] - a[3][2] * a[2][3];
+        let c4 = a[2][1] * a[3][3] - a[3][1] * a[2][3];
+        let c3 = a[2][1] * a[3][2] - a[3][1] * a[2][2];
+        let c2 = a[2][0] * a[3][3] - a[3][0] * a[2][3];
+        let c1 = a[2][0] * a[3][2] - a[3][0] * a[2][2];
+        let c0 = a[2][0] * a[3][1] - a[3][0] * a[2][1];
+
+        let det = s0 * c5 - s1 * c4 + s2 * c3 + s3 * c2 - s4 * c1 + s5 * c0;
+        // the determinant is at most the product of the column lengths, comparing
+        // against it instead of a fixed epsilon keeps tiny scale matrices invertible
+        let bound: f32 = self.cols.iter().map(|col| col.magnitude()).product();
+        if !det.is_finite() || det.abs() <= f32::EPSILON * bound {
+            return None;
+        }
+        let inv = 1. / det;
+
+        Some(Self::from([
+            [
+                ( a[1][1] * c5 - a[1][2] * c4 + a[1][3] * c3) * inv,
+                (-a[0][1] * c5 + a[0][2] * c4 - a[0][3] * c3) * inv,
+                ( a[3][1] * s5 - a[3][2] * s4 + a[3][3] * s3) * inv,
+                (-a[2][1] * s5 + a[2][2] * s4 - a[2][3] * s3) * inv,
+            ],
+            [
+                (-a[1][0] * c5 + a[1][2] * c2 - a[1][3] * c1) * inv,
+                ( a[0][0] * c5 - a[0][2] * c2 + a[0][3] * c1) * inv,
+                (-a[3][0] * s5 + a[3][2] * s2 - a[3][3] * s1) * inv,
+                ( a[2][0] * s5 - a[2][2] * s2 + a[2][3] * s1) * inv,
+            ],
+            [
+                ( a[1][0] * c4 - a[1][1] * c2 + a[1][3] * c0) * inv,
+                (-a[0][0] * c4 + a[0][1] * c2 - a[0][3] * c0) * inv,
+                ( a[3][0] * s4 - a[3][1] * s2 + a[3][3] * s0) * inv,
+                (-a[2][0] * s4 + a[2][1] * s2 - a[2][3] * s0) * inv,
+            ],
+            [
+                (-a[1][0] * c3 + a[1][1] * c1 - a[1][2] * c0) * inv,
+                ( a[0][0] * c3 - a[0][1] * c1 + a[0][2] * c0) * inv,
+                (-a[3][0] * s3 + a[3][1] * s1 - a[3][2] * s0) * inv,
+                ( a[2][0] * s3 - a[2][1] * s1 + a[2][2] * s0) * inv,
+            ],
+        ]))
+    }
+
     /// Creates a rotation matrix around `x` axis.
     pub fn from_angle_x<A: Into<Rad<f32>>>(angle: A) -> Self {
         let (s, c) = angle.into().0.sin_cos();
@@ -128,6 +205,31 @@ impl Matrix<f32, 4> {
             [0., 0., 0., 1.],
         ])
     }
+
+    /// Creates a rotation matrix from Euler angles. It rotates around the `z` axis
+    /// by `roll` first, then around the `x` axis by `pitch` and last around the `y`
+    /// axis by `yaw`, i.e. `from_angle_y(yaw) * from_angle_x(pitch) * from_angle_z(roll)`.
+    pub fn from_euler<A: Into<Rad<f32>>>(pitch: A, yaw: A, roll: A) -> Self {
+        Self::from_angle_y(yaw) * Self::from_angle_x(pitch) * Self::from_angle_z(roll)
+    }
+
+    /// Returns the pitch, yaw and roll of a rotation matrix, see `from_euler`.
+    ///
+    /// The pitch is in -90°..=90°, yaw and roll in -180°..=180°. At a pitch of ±90°
+    /// yaw and roll rotate around the same axis, then the roll is 0.
+    pub fn to_euler(&self) -> (Rad<f32>, Rad<f32>, Rad<f32>) {
+        // self[column][row]
+        let m = |row: usize, col: usize| self[col][row];
+        let pitch = (-m(1, 2)).clamp(-1., 1.).asin();
+        if m(1, 2).abs() < 1. - 1e-6 {
+            let yaw = m(0, 2).atan2(m(2, 2));
+            let roll = m(1, 0).atan2(m(1, 1));
+            (Rad(pitch), Rad(yaw), Rad(roll))
+        } else {
+            let yaw = (-m(2, 0)).atan2(m(0, 0));
+            (Rad(pitch), Rad(yaw), Rad(0.))
+        }
+    }
 }
 
 impl<T: ops::AddAssign, const M: usize, const N: usize> ops::Add for Matrix<T, M, N> {
@@ -171,6 +273,23 @@ where
     }
 }
 
+impl<T, const N: usize, const P: usize> ops::Mul<Vector<T, N>> for Matrix<T, N, P>
+where
+    T: Default + Copy + ops::AddAssign + ops::Mul<Output = T>,
+{
+    type Output = Vector<T, P>;
+
+    fn mul(self, rhs: Vector<T, N>) -> Self::Output {
+        let mut out = Self::Output::default();
+        for k in 0..N {
+            for j in 0..P {
+                out[j] += self.cols[k][j] * rhs[k];
+            }
+        }
+        out
+    }
+}
+
 impl<T, const M: usize, const N: usize> From<Matrix<T, M, N>> for [Vector<T, N>; M] {
     fn from(val: Matrix<T, M, N>) -> Self {
         val.cols
@@ -226,8 +345,8 @@ mod tests {
             Vector::from([3, 4]),
         ]);
         let b: Matrix<i32, 2> = Matrix::from([[1, 2], [3, 4]]);
-        assert_eq!(Vector::from([1, 2]), a[0].into());
-        assert_eq!(Vector::from([3, 4]), a[1].into());
+        assert_eq!(Vector::from([1, 2]), a[0]);
+        assert_eq!(Vector::from([3, 4]), a[1]);
         assert_eq!(a, b);
     }
 
@@ -249,6 +368,15 @@ mod tests {
         assert_eq!(b * a, c);
     }
 
+    #[test]
+    fn multiply_vector() {
+        let a = Matrix::from([[1, 4], [2, 5], [3, 6]]);
+        assert_eq!(a * Vector::from([1, 0, -1]), [-2, -2].into());
+        let translation = Matrix::<_, 4>::from_translation([1, 2, 3].into());
+        assert_eq!(translation * Vector::from([0, 0, 0]).to_point(), [1, 2, 3, 1].into());
+        assert_eq!(translation * Vector::from([4, 5, 6]).to_direction(), [4, 5, 6, 0].into());
+    }
+
     #[test]
     fn from_translation() {
         let a = Matrix::<_, 4, 4>::from_translation([1, 2, 3].into());
@@ -268,5 +396,83 @@ mod tests {
         let a = Matrix::from([[1, 4, 7], [2, 5, 8], [3, 6, 9]]);
         let b = Matrix::from([[1, 2, 3], [4, 5, 6], [7, 8, 9]]);
         assert_eq!(a.transpose_sqr(), b);
+        let a = Matrix::from([[1, 2, 3, 4], [5, 6, 7, 8], [9, 10, 11, 12], [13, 14, 15, 16]]);
+        let b = Matrix::from([[1, 5, 9, 13], [2, 6, 10, 14], [3, 7, 11, 15], [4, 8, 12, 16]]);
+        assert_eq!(a.transpose_sqr(), b);
+    }
+
+    #[test]
+    fn transpose() {
+        let a = Matrix::from([[1, 2, 3], [4, 5, 6]]);
+        let b = Matrix::from([[1, 4], [2, 5], [3, 6]]);
+        assert_eq!(a.transpose(), b);
+        assert_eq!(b.transpose(), a);
+    }
+
+    fn assert_approx_unit(m: Matrix<f32, 4>) {
+        assert!(m.approx_eq(&Matrix::unit(), 1e-5), "{m:?} is not the unit matrix");
+    }
+
+    #[test]
+    fn approx_eq() {
+        let a = Matrix::from([[1., 2.], [3., 4.]]);
+        assert!(a.approx_eq(&a, 0.));
+        assert!(a.approx_eq(&Matrix::from([[1., 2.], [3.1, 4.]]), 0.11));
+        assert!(!a.approx_eq(&Matrix::from([[1., 2.], [3.1, 4.]]), 0.09));
+        assert!(!a.approx_eq(&Matrix::from([[1., 2.], [3., f32::NAN]]), 1.));
+    }
+
+    #[test]
+    fn inverse() {
+        let matrices = [
+            Matrix::<f32, 4>::unit(),
+            Matrix::from_scale(0.001),
+            Matrix::from_translation([1., -2., 3.].into()) * Matrix::from_angle_x(Rad(0.7)),
+            Matrix::from_angle_y(Rad(2.)) * Matrix::from([
+                [2., 0., 0., 0.],
+                [0., 0.5, 0., 0.],
+                [0., 0., 3., 0.],
+                [0., 0., 0., 1.],
+            ]),
+            Matrix::from([[2., 1., 0., 3.], [1., 3., 1., 0.], [0., 1., 4., 1.], [1., 0., 2., 5.]]),
+        ];
+        for m in matrices {
+            let inv = m.inverse().expect("matrix should be invertible");
+            assert_approx_unit(m * inv);
+            assert_approx_unit(inv * m);
+        }
+    }
+
+    #[test]
+    fn euler() {
+        let matrix = Matrix::from_euler(Rad(0.3), Rad(-1.2), Rad(0.5));
+        let expected = Matrix::from_angle_y(Rad(-1.2)) * Matrix::from_angle_x(Rad(0.3)) * Matrix::from_angle_z(Rad(0.5));
+        assert_eq!(matrix, expected);
+
+        for pitch in [-1.5, -0.4, 0., 0.01, 1.] {
+            for yaw in [-3., -0.2, 0., 0.02, 2.5] {
+                for roll in [-2., 0., 0.001, 0.7] {
+                    let (p, y, r) = Matrix::from_euler(Rad(pitch), Rad(yaw), Rad(roll)).to_euler();
+                    for (a, b) in [(p.0, pitch), (y.0, yaw), (r.0, roll)] {
+                        assert!((a - b).abs() < 1e-4, "{a} != {b} for {pitch} {yaw} {roll}");
+                    }
+                }
+            }
+        }
+
+        // looking straight down only the sum of yaw and roll is known
+        let half_pi = std::f32::consts::FRAC_PI_2;
+        let (p, y, r) = Matrix::from_euler(Rad(-half_pi), Rad(0.5), Rad(0.2)).to_euler();
+        assert!((p.0 + half_pi).abs() < 1e-4);
+        assert_eq!(r.0, 0.);
+        let (a, b) = (Matrix::from_euler(p, y, r), Matrix::from_euler(Rad(-half_pi), Rad(0.5), Rad(0.2)));
+        assert!(a.approx_eq(&b, 1e-4), "{a:?} != {b:?}");
+    }
+
+    #[test]
+    fn inverse_singular() {
+        let a = Matrix::<f32, 4>::from([[1., 2., 3., 4.], [2., 4., 6., 8.], [0., 1., 0., 0.], [0., 0., 0., 1.]]);
+        assert_eq!(a.inverse(), None);
+        assert_eq!(Matrix::<f32, 4>::default().inverse(), None);
     }
 }
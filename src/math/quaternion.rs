@@ -0,0 +1,174 @@
+use super::angle::Rad;
+use super::{Matrix4, Vector3};
+use std::ops;
+
+/// A quaternion with the scalar part `s` and the vector part `v`.
+///
+/// Unit quaternions represent rotations and can be chained and
+/// interpolated without the drift of repeated matrix multiplications.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Quaternion {
+    pub s: f32,
+    pub v: Vector3,
+}
+
+impl Default for Quaternion {
+    /// Returns the identity rotation.
+    fn default() -> Self {
+        Self { s: 1., v: Vector3::default() }
+    }
+}
+
+impl Quaternion {
+    /// Creates a rotation of `angle` around `axis`.
+    pub fn from_axis_angle<A: Into<Rad<f32>>>(axis: Vector3, angle: A) -> Self {
+        let (s, c) = (angle.into().0 / 2.).sin_cos();
+        Self { s: c, v: axis.normalize() * s }
+    }
+
+    /// Returns the rotation of the upper 3x3 part of `m`, which must be orthonormal.
+    pub fn from_matrix4(m: Matrix4) -> Self {
+        // m is column-major, r(row, col) is the element in the row and column
+        let r = |row: usize, col: usize| m[col][row];
+        let trace = r(0, 0) + r(1, 1) + r(2, 2);
+        let (s, x, y, z) = if trace > 0. {
+            let f = (trace + 1.).sqrt() * 2.;
+            (f / 4., (r(2, 1) - r(1, 2)) / f, (r(0, 2) - r(2, 0)) / f, (r(1, 0) - r(0, 1)) / f)
+        } else if r(0, 0) > r(1, 1) && r(0, 0) > r(2, 2) {
+            let f = (1. + r(0, 0) - r(1, 1) - r(2, 2)).sqrt() * 2.;
+            ((r(2, 1) - r(1, 2)) / f, f / 4., (r(0, 1) + r(1, 0)) / f, (r(0, 2) + r(2, 0)) / f)
+        } else if r(1, 1) > r(2, 2) {
+            let f = (1. + r(1, 1) - r(0, 0) - r(2, 2)).sqrt() * 2.;
+            ((r(0, 2) - r(2, 0)) / f, (r(0, 1) + r(1, 0)) / f, f / 4., (r(1, 2) + r(2, 1)) / f)
+        } else {
+            let f = (1. + r(2, 2) - r(0, 0) - r(1, 1)).sqrt() * 2.;
+            ((r(1, 0) - r(0, 1)) / f, (r(0, 2) + r(2, 0)) / f, (r(1, 2) + r(2, 1)) / f, f / 4.)
+        };
+        Self { s, v: Vector3::from([x, y, z]) }.normalize()
+    }
+
+    /// Returns true if no component differs from the one of `other` by more than `epsilon`.
+    ///
+    /// `q` and `-q` are the same rotation but not approximately equal.
+    pub fn approx_eq(&self, other: &Self, epsilon: f32) -> bool {
+        (self.s - other.s).abs() <= epsilon && self.v.approx_eq(&other.v, epsilon)
+    }
+
+    pub fn dot(self, rhs: Self) -> f32 {
+        self.s * rhs.s + self.v.dot(rhs.v)
+    }
+
+    pub fn magnitude(self) -> f32 {
+        self.dot(self).sqrt()
+    }
+
+    /// Returns a quaternion with the magnitude 1.
+    pub fn normalize(self) -> Self {
+        let mag = self.magnitude();
+        Self { s: self.s / mag, v: self.v / mag }
+    }
+
+    /// Spherical linear interpolation between two rotations, `t` is from 0 to 1.
+    /// Always interpolates along the shorter arc.
+    pub fn slerp(self, mut other: Self, t: f32) -> Self {
+        let mut cos = self.dot(other);
+        // q and -q are the same rotation, take the one closer to self
+        if cos < 0. {
+            other = Self { s: -other.s, v: -other.v };
+            cos = -cos;
+        }
+        // fall back to linear interpolation for nearly identical rotations
+        let (a, b) = if cos > 0.9995 {
+            (1. - t, t)
+        } else {
+            let angle = cos.acos();
+            let sin = angle.sin();
+            (((1. - t) * angle).sin() / sin, (t * angle).sin() / sin)
+        };
+        Self {
+            s: self.s * a + other.s * b,
+            v: self.v * a + other.v * b,
+        }.normalize()
+    }
+
+    /// Converts a unit quaternion to a rotation matrix.
+    pub fn to_matrix4(self) -> Matrix4 {
+        let Self { s: w, v } = self;
+        let (x, y, z) = (v.x(), v.y(), v.z());
+        Matrix4::from([
+            [1. - 2. * (y * y + z * z), 2. * (x * y + w * z), 2. * (x * z - w * y), 0.],
+            [2. * (x * y - w * z), 1. - 2. * (x * x + z * z), 2. * (y * z + w * x), 0.],
+            [2. * (x * z + w * y), 2. * (y * z - w * x), 1. - 2. * (x * x + y * y), 0.],
+            [0., 0., 0., 1.],
+        ])
+    }
+}
+
+impl ops::Mul for Quaternion {
+    type Output = Quaternion;
+
+    /// Chains two rotations, `rhs` is applied first.
+    fn mul(self, rhs: Self) -> Self::Output {
+        Self {
+            s: self.s * rhs.s - self.v.dot(rhs.v),
+            v: rhs.v * self.s + self.v * rhs.s + self.v.cross(rhs.v),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::Deg;
+
+    fn assert_matrix_close(a: Matrix4, b: Matrix4) {
+        assert!(a.approx_eq(&b, 1e-5), "{a:?} != {b:?}");
+    }
+
+    #[test]
+    fn to_matrix4() {
+        let y = Vector3::from([0., 1., 0.]);
+        let q = Quaternion::from_axis_angle(y, Deg(30.));
+        assert_matrix_close(q.to_matrix4(), Matrix4::from_angle_y(Deg(30.)));
+        assert_matrix_close(Quaternion::default().to_matrix4(), Matrix4::unit());
+    }
+
+    #[test]
+    fn from_matrix4() {
+        // one rotation for each branch, the largest diagonal element decides
+        let rotations = [
+            Quaternion::from_axis_angle(Vector3::from([1., 2., 3.]), Deg(30.)),
+            Quaternion::from_axis_angle(Vector3::from([1., 0., 0.]), Deg(170.)),
+            Quaternion::from_axis_angle(Vector3::from([0., 1., 0.]), Deg(-170.)),
+            Quaternion::from_axis_angle(Vector3::from([0.2, 0.1, 1.]), Deg(160.)),
+        ];
+        for q in rotations {
+            let m = q.to_matrix4();
+            assert_matrix_close(Quaternion::from_matrix4(m).to_matrix4(), m);
+        }
+        assert!(Quaternion::from_matrix4(Matrix4::unit()).approx_eq(&Quaternion::default(), 1e-6));
+    }
+
+    #[test]
+    fn multiply() {
+        let a = Quaternion::from_axis_angle(Vector3::from([1., 0., 0.]), Deg(40.));
+        let b = Quaternion::from_axis_angle(Vector3::from([0., 0., 1.]), Deg(-70.));
+        assert_matrix_close((a * b).to_matrix4(), a.to_matrix4() * b.to_matrix4());
+    }
+
+    #[test]
+    fn slerp_halfway() {
+        let y = Vector3::from([0., 1., 0.]);
+        let a = Quaternion::from_axis_angle(y, Deg(0.));
+        let b = Quaternion::from_axis_angle(y, Deg(90.));
+        assert_matrix_close(a.slerp(b, 0.5).to_matrix4(), Matrix4::from_angle_y(Deg(45.)));
+        assert_matrix_close(a.slerp(b, 0.).to_matrix4(), a.to_matrix4());
+        assert_matrix_close(a.slerp(b, 1.).to_matrix4(), b.to_matrix4());
+
+        // from -45° to 45° passes through 0°
+        let a = Quaternion::from_axis_angle(y, Deg(-45.));
+        let b = Quaternion::from_axis_angle(y, Deg(45.));
+        assert_matrix_close(a.slerp(b, 0.5).to_matrix4(), Matrix4::unit());
+        assert!(a.slerp(b, 0.5).approx_eq(&Quaternion::default(), 1e-6));
+    }
+}
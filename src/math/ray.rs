@@ -0,0 +1,140 @@
+use super::{Matrix4, Vector3};
+
+/// Minimum determinant below which a ray is considered parallel to a triangle.
+const PARALLEL_EPSILON: f32 = 1e-8;
+
+/// A half-line starting at `origin` going in the direction `dir`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Ray {
+    pub origin: Vector3,
+    /// Not necessarily normalized, distances along the ray are in multiples of it.
+    pub dir: Vector3,
+}
+
+/// Intersection of a ray with a triangle.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TriangleHit {
+    /// Distance along the ray in multiples of its direction.
+    pub t: f32,
+    /// Weights of the three corners of the triangle at the hit point, they sum up to 1.
+    pub barycentric: [f32; 3],
+}
+
+impl Ray {
+    pub fn new(origin: Vector3, dir: Vector3) -> Self {
+        Self { origin, dir }
+    }
+
+    /// Creates the ray through a point in normalized device coordinates, from the
+    /// near to the far plane. `inverse` is the inverse of the projection and all
+    /// other transformations, the ray is in the space they transform from.
+    pub fn from_ndc(inverse: Matrix4, x: f32, y: f32) -> Self {
+        let unproject = |z: f32| (inverse * Vector3::from([x, y, z]).to_point()).project();
+        let near = unproject(0.);
+        let far = unproject(1.);
+        Self { origin: near, dir: far - near }
+    }
+
+    /// Returns the point at distance `t`.
+    pub fn at(&self, t: f32) -> Vector3 {
+        self.origin + self.dir * t
+    }
+
+    /// Intersects the ray with the triangle `a`, `b`, `c` from both sides with
+    /// the Möller-Trumbore algorithm. Hits behind the origin are ignored.
+    pub fn intersect_triangle(&self, a: Vector3, b: Vector3, c: Vector3) -> Option<TriangleHit> {
+        let ab = b - a;
+        let ac = c - a;
+        let p = self.dir.cross(ac);
+        let det = ab.dot(p);
+        if det.abs() < PARALLEL_EPSILON {
+            return None;
+        }
+        let inv_det = 1. / det;
+        let ao = self.origin - a;
+        let u = ao.dot(p) * inv_det;
+        if !(0. ..=1.).contains(&u) {
+            return None;
+        }
+        let q = ao.cross(ab);
+        let v = self.dir.dot(q) * inv_det;
+        if v < 0. || u + v > 1. {
+            return None;
+        }
+        let t = ac.dot(q) * inv_det;
+        (t >= 0.).then_some(TriangleHit { t, barycentric: [1. - u - v, u, v] })
+    }
+
+    /// Returns the closest hit with the triangles of `indices` into `positions`
+    /// and the index of the triangle, i.e. its first index divided by 3.
+    pub fn intersect_mesh(&self, positions: &[Vector3], indices: &[u32]) -> Option<(usize, TriangleHit)> {
+        indices.chunks_exact(3)
+            .enumerate()
+            .filter_map(|(triangle, indices)| {
+                let [a, b, c] = [0, 1, 2].map(|i| positions[indices[i] as usize]);
+                Some((triangle, self.intersect_triangle(a, b, c)?))
+            })
+            .min_by(|(_, a), (_, b)| a.t.total_cmp(&b.t))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::{self, Deg};
+
+    fn assert_close(a: Vector3, b: [f32; 3]) {
+        assert!(a.approx_eq(&Vector3::from(b), 1e-4), "{a:?} != {b:?}");
+    }
+
+    #[test]
+    fn triangle_hit() {
+        let ray = Ray::new(Vector3::from([0.25, 0.25, 1.]), Vector3::from([0., 0., -2.]));
+        let (a, b, c) = (Vector3::from([0., 0., 0.]), Vector3::from([1., 0., 0.]), Vector3::from([0., 1., 0.]));
+        let hit = ray.intersect_triangle(a, b, c).unwrap();
+        assert_eq!(hit.t, 0.5);
+        assert_eq!(hit.barycentric, [0.5, 0.25, 0.25]);
+        assert_close(ray.at(hit.t), [0.25, 0.25, 0.]);
+        // the winding order does not matter
+        assert!(ray.intersect_triangle(a, c, b).is_some());
+    }
+
+    #[test]
+    fn triangle_miss() {
+        let (a, b, c) = (Vector3::from([0., 0., 0.]), Vector3::from([1., 0., 0.]), Vector3::from([0., 1., 0.]));
+        let outside = Ray::new(Vector3::from([0.75, 0.75, 1.]), Vector3::from([0., 0., -1.]));
+        assert_eq!(outside.intersect_triangle(a, b, c), None);
+        let behind = Ray::new(Vector3::from([0.25, 0.25, 1.]), Vector3::from([0., 0., 1.]));
+        assert_eq!(behind.intersect_triangle(a, b, c), None);
+        let parallel = Ray::new(Vector3::from([0.25, 0.25, 1.]), Vector3::from([1., 0., 0.]));
+        assert_eq!(parallel.intersect_triangle(a, b, c), None);
+    }
+
+    #[test]
+    fn mesh_closest_hit() {
+        let positions = [[0., 0., 0.], [1., 0., 0.], [0., 1., 0.], [0., 0., 0.5], [1., 0., 0.5], [0., 1., 0.5]]
+            .map(Vector3::from);
+        let ray = Ray::new(Vector3::from([0.25, 0.25, 1.]), Vector3::from([0., 0., -1.]));
+        let (triangle, hit) = ray.intersect_mesh(&positions, &[0, 1, 2, 3, 4, 5]).unwrap();
+        assert_eq!((triangle, hit.t), (1, 0.5));
+        let (triangle, hit) = ray.intersect_mesh(&positions, &[3, 4, 5, 0, 1, 2]).unwrap();
+        assert_eq!((triangle, hit.t), (0, 0.5));
+        assert_eq!(ray.intersect_mesh(&positions, &[]), None);
+    }
+
+    #[test]
+    fn from_ndc() {
+        let eye = Vector3::from([0., 0., 3.]);
+        let view = Matrix4::look_at_rh(eye, Vector3::default(), Vector3::from([0., 1., 0.]));
+        let proj = math::perspective(Deg(90.), 1., 0.1, 10.);
+        let inverse = (proj * view).inverse().unwrap();
+
+        let center = Ray::from_ndc(inverse, 0., 0.);
+        assert_close(center.origin, [0., 0., 2.9]);
+        assert_close(center.dir.normalize(), [0., 0., -1.]);
+
+        // the projection flips y, so the top of the screen has negative y
+        let top_right = Ray::from_ndc(inverse, 1., -1.);
+        assert_close(top_right.at(2.9 / 9.9), [3., 3., 0.]);
+    }
+}
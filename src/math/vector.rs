@@ -9,7 +9,7 @@ pub struct Vector<T, const N: usize> {
 
 impl<T: Copy, const N: usize> Vector<T, N> {
     /// Creates a vector filled with `value`.
-    pub fn new(value: T) -> Self {
+    pub const fn new(value: T) -> Self {
         Self { array: [value; N] }
     }
 
@@ -44,6 +44,11 @@ impl<T: ops::Mul<Output = T> + std::iter::Sum, const N: usize> Vector<T, N> {
 }
 
 impl<const N: usize> Vector<f32, N> {
+    /// Returns true if no component differs from the one of `other` by more than `epsilon`.
+    pub fn approx_eq(&self, other: &Self, epsilon: f32) -> bool {
+        self.array.iter().zip(&other.array).all(|(a, b)| (a - b).abs() <= epsilon)
+    }
+
     /// Calculates the euclidian magnitude of a vector.
     pub fn magnitude(&self) -> f32 {
         self.array.iter().map(|&x| x * x).sum::<f32>().sqrt()
@@ -57,6 +62,22 @@ impl<const N: usize> Vector<f32, N> {
         }
         self
     }
+
+    /// Linear interpolation between `a` at `t` = 0 and `b` at `t` = 1.
+    pub fn lerp(a: Self, b: Self, t: f32) -> Self {
+        a + (b - a) * t
+    }
+
+    /// Interpolates between `p1` at `t` = 0 and `p2` at `t` = 1 on the uniform
+    /// Catmull-Rom spline through `p0`, `p1`, `p2` and `p3`.
+    pub fn catmull_rom(p0: Self, p1: Self, p2: Self, p3: Self, t: f32) -> Self {
+        let t2 = t * t;
+        let t3 = t2 * t;
+        (p1 * 2.
+            + (p2 - p0) * t
+            + (p0 * 2. - p1 * 5. + p2 * 4. - p3) * t2
+            + (p1 * 3. - p0 - p2 * 3. + p3) * t3) * 0.5
+    }
 }
 
 impl<T> Vector<T, 3>
@@ -73,6 +94,36 @@ where
     }
 }
 
+impl<T: Copy + Default + From<bool>> Vector<T, 3> {
+    /// Extends the vector to homogeneous coordinates of a point, so it is translated by transformations.
+    pub fn to_point(self) -> Vector<T, 4> {
+        let [x, y, z] = self.array;
+        Vector::from([x, y, z, true.into()])
+    }
+
+    /// Extends the vector to homogeneous coordinates of a direction, which ignores translations.
+    pub fn to_direction(self) -> Vector<T, 4> {
+        let [x, y, z] = self.array;
+        Vector::from([x, y, z, T::default()])
+    }
+}
+
+impl<T: Copy> Vector<T, 4> {
+    /// Drops the `w` component, e.g. of a transformed direction.
+    pub fn truncate(self) -> Vector<T, 3> {
+        let [x, y, z, _] = self.array;
+        Vector::from([x, y, z])
+    }
+}
+
+impl<T: Copy + ops::Div<Output = T>> Vector<T, 4> {
+    /// Divides by `w` to get back from homogeneous coordinates of a point, e.g. after a projection.
+    pub fn project(self) -> Vector<T, 3> {
+        let [x, y, z, w] = self.array;
+        Vector::from([x / w, y / w, z / w])
+    }
+}
+
 impl<T: ops::Neg<Output = T>, const N: usize> ops::Neg for Vector<T, N> {
     type Output = Self;
 
@@ -115,6 +166,13 @@ impl<T: ops::SubAssign, const N: usize> ops::SubAssign for Vector<T, N> {
     }
 }
 
+impl<T: Copy + ops::Mul<Output = T>, const N: usize> ops::Mul<T> for Vector<T, N> {
+    type Output = Self;
+    fn mul(self, rhs: T) -> Self::Output {
+        Self { array: self.array.map(|x| x * rhs) }
+    }
+}
+
 impl<T: Copy + ops::Div<Output = T>, const N: usize> ops::Div<T> for Vector<T, N> {
     type Output = Self;
     fn div(self, rhs: T) -> Self::Output {
@@ -174,6 +232,14 @@ mod tests {
         assert_eq!(a[0], 42);
     }
 
+    #[test]
+    fn approx_eq() {
+        let a = Vector::from([1., 2., 3.]);
+        assert!(a.approx_eq(&Vector::from([1., 2.05, 2.95]), 0.1));
+        assert!(!a.approx_eq(&Vector::from([1., 2.2, 3.]), 0.1));
+        assert!(!a.approx_eq(&Vector::from([1., 2., f32::NAN]), 0.1));
+    }
+
     #[test]
     fn neg() {
         let a = Vector::from([1, 2]);
@@ -194,6 +260,13 @@ mod tests {
         assert_eq!(a - b, [-2, -2].into());
     }
 
+    #[test]
+    fn mul_div_scalar() {
+        let a = Vector::from([2, 4]);
+        assert_eq!(a * 3, [6, 12].into());
+        assert_eq!(a / 2, [1, 2].into());
+    }
+
     #[test]
     fn dot() {
         let a = Vector::from([1, 2, 3]);
@@ -208,6 +281,33 @@ mod tests {
         assert_eq!(a.cross(b), [-2, 4, -2].into());
     }
 
+    #[test]
+    fn homogeneous() {
+        let v = Vector::from([2., 4., 6.]);
+        assert_eq!(v.to_point(), [2., 4., 6., 1.].into());
+        assert_eq!(v.to_direction(), [2., 4., 6., 0.].into());
+        assert_eq!(v.to_direction().truncate(), v);
+        assert_eq!(Vector::from([2., 4., 6., 2.]).project(), [1., 2., 3.].into());
+    }
+
+    #[test]
+    fn lerp() {
+        let [a, b] = [[0., 2., -1.], [4., 2., 1.]].map(Vector::from);
+        assert_eq!(Vector::lerp(a, b, 0.), a);
+        assert_eq!(Vector::lerp(a, b, 1.), b);
+        assert_eq!(Vector::lerp(a, b, 0.25), [1., 2., -0.5].into());
+    }
+
+    #[test]
+    fn catmull_rom() {
+        let [p0, p1, p2, p3] = [[0., 0.], [1., 2.], [3., 1.], [4., 4.]].map(Vector::from);
+        assert_eq!(Vector::catmull_rom(p0, p1, p2, p3, 0.), p1);
+        assert_eq!(Vector::catmull_rom(p0, p1, p2, p3, 1.), p2);
+        // evenly spaced points on a line are interpolated linearly
+        let [p0, p1, p2, p3] = [[0., 0.], [1., 1.], [2., 2.], [3., 3.]].map(Vector::from);
+        assert_eq!(Vector::catmull_rom(p0, p1, p2, p3, 0.5), [1.5, 1.5].into());
+    }
+
     #[test]
     fn magnitude_and_norm() {
         let v = Vector::from([3., 4.]);
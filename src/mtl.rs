@@ -0,0 +1,114 @@
+use crate::obj::ObjError;
+
+use std::io::{self, BufRead};
+use std::str;
+
+/// Material from a mtl file. Only the diffuse color and texture are supported.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Material {
+    pub name: String,
+    pub diffuse: [f32; 3],
+    /// Path of the diffuse texture as written in the mtl file.
+    pub diffuse_map: Option<String>,
+}
+
+impl Material {
+    fn new(name: String) -> Self {
+        Self { name, diffuse: [1.; 3], diffuse_map: None }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Mtl {
+    pub materials: Vec<Material>,
+}
+
+impl Mtl {
+    pub fn from_reader(reader: impl BufRead) -> Result<Self, ObjError> {
+        let mut mtl = Self::default();
+        for (line_num, line) in reader.split(b'\n').enumerate() {
+            mtl.parse_line(line, line_num + 1)?;
+        }
+        Ok(mtl)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Material> {
+        self.materials.iter().find(|material| material.name == name)
+    }
+
+    fn parse_line(&mut self, line: Result<Vec<u8>, io::Error>, line_num: usize) -> Result<(), ObjError> {
+        let line = line.map_err(|source| ObjError::Io { line: line_num, source })?;
+        let mut parts = line.split(|c| c.is_ascii_whitespace())
+            .filter(|part| !part.is_empty())
+            .map(String::from_utf8_lossy);
+        let Some(iden) = parts.next() else { return Ok(()) };
+        if iden == "newmtl" {
+            let name = parts.collect::<Vec<_>>().join(" ");
+            self.materials.push(Material::new(name));
+            return Ok(());
+        }
+        // ignore everything before the first material
+        let Some(material) = self.materials.last_mut() else { return Ok(()) };
+        match iden.as_ref() {
+            "Kd" => {
+                for (i, coord) in material.diffuse.iter_mut().enumerate() {
+                    let part = parts.next().ok_or(ObjError::NotEnoughNums {
+                        line: line_num,
+                        found: i as u32,
+                        expected: 3,
+                    })?;
+                    *coord = part.parse().map_err(|_| ObjError::ParseFloat {
+                        line: line_num,
+                        num: part.into_owned(),
+                    })?;
+                }
+            }
+            // options may precede the file name, so take the last part
+            "map_Kd" => material.diffuse_map = parts.next_back().map(|part| part.into_owned()),
+            // comments and everything else is not implemented
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn parse_materials() {
+        let file = r#"
+# comment
+newmtl floor
+Ns 10.0
+Kd 0.5 0.25 1
+map_Kd textures\floor.png
+
+newmtl brick wall
+map_Kd -bm 1.0 brick.jpg
+"#;
+        let mtl = Mtl::from_reader(Cursor::new(file.as_bytes())).expect("failed to parse");
+        assert_eq!(mtl.materials, [
+            Material {
+                name: "floor".to_owned(),
+                diffuse: [0.5, 0.25, 1.],
+                diffuse_map: Some(r"textures\floor.png".to_owned()),
+            },
+            Material {
+                name: "brick wall".to_owned(),
+                diffuse: [1.; 3],
+                diffuse_map: Some("brick.jpg".to_owned()),
+            },
+        ]);
+        assert_eq!(mtl.get("brick wall"), mtl.materials.get(1));
+    }
+
+    #[test]
+    fn parse_invalid_diffuse() {
+        let file = "newmtl a\nKd 1 x 1";
+        let err = Mtl::from_reader(Cursor::new(file.as_bytes())).unwrap_err();
+        assert!(matches!(err, ObjError::ParseFloat { line: 2, num } if num == "x"));
+    }
+}
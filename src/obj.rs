@@ -1,7 +1,10 @@
+use crate::math::{self, Aabb, Vector3};
+
 use std::collections::HashMap;
+use std::f32::consts::{PI, TAU};
 use std::error::Error;
 use std::fmt;
-use std::io::{self, BufRead};
+use std::io::{self, BufRead, Write};
 use std::num::NonZeroU32;
 use std::str;
 
@@ -9,56 +12,105 @@ use std::str;
 pub struct Obj {
     pub vertices: Vec<[f32; 3]>,
     pub tex_coords: Vec<[f32; 2]>,
-    pub faces: Vec<([Indices; 3], Option<Indices>)>,
+    pub normals: Vec<[f32; 3]>,
+    /// Faces with at least three vertices each.
+    pub faces: Vec<Vec<Indices>>,
+    /// Line number of each face, used for error messages.
+    pub face_lines: Vec<usize>,
+    /// Names of the material libraries referenced with `mtllib`.
+    pub mtllibs: Vec<String>,
+    /// Material names from `usemtl` and the index of the first face using them.
+    pub materials: Vec<(String, usize)>,
 }
 
 impl Obj {
-    pub fn from_reader(reader: impl BufRead) -> Result<Self, (ObjError, usize)> {
+    pub fn from_reader(reader: impl BufRead) -> Result<Self, ObjError> {
         let mut obj = Self::default();
         for (line_num, line) in reader.split(b'\n').enumerate() {
-            if let Err(err) = obj.parse_line(line) {
-                return Err((err, line_num + 1));
-            }
+            obj.parse_line(line, line_num + 1)?;
         }
         Ok(obj)
     }
 
-    fn parse_line(&mut self, line: Result<Vec<u8>, io::Error>) -> Result<(), ObjError> {
-        let line = line?;
+    fn parse_line(&mut self, line: Result<Vec<u8>, io::Error>, line_num: usize) -> Result<(), ObjError> {
+        let line = line.map_err(|source| ObjError::Io { line: line_num, source })?;
         if line.is_empty() || line[0] == b'#' {
             return Ok(());
         }
 
         let mut parts = line.split(|c| c.is_ascii_whitespace())
-            .filter(|part| !part.is_empty());
+            .filter(|part| !part.is_empty())
+            .peekable();
         let Some(iden) = parts.next() else { return Ok(()) };
         match iden {
-            b"f" => self.faces.push((
-                [
-                    Self::parse_part::<_, 3>(0, parts.next())?,
-                    Self::parse_part::<_, 3>(1, parts.next())?,
-                    Self::parse_part::<_, 3>(2, parts.next())?,
-                ],
-                parts.next().map(|part| Self::parse_part::<_, 3>(3, Some(part))).transpose()?,
-            )),
-            b"v" => self.vertices.push([
-                Self::parse_part::<_, 3>(0, parts.next())?,
-                Self::parse_part::<_, 3>(1, parts.next())?,
-                Self::parse_part::<_, 3>(2, parts.next())?,
-            ]),
+            b"f" => {
+                let counts = [self.vertices.len(), self.tex_coords.len(), self.normals.len()];
+                let face = parts.by_ref()
+                    .take_while(|part| part[0] != b'#')
+                    .map(|part| {
+                        str::from_utf8(part).ok()
+                            .and_then(|part| Indices::parse(part, counts))
+                            .ok_or_else(|| ObjError::InvalidIndex {
+                                line: line_num,
+                                index: String::from_utf8_lossy(part).into_owned(),
+                            })
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                if face.len() < 3 {
+                    return Err(ObjError::NotEnoughNums {
+                        line: line_num,
+                        found: face.len() as u32,
+                        expected: 3,
+                    });
+                }
+                self.faces.push(face);
+                self.face_lines.push(line_num);
+                return Ok(());
+            }
+            b"v" => {
+                self.vertices.push([
+                    Self::parse_part::<3>(line_num, 0, parts.next())?,
+                    Self::parse_part::<3>(line_num, 1, parts.next())?,
+                    Self::parse_part::<3>(line_num, 2, parts.next())?,
+                ]);
+                // the optional weight is only used by rational curves, it is checked but ignored
+                if let Some(w) = parts.next_if(|part| part[0] != b'#') {
+                    Self::parse_part::<4>(line_num, 3, Some(w))?;
+                }
+            }
             b"vt" => self.tex_coords.push([
-                Self::parse_part::<_, 2>(0, parts.next())?,
-                Self::parse_part::<_, 2>(1, parts.next())?,
+                Self::parse_part::<2>(line_num, 0, parts.next())?,
+                Self::parse_part::<2>(line_num, 1, parts.next())?,
+            ]),
+            b"mtllib" => {
+                self.mtllibs.extend(parts.map(|part| String::from_utf8_lossy(part).into_owned()));
+                return Ok(());
+            }
+            b"usemtl" => {
+                let name = parts.map(|part| String::from_utf8_lossy(part)).collect::<Vec<_>>().join(" ");
+                self.materials.push((name, self.faces.len()));
+                return Ok(());
+            }
+            b"vn" => self.normals.push([
+                Self::parse_part::<3>(line_num, 0, parts.next())?,
+                Self::parse_part::<3>(line_num, 1, parts.next())?,
+                Self::parse_part::<3>(line_num, 2, parts.next())?,
             ]),
             // not implemented
-            b"g" | b"o" | b"s" | b"vn" | b"mtllib" | b"usemtl" => return Ok(()),
+            b"g" | b"o" | b"s" => return Ok(()),
             other => {
-                return Err(ObjError::InvalidIden(String::from_utf8_lossy(other).into_owned()));
+                return Err(ObjError::UnexpectedToken {
+                    line: line_num,
+                    token: String::from_utf8_lossy(other).into_owned(),
+                });
             }
         };
         if let Some(next) = parts.next() {
             if next[0] != b'#' {
-                return Err(ObjError::TooManyNums);
+                return Err(ObjError::UnexpectedToken {
+                    line: line_num,
+                    token: String::from_utf8_lossy(next).into_owned(),
+                });
             }
         }
         Ok(())
@@ -66,55 +118,99 @@ impl Obj {
 
     pub fn normalize(&self) -> Result<NormalizedObj, ObjError> {
         let mut map = HashMap::<Indices, u32>::new();
-        let mut nobj = NormalizedObj::default();
-        for face in self.faces.iter() {
+        let mut nobj = NormalizedObj {
+            mtllibs: self.mtllibs.clone(),
+            ..Default::default()
+        };
+        let mut materials = self.materials.iter().peekable();
+        let mut missing_normals = false;
+        for (face_idx, face) in self.faces.iter().enumerate() {
+            let mut material = None;
+            while let Some((name, _)) = materials.next_if(|(_, first)| *first <= face_idx) {
+                material = Some(name);
+            }
+            if material.is_some() || (nobj.groups.is_empty() && !self.materials.is_empty()) {
+                // vertices are not shared between groups so they can be colored by material
+                map.clear();
+                nobj.groups.push(Group {
+                    material: material.cloned(),
+                    first_index: nobj.indices.len() as u32,
+                    index_count: 0,
+                });
+            }
+
             fn map_indices(
                 indices: Indices,
+                line: usize,
                 obj: &Obj,
                 nobj: &mut NormalizedObj,
                 map: &mut HashMap<Indices, u32>,
             ) -> Result<u32, ObjError> {
+                fn get<T: Copy>(items: &[T], idx: NonZeroU32, line: usize) -> Result<T, ObjError> {
+                    items.get(idx.get() as usize - 1)
+                        .copied()
+                        .ok_or_else(|| ObjError::InvalidIndex { line, index: idx.to_string() })
+                }
+
                 let vert_idx = *map.entry(indices).or_insert(nobj.vertices.len() as u32);
                 if vert_idx == nobj.vertices.len() as u32 {
-                    let pos_coords = *obj.vertices.get(indices.vertex.get() as usize - 1)
-                        .ok_or(ObjError::InvalidVertexIndex(indices.vertex.into()))?;
+                    let pos_coords = get(&obj.vertices, indices.vertex, line)?;
                     let tex_coords = if let Some(tex_coords_idx) = indices.texture {
                         nobj.has_tex_coords = true;
-                        *obj.tex_coords.get(tex_coords_idx.get() as usize - 1)
-                            .ok_or(ObjError::InvalidTextureIndex(tex_coords_idx.into()))?
+                        get(&obj.tex_coords, tex_coords_idx, line)?
                     } else {
                         [0.; 2]
                     };
-                    nobj.vertices.push(Vertex { pos_coords, tex_coords });
+                    let normal = if let Some(normal_idx) = indices.normal {
+                        get(&obj.normals, normal_idx, line)?
+                    } else {
+                        [0.; 3]
+                    };
+                    nobj.vertices.push(Vertex { pos_coords, tex_coords, normal, ..Default::default() });
                 }
                 Ok(vert_idx)
             }
 
-            let indices: Vec<_> = if let Some(v4) = face.1 {
-                let v = face.0;
-                [v[0], v[1], v[2], v[2], v4, v[0]]
-                    .map(|x| map_indices(x, self, &mut nobj, &mut map))
-                    .into_iter().collect::<Result<_, _>>()?
-            } else {
-                face.0
-                    .map(|x| map_indices(x, self, &mut nobj, &mut map))
-                    .into_iter().collect::<Result<_, _>>()?
-            };
+            let face_vertices = face.iter()
+                .map(|&x| map_indices(x, self.face_lines[face_idx], self, &mut nobj, &mut map))
+                .collect::<Result<Vec<_>, _>>()?;
+            // fan triangulation keeps the winding order of the polygon
+            let indices = face_vertices[1..].windows(2)
+                .flat_map(|pair| [face_vertices[0], pair[0], pair[1]])
+                .collect::<Vec<_>>();
+            if face.iter().any(|indices| indices.normal.is_some()) {
+                nobj.has_normals = true;
+            }
+            if face.iter().any(|indices| indices.normal.is_none()) {
+                missing_normals = true;
+            }
+            if let Some(group) = nobj.groups.last_mut() {
+                group.index_count += indices.len() as u32;
+            }
             nobj.indices.extend(indices);
         }
+        // without faces all vertices are kept, so point clouds can be displayed
+        if self.faces.is_empty() {
+            nobj.vertices = self.vertices.iter()
+                .map(|&pos_coords| Vertex { pos_coords, ..Default::default() })
+                .collect();
+        }
+        if nobj.has_normals && missing_normals {
+            log::warn!("Some but not all vertices have normals, normals of the file are ignored");
+            nobj.has_normals = false;
+        }
         Ok(nobj)
     }
 
-    fn parse_part<T, const N: u32>(n: u32, part: Option<&[u8]>) -> Result<T, ObjError>
-    where
-        T: str::FromStr,
-    {
+    fn parse_part<const N: u32>(line: usize, n: u32, part: Option<&[u8]>) -> Result<f32, ObjError> {
         match part {
-            Some(part) => str::from_utf8(part)
-                .map_err(|_| ObjError::InvalidNum(String::from_utf8_lossy(part).into_owned()))?
-                .parse()
-                .map_err(|_| ObjError::InvalidNum(String::from_utf8_lossy(part).into_owned())),
-            None => Err(ObjError::NotEnoughNums(n, N)),
+            Some(part) => str::from_utf8(part).ok()
+                .and_then(|part| part.parse().ok())
+                .ok_or_else(|| ObjError::ParseFloat {
+                    line,
+                    num: String::from_utf8_lossy(part).into_owned(),
+                }),
+            None => Err(ObjError::NotEnoughNums { line, found: n, expected: N }),
         }
     }
 }
@@ -124,18 +220,297 @@ pub struct NormalizedObj {
     pub indices: Vec<u32>,
     pub vertices: Vec<Vertex>,
     pub has_tex_coords: bool,
+    /// True if all vertices have normals from the obj file.
+    pub has_normals: bool,
+    /// True if the vertex colors come from the file instead of the materials, like in ply files.
+    pub has_colors: bool,
+    /// Names of the material libraries referenced by the obj file.
+    pub mtllibs: Vec<String>,
+    /// Ranges of indices using the same material, empty if the obj file uses no materials.
+    pub groups: Vec<Group>,
 }
 
 impl NormalizedObj {
     pub fn from_reader(reader: impl BufRead) -> Result<Self, ObjError> {
-        Obj::from_reader(reader).map_err(|(err, _)| err)?.normalize()
+        Obj::from_reader(reader)?.normalize()
+    }
+
+    /// Writes the model as obj file with one `v` line per vertex and triangular faces.
+    /// Texture coordinates and normals are written if the model has them, the
+    /// groups with their materials are kept.
+    ///
+    /// Floats are written with the fewest digits that read back to the same value.
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        for mtllib in &self.mtllibs {
+            writeln!(writer, "mtllib {mtllib}")?;
+        }
+        for vertex in &self.vertices {
+            let [x, y, z] = vertex.pos_coords;
+            writeln!(writer, "v {x} {y} {z}")?;
+        }
+        if self.has_tex_coords {
+            for vertex in &self.vertices {
+                let [u, v] = vertex.tex_coords;
+                writeln!(writer, "vt {u} {v}")?;
+            }
+        }
+        if self.has_normals {
+            for vertex in &self.vertices {
+                let [x, y, z] = vertex.normal;
+                writeln!(writer, "vn {x} {y} {z}")?;
+            }
+        }
+
+        let mut groups = self.groups.iter().peekable();
+        for (i, tri) in self.indices.chunks_exact(3).enumerate() {
+            while let Some(group) = groups.next_if(|group| group.first_index as usize <= i * 3) {
+                if let Some(material) = &group.material {
+                    writeln!(writer, "usemtl {material}")?;
+                }
+            }
+            write!(writer, "f")?;
+            for idx in tri.iter().map(|idx| idx + 1) {
+                match (self.has_tex_coords, self.has_normals) {
+                    (true, true) => write!(writer, " {idx}/{idx}/{idx}")?,
+                    (true, false) => write!(writer, " {idx}/{idx}")?,
+                    (false, true) => write!(writer, " {idx}//{idx}")?,
+                    (false, false) => write!(writer, " {idx}")?,
+                }
+            }
+            writeln!(writer)?;
+        }
+        writer.flush()
+    }
+
+    /// Merges vertices with the same position, texture coordinates, normal and color and drops
+    /// unused ones. Vertices are still not shared between groups. Returns the number of
+    /// removed vertices.
+    ///
+    /// Useful for files listing the same vertex several times, like stl files.
+    pub fn deduplicate(&mut self) -> usize {
+        let mut group_ends = self.groups.iter()
+            .map(|group| (group.first_index + group.index_count) as usize)
+            .peekable();
+        let mut group = 0;
+        let mut map = HashMap::<(usize, [u32; 11]), u32>::new();
+        let mut vertices = Vec::new();
+        for (i, idx) in self.indices.iter_mut().enumerate() {
+            while group_ends.next_if(|&end| end <= i).is_some() {
+                group += 1;
+            }
+            let vertex = self.vertices[*idx as usize];
+            let [x, y, z] = vertex.pos_coords;
+            let [u, v] = vertex.tex_coords;
+            let [nx, ny, nz] = vertex.normal;
+            let [r, g, b] = vertex.color;
+            // compares floats by their bits, so 0 and -0 are different but that does no harm
+            let key = [x, y, z, u, v, nx, ny, nz, r, g, b].map(f32::to_bits);
+            *idx = *map.entry((group, key)).or_insert_with(|| {
+                vertices.push(vertex);
+                vertices.len() as u32 - 1
+            });
+        }
+        let removed = self.vertices.len() - vertices.len();
+        self.vertices = vertices;
+        removed
+    }
+
+    /// Computes smooth vertex normals by summing up the normals of the adjacent
+    /// triangles weighted by their area.
+    pub fn compute_normals(&self) -> Vec<[f32; 3]> {
+        let mut normals = vec![Vector3::default(); self.vertices.len()];
+        for tri in self.indices.chunks_exact(3) {
+            let [a, b, c] = [tri[0], tri[1], tri[2]]
+                .map(|idx| Vector3::from(self.vertices[idx as usize].pos_coords));
+            // the length of the cross product is twice the area of the triangle
+            let normal = (b - a).cross(c - a);
+            for &idx in tri {
+                normals[idx as usize] += normal;
+            }
+        }
+        normals.into_iter().map(|normal| {
+            if normal.magnitude() == 0. {
+                [0.; 3]
+            } else {
+                normal.normalize().into()
+            }
+        }).collect()
+    }
+
+    /// Gives each triangle its own three vertices with the normal of the triangle,
+    /// so the faces look flat instead of being smoothed across their edges.
+    /// The order of the indices is kept, so the groups stay valid.
+    /// Point clouds are returned unchanged.
+    pub fn flat_shaded(self) -> Self {
+        if self.is_point_cloud() {
+            return self;
+        }
+        let mut vertices = Vec::with_capacity(self.indices.len());
+        for tri in self.indices.chunks_exact(3) {
+            let tri = [tri[0], tri[1], tri[2]].map(|idx| self.vertices[idx as usize]);
+            let [a, b, c] = tri.map(|vertex| Vector3::from(vertex.pos_coords));
+            let normal = (b - a).cross(c - a);
+            let normal = if normal.magnitude() == 0. { [0.; 3] } else { normal.normalize().into() };
+            vertices.extend(tri.map(|vertex| Vertex { normal, ..vertex }));
+        }
+        Self {
+            indices: (0..vertices.len() as u32).collect(),
+            vertices,
+            has_normals: true,
+            ..self
+        }
+    }
+
+    /// Computes a tangent for each vertex pointing in the direction of increasing u,
+    /// with Lengyel's method from the positions and the given `normals` and `tex_coords`.
+    ///
+    /// The tangents of the adjacent triangles are summed up and orthonormalized against
+    /// the normal. The w component is the handedness, the bitangent is `cross(n, t) * w`.
+    /// Vertices without usable texture coordinates get a zero tangent.
+    pub fn compute_tangents(&self, normals: &[[f32; 3]], tex_coords: &[[f32; 2]]) -> Vec<[f32; 4]> {
+        let mut tangents = vec![Vector3::default(); self.vertices.len()];
+        let mut bitangents = vec![Vector3::default(); self.vertices.len()];
+        for tri in self.indices.chunks_exact(3) {
+            let [a, b, c] = [tri[0], tri[1], tri[2]]
+                .map(|idx| Vector3::from(self.vertices[idx as usize].pos_coords));
+            let [uv_a, uv_b, uv_c] = [tri[0], tri[1], tri[2]].map(|idx| tex_coords[idx as usize]);
+            let (e1, e2) = (b - a, c - a);
+            let (du1, dv1) = (uv_b[0] - uv_a[0], uv_b[1] - uv_a[1]);
+            let (du2, dv2) = (uv_c[0] - uv_a[0], uv_c[1] - uv_a[1]);
+            let det = du1 * dv2 - du2 * dv1;
+            if det == 0. {
+                continue;
+            }
+            let tangent = (e1 * dv2 - e2 * dv1) / det;
+            let bitangent = (e2 * du1 - e1 * du2) / det;
+            for &idx in tri {
+                tangents[idx as usize] += tangent;
+                bitangents[idx as usize] += bitangent;
+            }
+        }
+        tangents.into_iter().zip(bitangents).zip(normals).map(|((tangent, bitangent), &normal)| {
+            let normal = Vector3::from(normal);
+            let tangent = tangent - normal * normal.dot(tangent);
+            if tangent.magnitude() == 0. {
+                return [0., 0., 0., 1.];
+            }
+            let tangent = tangent.normalize();
+            let handedness = if normal.cross(tangent).dot(bitangent) < 0. { -1. } else { 1. };
+            [tangent.x(), tangent.y(), tangent.z(), handedness]
+        }).collect()
+    }
+
+    /// Projects the positions onto a sphere around their centroid and returns
+    /// longitude and latitude as texture coordinates, u increases to the right
+    /// when looking along -z and v increases upwards.
+    pub fn spherical_tex_coords(&self) -> Vec<[f32; 2]> {
+        let count = self.vertices.len().max(1) as f32;
+        let centroid = self.vertices.iter()
+            .fold(Vector3::default(), |sum, vertex| sum + Vector3::from(vertex.pos_coords)) / count;
+        self.vertices.iter().map(|vertex| {
+            let dir = Vector3::from(vertex.pos_coords) - centroid;
+            if dir.magnitude() == 0. {
+                return [0.5, 0.5];
+            }
+            let dir = dir.normalize();
+            [0.5 + dir.x().atan2(dir.z()) / TAU, 0.5 + dir.y().asin() / PI]
+        }).collect()
+    }
+
+    /// Returns the bounding box of all vertices, which is empty if there are none.
+    pub fn aabb(&self) -> Aabb {
+        Aabb::from_points(self.vertices.iter().map(|vertex| Vector3::from(vertex.pos_coords)))
+    }
+
+    /// Returns the center and radius of a sphere containing all vertices, which is
+    /// tighter than the one around `aabb` for most models, see `math::bounding_sphere`.
+    pub fn bounding_sphere(&self) -> (Vector3, f32) {
+        math::bounding_sphere(self.vertices.iter().map(|vertex| Vector3::from(vertex.pos_coords)))
+    }
+
+    /// Returns true if the model has vertices but no faces, e.g. a scanned point cloud.
+    pub fn is_point_cloud(&self) -> bool {
+        self.indices.is_empty() && !self.vertices.is_empty()
+    }
+
+    /// Returns the vertex, triangle and material counts and the bounding box size.
+    pub fn stats(&self) -> ModelStats {
+        let mut materials = self.groups.iter()
+            .filter_map(|group| group.material.as_deref())
+            .collect::<Vec<_>>();
+        materials.sort_unstable();
+        materials.dedup();
+        ModelStats {
+            vertex_count: self.vertices.len(),
+            triangle_count: self.indices.len() / 3,
+            material_count: materials.len(),
+            has_normals: self.has_normals,
+            has_tex_coords: self.has_tex_coords,
+            has_colors: self.has_colors,
+            size: self.aabb().extents().into(),
+        }
     }
 }
 
-#[derive(Debug, Default, Clone, Copy, PartialEq)]
+/// Summary of a model, useful to find out why it is slow to load or render.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ModelStats {
+    pub vertex_count: usize,
+    pub triangle_count: usize,
+    /// Number of distinct materials used by the faces.
+    pub material_count: usize,
+    pub has_normals: bool,
+    pub has_tex_coords: bool,
+    pub has_colors: bool,
+    /// Size of the axis aligned bounding box.
+    pub size: [f32; 3],
+}
+
+impl fmt::Display for ModelStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} vertices, {} triangles, {} materials, normals: {}, texture coordinates: {}, colors: {}, size: {} x {} x {}",
+            self.vertex_count,
+            self.triangle_count,
+            self.material_count,
+            self.has_normals,
+            self.has_tex_coords,
+            self.has_colors,
+            self.size[0],
+            self.size[1],
+            self.size[2],
+        )
+    }
+}
+
+/// Consecutive range of indices drawn with the same material.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Group {
+    /// Name of the material or `None` for faces before the first `usemtl`.
+    pub material: Option<String>,
+    pub first_index: u32,
+    pub index_count: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Vertex {
     pub pos_coords: [f32; 3],
     pub tex_coords: [f32; 2],
+    pub normal: [f32; 3],
+    /// Linear RGB color, white unless the file has vertex colors.
+    pub color: [f32; 3],
+}
+
+impl Default for Vertex {
+    fn default() -> Self {
+        Self {
+            pos_coords: [0.; 3],
+            tex_coords: [0.; 2],
+            normal: [0.; 3],
+            color: [1.; 3],
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -145,52 +520,70 @@ pub struct Indices {
     pub normal: Option<NonZeroU32>,
 }
 
-impl str::FromStr for Indices {
-    type Err = ObjError;
+impl Indices {
+    /// Parses indices of the form `v`, `v/vt`, `v//vn` or `v/vt/vn`.
+    ///
+    /// Negative indices are relative to the end of the vertices, texture coordinates
+    /// and normals given by `counts`, which are the numbers of elements seen so far.
+    pub fn parse(s: &str, counts: [usize; 3]) -> Option<Self> {
+        fn parse_index(part: &str, count: usize) -> Option<NonZeroU32> {
+            let idx: i64 = part.parse().ok()?;
+            let idx = if idx < 0 { count as i64 + idx + 1 } else { idx };
+            NonZeroU32::new(u32::try_from(idx).ok()?)
+        }
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
         let mut parts = s.split('/');
-        let Some(part) = parts.next() else {
-            return Err(ObjError::NotEnoughNums(0, 1));
-        };
-        let vertex = part.parse().map_err(|_| ObjError::InvalidNum(part.to_owned()))?;
+        let vertex = parse_index(parts.next()?, counts[0])?;
         let texture = match parts.next() {
-            Some(part) if !part.is_empty() =>
-                Some(part.parse().map_err(|_| ObjError::InvalidNum(part.to_owned()))?),
+            Some(part) if !part.is_empty() => Some(parse_index(part, counts[1])?),
             _ => None,
         };
-        let normal = if let Some(part) = parts.next() {
-            Some(part.parse().map_err(|_| ObjError::InvalidNum(part.to_owned()))?)
-        } else {
-            None
+        let normal = match parts.next() {
+            Some(part) => Some(parse_index(part, counts[2])?),
+            None => None,
         };
 
-        Ok(Self { vertex, texture, normal })
+        Some(Self { vertex, texture, normal })
     }
 }
 
+/// Error while parsing an obj or mtl file. All variants carry the 1-based line number.
 #[derive(Debug)]
 pub enum ObjError {
-   InvalidIden(String),
-   InvalidNum(String),
-   InvalidTextureIndex(u32),
-   InvalidVertexIndex(u32),
-   Io(io::Error),
-   NotEnoughNums(u32, u32),
-   TooManyNums,
+    /// A face index is not a number or refers to a missing element.
+    InvalidIndex { line: usize, index: String },
+    ParseFloat { line: usize, num: String },
+    /// Unknown identifier at the line start or unexpected trailing token.
+    UnexpectedToken { line: usize, token: String },
+    NotEnoughNums { line: usize, found: u32, expected: u32 },
+    Io { line: usize, source: io::Error },
+}
+
+impl ObjError {
+    /// Returns the line number at which the error occurred.
+    pub fn line(&self) -> usize {
+        match self {
+            Self::InvalidIndex { line, .. }
+            | Self::ParseFloat { line, .. }
+            | Self::UnexpectedToken { line, .. }
+            | Self::NotEnoughNums { line, .. }
+            | Self::Io { line, .. } => *line,
+        }
+    }
 }
 
 impl fmt::Display for ObjError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::InvalidIden(iden) => write!(f, "Invalid identifier at line start: {iden}"),
-            Self::InvalidNum(num) => write!(f, "Invalid number: {num}"),
-            Self::InvalidTextureIndex(idx) => write!(f, "Invalid texture index: {idx}"),
-            Self::InvalidVertexIndex(idx) => write!(f, "Invalid vertex index: {idx}"),
-            Self::Io(err) => write!(f, "IO error: {err}"),
-            Self::NotEnoughNums(found, expt) =>
-                write!(f, "Not enough numbers at line: found {found} expected at least {expt}"),
-            Self::TooManyNums => write!(f, "Too many numbers at line"),
+            Self::InvalidIndex { line, index } => write!(f, "Invalid index {index} on line {line}"),
+            Self::ParseFloat { line, num } => write!(f, "Invalid number {num} on line {line}"),
+            Self::UnexpectedToken { line, token } =>
+                write!(f, "Unexpected token {token} on line {line}"),
+            Self::NotEnoughNums { line, found, expected } => write!(
+                f,
+                "Not enough numbers on line {line}: found {found} expected at least {expected}",
+            ),
+            Self::Io { line, source } => write!(f, "IO error on line {line}: {source}"),
         }
     }
 }
@@ -198,19 +591,12 @@ impl fmt::Display for ObjError {
 impl Error for ObjError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         match self {
-            Self::Io(err) => Some(err),
+            Self::Io { source, .. } => Some(source),
             _ => None,
         }
     }
 }
 
-impl From<io::Error> for ObjError {
-    fn from(source: io::Error) -> Self {
-        Self::Io(source)
-    }
-}
-
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -219,6 +605,7 @@ mod tests {
     use std::path::Path;
 
     #[test]
+    #[allow(clippy::approx_constant)]
     fn parse_vertice() {
         let file = "v 1 2.2  3.14159";
         let obj = Obj::from_reader(Cursor::new(file.as_bytes())).expect("failed to parse");
@@ -226,12 +613,62 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::approx_constant)]
     fn parse_vertices() {
         let file = "v 1 2.2  3.14159\nv 1 2 3   ";
         let obj = Obj::from_reader(Cursor::new(file.as_bytes())).expect("failed to parse");
         assert_eq!(obj.vertices, [[1., 2.2, 3.14159], [1., 2., 3.]]);
     }
 
+    #[test]
+    fn deduplicate() {
+        let file = r#"
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+v 0 0 0
+v 1 1 0
+f 1 2 3
+f 5 6 4
+usemtl a
+f 1 2 3
+"#;
+        let mut nobj = NormalizedObj::from_reader(Cursor::new(file.as_bytes())).expect("failed to parse");
+        assert_eq!(nobj.vertices.len(), 9);
+        assert_eq!(nobj.deduplicate(), 2);
+        assert_eq!(nobj.vertices.len(), 7);
+        // the vertices of the second group are not shared with the first
+        assert_eq!(nobj.indices, [0, 1, 2, 0, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn write_roundtrip() {
+        let file = r#"
+mtllib scene.mtl
+v 0 0 0
+v 1 0 0.5
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vn 0 0 1
+f 1/1/1 2/2/1 3/3/1
+usemtl brick wall
+f 1/1/1 3/3/1 4/2/1
+"#;
+        let nobj = NormalizedObj::from_reader(Cursor::new(file.as_bytes())).expect("failed to parse");
+        let mut written = Vec::new();
+        nobj.write(&mut written).unwrap();
+        let read = NormalizedObj::from_reader(Cursor::new(written)).expect("failed to parse written obj");
+        assert_eq!(read.vertices, nobj.vertices);
+        assert_eq!(read.indices, nobj.indices);
+        assert_eq!(read.groups, nobj.groups);
+        assert_eq!(read.mtllibs, nobj.mtllibs);
+        assert!(read.has_tex_coords && read.has_normals);
+    }
+
     #[test]
     fn parse_obj_file_chalet() {
         let src_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets").join("models");
@@ -279,13 +716,53 @@ f 1/1 2/2 3/3
 
         let nobj = obj.normalize().expect("failed to normalize");
         assert_eq!(nobj.vertices, [
-            Vertex { pos_coords: [1.1, 1.2, 1.3], tex_coords: [0.1, 0.2] },
-            Vertex { pos_coords: [2.1, 2.2, 2.3], tex_coords: [0.3, 0.4] },
-            Vertex { pos_coords: [3.1, 3.2, 3.3], tex_coords: [0.5, 0.6] },
+            Vertex { pos_coords: [1.1, 1.2, 1.3], tex_coords: [0.1, 0.2], normal: [0.; 3], ..Default::default() },
+            Vertex { pos_coords: [2.1, 2.2, 2.3], tex_coords: [0.3, 0.4], normal: [0.; 3], ..Default::default() },
+            Vertex { pos_coords: [3.1, 3.2, 3.3], tex_coords: [0.5, 0.6], normal: [0.; 3], ..Default::default() },
         ]);
         assert_eq!(nobj.indices, [0, 1, 2]);
     }
 
+    #[test]
+    fn normalize_point_cloud() {
+        let file = "v 1 2 3\nv 4 5 6\nvn 0 1 0\n";
+        let nobj = NormalizedObj::from_reader(Cursor::new(file.as_bytes())).expect("failed to parse");
+        assert!(nobj.is_point_cloud());
+        assert!(!nobj.has_normals);
+        assert_eq!(nobj.vertices, [
+            Vertex { pos_coords: [1., 2., 3.], ..Default::default() },
+            Vertex { pos_coords: [4., 5., 6.], ..Default::default() },
+        ]);
+        assert_eq!(nobj.clone().flat_shaded().vertices, nobj.vertices);
+        assert!(!NormalizedObj::default().is_point_cloud());
+    }
+
+    #[test]
+    fn stats() {
+        let file = r#"
+v 0 0 0
+v 2 0 0
+v 2 1 0
+v 0 1 3
+usemtl a
+f 1 2 3
+usemtl b
+f 1 3 4
+usemtl a
+f 2 3 4
+"#;
+        let nobj = NormalizedObj::from_reader(Cursor::new(file.as_bytes())).expect("failed to parse");
+        assert_eq!(nobj.stats(), ModelStats {
+            vertex_count: nobj.vertices.len(),
+            triangle_count: 3,
+            material_count: 2,
+            has_normals: false,
+            has_tex_coords: false,
+            has_colors: false,
+            size: [2., 1., 3.],
+        });
+    }
+
     #[test]
     fn parse_normalize_complex() {
         let file = r#"
@@ -305,13 +782,298 @@ f 2/1 1/2 3/4
 
         let nobj = obj.normalize().expect("failed to normalize");
         assert_eq!(nobj.vertices, [
-            Vertex { pos_coords: [1.1, 1.2, 1.3], tex_coords: [0.1, 0.2] },
-            Vertex { pos_coords: [2.1, 2.2, 2.3], tex_coords: [0.3, 0.4] },
-            Vertex { pos_coords: [3.1, 3.2, 3.3], tex_coords: [0.5, 0.6] },
-            Vertex { pos_coords: [2.1, 2.2, 2.3], tex_coords: [0.1, 0.2] },
-            Vertex { pos_coords: [1.1, 1.2, 1.3], tex_coords: [0.3, 0.4] },
-            Vertex { pos_coords: [3.1, 3.2, 3.3], tex_coords: [0.7, 0.8] },
+            Vertex { pos_coords: [1.1, 1.2, 1.3], tex_coords: [0.1, 0.2], normal: [0.; 3], ..Default::default() },
+            Vertex { pos_coords: [2.1, 2.2, 2.3], tex_coords: [0.3, 0.4], normal: [0.; 3], ..Default::default() },
+            Vertex { pos_coords: [3.1, 3.2, 3.3], tex_coords: [0.5, 0.6], normal: [0.; 3], ..Default::default() },
+            Vertex { pos_coords: [2.1, 2.2, 2.3], tex_coords: [0.1, 0.2], normal: [0.; 3], ..Default::default() },
+            Vertex { pos_coords: [1.1, 1.2, 1.3], tex_coords: [0.3, 0.4], normal: [0.; 3], ..Default::default() },
+            Vertex { pos_coords: [3.1, 3.2, 3.3], tex_coords: [0.7, 0.8], normal: [0.; 3], ..Default::default() },
         ]);
         assert_eq!(nobj.indices, [0, 1, 2, 3, 4, 5]);
     }
+
+    #[test]
+    fn parse_materials() {
+        let file = r#"
+mtllib scene.mtl
+v 0 0 0
+v 1 0 0
+v 0 1 0
+f 1 2 3
+usemtl floor
+f 1 2 3
+f 3 2 1
+usemtl brick wall
+f 1 2 3
+"#;
+        let obj = Obj::from_reader(Cursor::new(file.as_bytes())).expect("failed to parse");
+        assert_eq!(obj.mtllibs, ["scene.mtl"]);
+        assert_eq!(obj.materials, [("floor".to_owned(), 1), ("brick wall".to_owned(), 3)]);
+
+        let nobj = obj.normalize().expect("failed to normalize");
+        assert_eq!(nobj.groups, [
+            Group { material: None, first_index: 0, index_count: 3 },
+            Group { material: Some("floor".to_owned()), first_index: 3, index_count: 6 },
+            Group { material: Some("brick wall".to_owned()), first_index: 9, index_count: 3 },
+        ]);
+        // vertices are not shared between groups
+        assert_eq!(nobj.vertices.len(), 9);
+        assert_eq!(nobj.indices[9..], [6, 7, 8]);
+    }
+
+    #[test]
+    fn compute_normals_cube() {
+        // each side has its own vertices so the normals are not shared between sides
+        let file = r#"
+v 0 0 1
+v 1 0 1
+v 1 1 1
+v 0 1 1
+v 1 0 0
+v 0 0 0
+v 0 1 0
+v 1 1 0
+v 1 0 1
+v 1 0 0
+v 1 1 0
+v 1 1 1
+v 0 0 0
+v 0 0 1
+v 0 1 1
+v 0 1 0
+v 0 1 1
+v 1 1 1
+v 1 1 0
+v 0 1 0
+v 0 0 0
+v 1 0 0
+v 1 0 1
+v 0 0 1
+f 1 2 3 4
+f 5 6 7 8
+f 9 10 11 12
+f 13 14 15 16
+f 17 18 19 20
+f 21 22 23 24
+"#;
+        let nobj = NormalizedObj::from_reader(Cursor::new(file.as_bytes())).expect("failed to parse");
+        let normals = nobj.compute_normals();
+        assert_eq!(normals.len(), 24);
+        let expected = [[0., 0., 1.], [0., 0., -1.], [1., 0., 0.], [-1., 0., 0.], [0., 1., 0.], [0., -1., 0.]];
+        for (side, normal) in expected.into_iter().enumerate() {
+            assert_eq!(normals[side * 4..side * 4 + 4], [normal; 4]);
+        }
+    }
+
+    #[test]
+    fn parse_normals() {
+        let file = r#"
+v 0 0 0
+v 1 0 0
+v 0 1 0
+vn 0 0 1
+vn 0 0 -1
+f 1//1 2//1 3//1
+f 1//2 3//2 2//2
+"#;
+        let obj = Obj::from_reader(Cursor::new(file.as_bytes())).expect("failed to parse");
+        assert_eq!(obj.normals, [[0., 0., 1.], [0., 0., -1.]]);
+
+        let nobj = obj.normalize().expect("failed to normalize");
+        assert!(nobj.has_normals);
+        assert!(!nobj.has_tex_coords);
+        assert_eq!(nobj.vertices.len(), 6);
+        assert_eq!(nobj.vertices[0].normal, [0., 0., 1.]);
+        assert_eq!(nobj.vertices[3].normal, [0., 0., -1.]);
+    }
+
+    #[test]
+    fn parse_mixed_normals() {
+        let file = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 1\nf 1//1 2//1 3//1\nf 1 3 2";
+        let nobj = NormalizedObj::from_reader(Cursor::new(file.as_bytes())).expect("failed to parse");
+        assert!(!nobj.has_normals);
+    }
+
+    #[test]
+    fn triangulate_quad_cube() {
+        let file = r#"
+v 0 0 0
+v 0 1 0
+v 1 1 0
+v 1 0 0
+v 0 0 1
+v 0 1 1
+v 1 1 1
+v 1 0 1
+f 1 2 3 4
+f 5 8 7 6
+f 1 5 6 2
+f 4 3 7 8
+f 2 6 7 3
+f 1 4 8 5
+"#;
+        let obj = Obj::from_reader(Cursor::new(file.as_bytes())).expect("failed to parse");
+        assert_eq!(obj.faces.len(), 6);
+
+        let nobj = obj.normalize().expect("failed to normalize");
+        assert_eq!(nobj.vertices.len(), 8);
+        assert_eq!(nobj.indices.len(), 12 * 3);
+        assert_eq!(nobj.indices[..6], [0, 1, 2, 0, 2, 3]);
+    }
+
+    #[test]
+    fn triangulate_ngon() {
+        let file = "v 0 0 0\nv 1 0 0\nv 2 1 0\nv 1 2 0\nv 0 1 0\nf 1 2 3 4 5 # pentagon";
+        let nobj = NormalizedObj::from_reader(Cursor::new(file.as_bytes())).expect("failed to parse");
+        assert_eq!(nobj.indices, [0, 1, 2, 0, 2, 3, 0, 3, 4]);
+    }
+
+    #[test]
+    fn face_not_enough_vertices() {
+        let file = "v 0 0 0\nv 1 0 0\nf 1 2";
+        let err = Obj::from_reader(Cursor::new(file.as_bytes())).unwrap_err();
+        assert!(matches!(err, ObjError::NotEnoughNums { line: 3, found: 2, expected: 3 }));
+    }
+
+    #[test]
+    fn parse_negative_indices() {
+        let positive = r#"
+v 0 0 0
+v 1 0 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 0 1
+f 1/1 2/2 3/3
+v 1 1 0
+vt 1 1
+f 2/2 4/4 3/3
+"#;
+        let negative = r#"
+v 0 0 0
+v 1 0 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 0 1
+f -3/-3 -2/-2 -1/-1
+v 1 1 0
+vt 1 1
+f -3/-3 -1/-1 -2/-2
+"#;
+        let positive = NormalizedObj::from_reader(Cursor::new(positive.as_bytes())).expect("failed to parse");
+        let negative = NormalizedObj::from_reader(Cursor::new(negative.as_bytes())).expect("failed to parse");
+        assert_eq!(positive.vertices, negative.vertices);
+        assert_eq!(positive.indices, negative.indices);
+    }
+
+    #[test]
+    fn parse_negative_index_out_of_range() {
+        let file = "v 0 0 0\nv 1 0 0\nv 0 1 0\nf -1 -2 -4";
+        let err = Obj::from_reader(Cursor::new(file.as_bytes())).unwrap_err();
+        assert!(matches!(err, ObjError::InvalidIndex { line: 4, index } if index == "-4"));
+    }
+
+    #[test]
+    fn spherical_tex_coords() {
+        let file = "v 0 1 0\nv 0 -1 0\nv 1 0 0\nv -1 0 0\nv 0 0 1\nv 0 0 -1\nf 1 3 5\nf 2 4 6";
+        let nobj = NormalizedObj::from_reader(Cursor::new(file.as_bytes())).expect("failed to parse");
+        let coords = nobj.spherical_tex_coords();
+        // vertices are ordered by their first use in the faces
+        let expected = [[0.5, 1.], [0.75, 0.5], [0.5, 0.5], [0.5, 0.], [0.25, 0.5], [1., 0.5]];
+        for (coords, expected) in coords.iter().zip(expected) {
+            assert!((coords[0] - expected[0]).abs() < 0.01, "{coords:?} != {expected:?}");
+            assert!((coords[1] - expected[1]).abs() < 0.01, "{coords:?} != {expected:?}");
+        }
+    }
+
+    #[test]
+    fn tangents() {
+        // a quad in the xy plane with u along x and v along -y, so the uvs are mirrored
+        let file = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvt 0 1\nvt 1 1\nvt 1 0\nvt 0 0\nf 1/1 2/2 3/3 4/4";
+        let nobj = NormalizedObj::from_reader(Cursor::new(file.as_bytes())).expect("failed to parse");
+        let normals = nobj.compute_normals();
+        let tex_coords = nobj.vertices.iter().map(|v| v.tex_coords).collect::<Vec<_>>();
+        for tangent in nobj.compute_tangents(&normals, &tex_coords) {
+            assert_eq!(tangent, [1., 0., 0., -1.]);
+        }
+
+        // the same quad with v along y is right-handed
+        let file = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\nf 1/1 2/2 3/3 4/4";
+        let nobj = NormalizedObj::from_reader(Cursor::new(file.as_bytes())).expect("failed to parse");
+        let tex_coords = nobj.vertices.iter().map(|v| v.tex_coords).collect::<Vec<_>>();
+        for tangent in nobj.compute_tangents(&normals, &tex_coords) {
+            assert_eq!(tangent, [1., 0., 0., 1.]);
+        }
+
+        // without texture coordinates there is no tangent
+        let tex_coords = vec![[0.; 2]; nobj.vertices.len()];
+        for tangent in nobj.compute_tangents(&normals, &tex_coords) {
+            assert_eq!(tangent, [0., 0., 0., 1.]);
+        }
+    }
+
+    #[test]
+    fn flat_shaded() {
+        // a roof of two triangles sharing the ridge from 0 1 0 to 0 1 1
+        let file = "v -1 0 0\nv 0 1 0\nv 0 1 1\nv -1 0 1\nv 1 0 0\nv 1 0 1\nf 1 3 2\nf 1 4 3\nf 5 2 3\nf 5 3 6";
+        let nobj = NormalizedObj::from_reader(Cursor::new(file.as_bytes())).expect("failed to parse");
+        assert_eq!(nobj.vertices.len(), 6);
+        let smooth = nobj.compute_normals();
+        assert!(Vector3::from(smooth[1]).approx_eq(&Vector3::from([0., 1., 0.]), 1e-6));
+
+        let flat = nobj.flat_shaded();
+        assert_eq!(flat.vertices.len(), 12);
+        assert_eq!(flat.indices, (0..12).collect::<Vec<_>>());
+        assert!(flat.has_normals);
+        let s = 0.5f32.sqrt();
+        for (i, vertex) in flat.vertices.iter().enumerate() {
+            let expected = if i < 6 { [-s, s, 0.] } else { [s, s, 0.] };
+            assert!(Vector3::from(vertex.normal).approx_eq(&Vector3::from(expected), 1e-6), "{i}: {vertex:?}");
+        }
+        assert_eq!(flat.vertices[0].pos_coords, [-1., 0., 0.]);
+        assert_eq!(flat.vertices[2].pos_coords, [0., 1., 0.]);
+    }
+
+    #[test]
+    fn bounding_sphere() {
+        // octahedron around 2 3 4, its vertices lie on the unit sphere around it
+        let file = "v 3 3 4\nv 1 3 4\nv 2 4 4\nv 2 2 4\nv 2 3 5\nv 2 3 3\n\
+            f 1 3 5\nf 3 2 5\nf 2 4 5\nf 4 1 5\nf 3 1 6\nf 2 3 6\nf 4 2 6\nf 1 4 6";
+        let nobj = NormalizedObj::from_reader(Cursor::new(file.as_bytes())).expect("failed to parse");
+        let (center, radius) = nobj.bounding_sphere();
+        assert!(center.approx_eq(&Vector3::from([2., 3., 4.]), 1e-6), "{center:?}");
+        assert!((radius - 1.).abs() < 1e-6, "{radius}");
+        // the sphere around the bounding box is larger
+        assert!(radius < nobj.aabb().extents().magnitude() / 2.);
+
+        let empty = NormalizedObj::from_reader(Cursor::new(b"")).expect("failed to parse");
+        assert_eq!(empty.bounding_sphere(), (Vector3::default(), 0.));
+    }
+
+    #[test]
+    fn error_line_numbers() {
+        let file = "v 0 0 0\nv 1 x 0";
+        let err = NormalizedObj::from_reader(Cursor::new(file.as_bytes())).unwrap_err();
+        assert!(matches!(err, ObjError::ParseFloat { line: 2, ref num } if num == "x"));
+        assert_eq!(err.to_string(), "Invalid number x on line 2");
+
+        let file = "v 0 0 0\nv 1 0 0\nv 0 1 0\n\nf 1 2 4";
+        let err = NormalizedObj::from_reader(Cursor::new(file.as_bytes())).unwrap_err();
+        assert!(matches!(err, ObjError::InvalidIndex { line: 5, ref index } if index == "4"));
+
+        let file = "v 0 0 0 1 2\nfoo";
+        let err = NormalizedObj::from_reader(Cursor::new(file.as_bytes())).unwrap_err();
+        assert!(matches!(err, ObjError::UnexpectedToken { line: 1, ref token } if token == "2"));
+    }
+
+    #[test]
+    fn parse_vertex_weight() {
+        let file = "v 1 2 3 0.5\nv 4 5 6 1 # weight";
+        let obj = Obj::from_reader(Cursor::new(file.as_bytes())).expect("failed to parse");
+        assert_eq!(obj.vertices, [[1., 2., 3.], [4., 5., 6.]]);
+
+        let err = Obj::from_reader(Cursor::new(b"v 1 2 3 w")).unwrap_err();
+        assert!(matches!(err, ObjError::ParseFloat { line: 1, ref num } if num == "w"));
+    }
 }
@@ -0,0 +1,546 @@
+use crate::obj::{NormalizedObj, Vertex};
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read};
+use std::str;
+
+impl NormalizedObj {
+    /// Loads an ASCII or binary PLY file with a `vertex` and a `face` element.
+    /// Without a `face` element the vertices are loaded as point cloud.
+    ///
+    /// The properties are read by their names from the header in any order, only `x`, `y`
+    /// and `z` are required. Normals are read from `nx`, `ny` and `nz`, texture coordinates
+    /// from `u` and `v` or `s` and `t` and colors from `red`, `green` and `blue`, which are
+    /// shown instead of the random colors. Faces with more than three vertices are split
+    /// into triangles, other elements are skipped.
+    pub fn from_ply<R: Read>(mut reader: R) -> Result<Self, PlyError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let (header, body) = Header::parse(&bytes)?;
+        let mut values = match header.format {
+            Format::Ascii => Values::Ascii(
+                str::from_utf8(body)
+                    .map_err(|_| PlyError::InvalidValue("not valid UTF-8".to_owned()))?
+                    .split_ascii_whitespace(),
+            ),
+            Format::BinaryLittleEndian => Values::Binary { bytes: body, big_endian: false },
+            Format::BinaryBigEndian => Values::Binary { bytes: body, big_endian: true },
+        };
+
+        let mut nobj = Self::default();
+        let mut faces = Vec::new();
+        for element in &header.elements {
+            match element.name.as_str() {
+                "vertex" => {
+                    let layout = VertexLayout::new(element)?;
+                    nobj.has_normals = layout.normal.is_some();
+                    nobj.has_tex_coords = layout.tex_coords.is_some();
+                    nobj.has_colors = layout.color.is_some();
+                    // each property takes at least a byte, so a wrong count can not reserve more
+                    nobj.vertices.reserve(element.count.min(body.len() / element.properties.len().max(1)));
+                    let mut row = vec![0.; element.properties.len()];
+                    for _ in 0..element.count {
+                        for (value, property) in row.iter_mut().zip(&element.properties) {
+                            *value = match property.kind {
+                                PropertyKind::Scalar(ty) => values.next(ty)?,
+                                PropertyKind::List { .. } => {
+                                    values.skip_property(property)?;
+                                    0.
+                                }
+                            };
+                        }
+                        nobj.vertices.push(layout.vertex(&row));
+                    }
+                }
+                "face" => {
+                    let indices = element.properties.iter()
+                        .position(|property| {
+                            matches!(property.name.as_str(), "vertex_indices" | "vertex_index")
+                        })
+                        .ok_or(PlyError::InvalidHeader("face without vertex_indices".to_owned()))?;
+                    for _ in 0..element.count {
+                        for (i, property) in element.properties.iter().enumerate() {
+                            match property.kind {
+                                PropertyKind::List { count, item } if i == indices => {
+                                    let count = values.next_int(count)? as usize;
+                                    let face = (0..count)
+                                        .map(|_| values.next_int(item))
+                                        .collect::<Result<Vec<_>, _>>()?;
+                                    faces.push(face);
+                                }
+                                _ => values.skip_property(property)?,
+                            }
+                        }
+                    }
+                }
+                _ => {
+                    for _ in 0..element.count {
+                        for property in &element.properties {
+                            values.skip_property(property)?;
+                        }
+                    }
+                }
+            }
+        }
+
+        for face in faces {
+            if let Some(&index) = face.iter().find(|&&index| index as usize >= nobj.vertices.len()) {
+                return Err(PlyError::InvalidIndex(index));
+            }
+            for i in 2..face.len() {
+                nobj.indices.extend([face[0], face[i - 1], face[i]]);
+            }
+        }
+        Ok(nobj)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ScalarType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl ScalarType {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "char" | "int8" => Self::I8,
+            "uchar" | "uint8" => Self::U8,
+            "short" | "int16" => Self::I16,
+            "ushort" | "uint16" => Self::U16,
+            "int" | "int32" => Self::I32,
+            "uint" | "uint32" => Self::U32,
+            "float" | "float32" => Self::F32,
+            "double" | "float64" => Self::F64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Self::I8 | Self::U8 => 1,
+            Self::I16 | Self::U16 => 2,
+            Self::I32 | Self::U32 | Self::F32 => 4,
+            Self::F64 => 8,
+        }
+    }
+
+    /// Returns the value of an integer color channel as 0..=1, floats are taken as is.
+    fn normalize(self, value: f32) -> f32 {
+        match self {
+            Self::U8 => value / u8::MAX as f32,
+            Self::U16 => value / u16::MAX as f32,
+            _ => value,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PropertyKind {
+    Scalar(ScalarType),
+    /// A count followed by that many items.
+    List { count: ScalarType, item: ScalarType },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Property {
+    name: String,
+    kind: PropertyKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Header {
+    format: Format,
+    elements: Vec<Element>,
+}
+
+impl Header {
+    /// Parses the header and returns it with the bytes of the body after it.
+    fn parse(bytes: &[u8]) -> Result<(Self, &[u8]), PlyError> {
+        const END: &[u8] = b"end_header";
+        let invalid = |msg: &str| PlyError::InvalidHeader(msg.to_owned());
+        if !bytes.starts_with(b"ply") {
+            return Err(invalid("missing ply magic number"));
+        }
+        let end = bytes.windows(END.len())
+            .position(|window| window == END)
+            .ok_or(invalid("missing end_header"))?;
+        let body_start = bytes[end..].iter()
+            .position(|&b| b == b'\n')
+            .map_or(bytes.len(), |newline| end + newline + 1);
+        let text = str::from_utf8(&bytes[..end]).map_err(|_| invalid("not valid UTF-8"))?;
+
+        let mut format = None;
+        let mut elements = Vec::<Element>::new();
+        for line in text.lines().skip(1) {
+            let tokens = line.split_ascii_whitespace().collect::<Vec<_>>();
+            match tokens.as_slice() {
+                ["format", format_name, _version] => {
+                    format = Some(match *format_name {
+                        "ascii" => Format::Ascii,
+                        "binary_little_endian" => Format::BinaryLittleEndian,
+                        "binary_big_endian" => Format::BinaryBigEndian,
+                        _ => return Err(PlyError::InvalidHeader(format!("unknown format {format_name}"))),
+                    });
+                }
+                ["element", name, count] => elements.push(Element {
+                    name: name.to_string(),
+                    count: count.parse().map_err(|_| PlyError::InvalidHeader(format!("invalid count {count}")))?,
+                    properties: Vec::new(),
+                }),
+                ["property", "list", count, item, name] => {
+                    let element = elements.last_mut().ok_or(invalid("property before element"))?;
+                    let scalar = |name: &str| {
+                        ScalarType::parse(name).ok_or_else(|| PlyError::InvalidHeader(format!("unknown type {name}")))
+                    };
+                    element.properties.push(Property {
+                        name: name.to_string(),
+                        kind: PropertyKind::List { count: scalar(count)?, item: scalar(item)? },
+                    });
+                }
+                ["property", ty, name] => {
+                    let element = elements.last_mut().ok_or(invalid("property before element"))?;
+                    let ty = ScalarType::parse(ty).ok_or_else(|| PlyError::InvalidHeader(format!("unknown type {ty}")))?;
+                    element.properties.push(Property { name: name.to_string(), kind: PropertyKind::Scalar(ty) });
+                }
+                ["comment" | "obj_info", ..] | [] => {}
+                _ => return Err(PlyError::InvalidHeader(format!("invalid line {line}"))),
+            }
+        }
+        let format = format.ok_or(invalid("missing format"))?;
+        // such elements take no bytes, so a huge count would be read without end
+        if let Some(element) = elements.iter().find(|element| element.count > 0 && element.properties.is_empty()) {
+            return Err(PlyError::InvalidHeader(format!("element {} without properties", element.name)));
+        }
+        Ok((Self { format, elements }, &bytes[body_start..]))
+    }
+}
+
+/// Indices of the properties of a vertex element used for the `Vertex` fields.
+struct VertexLayout {
+    pos: [usize; 3],
+    normal: Option<[usize; 3]>,
+    tex_coords: Option<[usize; 2]>,
+    /// Indices and types of the red, green and blue channel.
+    color: Option<[(usize, ScalarType); 3]>,
+}
+
+impl VertexLayout {
+    fn new(element: &Element) -> Result<Self, PlyError> {
+        let find = |name: &str| element.properties.iter().position(|property| property.name == name);
+        let find_all = |names: [&str; 3]| -> Option<[usize; 3]> {
+            Some([find(names[0])?, find(names[1])?, find(names[2])?])
+        };
+        let pos = find_all(["x", "y", "z"])
+            .ok_or(PlyError::InvalidHeader("vertex without x, y and z".to_owned()))?;
+        let tex_coords = [["u", "v"], ["s", "t"], ["texture_u", "texture_v"]]
+            .into_iter()
+            .find_map(|[u, v]| Some([find(u)?, find(v)?]));
+        let color = find_all(["red", "green", "blue"]).map(|indices| {
+            indices.map(|i| match element.properties[i].kind {
+                PropertyKind::Scalar(ty) => (i, ty),
+                // lists are read as 0, so the channel is black
+                PropertyKind::List { .. } => (i, ScalarType::F32),
+            })
+        });
+        Ok(Self { pos, normal: find_all(["nx", "ny", "nz"]), tex_coords, color })
+    }
+
+    fn vertex(&self, row: &[f32]) -> Vertex {
+        let mut vertex = Vertex { pos_coords: self.pos.map(|i| row[i]), ..Default::default() };
+        if let Some(normal) = self.normal {
+            vertex.normal = normal.map(|i| row[i]);
+        }
+        if let Some(tex_coords) = self.tex_coords {
+            vertex.tex_coords = tex_coords.map(|i| row[i]);
+        }
+        if let Some(color) = self.color {
+            vertex.color = color.map(|(i, ty)| srgb_to_linear(ty.normalize(row[i])));
+        }
+        vertex
+    }
+}
+
+/// Vertex colors are stored in sRGB like images.
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+/// Reads the values of the body one after the other.
+enum Values<'a> {
+    Ascii(str::SplitAsciiWhitespace<'a>),
+    Binary { bytes: &'a [u8], big_endian: bool },
+}
+
+impl Values<'_> {
+    fn next(&mut self, ty: ScalarType) -> Result<f32, PlyError> {
+        match self {
+            Self::Ascii(tokens) => {
+                let token = tokens.next().ok_or(PlyError::UnexpectedEof)?;
+                token.parse().map_err(|_| PlyError::InvalidValue(format!("invalid number {token}")))
+            }
+            Self::Binary { .. } => {
+                let buf = self.next_bytes(ty)?;
+                Ok(match ty {
+                    ScalarType::F32 => f32::from_le_bytes(buf[..4].try_into().unwrap()),
+                    ScalarType::F64 => f64::from_le_bytes(buf) as f32,
+                    _ => Self::int_from_bytes(ty, buf) as f32,
+                })
+            }
+        }
+    }
+
+    /// Reads a count or an index, which must be a non-negative integer. Unlike `next`
+    /// integers are not read through `f32`, which is only exact up to 2^24.
+    fn next_int(&mut self, ty: ScalarType) -> Result<u32, PlyError> {
+        let value = match self {
+            Self::Ascii(tokens) => {
+                let token = tokens.next().ok_or(PlyError::UnexpectedEof)?;
+                token.parse().map_err(|_| PlyError::InvalidValue(format!("invalid integer {token}")))?
+            }
+            Self::Binary { .. } => {
+                if matches!(ty, ScalarType::F32 | ScalarType::F64) {
+                    return Err(PlyError::InvalidValue(format!("{ty:?} count or index")));
+                }
+                let buf = self.next_bytes(ty)?;
+                Self::int_from_bytes(ty, buf)
+            }
+        };
+        u32::try_from(value).map_err(|_| PlyError::InvalidValue(format!("invalid integer {value}")))
+    }
+
+    /// Returns the bytes of the next binary value of type `ty` in little endian order.
+    fn next_bytes(&mut self, ty: ScalarType) -> Result<[u8; 8], PlyError> {
+        let Self::Binary { bytes, big_endian } = self else {
+            unreachable!("ascii values are not read as bytes");
+        };
+        let size = ty.size();
+        if bytes.len() < size {
+            return Err(PlyError::UnexpectedEof);
+        }
+        let (value, rest) = bytes.split_at(size);
+        *bytes = rest;
+        let mut buf = [0; 8];
+        buf[..size].copy_from_slice(value);
+        if *big_endian {
+            buf[..size].reverse();
+        }
+        Ok(buf)
+    }
+
+    /// Returns the value of an integer type from its little endian bytes.
+    fn int_from_bytes(ty: ScalarType, buf: [u8; 8]) -> i64 {
+        match ty {
+            ScalarType::I8 => buf[0] as i8 as i64,
+            ScalarType::U8 => buf[0] as i64,
+            ScalarType::I16 => i16::from_le_bytes([buf[0], buf[1]]) as i64,
+            ScalarType::U16 => u16::from_le_bytes([buf[0], buf[1]]) as i64,
+            ScalarType::I32 => i32::from_le_bytes(buf[..4].try_into().unwrap()) as i64,
+            ScalarType::U32 => u32::from_le_bytes(buf[..4].try_into().unwrap()) as i64,
+            ScalarType::F32 | ScalarType::F64 => unreachable!("{ty:?} is not an integer type"),
+        }
+    }
+
+    fn skip_property(&mut self, property: &Property) -> Result<(), PlyError> {
+        match property.kind {
+            PropertyKind::Scalar(ty) => {
+                self.next(ty)?;
+            }
+            PropertyKind::List { count, item } => {
+                for _ in 0..self.next_int(count)? {
+                    self.next(item)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum PlyError {
+    InvalidHeader(String),
+    InvalidValue(String),
+    /// A face refers to a vertex that does not exist.
+    InvalidIndex(u32),
+    Io(io::Error),
+    UnexpectedEof,
+}
+
+impl fmt::Display for PlyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidHeader(msg) => write!(f, "Invalid PLY header: {msg}"),
+            Self::InvalidValue(msg) => write!(f, "Invalid PLY value: {msg}"),
+            Self::InvalidIndex(index) => write!(f, "Invalid vertex index {index}"),
+            Self::Io(err) => write!(f, "IO error: {err}"),
+            Self::UnexpectedEof => write!(f, "Unexpected end of file"),
+        }
+    }
+}
+
+impl Error for PlyError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for PlyError {
+    fn from(source: io::Error) -> Self {
+        Self::Io(source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn parse_ascii_quad() {
+        let file = "ply
+format ascii 1.0
+comment colors before positions to check the order is taken from the header
+element vertex 4
+property uchar red
+property uchar green
+property uchar blue
+property float x
+property float y
+property float z
+element face 1
+property list uchar int vertex_indices
+end_header
+255 0 0 0 0 0
+0 255 0 1 0 0
+0 0 255 1 1 0
+255 255 255 0 1 0
+4 0 1 2 3
+";
+        let nobj = NormalizedObj::from_ply(Cursor::new(file.as_bytes())).expect("failed to parse");
+        assert_eq!(nobj.indices, [0, 1, 2, 0, 2, 3]);
+        assert_eq!(nobj.vertices[2].pos_coords, [1., 1., 0.]);
+        assert_eq!(nobj.vertices[0].color, [1., 0., 0.]);
+        assert_eq!(nobj.vertices[2].color, [0., 0., 1.]);
+        assert!(!nobj.has_normals && !nobj.has_tex_coords);
+        assert!(nobj.has_colors);
+    }
+
+    #[test]
+    fn parse_binary_little_endian() {
+        let mut file = b"ply
+format binary_little_endian 1.0
+element vertex 3
+property float x
+property float y
+property float z
+property float nx
+property float ny
+property float nz
+element material 1
+property list uchar uchar name
+element face 1
+property uchar flags
+property list uchar uint vertex_indices
+end_header
+".to_vec();
+        for vertex in [[0., 0., 0.], [1., 0., 0.], [0., 1., 0.]] {
+            for coord in vertex.into_iter().chain([0., 0., 1.]) {
+                file.extend((coord as f32).to_le_bytes());
+            }
+        }
+        file.extend([2, b'a', b'b']);
+        file.extend([7, 3]);
+        for index in [0u32, 1, 2] {
+            file.extend(index.to_le_bytes());
+        }
+        let nobj = NormalizedObj::from_ply(Cursor::new(file)).expect("failed to parse");
+        assert_eq!(nobj.indices, [0, 1, 2]);
+        assert_eq!(nobj.vertices[1].pos_coords, [1., 0., 0.]);
+        assert_eq!(nobj.vertices[1].normal, [0., 0., 1.]);
+        assert_eq!(nobj.vertices[1].color, [1.; 3]);
+        assert!(nobj.has_normals && !nobj.has_colors);
+    }
+
+    #[test]
+    fn parse_large_binary_index() {
+        // 2^24 + 1 is the first integer f32 can not represent
+        let index = (1u32 << 24) + 1;
+        let mut file = b"ply
+format binary_big_endian 1.0
+element vertex 0
+property float x
+property float y
+property float z
+element face 1
+property list uchar uint vertex_indices
+end_header
+".to_vec();
+        file.push(3);
+        for index in [index, 0, 1] {
+            file.extend(index.to_be_bytes());
+        }
+        // the header claims more vertices than the body can hold, which must not be reserved
+        let huge = String::from_utf8(file.clone()).unwrap().replace("vertex 0", "vertex 4000000000");
+        assert!(NormalizedObj::from_ply(Cursor::new(huge)).is_err());
+        assert!(matches!(
+            NormalizedObj::from_ply(Cursor::new(file)),
+            Err(PlyError::InvalidIndex(i)) if i == index,
+        ));
+    }
+
+    #[test]
+    fn parse_point_cloud() {
+        let file = "ply
+format ascii 1.0
+element vertex 2
+property float x
+property float y
+property float z
+end_header
+0 0 0
+1 2 3
+";
+        let nobj = NormalizedObj::from_ply(Cursor::new(file.as_bytes())).expect("failed to parse");
+        assert!(nobj.is_point_cloud());
+        assert_eq!(nobj.vertices[1].pos_coords, [1., 2., 3.]);
+    }
+
+    #[test]
+    fn errors() {
+        let header = "ply\nformat ascii 1.0\nelement vertex 1\nproperty float x\nproperty float y\nproperty float z\n";
+        let parse = |file: String| NormalizedObj::from_ply(Cursor::new(file.into_bytes())).unwrap_err();
+        assert!(matches!(parse(format!("{header}end_header\n0 0")), PlyError::UnexpectedEof));
+        assert!(matches!(parse(format!("{header}end_header\n0 0 x")), PlyError::InvalidValue(_)));
+        let face = "element face 1\nproperty list uchar int vertex_indices\nend_header\n0 0 0\n3 0 0 1\n";
+        assert!(matches!(parse(format!("{header}{face}")), PlyError::InvalidIndex(1)));
+        assert!(matches!(parse("ply\nformat ascii 1.0\n".to_owned()), PlyError::InvalidHeader(_)));
+        for element in ["vertex", "extra"] {
+            let file = format!("ply\nformat ascii 1.0\nelement {element} 1000000000000\nend_header\n");
+            assert!(matches!(parse(file), PlyError::InvalidHeader(msg) if msg.contains(element)));
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use image::RgbaImage;
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, SyncSender};
+use std::thread::{self, JoinHandle};
+
+/// Number of frames waiting to be saved before `FrameRecorder::record` blocks.
+const QUEUE_SIZE: usize = 8;
+const FILE_PREFIX: &str = "frame_";
+
+/// Saves frames as numbered png files, e.g. `frame_00001.png`, on a worker thread
+/// so rendering does not wait for the disk.
+///
+/// The numbers keep counting up across recordings and continue after frames already
+/// in the directory, so no frame is overwritten. Assemble them with e.g.
+/// `ffmpeg -framerate 60 -i frame_%05d.png video.mp4`.
+pub struct FrameRecorder {
+    dir: PathBuf,
+    next_frame: u32,
+    sender: Option<SyncSender<(PathBuf, RgbaImage)>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl FrameRecorder {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self {
+            dir: dir.into(),
+            next_frame: 1,
+            sender: None,
+            worker: None,
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.sender.is_some()
+    }
+
+    /// Creates the directory if needed and starts the worker thread.
+    pub fn start(&mut self) -> io::Result<()> {
+        if self.is_recording() {
+            return Ok(());
+        }
+        fs::create_dir_all(&self.dir)?;
+        self.next_frame = self.next_frame.max(self.last_frame_in_dir()? + 1);
+
+        let (sender, receiver) = mpsc::sync_channel::<(PathBuf, RgbaImage)>(QUEUE_SIZE);
+        self.worker = Some(thread::spawn(move || {
+            for (path, image) in receiver {
+                if let Err(err) = image.save(&path) {
+                    log::warn!("Failed to save frame {}: {err}", path.display());
+                }
+            }
+        }));
+        self.sender = Some(sender);
+        Ok(())
+    }
+
+    /// Queues `image` to be saved as the next frame. Blocks if the worker is too far behind.
+    pub fn record(&mut self, image: RgbaImage) {
+        let Some(sender) = &self.sender else { return };
+        let path = self.dir.join(format!("{FILE_PREFIX}{:05}.png", self.next_frame));
+        self.next_frame += 1;
+        if sender.send((path, image)).is_err() {
+            log::warn!("Frame recorder stopped unexpectedly");
+            self.stop();
+        }
+    }
+
+    /// Waits until all queued frames are saved and stops the worker thread.
+    pub fn stop(&mut self) {
+        self.sender = None;
+        if let Some(worker) = self.worker.take() {
+            if worker.join().is_err() {
+                log::warn!("Frame recorder thread panicked");
+            }
+        }
+    }
+
+    /// Returns the highest number of the frames in the directory or 0 if there are none.
+    fn last_frame_in_dir(&self) -> io::Result<u32> {
+        let mut last = 0;
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != "png") {
+                continue;
+            }
+            let number = path.file_stem()
+                .and_then(|stem| stem.to_str()?.strip_prefix(FILE_PREFIX)?.parse().ok());
+            if let Some(number) = number {
+                last = last.max(number);
+            }
+        }
+        Ok(last)
+    }
+}
+
+impl Drop for FrameRecorder {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn continue_after_existing_frames() {
+        let dir = std::env::temp_dir().join(format!("scop-recorder-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        // only png files with the prefix count
+        for name in ["frame_00003.png", "frame_00012.png", "frame_00099.jpg", "other_00050.png"] {
+            fs::write(dir.join(name), b"").unwrap();
+        }
+
+        let mut recorder = FrameRecorder::new(&dir);
+        assert_eq!(recorder.last_frame_in_dir().unwrap(), 12);
+        recorder.start().unwrap();
+        recorder.record(RgbaImage::new(1, 1));
+        recorder.stop();
+        assert!(image::open(dir.join("frame_00013.png")).is_ok());
+
+        // a second recording continues after the frames of the first one
+        recorder.start().unwrap();
+        recorder.record(RgbaImage::new(1, 1));
+        recorder.stop();
+        assert!(image::open(dir.join("frame_00014.png")).is_ok());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,170 @@
+use crate::camera::OrbitCamera;
+use crate::math::{Deg, Matrix4, Vector3};
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// What was displayed when the viewer was closed, to continue from there on the next start.
+///
+/// It is stored as a TOML file with the matrices as lists of 16 numbers in column-major order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Session {
+    pub model_path: PathBuf,
+    pub image_path: PathBuf,
+    pub model_matrix: Matrix4,
+    pub view_matrix: Matrix4,
+    pub orbit_camera: OrbitCamera,
+    pub free_fly: bool,
+    pub rotate: bool,
+}
+
+impl Session {
+    /// Loads a session, returns `None` if the file does not exist.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Option<Self>, SessionError> {
+        match fs::read_to_string(path) {
+            Ok(text) => Self::from_toml(&text).map(Some),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(SessionError::Io(err)),
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_toml())
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self, SessionError> {
+        let table = text.parse::<toml::Table>().map_err(SessionError::Toml)?;
+        let get = |key: &'static str| table.get(key).ok_or(SessionError::Missing(key));
+        let path = |key| get(key)?.as_str().map(PathBuf::from).ok_or(SessionError::Invalid(key));
+        let bool = |key| get(key)?.as_bool().ok_or(SessionError::Invalid(key));
+        let float = |key| get(key)?.as_float().map(|f| f as f32).ok_or(SessionError::Invalid(key));
+        let floats = |key, len| {
+            let floats = get(key)?.as_array()
+                .ok_or(SessionError::Invalid(key))?
+                .iter()
+                .map(|value| value.as_float().map(|f| f as f32))
+                .collect::<Option<Vec<_>>>()
+                .filter(|floats| floats.len() == len);
+            floats.ok_or(SessionError::Invalid(key))
+        };
+        let matrix = |key| {
+            let floats = floats(key, 16)?;
+            Ok::<_, SessionError>(Matrix4::from(std::array::from_fn::<_, 4, _>(|col| {
+                std::array::from_fn(|row| floats[col * 4 + row])
+            })))
+        };
+
+        let target = floats("camera_target", 3)?;
+        Ok(Self {
+            model_path: path("model_path")?,
+            image_path: path("image_path")?,
+            model_matrix: matrix("model_matrix")?,
+            view_matrix: matrix("view_matrix")?,
+            orbit_camera: OrbitCamera {
+                target: Vector3::from([target[0], target[1], target[2]]),
+                distance: float("camera_distance")?,
+                yaw: Deg(float("camera_yaw")?),
+                pitch: Deg(float("camera_pitch")?),
+            },
+            free_fly: bool("free_fly")?,
+            rotate: bool("rotate")?,
+        })
+    }
+
+    pub fn to_toml(&self) -> String {
+        let floats = |floats: &[f32]| {
+            toml::Value::Array(floats.iter().map(|&f| toml::Value::Float(f as f64)).collect())
+        };
+        let matrix = |matrix: Matrix4| floats(<[[f32; 4]; 4]>::from(matrix).as_flattened());
+        let path = |path: &Path| toml::Value::String(path.to_string_lossy().into_owned());
+
+        let mut table = toml::Table::new();
+        table.insert("model_path".into(), path(&self.model_path));
+        table.insert("image_path".into(), path(&self.image_path));
+        table.insert("model_matrix".into(), matrix(self.model_matrix));
+        table.insert("view_matrix".into(), matrix(self.view_matrix));
+        table.insert("camera_target".into(), floats(&<[f32; 3]>::from(self.orbit_camera.target)));
+        table.insert("camera_distance".into(), toml::Value::Float(self.orbit_camera.distance as f64));
+        table.insert("camera_yaw".into(), toml::Value::Float(self.orbit_camera.yaw.0 as f64));
+        table.insert("camera_pitch".into(), toml::Value::Float(self.orbit_camera.pitch.0 as f64));
+        table.insert("free_fly".into(), toml::Value::Boolean(self.free_fly));
+        table.insert("rotate".into(), toml::Value::Boolean(self.rotate));
+        table.to_string()
+    }
+}
+
+/// Error while loading a session.
+#[derive(Debug)]
+pub enum SessionError {
+    Io(io::Error),
+    Toml(toml::de::Error),
+    Missing(&'static str),
+    /// The value of a key has the wrong type or length.
+    Invalid(&'static str),
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "IO error: {err}"),
+            Self::Toml(err) => write!(f, "Invalid TOML: {err}"),
+            Self::Missing(key) => write!(f, "Missing {key}"),
+            Self::Invalid(key) => write!(f, "Invalid value of {key}"),
+        }
+    }
+}
+
+impl Error for SessionError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            Self::Toml(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session() -> Session {
+        Session {
+            model_path: PathBuf::from("assets/models/teapot.obj"),
+            image_path: PathBuf::from("assets/images/cat.png"),
+            model_matrix: Matrix4::from_angle_y(Deg(30.)),
+            view_matrix: Matrix4::from_translation(Vector3::from([0., -1., -4.])),
+            orbit_camera: OrbitCamera {
+                target: Vector3::from([0.5, 0., 0.]),
+                distance: 2.5,
+                yaw: Deg(45.),
+                pitch: Deg(-10.),
+            },
+            free_fly: true,
+            rotate: false,
+        }
+    }
+
+    #[test]
+    fn roundtrip() {
+        let session = session();
+        assert_eq!(Session::from_toml(&session.to_toml()).unwrap(), session);
+    }
+
+    #[test]
+    fn invalid() {
+        let text = session().to_toml();
+        let without_rotate = text.lines()
+            .filter(|line| !line.starts_with("rotate"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        assert!(matches!(Session::from_toml(&without_rotate), Err(SessionError::Missing("rotate"))));
+
+        let short_matrix = text.replace("model_matrix = [", "model_matrix = [1.0, ");
+        assert!(matches!(Session::from_toml(&short_matrix), Err(SessionError::Invalid("model_matrix"))));
+        assert!(matches!(Session::from_toml("rotate ="), Err(SessionError::Toml(_))));
+    }
+}
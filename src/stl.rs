@@ -0,0 +1,229 @@
+use crate::math::Vector3;
+use crate::obj::{NormalizedObj, Vertex};
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read};
+use std::str;
+
+const BINARY_HEADER_LEN: usize = 80;
+const BINARY_TRIANGLE_LEN: usize = 50;
+
+impl NormalizedObj {
+    /// Loads an ASCII or binary STL file. Vertices are not shared between
+    /// triangles so each one gets the normal of its facet.
+    pub fn from_stl<R: Read>(mut reader: R) -> Result<Self, StlError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let triangles = if is_ascii(&bytes) {
+            parse_ascii(&bytes)?
+        } else {
+            parse_binary(&bytes)?
+        };
+
+        let mut nobj = Self {
+            has_normals: true,
+            ..Default::default()
+        };
+        for (normal, corners) in triangles {
+            let normal = if Vector3::from(normal).magnitude() == 0. {
+                // some exporters leave the facet normal zero, compute it from the corners
+                let [a, b, c] = corners.map(Vector3::from);
+                let normal = (b - a).cross(c - a);
+                if normal.magnitude() == 0. { normal.into() } else { normal.normalize().into() }
+            } else {
+                normal
+            };
+            for pos_coords in corners {
+                nobj.indices.push(nobj.vertices.len() as u32);
+                nobj.vertices.push(Vertex { pos_coords, normal, ..Default::default() });
+            }
+        }
+        Ok(nobj)
+    }
+}
+
+type Triangle = ([f32; 3], [[f32; 3]; 3]);
+
+/// Binary files may also start with `solid`, so the first token
+/// after the header line must start a facet or end the solid.
+fn is_ascii(bytes: &[u8]) -> bool {
+    if !bytes.starts_with(b"solid") {
+        return false;
+    }
+    let Some(line_end) = bytes.iter().position(|&b| b == b'\n') else {
+        return false;
+    };
+    let rest = &bytes[line_end + 1..];
+    let rest = &rest[..rest.len().min(256)];
+    let token = rest.split(|b| b.is_ascii_whitespace()).find(|part| !part.is_empty());
+    matches!(token, Some(b"facet" | b"endsolid"))
+}
+
+fn parse_ascii(bytes: &[u8]) -> Result<Vec<Triangle>, StlError> {
+    let text = str::from_utf8(bytes).map_err(|_| StlError::InvalidAscii("not valid UTF-8".to_owned()))?;
+    let parse_vec = |tokens: &mut str::SplitAsciiWhitespace| -> Result<[f32; 3], StlError> {
+        let mut vec = [0.; 3];
+        for coord in vec.iter_mut() {
+            let token = tokens.next().ok_or(StlError::UnexpectedEof)?;
+            *coord = token.parse().map_err(|_| StlError::InvalidAscii(format!("invalid number {token}")))?;
+        }
+        Ok(vec)
+    };
+
+    let mut triangles = Vec::new();
+    let mut normal = [0.; 3];
+    let mut corners = Vec::with_capacity(3);
+    // every keyword starts a line, so the optional name after solid and endsolid is skipped with it
+    for line in text.lines() {
+        let mut tokens = line.split_ascii_whitespace();
+        match tokens.next() {
+            Some("facet") => {
+                if tokens.next() != Some("normal") {
+                    return Err(StlError::InvalidAscii("expected normal after facet".to_owned()));
+                }
+                normal = parse_vec(&mut tokens)?;
+                corners.clear();
+            }
+            Some("vertex") => corners.push(parse_vec(&mut tokens)?),
+            Some("endfacet") => {
+                let Ok(corners) = <[[f32; 3]; 3]>::try_from(corners.as_slice()) else {
+                    return Err(StlError::InvalidAscii(format!(
+                        "facet with {} vertices, expected 3",
+                        corners.len(),
+                    )));
+                };
+                triangles.push((normal, corners));
+            }
+            // solid, endsolid, outer loop and endloop carry no data
+            _ => {}
+        }
+    }
+    Ok(triangles)
+}
+
+fn parse_binary(bytes: &[u8]) -> Result<Vec<Triangle>, StlError> {
+    let count_bytes = bytes.get(BINARY_HEADER_LEN..BINARY_HEADER_LEN + 4).ok_or(StlError::UnexpectedEof)?;
+    let count = u32::from_le_bytes(count_bytes.try_into().unwrap()) as usize;
+    let data = &bytes[BINARY_HEADER_LEN + 4..];
+    if data.len() < count * BINARY_TRIANGLE_LEN {
+        return Err(StlError::UnexpectedEof);
+    }
+
+    let read_vec = |bytes: &[u8]| -> [f32; 3] {
+        [0, 1, 2].map(|i| f32::from_le_bytes(bytes[i * 4..i * 4 + 4].try_into().unwrap()))
+    };
+    // each triangle is a normal, three corners and a two byte attribute count
+    Ok(data.chunks_exact(BINARY_TRIANGLE_LEN).take(count).map(|triangle| {
+        (
+            read_vec(&triangle[0..12]),
+            [read_vec(&triangle[12..24]), read_vec(&triangle[24..36]), read_vec(&triangle[36..48])],
+        )
+    }).collect())
+}
+
+#[derive(Debug)]
+pub enum StlError {
+    InvalidAscii(String),
+    Io(io::Error),
+    UnexpectedEof,
+}
+
+impl fmt::Display for StlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidAscii(msg) => write!(f, "Invalid ASCII STL: {msg}"),
+            Self::Io(err) => write!(f, "IO error: {err}"),
+            Self::UnexpectedEof => write!(f, "Unexpected end of file"),
+        }
+    }
+}
+
+impl Error for StlError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for StlError {
+    fn from(source: io::Error) -> Self {
+        Self::Io(source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn parse_ascii_triangle() {
+        let file = r#"solid test
+  facet normal 0 0 1
+    outer loop
+      vertex 0 0 0
+      vertex 1 0 0
+      vertex 0 1 0
+    endloop
+  endfacet
+  facet normal 0 0 0
+    outer loop
+      vertex 0 0 0
+      vertex 0 1 0
+      vertex 1 0 0
+    endloop
+  endfacet
+endsolid test
+"#;
+        let nobj = NormalizedObj::from_stl(Cursor::new(file.as_bytes())).expect("failed to parse");
+        assert_eq!(nobj.indices, [0, 1, 2, 3, 4, 5]);
+        assert_eq!(nobj.vertices[1].pos_coords, [1., 0., 0.]);
+        assert_eq!(nobj.vertices[0].normal, [0., 0., 1.]);
+        // computed from the corners for zero facet normals
+        assert_eq!(nobj.vertices[3].normal, [0., 0., -1.]);
+    }
+
+    #[test]
+    fn parse_ascii_unnamed_solid() {
+        let file = r#"solid
+  facet normal 0 0 1
+    outer loop
+      vertex 0 0 0
+      vertex 1 0 0
+      vertex 0 1 0
+    endloop
+  endfacet
+endsolid
+"#;
+        let nobj = NormalizedObj::from_stl(Cursor::new(file.as_bytes())).expect("failed to parse");
+        assert_eq!(nobj.indices, [0, 1, 2]);
+        assert_eq!(nobj.vertices[0].normal, [0., 0., 1.]);
+    }
+
+    #[test]
+    fn parse_binary_starting_with_solid() {
+        let mut file = b"solid but actually binary".to_vec();
+        file.resize(BINARY_HEADER_LEN, 0);
+        file.extend(1u32.to_le_bytes());
+        for coord in [0., 0., 1., 0., 0., 0., 1., 0., 0., 0., 1., 0.] {
+            file.extend((coord as f32).to_le_bytes());
+        }
+        file.extend([0, 0]);
+        let nobj = NormalizedObj::from_stl(Cursor::new(file)).expect("failed to parse");
+        assert_eq!(nobj.indices, [0, 1, 2]);
+        assert_eq!(nobj.vertices[2].pos_coords, [0., 1., 0.]);
+        assert_eq!(nobj.vertices[2].normal, [0., 0., 1.]);
+    }
+
+    #[test]
+    fn binary_truncated() {
+        let mut file = vec![0; BINARY_HEADER_LEN];
+        file.extend(2u32.to_le_bytes());
+        file.extend([0; BINARY_TRIANGLE_LEN]);
+        let err = NormalizedObj::from_stl(Cursor::new(file)).unwrap_err();
+        assert!(matches!(err, StlError::UnexpectedEof));
+    }
+}
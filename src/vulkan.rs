@@ -1,12 +1,19 @@
 mod app;
 mod buffer;
 mod cmd;
+mod config;
 mod context;
 mod debug;
+mod fxaa;
+mod load_queue;
 mod pipeline;
 mod structs;
 mod swapchain;
 mod texture;
+#[cfg(feature = "ui")]
+mod ui;
 
-pub use app::VkApp;
-pub use structs::ShaderSpv;
+pub use app::{DrawError, ModelId, PickResult, ProjectionMode, RotationAxis, Shading, TextureFilter, TextureSource, UvMode, VkApp};
+pub use config::VkAppConfig;
+pub use load_queue::{LoadStatus, LoadTicket, ModelLoadQueue};
+pub use structs::{CubemapPaths, ShaderSpv};
@@ -1,14 +1,20 @@
 use crate::fs;
-use crate::math::{self, Deg, Matrix4, Vector3};
-use crate::obj::NormalizedObj;
-use super::buffer;
-use super::cmd;
+use crate::math::{self, Aabb, Deg, Frustum, Matrix4, Ray, Vector3};
+use crate::mtl::{Material, Mtl};
+use crate::obj::{ModelStats, NormalizedObj};
+use super::buffer::{self, StagingBuffer};
+use super::cmd::{self, PendingCommands};
+use super::config::VkAppConfig;
 use super::context::VkContext;
 use super::debug::*;
-use super::pipeline::{Geometry, Pipeline};
-use super::structs::{ShaderSpv, UniformBufferObject, Vertex};
+use super::fxaa::FxaaPass;
+use super::load_queue::{LoadTicket, ModelLoadQueue};
+use super::pipeline::{self, Geometry, Pipeline, PipelineState};
+use super::structs::{CubemapPaths, PushConstants, ShaderSpv, UniformBufferObject, Vertex};
 use super::swapchain::{SwapchainProperties, SwapchainSupportDetails};
-use super::texture::Texture;
+use super::texture::{self, Texture, TexturePixels};
+#[cfg(feature = "ui")]
+use super::ui::UiRenderer;
 
 use anyhow::Context;
 use ash::{
@@ -16,16 +22,210 @@ use ash::{
     khr::{surface, swapchain as khr_swapchain},
     vk, Device, Entry, Instance,
 };
-use image::ImageReader;
+use gpu_allocator::{vulkan::Allocation, MemoryLocation};
+use image::{DynamicImage, ImageReader};
 use raw_window_handle::{HasDisplayHandle, HasWindowHandle};
 use std::{
-    ffi::CString,
-    mem::{align_of, size_of},
-    path::Path,
+    collections::{HashMap, VecDeque},
+    error::Error,
+    ffi::{CStr, CString},
+    fmt,
+    io,
+    mem::size_of,
+    path::{Path, PathBuf},
+    thread::{self, JoinHandle},
 };
 use winit::window::Window;
 
 const MAX_FRAMES_IN_FLIGHT: u32 = 2;
+const FOV_Y: Deg<f32> = Deg(75.);
+/// Extra space around the bounding sphere of the model when framing it.
+const FRAME_MARGIN: f32 = 1.1;
+/// Largest near plane of the automatic clip planes, see `clip_planes`.
+const NEAR: f32 = 0.1;
+/// Smallest far plane of the automatic clip planes, which keeps the grid and the skybox visible.
+const FAR: f32 = 20.;
+/// Smallest ratio of near to far plane, closer near planes waste the depth precision.
+const MIN_NEAR_RATIO: f32 = 1e-4;
+/// Extra space around the bounding sphere of the model for the automatic clip planes.
+const CLIP_MARGIN: f32 = 1.1;
+/// Format of the offscreen image of headless apps, the same as the preferred
+/// swapchain format so both produce the same output.
+const OFFSCREEN_FORMAT: vk::Format = vk::Format::B8G8R8A8_SRGB;
+/// Format of textures with colors, which are converted to linear values when sampled.
+const COLOR_TEXTURE_FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
+/// Format of textures with other data like normals, which are used as they are.
+const DATA_TEXTURE_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+/// Format of textures with more than 8 bits per channel. There is no 16 bit sRGB
+/// format, so colors are converted to linear values before they are uploaded.
+const WIDE_TEXTURE_FORMAT: vk::Format = vk::Format::R16G16B16A16_UNORM;
+/// Format of cubemaps loaded from HDR images, which supports linear filtering
+/// and blitting for mipmaps on all devices unlike 32 bit floats.
+const HDR_CUBEMAP_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+/// Half the side length of the ground grid.
+const GRID_EXTENT: f32 = 5.;
+const GRID_SPACING: f32 = 0.5;
+/// Length of the displayed normals relative to the diagonal of the bounding box.
+const NORMAL_LENGTH: f32 = 0.03;
+/// Side length in pixels and number of squares per side of the checkerboard texture.
+const CHECKERBOARD_SIZE: u32 = 512;
+const CHECKERBOARD_CELLS: u32 = 16;
+/// Speed of `VkApp::auto_rotate` in degrees per second until it is changed.
+const DEFAULT_ROTATION_SPEED: f32 = 90.;
+
+/// Projection used to render the scene.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProjectionMode {
+    #[default]
+    Perspective,
+    /// Parallel projection with bounds that fit the model, independent of the camera distance.
+    Orthographic,
+}
+
+/// Source of the texture coordinates of the model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UvMode {
+    /// Coordinates from the model file, spherical ones if it has none.
+    #[default]
+    File,
+    /// Projection along the x axis, mirrored for the halves of the model.
+    Planar,
+    /// Longitude and latitude around the centroid of the model.
+    Spherical,
+}
+
+impl UvMode {
+    /// Returns the next mode in the order file, planar, spherical.
+    pub fn next(self) -> Self {
+        match self {
+            Self::File => Self::Planar,
+            Self::Planar => Self::Spherical,
+            Self::Spherical => Self::File,
+        }
+    }
+}
+
+/// Normals of the model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Shading {
+    /// Normals from the model file or averaged over the adjacent triangles.
+    #[default]
+    Smooth,
+    /// Each triangle has its own normal, so the faces are visible.
+    Flat,
+}
+
+impl Shading {
+    /// Returns the other shading.
+    pub fn toggled(self) -> Self {
+        match self {
+            Self::Smooth => Self::Flat,
+            Self::Flat => Self::Smooth,
+        }
+    }
+}
+
+/// World axis the model turns around with `VkApp::auto_rotate`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RotationAxis {
+    X,
+    #[default]
+    Y,
+    Z,
+}
+
+impl RotationAxis {
+    /// Returns the next axis in the order x, y, z.
+    pub fn next(self) -> Self {
+        match self {
+            Self::X => Self::Y,
+            Self::Y => Self::Z,
+            Self::Z => Self::X,
+        }
+    }
+}
+
+/// Filter used to sample the textures of the model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TextureFilter {
+    /// Takes the nearest texel of the nearest mip level, so texels have sharp edges.
+    Nearest,
+    /// Interpolates between texels and mip levels.
+    #[default]
+    Linear,
+}
+
+impl TextureFilter {
+    /// Returns the other filter.
+    pub fn toggled(self) -> Self {
+        match self {
+            Self::Nearest => Self::Linear,
+            Self::Linear => Self::Nearest,
+        }
+    }
+}
+
+/// Where the color texture of the model comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureSource {
+    /// Loaded from `VkApp::texture_path`.
+    File,
+    /// Generated by `VkApp::load_checkerboard`.
+    Checkerboard,
+    /// Loaded from pixels or an image file in memory.
+    Memory,
+    /// The white texture used when no image could be loaded.
+    White,
+}
+
+/// Error of `VkApp::draw_frame` after which no more frames can be drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DrawError {
+    /// The GPU hung, was reset by the driver or removed. Everything created
+    /// with the device is unusable, the `VkApp` has to be created again.
+    DeviceLost,
+    /// Any other error while acquiring, submitting or presenting a frame.
+    Vulkan(vk::Result),
+}
+
+impl fmt::Display for DrawError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DeviceLost => write!(f, "The GPU device was lost"),
+            Self::Vulkan(err) => write!(f, "Vulkan error: {err}"),
+        }
+    }
+}
+
+impl Error for DrawError {}
+
+impl From<vk::Result> for DrawError {
+    fn from(result: vk::Result) -> Self {
+        match result {
+            vk::Result::ERROR_DEVICE_LOST => Self::DeviceLost,
+            result => Self::Vulkan(result),
+        }
+    }
+}
+
+/// Identifies a model added with `VkApp::add_model`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ModelId(u64);
+
+/// Triangle of the model under a point of the screen, see `VkApp::pick`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PickResult {
+    /// Model added with `VkApp::add_model` the triangle belongs to, `None` for the main model.
+    pub model: Option<ModelId>,
+    /// Index of the triangle in the index buffer, i.e. the first index divided by 3.
+    pub triangle: usize,
+    /// Indices of the vertices of the triangle.
+    pub vertices: [u32; 3],
+    /// Weights of the three vertices at the hit point.
+    pub barycentric: [f32; 3],
+    /// Hit point in the coordinates of the model file.
+    pub position: Vector3,
+}
 
 pub struct VkApp {
     pub dirty_swapchain: bool,
@@ -33,55 +233,141 @@ pub struct VkApp {
     pub view_matrix: Matrix4,
     pub model_matrix: Matrix4,
     pub texture_weight: f32,
+    /// How much of the skybox is reflected by the model, from 0 to 1.
+    pub reflectivity: f32,
+    /// Direction towards the directional light in world space.
+    pub light_dir: Vector3,
+    pub projection_mode: ProjectionMode,
+    /// Used for models loaded afterwards.
+    pub uv_mode: UvMode,
+    /// Used for models loaded afterwards.
+    pub shading: Shading,
+    /// Whether textures and cubemaps loaded afterwards get mipmaps. Without them
+    /// minified textures alias and formats without linear blitting can be loaded.
+    pub generate_mips: bool,
+    /// Interpolation factor between the model (0) and its morph target (1).
+    pub morph_weight: f32,
+    /// Apply changes with `set_cull_mode`.
     pub cull_mode: vk::CullModeFlags,
-    pub show_cubemap: bool,
+    /// Constant depth bias added to each fragment of the model, in units of the
+    /// smallest resolvable depth difference. Values around 1 to 4 are typical
+    /// to resolve z-fighting, too large values detach the model from surfaces
+    /// behind it (peter-panning). Apply changes with `set_depth_bias`.
+    pub depth_bias_constant: f32,
+    /// Depth bias scaled by the depth slope of each polygon of the model.
+    /// Values around 1 to 2 are typical.
+    pub depth_bias_slope: f32,
+    pub rotation_axis: RotationAxis,
+    /// In degrees per second, see `set_rotation_speed`.
+    rotation_speed: f32,
+    show_cubemap: bool,
+    show_uv_layout: bool,
+    show_grid: bool,
+    show_normals: bool,
+    show_bbox: bool,
+    show_depth: bool,
+    /// Near and far plane set with `set_clip_planes`, derived from the model if `None`.
+    clip_planes: Option<(f32, f32)>,
+    clear_color: [f32; 4],
+    letterbox: Option<f32>,
+    present_mode_preference: vk::PresentModeKHR,
+    polygon_mode: vk::PolygonMode,
+    transparent: bool,
+    depth_test: bool,
+    texture_filter: TextureFilter,
+    anisotropy: f32,
     initial_model_matrix: Matrix4,
-    model_extent: (Vector3, Vector3),
+    model_aabb: Aabb,
+    /// Radius of a sphere around the center of `model_aabb` containing the model, in model space.
+    model_radius: f32,
+    model_stats: ModelStats,
+    /// The model as displayed on the CPU for picking and `write_model`.
+    model_obj: NormalizedObj,
+    /// Last image file the texture was loaded from, empty if none was loaded.
+    texture_path: PathBuf,
+    texture_source: TextureSource,
+    /// Whether the next frame is copied to its readback buffer, see `request_capture`.
+    capture_requested: bool,
+    /// Buffer the frame of each frame in flight is copied to when a capture was requested.
+    readbacks: Vec<Option<Readback>>,
+    /// Captured frames that were not taken yet, oldest first.
+    captured_frames: VecDeque<image::RgbaImage>,
+    load_queue: ModelLoadQueue,
+    /// Models being parsed or uploaded, see `request_model_load`.
+    model_loads: Vec<ModelLoad>,
+    /// Textures being decoded or uploaded, see `request_texture_load`.
+    texture_loads: Vec<TextureLoad>,
+    next_texture_load_id: u64,
+    scene: Option<Scene>,
+    /// Replaced resources frames in flight may still use, see `release_retired`.
+    retired: Vec<Retired>,
+    /// Models displayed next to the main one, see `add_model`.
+    instances: Vec<ModelInstance>,
+    next_model_id: u64,
 
     vk_context: VkContext,
     graphics_queue: vk::Queue,
     present_queue: vk::Queue,
-    swapchain: khr_swapchain::Device,
-    swapchain_khr: vk::SwapchainKHR,
+    render_target: RenderTarget,
     swapchain_properties: SwapchainProperties,
     images: Vec<vk::Image>,
     swapchain_image_views: Vec<vk::ImageView>,
     render_pass: vk::RenderPass,
     descriptor_set_layout: vk::DescriptorSetLayout,
+    pipeline_cache: vk::PipelineCache,
     pipeline: Pipeline,
     pipeline_cubemap: Pipeline,
+    pipeline_uv: Pipeline,
+    pipeline_grid: Pipeline,
+    pipeline_normals: Pipeline,
+    pipeline_bbox: Pipeline,
+    /// Draws the vertices of models without faces as points instead of `pipeline`.
+    pipeline_points: Pipeline,
+    /// Smooths the scene after the main render pass, `Some` while FXAA is enabled.
+    fxaa: Option<FxaaPass>,
     swapchain_framebuffers: Vec<vk::Framebuffer>,
     command_pool: vk::CommandPool,
     transient_command_pool: vk::CommandPool,
+    /// Reused for all uploads of models and textures.
+    staging: StagingBuffer,
     msaa_samples: vk::SampleCountFlags,
+    /// Whether FXAA is used, takes effect when the pipelines are recreated.
+    fxaa_enabled: bool,
     color_texture: Texture,
     depth_format: vk::Format,
     depth_texture: Texture,
-    textures: [Texture; 2],
+    /// Texture of the model, the cubemap and the normal map.
+    textures: [Texture; 3],
+    /// Paths the cubemap was loaded from, to load it again with `reload_cubemap`.
+    cubemap_paths: CubemapPaths,
     uniform_buffers: Vec<vk::Buffer>,
-    uniform_buffer_memories: Vec<vk::DeviceMemory>,
+    uniform_buffer_memories: Vec<Allocation>,
     descriptor_pool: vk::DescriptorPool,
     descriptor_sets: Vec<vk::DescriptorSet>,
     command_buffers: Vec<vk::CommandBuffer>,
     in_flight_frames: InFlightFrames,
+    gpu_timer: GpuTimer,
+    #[cfg(feature = "ui")]
+    ui: UiRenderer,
     shader_spv: ShaderSpv,
     cubemap_spv: ShaderSpv,
+    uv_spv: ShaderSpv,
+    grid_spv: ShaderSpv,
+    normals_spv: ShaderSpv,
+    points_spv: ShaderSpv,
+    fxaa_spv: ShaderSpv,
+    /// Whether the render pass and pipelines must be recreated with the swapchain.
+    rebuild_pipelines: bool,
 }
 
 impl VkApp {
-    pub fn new<P: AsRef<Path>>(
-        window: &Window,
-        width: u32,
-        height: u32,
-        image_path: P,
-        nobj: NormalizedObj,
-        shader_spv: ShaderSpv,
-        cubemap_spv: ShaderSpv,
-    ) -> Result<Self, anyhow::Error> {
+    pub fn new(window: &Window, config: &VkAppConfig) -> Result<Self, anyhow::Error> {
         log::debug!("Creating application.");
+        let nobj = fs::load_model(&config.model_path)
+            .with_context(|| format!("Failed to load model {}", config.model_path.display()))?;
 
         let entry = unsafe { Entry::load().expect("Failed to create entry.") };
-        let instance = Self::create_instance(&entry, window);
+        let instance = Self::create_instance(&entry, Some(window));
 
         let surface = surface::Instance::new(&entry, &instance);
         let surface_khr = unsafe {
@@ -95,11 +381,49 @@ impl VkApp {
             .unwrap()
         };
 
+        Self::with_instance(entry, instance, Some((surface, surface_khr)), nobj, config)
+    }
+
+    /// Creates an application without a window that renders into an offscreen
+    /// image of `config.width` x `config.height`, e.g. to generate thumbnails.
+    ///
+    /// Frames are rendered with `render_to_image`, the present mode is ignored.
+    pub fn new_headless(config: &VkAppConfig) -> Result<Self, anyhow::Error> {
+        log::debug!("Creating headless application.");
+        if config.width == 0 || config.height == 0 {
+            anyhow::bail!("Invalid image size {}x{}", config.width, config.height);
+        }
+        let nobj = fs::load_model(&config.model_path)
+            .with_context(|| format!("Failed to load model {}", config.model_path.display()))?;
+
+        let entry = unsafe { Entry::load().expect("Failed to create entry.") };
+        let instance = Self::create_instance(&entry, None);
+        Self::with_instance(entry, instance, None, nobj, config)
+    }
+
+    /// Creates everything after the instance and the surface, which is only
+    /// given if there is a window.
+    fn with_instance(
+        entry: Entry,
+        instance: Instance,
+        surface: Option<(surface::Instance, vk::SurfaceKHR)>,
+        nobj: NormalizedObj,
+        config: &VkAppConfig,
+    ) -> Result<Self, anyhow::Error> {
+        let texture_path = config.image_path.clone();
+        let present_mode_preference = config.present_mode;
+        let (shader_spv, cubemap_spv, uv_spv) = (config.shader_spv, config.cubemap_spv, config.uv_spv);
+        let (grid_spv, normals_spv, points_spv) = (config.grid_spv, config.normals_spv, config.points_spv);
+        let fxaa_spv = config.fxaa_spv;
+
+        for (i, (name, device_type)) in VkContext::list_physical_devices(&instance).iter().enumerate() {
+            log::info!("GPU {i}: {name} ({device_type:?})");
+        }
         let vk_context = VkContext::new(
             entry,
             instance,
             surface,
-            surface_khr,
+            config.device_index,
         ).context("Failed to create vulkan context")?;
         let graphics_queue = unsafe {
             vk_context.device().get_device_queue(vk_context.graphics_queue_index(), 0)
@@ -108,21 +432,39 @@ impl VkApp {
             vk_context.device().get_device_queue(vk_context.present_queue_index(), 0)
         };
 
-        let (swapchain, swapchain_khr, properties, images) =
-            Self::create_swapchain_and_images(&vk_context, [width, height]);
+        let (render_target, properties, images) = Self::create_render_target(
+            &vk_context,
+            [config.width, config.height],
+            present_mode_preference,
+            vk::SwapchainKHR::null(),
+        )?;
         let swapchain_image_views =
             Self::create_swapchain_image_views(vk_context.device(), &images, properties);
 
-        let msaa_samples = vk_context.get_max_usable_sample_count();
+        let msaa_samples = match config.msaa_samples {
+            Some(max) => vk::SampleCountFlags::from_raw(
+                max.as_raw().min(vk_context.get_max_usable_sample_count().as_raw()),
+            ),
+            None => vk_context.get_max_usable_sample_count(),
+        };
         log::debug!("Chosen msaa: {msaa_samples:?}");
         let depth_format = Self::find_depth_format(&vk_context);
 
-        let render_pass =
-            Self::create_render_pass(vk_context.device(), properties, msaa_samples, depth_format);
+        let render_pass = Self::create_render_pass(
+            vk_context.device(),
+            properties,
+            msaa_samples,
+            depth_format,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        );
         let descriptor_set_layout = Self::create_descriptor_set_layout(vk_context.device());
+        let pipeline_cache = pipeline::create_pipeline_cache(
+            vk_context.device(),
+            pipeline::pipeline_cache_path().as_deref(),
+        );
 
         let command_pool =
-            vk_context.create_command_pool(vk::CommandPoolCreateFlags::empty());
+            vk_context.create_command_pool(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER);
         let transient_command_pool =
             vk_context.create_command_pool(vk::CommandPoolCreateFlags::TRANSIENT);
 
@@ -146,67 +488,213 @@ impl VkApp {
         let swapchain_framebuffers = Self::create_framebuffers(
             vk_context.device(),
             &swapchain_image_views,
-            color_texture,
-            depth_texture,
+            &color_texture,
+            &depth_texture,
             render_pass,
             properties,
         );
 
-        let texture = Self::create_texture_image(
+        let mut staging = StagingBuffer::default();
+        let texture = texture_path.as_ref().and_then(|path| {
+            let texture = Self::create_texture_image(
+                &vk_context,
+                &mut staging,
+                command_pool,
+                graphics_queue,
+                path,
+                COLOR_TEXTURE_FORMAT,
+                TextureFilter::default(),
+                config.anisotropy,
+                config.generate_mips,
+            );
+            // the model can still be shown without its texture
+            texture.map_err(|err| {
+                log::error!("Failed to load image {}, using white instead: {err:#}", path.display());
+            }).ok()
+        });
+        let (texture, texture_source, texture_path) = match texture {
+            Some(texture) => (texture, TextureSource::File, texture_path),
+            None => {
+                let texture = Self::create_white_texture(&vk_context, &mut staging, command_pool, graphics_queue)
+                    .context("Failed to create fallback texture")?;
+                (texture, TextureSource::White, None)
+            }
+        };
+        let texture_cubemap = Self::create_cubemap(
             &vk_context,
+            &mut staging,
             command_pool,
             graphics_queue,
-            image_path,
+            &config.cubemap_paths,
+            config.generate_mips,
         ).unwrap();
-        let texture_cubemap = Self::create_cubemap(
+        let normal_map = Self::create_flat_normal_map(
             &vk_context,
+            &mut staging,
             command_pool,
             graphics_queue,
-            [
-                "assets/cubemap/right.png",
-                "assets/cubemap/left.png",
-                "assets/cubemap/top.png",
-                "assets/cubemap/bottom.png",
-                "assets/cubemap/back.png",
-                "assets/cubemap/front.png",
-            ],
-        ).unwrap();
+            config.anisotropy,
+        )?;
 
-        let (pipeline, model_extent) = {
+        let model_stats = nobj.stats();
+        let model_obj = Self::display_obj(nobj, UvMode::default(), Shading::default());
+        let (vertices, model_aabb) = Self::model_vertices(&model_obj);
+        let indices = &model_obj.indices;
+        let point_indices = Self::point_cloud_indices(&vertices, indices);
+        let pipeline = {
             let mut pipeline = Pipeline::new(
                 vk_context.device(),
-                properties,
-                vk::CullModeFlags::NONE,
+                PipelineState::default(),
                 msaa_samples,
                 render_pass,
                 descriptor_set_layout,
+                pipeline_cache,
                 shader_spv,
             );
-            let (vertices, indices, model_extent) = Self::load_model(nobj);
+            if point_indices.is_none() {
+                pipeline.geometry = Some(Geometry::new(
+                    &vk_context,
+                    &mut staging,
+                    transient_command_pool,
+                    graphics_queue,
+                    &vertices,
+                    indices,
+                ));
+            }
+            pipeline
+        };
+
+        let pipeline_points = {
+            let mut pipeline = Pipeline::new(
+                vk_context.device(),
+                Self::points_pipeline_state(),
+                msaa_samples,
+                render_pass,
+                descriptor_set_layout,
+                pipeline_cache,
+                points_spv,
+            );
+            pipeline.geometry = point_indices.map(|indices| Geometry::new(
+                &vk_context,
+                &mut staging,
+                transient_command_pool,
+                graphics_queue,
+                &vertices,
+                &indices,
+            ));
+            pipeline
+        };
+
+        let pipeline_uv = {
+            let mut pipeline = Pipeline::new(
+                vk_context.device(),
+                Self::uv_layout_pipeline_state(),
+                msaa_samples,
+                render_pass,
+                descriptor_set_layout,
+                pipeline_cache,
+                uv_spv,
+            );
+            let (vertices, indices) = Self::load_uv_layout(&vertices, indices);
             pipeline.geometry = Some(Geometry::new(
                 &vk_context,
+                &mut staging,
                 transient_command_pool,
                 graphics_queue,
                 &vertices,
                 &indices,
             ));
-            (pipeline, model_extent)
+            pipeline
         };
 
         let pipeline_cubemap = {
             let mut pipeline = Pipeline::new(
                 vk_context.device(),
-                properties,
-                vk::CullModeFlags::BACK,
+                Self::cubemap_pipeline_state(),
                 msaa_samples,
                 render_pass,
                 descriptor_set_layout,
+                pipeline_cache,
                 cubemap_spv,
             );
-            let nobj = NormalizedObj::from_reader(fs::load("assets/cubemap/skybox.obj")?)?;
-            let (vertices, indices, _) = Self::load_model(nobj);
+            let skybox_path = "assets/cubemap/skybox.obj";
+            let reader = fs::load(skybox_path)
+                .with_context(|| format!("Failed to open {skybox_path}"))?;
+            let nobj = NormalizedObj::from_reader(reader)
+                .with_context(|| format!("Failed to parse {skybox_path}"))?;
+            let (vertices, indices, _) = Self::load_model(nobj, UvMode::default(), Shading::default());
+            pipeline.geometry = Some(Geometry::new(
+                &vk_context,
+                &mut staging,
+                transient_command_pool,
+                graphics_queue,
+                &vertices,
+                &indices,
+            ));
+            pipeline
+        };
+
+        let pipeline_grid = {
+            let mut pipeline = Pipeline::new(
+                vk_context.device(),
+                Self::lines_pipeline_state(),
+                msaa_samples,
+                render_pass,
+                descriptor_set_layout,
+                pipeline_cache,
+                grid_spv,
+            );
+            let (vertices, indices) = Self::load_grid();
+            pipeline.geometry = Some(Geometry::new(
+                &vk_context,
+                &mut staging,
+                transient_command_pool,
+                graphics_queue,
+                &vertices,
+                &indices,
+            ));
+            pipeline
+        };
+
+        let pipeline_normals = {
+            let mut pipeline = Pipeline::new(
+                vk_context.device(),
+                Self::lines_pipeline_state(),
+                msaa_samples,
+                render_pass,
+                descriptor_set_layout,
+                pipeline_cache,
+                normals_spv,
+            );
+            let (vertices, indices) = Self::load_normals(&vertices, model_aabb);
+            if !vertices.is_empty() {
+                pipeline.geometry = Some(Geometry::new(
+                    &vk_context,
+                    &mut staging,
+                    transient_command_pool,
+                    graphics_queue,
+                    &vertices,
+                    &indices,
+                ));
+            }
+            pipeline
+        };
+
+        // the bounding box is in model space like the normals
+        let pipeline_bbox = {
+            let mut pipeline = Pipeline::new(
+                vk_context.device(),
+                Self::lines_pipeline_state(),
+                msaa_samples,
+                render_pass,
+                descriptor_set_layout,
+                pipeline_cache,
+                normals_spv,
+            );
+            let (vertices, indices) = Self::load_bbox(model_aabb);
             pipeline.geometry = Some(Geometry::new(
                 &vk_context,
+                &mut staging,
                 transient_command_pool,
                 graphics_queue,
                 &vertices,
@@ -224,66 +712,127 @@ impl VkApp {
             descriptor_pool,
             descriptor_set_layout,
             &uniform_buffers,
-            &[texture, texture_cubemap],
-            //&[texture],
+            [&texture, &texture_cubemap, &normal_map],
         );
 
-        let command_buffers = Self::create_and_register_command_buffers(
+        let command_buffers = Self::allocate_command_buffers(vk_context.device(), command_pool);
+
+        let in_flight_frames = Self::create_sync_objects(vk_context.device());
+        let gpu_timer = GpuTimer::new(vk_context.device(), vk_context.physical_device_properties());
+        #[cfg(feature = "ui")]
+        let ui = UiRenderer::new(
             vk_context.device(),
-            command_pool,
-            &swapchain_framebuffers,
             render_pass,
-            properties,
-            &descriptor_sets,
-            &[pipeline_cubemap, pipeline],
+            msaa_samples,
+            pipeline_cache,
+            config.ui_spv,
+            MAX_FRAMES_IN_FLIGHT as _,
         );
 
-        let in_flight_frames = Self::create_sync_objects(vk_context.device());
-
-        Ok(Self {
+        let mut app = Self {
             view_matrix: UniformBufferObject::view_matrix(),
             model_matrix: Matrix4::unit(),
-            initial_model_matrix: UniformBufferObject::model_matrix(
-                model_extent.0,
-                model_extent.1,
-            ),
+            initial_model_matrix: UniformBufferObject::model_matrix(model_aabb),
             texture_weight: 0.,
+            reflectivity: 0.,
+            light_dir: Vector3::from([0.5, 1., 1.]).normalize(),
+            projection_mode: ProjectionMode::Perspective,
+            uv_mode: UvMode::default(),
+            shading: Shading::default(),
+            generate_mips: config.generate_mips,
+            morph_weight: 0.,
             cull_mode: vk::CullModeFlags::NONE,
+            depth_bias_constant: 0.,
+            depth_bias_slope: 0.,
+            rotation_axis: RotationAxis::default(),
+            rotation_speed: DEFAULT_ROTATION_SPEED,
             show_cubemap: true,
-            model_extent,
+            show_uv_layout: false,
+            show_grid: false,
+            show_normals: false,
+            show_bbox: false,
+            show_depth: false,
+            clip_planes: None,
+            clear_color: config.clear_color,
+            letterbox: None,
+            present_mode_preference,
+            model_aabb,
+            model_radius: Self::model_radius(model_aabb, &vertices),
+            model_stats,
+            model_obj,
+            texture_path: texture_path.unwrap_or_default(),
+            texture_source,
+            capture_requested: false,
+            readbacks: (0..MAX_FRAMES_IN_FLIGHT).map(|_| None).collect(),
+            captured_frames: VecDeque::new(),
+            load_queue: ModelLoadQueue::new(),
+            model_loads: Vec::new(),
+            texture_loads: Vec::new(),
+            next_texture_load_id: 0,
+            polygon_mode: vk::PolygonMode::FILL,
+            transparent: false,
+            depth_test: true,
+            texture_filter: TextureFilter::default(),
+            anisotropy: config.anisotropy,
+            scene: None,
+            retired: Vec::new(),
+            instances: Vec::new(),
+            next_model_id: 0,
             dirty_swapchain: false,
             vk_context,
             graphics_queue,
             present_queue,
-            swapchain,
-            swapchain_khr,
+            render_target,
             swapchain_properties: properties,
             images,
             swapchain_image_views,
             render_pass,
             descriptor_set_layout,
+            pipeline_cache,
             pipeline,
             pipeline_cubemap,
+            pipeline_uv,
+            pipeline_grid,
+            pipeline_normals,
+            pipeline_bbox,
+            pipeline_points,
+            fxaa: None,
             swapchain_framebuffers,
             command_pool,
             transient_command_pool,
+            staging,
             msaa_samples,
+            fxaa_enabled: false,
             color_texture,
             depth_format,
             depth_texture,
-            textures: [texture, texture_cubemap],
+            textures: [texture, texture_cubemap, normal_map],
+            cubemap_paths: config.cubemap_paths.clone(),
             uniform_buffers,
             uniform_buffer_memories,
             descriptor_pool,
             descriptor_sets,
             command_buffers,
             in_flight_frames,
+            gpu_timer,
+            #[cfg(feature = "ui")]
+            ui,
             shader_spv,
             cubemap_spv,
-        })
+            uv_spv,
+            grid_spv,
+            normals_spv,
+            points_spv,
+            fxaa_spv,
+            rebuild_pipelines: false,
+        };
+        app.update_viewports();
+        Ok(app)
     }
 
-    fn create_instance(entry: &Entry, window: &Window) -> Instance {
+    /// Creates the instance with the extensions needed to present to `window`,
+    /// or without surface extensions if there is no window.
+    fn create_instance(entry: &Entry, window: Option<&Window>) -> Instance {
         let app_name = CString::new("Vulkan Application").unwrap();
         let engine_name = CString::new("No Engine").unwrap();
         let app_info = vk::ApplicationInfo::default()
@@ -293,11 +842,16 @@ impl VkApp {
             .engine_version(vk::make_api_version(0, 0, 1, 0))
             .api_version(vk::make_api_version(0, 1, 0, 0));
 
-        let extension_names =
-            ash_window::enumerate_required_extensions(window.display_handle().unwrap().as_raw())
-                .unwrap();
-        let mut extension_names = extension_names.to_vec();
-        if ENABLE_VALIDATION_LAYERS {
+        let mut extension_names = match window {
+            Some(window) => {
+                ash_window::enumerate_required_extensions(window.display_handle().unwrap().as_raw())
+                    .unwrap()
+                    .to_vec()
+            }
+            None => Vec::new(),
+        };
+        let validation = validation_enabled(entry);
+        if validation {
             extension_names.push(debug_utils::NAME.as_ptr());
         }
         #[cfg(any(target_os = "macos", target_os = "ios"))]
@@ -318,34 +872,72 @@ impl VkApp {
             .application_info(&app_info)
             .enabled_extension_names(&extension_names)
             .flags(create_flags);
-        if ENABLE_VALIDATION_LAYERS {
-            check_validation_layer_support(entry);
+        if validation {
             instance_create_info = instance_create_info.enabled_layer_names(&layer_names_ptrs);
         }
 
         unsafe { entry.create_instance(&instance_create_info, None).unwrap() }
     }
 
+    /// Create the images to render to, a swapchain if the context has a surface
+    /// or a single offscreen image otherwise.
+    fn create_render_target(
+        vk_context: &VkContext,
+        dimensions: [u32; 2],
+        present_mode_preference: vk::PresentModeKHR,
+        old_swapchain: vk::SwapchainKHR,
+    ) -> Result<(RenderTarget, SwapchainProperties, Vec<vk::Image>), anyhow::Error> {
+        match vk_context.swapchain_support_details() {
+            Some(details) => Self::create_swapchain_and_images(
+                vk_context,
+                details,
+                dimensions,
+                present_mode_preference,
+                old_swapchain,
+            ),
+            None => Ok(Self::create_offscreen_image(vk_context, dimensions)),
+        }
+    }
+
+    /// Create an image of size `dimensions` that can be copied from after rendering.
+    fn create_offscreen_image(
+        vk_context: &VkContext,
+        dimensions: [u32; 2],
+    ) -> (RenderTarget, SwapchainProperties, Vec<vk::Image>) {
+        let properties = SwapchainProperties {
+            format: vk::SurfaceFormatKHR {
+                format: OFFSCREEN_FORMAT,
+                color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+            },
+            present_mode: vk::PresentModeKHR::FIFO,
+            extent: vk::Extent2D { width: dimensions[0], height: dimensions[1] },
+        };
+        log::debug!("Creating offscreen image.\n\tFormat: {OFFSCREEN_FORMAT:?}\n\tExtent: {:?}", properties.extent);
+        let (image, memory) = Self::create_image(
+            vk_context,
+            MemoryLocation::GpuOnly,
+            properties.extent,
+            1,
+            vk::SampleCountFlags::TYPE_1,
+            OFFSCREEN_FORMAT,
+            vk::ImageTiling::OPTIMAL,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC,
+        );
+        (RenderTarget::Offscreen { image, memory: Some(memory) }, properties, vec![image])
+    }
+
     /// Create the swapchain with optimal settings possible with `device`.
     ///
-    /// # Returns
-    ///
-    /// A tuple containing the swapchain loader and the actual swapchain.
+    /// `old_swapchain` is retired by this, even on failure, but must still be destroyed.
     fn create_swapchain_and_images(
         vk_context: &VkContext,
+        details: SwapchainSupportDetails,
         dimensions: [u32; 2],
-    ) -> (
-        khr_swapchain::Device,
-        vk::SwapchainKHR,
-        SwapchainProperties,
-        Vec<vk::Image>,
-    ) {
-        let details = SwapchainSupportDetails::new(
-            vk_context.physical_device(),
-            vk_context.surface(),
-            vk_context.surface_khr(),
-        );
-        let properties = details.get_ideal_swapchain_properties(dimensions);
+        present_mode_preference: vk::PresentModeKHR,
+        old_swapchain: vk::SwapchainKHR,
+    ) -> Result<(RenderTarget, SwapchainProperties, Vec<vk::Image>), anyhow::Error> {
+        let properties = details.get_ideal_swapchain_properties(dimensions, present_mode_preference);
+        log::info!("Present mode: {:?}", properties.present_mode);
 
         let format = properties.format;
         let present_mode = properties.present_mode;
@@ -374,13 +966,13 @@ impl VkApp {
 
         let create_info = {
             let mut builder = vk::SwapchainCreateInfoKHR::default()
-                .surface(vk_context.surface_khr())
+                .surface(vk_context.surface().unwrap().1)
                 .min_image_count(image_count)
                 .image_format(format.format)
                 .image_color_space(format.color_space)
                 .image_extent(extent)
                 .image_array_layers(1)
-                .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT);
+                .image_usage(Self::swapchain_image_usage(&details));
 
             builder = if graphics != present {
                 builder
@@ -395,12 +987,27 @@ impl VkApp {
                 .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
                 .present_mode(present_mode)
                 .clipped(true)
+                .old_swapchain(old_swapchain)
+        };
+
+        let loader = khr_swapchain::Device::new(vk_context.instance(), vk_context.device());
+        let swapchain_khr = unsafe { loader.create_swapchain(&create_info, None) }
+            .context("Failed to create swapchain")?;
+        let images = match unsafe { loader.get_swapchain_images(swapchain_khr) } {
+            Ok(images) => images,
+            Err(err) => {
+                unsafe { loader.destroy_swapchain(swapchain_khr, None) };
+                return Err(err).context("Failed to get swapchain images");
+            }
         };
+        Ok((RenderTarget::Swapchain { loader, swapchain_khr }, properties, images))
+    }
 
-        let swapchain = khr_swapchain::Device::new(vk_context.instance(), vk_context.device());
-        let swapchain_khr = unsafe { swapchain.create_swapchain(&create_info, None).unwrap() };
-        let images = unsafe { swapchain.get_swapchain_images(swapchain_khr).unwrap() };
-        (swapchain, swapchain_khr, properties, images)
+    /// Returns the usage of swapchain images, which includes `TRANSFER_SRC`
+    /// if supported to be able to capture frames.
+    fn swapchain_image_usage(details: &SwapchainSupportDetails) -> vk::ImageUsageFlags {
+        let supported = details.capabilities.supported_usage_flags;
+        vk::ImageUsageFlags::COLOR_ATTACHMENT | (supported & vk::ImageUsageFlags::TRANSFER_SRC)
     }
 
     /// Create one image view for each image of the swapchain.
@@ -422,7 +1029,7 @@ impl VkApp {
             .collect::<Vec<_>>()
     }
 
-    fn create_image_view(
+    pub(super) fn create_image_view(
         device: &Device,
         image: vk::Image,
         mip_levels: u32,
@@ -449,6 +1056,7 @@ impl VkApp {
         swapchain_properties: SwapchainProperties,
         msaa_samples: vk::SampleCountFlags,
         depth_format: vk::Format,
+        final_layout: vk::ImageLayout,
     ) -> vk::RenderPass {
         let color_attachment_desc = vk::AttachmentDescription::default()
             .format(swapchain_properties.format.format)
@@ -466,15 +1074,18 @@ impl VkApp {
             .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
             .initial_layout(vk::ImageLayout::UNDEFINED)
             .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+        // without multisampling the swapchain image is rendered to directly,
+        // the color texture stays in the framebuffer but is not used
+        let multisampled = msaa_samples != vk::SampleCountFlags::TYPE_1;
         let resolve_attachment_desc = vk::AttachmentDescription::default()
             .format(swapchain_properties.format.format)
             .samples(vk::SampleCountFlags::TYPE_1)
-            .load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .load_op(if multisampled { vk::AttachmentLoadOp::DONT_CARE } else { vk::AttachmentLoadOp::CLEAR })
             .store_op(vk::AttachmentStoreOp::STORE)
             .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
             .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
             .initial_layout(vk::ImageLayout::UNDEFINED)
-            .final_layout(vk::ImageLayout::PRESENT_SRC_KHR);
+            .final_layout(final_layout);
         let attachment_descs = [
             color_attachment_desc,
             depth_attachement_desc,
@@ -482,7 +1093,7 @@ impl VkApp {
         ];
 
         let color_attachment_ref = vk::AttachmentReference::default()
-            .attachment(0)
+            .attachment(if multisampled { 0 } else { 2 })
             .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
         let color_attachment_refs = [color_attachment_ref];
 
@@ -495,11 +1106,13 @@ impl VkApp {
             .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
         let resolve_attachment_refs = [resolve_attachment_ref];
 
-        let subpass_desc = vk::SubpassDescription::default()
+        let mut subpass_desc = vk::SubpassDescription::default()
             .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
             .color_attachments(&color_attachment_refs)
-            .resolve_attachments(&resolve_attachment_refs)
             .depth_stencil_attachment(&depth_attachment_ref);
+        if multisampled {
+            subpass_desc = subpass_desc.resolve_attachments(&resolve_attachment_refs);
+        }
         let subpass_descs = [subpass_desc];
 
         let subpass_dep = vk::SubpassDependency::default()
@@ -511,7 +1124,19 @@ impl VkApp {
             .dst_access_mask(
                 vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
             );
-        let subpass_deps = [subpass_dep];
+        // the FXAA pass samples the rendered image, so it must be written before
+        let sampled_dep = vk::SubpassDependency::default()
+            .src_subpass(0)
+            .dst_subpass(vk::SUBPASS_EXTERNAL)
+            .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+            .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+            .dst_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ);
+        let subpass_deps = if final_layout == vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL {
+            vec![subpass_dep, sampled_dep]
+        } else {
+            vec![subpass_dep]
+        };
 
         let render_pass_info = vk::RenderPassCreateInfo::default()
             .attachments(&attachment_descs)
@@ -533,7 +1158,12 @@ impl VkApp {
             .descriptor_count(1)
             .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
             .stage_flags(vk::ShaderStageFlags::FRAGMENT);
-        let bindings = [ubo_binding, sampler_binding, cubemap_binding];
+        let normal_map_binding = vk::DescriptorSetLayoutBinding::default()
+            .binding(3)
+            .descriptor_count(1)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT);
+        let bindings = [ubo_binding, sampler_binding, cubemap_binding, normal_map_binding];
         let layout_info = vk::DescriptorSetLayoutCreateInfo::default().bindings(&bindings);
 
         unsafe {
@@ -550,7 +1180,7 @@ impl VkApp {
             },
             vk::DescriptorPoolSize {
                 ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-                descriptor_count: size * 2,
+                descriptor_count: size * 3,
             },
         ];
         let pool_info = vk::DescriptorPoolCreateInfo::default()
@@ -566,7 +1196,7 @@ impl VkApp {
         pool: vk::DescriptorPool,
         layout: vk::DescriptorSetLayout,
         uniform_buffers: &[vk::Buffer],
-        textures: &[Texture],
+        textures: [&Texture; 3],
     ) -> Vec<vk::DescriptorSet> {
         let layouts = (0..uniform_buffers.len())
             .map(|_| layout)
@@ -615,7 +1245,25 @@ impl VkApp {
                 .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
                 .image_info(&image_infos2);
 
-            let writes = [ubo_descriptor_write, sampler_descriptor_write, sampler2_descriptor_write];
+            let normal_map = textures[2];
+            let image_info = vk::DescriptorImageInfo::default()
+                .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                .image_view(normal_map.view)
+                .sampler(normal_map.sampler.unwrap());
+            let image_infos3 = [image_info];
+            let sampler3_descriptor_write = vk::WriteDescriptorSet::default()
+                .dst_set(*set)
+                .dst_binding(3)
+                .dst_array_element(0)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .image_info(&image_infos3);
+
+            let writes = [
+                ubo_descriptor_write,
+                sampler_descriptor_write,
+                sampler2_descriptor_write,
+                sampler3_descriptor_write,
+            ];
             unsafe { device.update_descriptor_sets(&writes, &[]) }
         }
 
@@ -625,8 +1273,8 @@ impl VkApp {
     fn create_framebuffers(
         device: &Device,
         image_views: &[vk::ImageView],
-        color_texture: Texture,
-        depth_texture: Texture,
+        color_texture: &Texture,
+        depth_texture: &Texture,
         render_pass: vk::RenderPass,
         swapchain_properties: SwapchainProperties,
     ) -> Vec<vk::Framebuffer> {
@@ -654,7 +1302,7 @@ impl VkApp {
         let format = swapchain_properties.format.format;
         let (image, memory) = Self::create_image(
             vk_context,
-            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            MemoryLocation::GpuOnly,
             swapchain_properties.extent,
             1,
             msaa_samples,
@@ -683,7 +1331,7 @@ impl VkApp {
             vk::ImageAspectFlags::COLOR,
         );
 
-        Texture::new(image, memory, view, None)
+        Texture::new(image, memory, view, None, 1)
     }
 
     /// Create the depth buffer texture (image, memory and view).
@@ -700,7 +1348,7 @@ impl VkApp {
     ) -> Texture {
         let (image, mem) = Self::create_image(
             vk_context,
-            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            MemoryLocation::GpuOnly,
             extent,
             1,
             msaa_samples,
@@ -724,7 +1372,7 @@ impl VkApp {
 
         let view = Self::create_image_view(device, image, 1, format, vk::ImageAspectFlags::DEPTH);
 
-        Texture::new(image, mem, view, None)
+        Texture::new(image, mem, view, None, 1)
     }
 
     fn find_depth_format(vk_context: &VkContext) -> vk::Format {
@@ -746,11 +1394,42 @@ impl VkApp {
         format == vk::Format::D32_SFLOAT_S8_UINT || format == vk::Format::D24_UNORM_S8_UINT
     }
 
-    fn create_cubemap<P: AsRef<Path>>(
+    /// Creates the skybox from the equirectangular image of `paths` or its six faces.
+    fn create_cubemap(
+        vk_context: &VkContext,
+        staging: &mut StagingBuffer,
+        command_pool: vk::CommandPool,
+        copy_queue: vk::Queue,
+        paths: &CubemapPaths,
+        generate_mips: bool,
+    ) -> Result<Texture, anyhow::Error> {
+        match &paths.equirect {
+            Some(path) => Self::create_cubemap_from_equirect(
+                vk_context,
+                staging,
+                command_pool,
+                copy_queue,
+                path,
+                generate_mips,
+            ),
+            None => Self::create_cubemap_from_faces(
+                vk_context,
+                staging,
+                command_pool,
+                copy_queue,
+                paths.to_array(),
+                generate_mips,
+            ),
+        }
+    }
+
+    fn create_cubemap_from_faces<P: AsRef<Path>>(
         vk_context: &VkContext,
+        staging: &mut StagingBuffer,
         command_pool: vk::CommandPool,
         copy_queue: vk::Queue,
         pathes: [P; 6],
+        generate_mips: bool,
     ) -> Result<Texture, anyhow::Error> {
         let mut dims = None;
         let mut images = Vec::new();
@@ -773,29 +1452,126 @@ impl VkApp {
             images.push(pixels);
         }
         let (width, height) = dims.unwrap();
-        let max_mip_levels = ((width.min(height) as f32).log2().floor() + 1.0) as u32;
         let extent = vk::Extent2D { width, height };
-        let image_size = (images[0].len() * size_of::<u8>()) as vk::DeviceSize;
-        let device = vk_context.device();
+        Self::create_cubemap_texture(
+            vk_context,
+            staging,
+            command_pool,
+            copy_queue,
+            &images.concat(),
+            extent,
+            COLOR_TEXTURE_FORMAT,
+            generate_mips,
+        )
+    }
 
-        let (buffer, memory, mem_size) = buffer::create_buffer(
+    /// Creates a skybox from an equirectangular HDR image. It is projected to the
+    /// six faces on the CPU, each face is a quarter of the image width wide.
+    fn create_cubemap_from_equirect(
+        vk_context: &VkContext,
+        staging: &mut StagingBuffer,
+        command_pool: vk::CommandPool,
+        copy_queue: vk::Queue,
+        path: &Path,
+        generate_mips: bool,
+    ) -> Result<Texture, anyhow::Error> {
+        let image = ImageReader::open(path)
+            .with_context(|| format!("Failed to open image at {path:?}"))?
+            .decode()
+            .with_context(|| format!("Failed to decode image at {path:?}"))?
+            .to_rgba32f();
+        let size = (image.width() / 4).max(1);
+        let pixels = Self::equirect_to_cube_faces(&image, size)
+            .into_iter()
+            .map(half::f16::from_f32)
+            .collect::<Vec<_>>();
+        let extent = vk::Extent2D { width: size, height: size };
+        Self::create_cubemap_texture(
             vk_context,
-            image_size * 6,
-            vk::BufferUsageFlags::TRANSFER_SRC,
-            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
-        );
+            staging,
+            command_pool,
+            copy_queue,
+            &pixels,
+            extent,
+            HDR_CUBEMAP_FORMAT,
+            generate_mips,
+        )
+    }
 
-        unsafe {
-            for (i, image) in images.into_iter().enumerate() {
-                let offset = image_size * i as vk::DeviceSize;
-                let ptr = device
-                    .map_memory(memory, offset, image_size, vk::MemoryMapFlags::empty())
-                    .context("Failed to map memory for cubemap image")?;
-                let mut align = ash::util::Align::new(ptr, align_of::<u8>() as _, mem_size);
-                align.copy_from_slice(&image);
-                device.unmap_memory(memory);
+    /// Projects the equirectangular `image` to the faces of a cubemap of `size` x `size`
+    /// pixels. Returns the RGBA values of the faces in the order of the cubemap layers.
+    fn equirect_to_cube_faces(image: &image::Rgba32FImage, size: u32) -> Vec<f32> {
+        use std::f32::consts::PI;
+
+        let (width, height) = image.dimensions();
+        let pixel = |x: i64, y: i64| {
+            // wrap around horizontally and clamp at the poles
+            let x = x.rem_euclid(width as i64) as u32;
+            let y = y.clamp(0, height as i64 - 1) as u32;
+            image.get_pixel(x, y).0
+        };
+        let mut pixels = Vec::with_capacity((size * size * 6 * 4) as usize);
+        for face in 0..6 {
+            for row in 0..size {
+                for col in 0..size {
+                    let s = 2. * (col as f32 + 0.5) / size as f32 - 1.;
+                    let t = 2. * (row as f32 + 0.5) / size as f32 - 1.;
+                    // see the cube map face selection table of the Vulkan specification
+                    let [x, y, z] = match face {
+                        0 => [1., -t, -s],
+                        1 => [-1., -t, s],
+                        2 => [s, 1., t],
+                        3 => [s, -1., -t],
+                        4 => [s, -t, 1.],
+                        _ => [-s, -t, -1.],
+                    };
+                    // the skybox shader mirrors x, so undo it to get the world direction
+                    let dir = Vector3::from([-x, y, z]).normalize();
+                    // the center of the image is in front of the camera looking along -z
+                    let u = 0.5 + dir.x().atan2(-dir.z()) / (2. * PI);
+                    let v = dir.y().clamp(-1., 1.).acos() / PI;
+
+                    // bilinear interpolation between the four nearest pixels
+                    let fx = u * width as f32 - 0.5;
+                    let fy = v * height as f32 - 0.5;
+                    let (x0, y0) = (fx.floor(), fy.floor());
+                    let (ax, ay) = (fx - x0, fy - y0);
+                    let (x0, y0) = (x0 as i64, y0 as i64);
+                    let [p00, p10, p01, p11] = [
+                        pixel(x0, y0),
+                        pixel(x0 + 1, y0),
+                        pixel(x0, y0 + 1),
+                        pixel(x0 + 1, y0 + 1),
+                    ];
+                    pixels.extend((0..4).map(|c| {
+                        let top = p00[c] + (p10[c] - p00[c]) * ax;
+                        let bottom = p01[c] + (p11[c] - p01[c]) * ax;
+                        top + (bottom - top) * ay
+                    }));
+                }
             }
         }
+        pixels
+    }
+
+    /// Creates a cubemap from the `pixels` of the six faces, which are stored
+    /// one after the other in the order of the layers.
+    #[allow(clippy::too_many_arguments)]
+    fn create_cubemap_texture<T: Copy>(
+        vk_context: &VkContext,
+        staging: &mut StagingBuffer,
+        command_pool: vk::CommandPool,
+        copy_queue: vk::Queue,
+        pixels: &[T],
+        extent: vk::Extent2D,
+        format: vk::Format,
+        generate_mips: bool,
+    ) -> Result<Texture, anyhow::Error> {
+        let mip_levels = Self::mip_level_count(extent, generate_mips);
+        let device = vk_context.device();
+
+        // the faces are copied to consecutive layers
+        let buffer = staging.write::<T, _>(vk_context, pixels);
 
         let (image, image_memory) = {
             let image_info = vk::ImageCreateInfo::default()
@@ -805,9 +1581,9 @@ impl VkApp {
                     height: extent.height,
                     depth: 1,
                 })
-                .mip_levels(max_mip_levels)
+                .mip_levels(mip_levels)
                 .array_layers(6)
-                .format(vk::Format::R8G8B8A8_UNORM)
+                .format(format)
                 .tiling(vk::ImageTiling::OPTIMAL)
                 .initial_layout(vk::ImageLayout::UNDEFINED)
                 .usage(vk::ImageUsageFlags::TRANSFER_SRC
@@ -819,63 +1595,34 @@ impl VkApp {
             let device = vk_context.device();
             let image = unsafe { device.create_image(&image_info, None).unwrap() };
             let mem_requirements = unsafe { device.get_image_memory_requirements(image) };
-            let mem_type_index = vk_context.find_memory_type(
-                mem_requirements,
-                vk::MemoryPropertyFlags::DEVICE_LOCAL,
-            );
-            let alloc_info = vk::MemoryAllocateInfo::default()
-                .allocation_size(mem_requirements.size)
-                .memory_type_index(mem_type_index);
-            let memory = unsafe {
-                let mem = device.allocate_memory(&alloc_info, None).unwrap();
-                device.bind_image_memory(image, mem, 0).unwrap();
-                mem
-            };
+            let memory = vk_context.allocate("cubemap", mem_requirements, MemoryLocation::GpuOnly, false);
+            unsafe { device.bind_image_memory(image, memory.memory(), memory.offset()).unwrap() };
             (image, memory)
         };
 
         // Transition the image layout and copy the buffer into the image
         // and transition the layout again to be readable from fragment shader.
-        {
-            Self::transition_image_layout(
-                device,
-                command_pool,
-                copy_queue,
+        cmd::execute_one_time_commands(device, command_pool, copy_queue, |command_buffer| {
+            Self::record_image_upload(
+                vk_context,
+                command_buffer,
+                buffer,
                 image,
-                max_mip_levels,
-                vk::Format::R8G8B8A8_UNORM,
-                vk::ImageLayout::UNDEFINED,
-                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                extent,
+                format,
+                mip_levels,
                 6,
             );
-
-            Self::copy_buffer_to_image(device, command_pool, copy_queue, buffer, image, extent, 6);
-
-            Self::generate_mipmaps(
-                vk_context,
-                command_pool,
-                copy_queue,
-                image,
-                extent,
-                vk::Format::R8G8B8A8_UNORM,
-                max_mip_levels,
-                6,
-            );
-        }
-
-        unsafe {
-            device.destroy_buffer(buffer, None);
-            device.free_memory(memory, None);
-        }
+        });
 
         let create_info = vk::ImageViewCreateInfo::default()
             .image(image)
             .view_type(vk::ImageViewType::CUBE)
-            .format(vk::Format::R8G8B8A8_UNORM)
+            .format(format)
             .subresource_range(vk::ImageSubresourceRange {
                 aspect_mask: vk::ImageAspectFlags::COLOR,
                 base_mip_level: 0,
-                level_count: max_mip_levels,
+                level_count: mip_levels,
                 base_array_layer: 0,
                 layer_count: 6,
             });
@@ -883,15 +1630,15 @@ impl VkApp {
             device.create_image_view(&create_info, None).unwrap()
         };
 
-        let max_aniso = vk_context.physical_device_properties().limits.max_sampler_anisotropy;
+        let anisotropy = Self::clamp_anisotropy(vk_context, 16.);
         let sampler_info = vk::SamplerCreateInfo::default()
             .mag_filter(vk::Filter::LINEAR)
             .min_filter(vk::Filter::LINEAR)
             .address_mode_u(vk::SamplerAddressMode::REPEAT)
             .address_mode_v(vk::SamplerAddressMode::REPEAT)
             .address_mode_w(vk::SamplerAddressMode::REPEAT)
-            .anisotropy_enable(true)
-            .max_anisotropy(max_aniso.max(16.))
+            .anisotropy_enable(anisotropy.is_some())
+            .max_anisotropy(anisotropy.unwrap_or(1.))
             .border_color(vk::BorderColor::INT_OPAQUE_BLACK)
             .unnormalized_coordinates(false)
             .compare_enable(false)
@@ -899,141 +1646,312 @@ impl VkApp {
             .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
             .mip_lod_bias(0.0)
             .min_lod(0.0)
-            .max_lod(max_mip_levels as _);
+            .max_lod((mip_levels - 1) as f32);
         let sampler = unsafe {
             device.create_sampler(&sampler_info, None)
                 .context("Failed to create sampler for cubemap")?
         };
 
-        Ok(Texture::new(image, image_memory, image_view, Some(sampler)))
+        Ok(Texture::new(image, image_memory, image_view, Some(sampler), mip_levels))
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn create_texture_image<P: AsRef<Path>>(
         vk_context: &VkContext,
+        staging: &mut StagingBuffer,
         command_pool: vk::CommandPool,
         copy_queue: vk::Queue,
         path: P,
+        format: vk::Format,
+        filter: TextureFilter,
+        anisotropy: f32,
+        generate_mips: bool,
     ) -> Result<Texture, anyhow::Error> {
-        let image = ImageReader::open(path)
-            .context("Failed to open image")?
-            .decode()
-            .context("Failed to decode image")?
-            .flipv();
-        let image_as_rgb = image.to_rgba8();
-        let width = image_as_rgb.width();
-        let height = image_as_rgb.height();
-        let max_mip_levels = ((width.min(height) as f32).log2().floor() + 1.0) as u32;
-        let extent = vk::Extent2D { width, height };
-        let pixels = image_as_rgb.into_raw();
-        let image_size = (pixels.len() * size_of::<u8>()) as vk::DeviceSize;
+        Self::create_texture_from_image(
+            vk_context,
+            staging,
+            command_pool,
+            copy_queue,
+            Self::decode_texture(path)?,
+            format,
+            filter,
+            anisotropy,
+            generate_mips,
+        )
+    }
+
+    /// Reads the image at `path` flipped so that v points up like in obj files.
+    fn decode_texture<P: AsRef<Path>>(path: P) -> Result<TexturePixels, anyhow::Error> {
+        let path = path.as_ref();
+        let file = std::fs::File::open(path).context("Failed to open image")?;
+        let image = texture::decode_image(std::io::BufReader::new(file), path)
+            .context("Failed to decode image")?;
+        Ok(Self::texture_pixels(image))
+    }
+
+    /// Flips `image` so that v points up like in obj files.
+    fn texture_pixels(image: DynamicImage) -> TexturePixels {
+        TexturePixels::from_image(image.flipv())
+    }
+
+    /// Creates a texture from `pixels`. Without `generate_mips` the texture has a single mip level.
+    ///
+    /// `format` is `COLOR_TEXTURE_FORMAT` or `DATA_TEXTURE_FORMAT`, 16 bit pixels use
+    /// `WIDE_TEXTURE_FORMAT` instead if the device can sample it.
+    #[allow(clippy::too_many_arguments)]
+    fn create_texture_from_image(
+        vk_context: &VkContext,
+        staging: &mut StagingBuffer,
+        command_pool: vk::CommandPool,
+        copy_queue: vk::Queue,
+        pixels: TexturePixels,
+        format: vk::Format,
+        filter: TextureFilter,
+        anisotropy: f32,
+        generate_mips: bool,
+    ) -> Result<Texture, anyhow::Error> {
+        cmd::execute_one_time_commands(vk_context.device(), command_pool, copy_queue, |command_buffer| {
+            Self::record_texture_upload(
+                vk_context,
+                staging,
+                command_buffer,
+                pixels,
+                format,
+                filter,
+                anisotropy,
+                generate_mips,
+            )
+        })
+    }
+
+    /// Creates a texture like `create_texture_from_image` but only records the commands
+    /// filling it into `command_buffer`. `staging` must be kept until they are executed.
+    #[allow(clippy::too_many_arguments)]
+    fn record_texture_upload(
+        vk_context: &VkContext,
+        staging: &mut StagingBuffer,
+        command_buffer: vk::CommandBuffer,
+        pixels: TexturePixels,
+        format: vk::Format,
+        filter: TextureFilter,
+        anisotropy: f32,
+        generate_mips: bool,
+    ) -> Result<Texture, anyhow::Error> {
+        let extent = pixels.extent();
+        let mip_levels = Self::mip_level_count(extent, generate_mips);
         let device = vk_context.device();
 
-        let (buffer, memory, mem_size) = buffer::create_buffer(
-            vk_context,
-            image_size,
-            vk::BufferUsageFlags::TRANSFER_SRC,
-            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
-        );
+        let (buffer, format) = match pixels {
+            TexturePixels::Rgba16(mut image) if Self::supports_linear_sampling(vk_context, WIDE_TEXTURE_FORMAT) => {
+                if format == COLOR_TEXTURE_FORMAT {
+                    texture::srgb_to_linear(&mut image);
+                }
+                (staging.write::<u16, _>(vk_context, image.as_raw()), WIDE_TEXTURE_FORMAT)
+            }
+            TexturePixels::Rgba16(image) => {
+                log::warn!("{WIDE_TEXTURE_FORMAT:?} is not supported, the texture is truncated to 8 bits");
+                let image = image::DynamicImage::from(image).into_rgba8();
+                (staging.write::<u8, _>(vk_context, image.as_raw()), format)
+            }
+            TexturePixels::Rgba8(image) => (staging.write::<u8, _>(vk_context, image.as_raw()), format),
+        };
 
-        unsafe {
-            let ptr = device.map_memory(memory, 0, image_size, vk::MemoryMapFlags::empty())
-                .context("Failed to map memory for texture image")?;
-            let mut align = ash::util::Align::new(ptr, align_of::<u8>() as _, mem_size);
-            align.copy_from_slice(&pixels);
-            device.unmap_memory(memory);
-        }
+        // created first so nothing is left to clean up when it fails
+        let sampler = Self::create_texture_sampler(vk_context, filter, anisotropy, mip_levels)?;
 
         let (image, image_memory) = Self::create_image(
             vk_context,
-            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            MemoryLocation::GpuOnly,
             extent,
-            max_mip_levels,
+            mip_levels,
             vk::SampleCountFlags::TYPE_1,
-            vk::Format::R8G8B8A8_UNORM,
+            format,
             vk::ImageTiling::OPTIMAL,
             vk::ImageUsageFlags::TRANSFER_SRC
                 | vk::ImageUsageFlags::TRANSFER_DST
                 | vk::ImageUsageFlags::SAMPLED,
         );
 
-        // Transition the image layout and copy the buffer into the image
-        // and transition the layout again to be readable from fragment shader.
-        {
-            Self::transition_image_layout(
-                device,
-                command_pool,
-                copy_queue,
-                image,
-                max_mip_levels,
-                vk::Format::R8G8B8A8_UNORM,
-                vk::ImageLayout::UNDEFINED,
-                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-                1,
-            );
-
-            Self::copy_buffer_to_image(device, command_pool, copy_queue, buffer, image, extent, 1);
-
-            Self::generate_mipmaps(
-                vk_context,
-                command_pool,
-                copy_queue,
-                image,
-                extent,
-                vk::Format::R8G8B8A8_UNORM,
-                max_mip_levels,
-                1,
-            );
-        }
-
-        unsafe {
-            device.destroy_buffer(buffer, None);
-            device.free_memory(memory, None);
-        }
+        Self::record_image_upload(
+            vk_context,
+            command_buffer,
+            buffer,
+            image,
+            extent,
+            format,
+            mip_levels,
+            1,
+        );
 
         let image_view = Self::create_image_view(
             device,
             image,
-            max_mip_levels,
-            vk::Format::R8G8B8A8_UNORM,
+            mip_levels,
+            format,
             vk::ImageAspectFlags::COLOR,
         );
 
-        let max_aniso = vk_context.physical_device_properties().limits.max_sampler_anisotropy;
+        Ok(Texture::new(image, image_memory, image_view, Some(sampler), mip_levels))
+    }
+
+    /// Records transitioning the image layout, copying the buffer into the image
+    /// and transitioning the layout again to be readable from fragment shader.
+    #[allow(clippy::too_many_arguments)]
+    fn record_image_upload(
+        vk_context: &VkContext,
+        command_buffer: vk::CommandBuffer,
+        buffer: vk::Buffer,
+        image: vk::Image,
+        extent: vk::Extent2D,
+        format: vk::Format,
+        mip_levels: u32,
+        layer_count: u32,
+    ) {
+        let device = vk_context.device();
+        Self::record_layout_transition(
+            device,
+            command_buffer,
+            image,
+            mip_levels,
+            format,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            layer_count,
+        );
+        Self::copy_buffer_to_image(device, command_buffer, buffer, image, extent, layer_count);
+        Self::generate_mipmaps(
+            vk_context,
+            command_buffer,
+            image,
+            extent,
+            format,
+            mip_levels,
+            layer_count,
+        );
+    }
+
+    /// Whether textures of `format` can be sampled and blitted for mipmaps with linear filtering.
+    fn supports_linear_sampling(vk_context: &VkContext, format: vk::Format) -> bool {
+        let properties = unsafe {
+            vk_context.instance()
+                .get_physical_device_format_properties(vk_context.physical_device(), format)
+        };
+        properties.optimal_tiling_features.contains(vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR)
+    }
+
+    /// Number of mip levels down to 1x1 for an image of size `extent`, or 1 without `generate_mips`.
+    fn mip_level_count(extent: vk::Extent2D, generate_mips: bool) -> u32 {
+        if generate_mips {
+            extent.width.min(extent.height).max(1).ilog2() + 1
+        } else {
+            1
+        }
+    }
+
+    /// Creates a sampler for textures of the model using all their `mip_levels`.
+    fn create_texture_sampler(
+        vk_context: &VkContext,
+        filter: TextureFilter,
+        anisotropy: f32,
+        mip_levels: u32,
+    ) -> Result<vk::Sampler, anyhow::Error> {
+        let (filter, mipmap_mode) = match filter {
+            TextureFilter::Nearest => (vk::Filter::NEAREST, vk::SamplerMipmapMode::NEAREST),
+            TextureFilter::Linear => (vk::Filter::LINEAR, vk::SamplerMipmapMode::LINEAR),
+        };
+        // anisotropic filtering would blend texels again
+        let anisotropy = Self::clamp_anisotropy(vk_context, anisotropy)
+            .filter(|_| filter == vk::Filter::LINEAR);
         let sampler_info = vk::SamplerCreateInfo::default()
-            .mag_filter(vk::Filter::LINEAR)
-            .min_filter(vk::Filter::LINEAR)
+            .mag_filter(filter)
+            .min_filter(filter)
             .address_mode_u(vk::SamplerAddressMode::REPEAT)
             .address_mode_v(vk::SamplerAddressMode::REPEAT)
             .address_mode_w(vk::SamplerAddressMode::REPEAT)
-            .anisotropy_enable(true)
-            .max_anisotropy(max_aniso.max(16.))
+            .anisotropy_enable(anisotropy.is_some())
+            .max_anisotropy(anisotropy.unwrap_or(1.))
             .border_color(vk::BorderColor::INT_OPAQUE_BLACK)
             .unnormalized_coordinates(false)
             .compare_enable(false)
             .compare_op(vk::CompareOp::ALWAYS)
-            .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
+            .mipmap_mode(mipmap_mode)
             .mip_lod_bias(0.0)
             .min_lod(0.0)
-            .max_lod(max_mip_levels as _);
-        let sampler = unsafe {
-            device.create_sampler(&sampler_info, None)
-                .context("Failed to create sampler for texture")?
-        };
+            .max_lod((mip_levels - 1) as f32);
+        unsafe {
+            vk_context.device().create_sampler(&sampler_info, None)
+                .context("Failed to create sampler for texture")
+        }
+    }
+
+    /// Returns `anisotropy` clamped to the device limit or `None` if it is disabled,
+    /// because it is below 1 or the device does not support anisotropic filtering.
+    fn clamp_anisotropy(vk_context: &VkContext, anisotropy: f32) -> Option<f32> {
+        if anisotropy < 1. || !vk_context.supports_sampler_anisotropy() {
+            return None;
+        }
+        let max_aniso = vk_context.physical_device_properties().limits.max_sampler_anisotropy;
+        Some(anisotropy.min(max_aniso))
+    }
+
+    /// Creates a 1x1 normal map pointing along the geometric normal, which is bound
+    /// as long as no normal map is loaded.
+    fn create_flat_normal_map(
+        vk_context: &VkContext,
+        staging: &mut StagingBuffer,
+        command_pool: vk::CommandPool,
+        copy_queue: vk::Queue,
+        anisotropy: f32,
+    ) -> Result<Texture, anyhow::Error> {
+        let image = image::RgbaImage::from_pixel(1, 1, image::Rgba([128, 128, 255, 255]));
+        Self::create_texture_from_image(
+            vk_context,
+            staging,
+            command_pool,
+            copy_queue,
+            TexturePixels::Rgba8(image),
+            DATA_TEXTURE_FORMAT,
+            TextureFilter::default(),
+            anisotropy,
+            true,
+        )
+    }
+
+    fn white_image() -> image::RgbaImage {
+        image::RgbaImage::from_pixel(1, 1, image::Rgba([255; 4]))
+    }
 
-        Ok(Texture::new(image, image_memory, image_view, Some(sampler)))
+    /// Creates a 1x1 white texture, used when the image to display cannot be loaded.
+    fn create_white_texture(
+        vk_context: &VkContext,
+        staging: &mut StagingBuffer,
+        command_pool: vk::CommandPool,
+        copy_queue: vk::Queue,
+    ) -> Result<Texture, anyhow::Error> {
+        Self::create_texture_from_image(
+            vk_context,
+            staging,
+            command_pool,
+            copy_queue,
+            TexturePixels::Rgba8(Self::white_image()),
+            COLOR_TEXTURE_FORMAT,
+            TextureFilter::default(),
+            1.,
+            false,
+        )
     }
 
     #[allow(clippy::too_many_arguments)]
-    fn create_image(
+    pub(super) fn create_image(
         vk_context: &VkContext,
-        mem_properties: vk::MemoryPropertyFlags,
+        location: MemoryLocation,
         extent: vk::Extent2D,
         mip_levels: u32,
         sample_count: vk::SampleCountFlags,
         format: vk::Format,
         tiling: vk::ImageTiling,
         usage: vk::ImageUsageFlags,
-    ) -> (vk::Image, vk::DeviceMemory) {
+    ) -> (vk::Image, Allocation) {
         let image_info = vk::ImageCreateInfo::default()
             .image_type(vk::ImageType::TYPE_2D)
             .extent(vk::Extent3D {
@@ -1054,15 +1972,9 @@ impl VkApp {
         let device = vk_context.device();
         let image = unsafe { device.create_image(&image_info, None).unwrap() };
         let mem_requirements = unsafe { device.get_image_memory_requirements(image) };
-        let mem_type_index = vk_context.find_memory_type(mem_requirements, mem_properties);
-        let alloc_info = vk::MemoryAllocateInfo::default()
-            .allocation_size(mem_requirements.size)
-            .memory_type_index(mem_type_index);
-        let memory = unsafe {
-            let mem = device.allocate_memory(&alloc_info, None).unwrap();
-            device.bind_image_memory(image, mem, 0).unwrap();
-            mem
-        };
+        let linear = tiling == vk::ImageTiling::LINEAR;
+        let memory = vk_context.allocate("image", mem_requirements, location, linear);
+        unsafe { device.bind_image_memory(image, memory.memory(), memory.offset()).unwrap() };
 
         (image, memory)
     }
@@ -1080,330 +1992,494 @@ impl VkApp {
         layer_count: u32,
     ) {
         cmd::execute_one_time_commands(device, command_pool, transition_queue, |buffer| {
-            let (src_access_mask, dst_access_mask, src_stage, dst_stage) =
-                match (old_layout, new_layout) {
-                    (vk::ImageLayout::UNDEFINED, vk::ImageLayout::TRANSFER_DST_OPTIMAL) => (
-                        vk::AccessFlags::empty(),
-                        vk::AccessFlags::TRANSFER_WRITE,
-                        vk::PipelineStageFlags::TOP_OF_PIPE,
-                        vk::PipelineStageFlags::TRANSFER,
-                    ),
-                    (
-                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-                        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
-                    ) => (
-                        vk::AccessFlags::TRANSFER_WRITE,
-                        vk::AccessFlags::SHADER_READ,
-                        vk::PipelineStageFlags::TRANSFER,
-                        vk::PipelineStageFlags::FRAGMENT_SHADER,
-                    ),
-                    (
-                        vk::ImageLayout::UNDEFINED,
-                        vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
-                    ) => (
-                        vk::AccessFlags::empty(),
-                        vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
-                            | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
-                        vk::PipelineStageFlags::TOP_OF_PIPE,
-                        vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
-                    ),
-                    (vk::ImageLayout::UNDEFINED, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL) => (
-                        vk::AccessFlags::empty(),
-                        vk::AccessFlags::COLOR_ATTACHMENT_READ
-                            | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
-                        vk::PipelineStageFlags::TOP_OF_PIPE,
-                        vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
-                    ),
-                    _ => panic!(
-                        "Unsupported layout transition({:?} => {:?}).",
-                        old_layout, new_layout
-                    ),
-                };
+            Self::record_layout_transition(
+                device,
+                buffer,
+                image,
+                mip_levels,
+                format,
+                old_layout,
+                new_layout,
+                layer_count,
+            )
+        });
+    }
 
-            let aspect_mask = if new_layout == vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL {
-                let mut mask = vk::ImageAspectFlags::DEPTH;
-                if Self::has_stencil_component(format) {
-                    mask |= vk::ImageAspectFlags::STENCIL;
-                }
-                mask
-            } else {
-                vk::ImageAspectFlags::COLOR
+    #[allow(clippy::too_many_arguments)]
+    fn record_layout_transition(
+        device: &Device,
+        buffer: vk::CommandBuffer,
+        image: vk::Image,
+        mip_levels: u32,
+        format: vk::Format,
+        old_layout: vk::ImageLayout,
+        new_layout: vk::ImageLayout,
+        layer_count: u32,
+    ) {
+        let (src_access_mask, dst_access_mask, src_stage, dst_stage) =
+            match (old_layout, new_layout) {
+                (vk::ImageLayout::UNDEFINED, vk::ImageLayout::TRANSFER_DST_OPTIMAL) => (
+                    vk::AccessFlags::empty(),
+                    vk::AccessFlags::TRANSFER_WRITE,
+                    vk::PipelineStageFlags::TOP_OF_PIPE,
+                    vk::PipelineStageFlags::TRANSFER,
+                ),
+                (
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                ) => (
+                    vk::AccessFlags::TRANSFER_WRITE,
+                    vk::AccessFlags::SHADER_READ,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::PipelineStageFlags::FRAGMENT_SHADER,
+                ),
+                (
+                    vk::ImageLayout::UNDEFINED,
+                    vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                ) => (
+                    vk::AccessFlags::empty(),
+                    vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                        | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                    vk::PipelineStageFlags::TOP_OF_PIPE,
+                    vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+                ),
+                (vk::ImageLayout::UNDEFINED, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL) => (
+                    vk::AccessFlags::empty(),
+                    vk::AccessFlags::COLOR_ATTACHMENT_READ
+                        | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                    vk::PipelineStageFlags::TOP_OF_PIPE,
+                    vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                ),
+                (vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL, vk::ImageLayout::PRESENT_SRC_KHR) => (
+                    vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                    vk::AccessFlags::empty(),
+                    vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                    vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                ),
+                (vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL, vk::ImageLayout::TRANSFER_SRC_OPTIMAL) => (
+                    vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                    vk::AccessFlags::TRANSFER_READ,
+                    vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                    vk::PipelineStageFlags::TRANSFER,
+                ),
+                (vk::ImageLayout::TRANSFER_SRC_OPTIMAL, vk::ImageLayout::PRESENT_SRC_KHR) => (
+                    vk::AccessFlags::empty(),
+                    vk::AccessFlags::empty(),
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                ),
+                _ => panic!(
+                    "Unsupported layout transition({:?} => {:?}).",
+                    old_layout, new_layout
+                ),
             };
 
-            let barrier = vk::ImageMemoryBarrier::default()
-                .old_layout(old_layout)
-                .new_layout(new_layout)
-                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
-                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
-                .image(image)
-                .subresource_range(vk::ImageSubresourceRange {
-                    aspect_mask,
-                    base_mip_level: 0,
-                    level_count: mip_levels,
-                    base_array_layer: 0,
-                    layer_count,
-                })
-                .src_access_mask(src_access_mask)
-                .dst_access_mask(dst_access_mask);
+        let aspect_mask = if new_layout == vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL {
+            let mut mask = vk::ImageAspectFlags::DEPTH;
+            if Self::has_stencil_component(format) {
+                mask |= vk::ImageAspectFlags::STENCIL;
+            }
+            mask
+        } else {
+            vk::ImageAspectFlags::COLOR
+        };
 
-            unsafe {
-                device.cmd_pipeline_barrier(
-                    buffer,
-                    src_stage,
-                    dst_stage,
-                    vk::DependencyFlags::empty(),
-                    &[],
-                    &[],
-                    &[barrier],
-                )
-            };
-        });
+        let barrier = vk::ImageMemoryBarrier::default()
+            .old_layout(old_layout)
+            .new_layout(new_layout)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(image)
+            .subresource_range(vk::ImageSubresourceRange {
+                aspect_mask,
+                base_mip_level: 0,
+                level_count: mip_levels,
+                base_array_layer: 0,
+                layer_count,
+            })
+            .src_access_mask(src_access_mask)
+            .dst_access_mask(dst_access_mask);
+
+        unsafe {
+            device.cmd_pipeline_barrier(
+                buffer,
+                src_stage,
+                dst_stage,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[barrier],
+            )
+        };
     }
 
     fn copy_buffer_to_image(
         device: &Device,
-        command_pool: vk::CommandPool,
-        transition_queue: vk::Queue,
+        command_buffer: vk::CommandBuffer,
         buffer: vk::Buffer,
         image: vk::Image,
         extent: vk::Extent2D,
         layer_count: u32,
     ) {
-        cmd::execute_one_time_commands(device, command_pool, transition_queue, |command_buffer| {
-            let region = vk::BufferImageCopy::default()
-                .buffer_offset(0)
-                .buffer_row_length(0)
-                .buffer_image_height(0)
-                .image_subresource(vk::ImageSubresourceLayers {
-                    aspect_mask: vk::ImageAspectFlags::COLOR,
-                    mip_level: 0,
-                    base_array_layer: 0,
-                    layer_count,
-                })
-                .image_offset(vk::Offset3D { x: 0, y: 0, z: 0 })
-                .image_extent(vk::Extent3D {
-                    width: extent.width,
-                    height: extent.height,
-                    depth: 1,
-                });
-            let regions = [region];
-            unsafe {
-                device.cmd_copy_buffer_to_image(
-                    command_buffer,
-                    buffer,
-                    image,
-                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-                    &regions,
-                )
-            }
-        })
+        let region = vk::BufferImageCopy::default()
+            .buffer_offset(0)
+            .buffer_row_length(0)
+            .buffer_image_height(0)
+            .image_subresource(vk::ImageSubresourceLayers {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                mip_level: 0,
+                base_array_layer: 0,
+                layer_count,
+            })
+            .image_offset(vk::Offset3D { x: 0, y: 0, z: 0 })
+            .image_extent(vk::Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            });
+        let regions = [region];
+        unsafe {
+            device.cmd_copy_buffer_to_image(
+                command_buffer,
+                buffer,
+                image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &regions,
+            )
+        }
     }
 
+    /// Blits each mip level from the previous one and transitions all levels to be
+    /// read by shaders. With a single level the image is only transitioned.
     #[allow(clippy::too_many_arguments)]
     fn generate_mipmaps(
         vk_context: &VkContext,
-        command_pool: vk::CommandPool,
-        transfer_queue: vk::Queue,
+        buffer: vk::CommandBuffer,
         image: vk::Image,
         extent: vk::Extent2D,
         format: vk::Format,
         mip_levels: u32,
         layer_count: u32,
     ) {
-        let format_properties = unsafe {
-            vk_context.instance()
-                .get_physical_device_format_properties(vk_context.physical_device(), format)
-        };
-        if !format_properties.optimal_tiling_features
-            .contains(vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR)
-        {
+        if mip_levels > 1 && !Self::supports_linear_sampling(vk_context, format) {
             panic!("Linear blitting is not supported for format {:?}.", format)
         }
 
-        cmd::execute_one_time_commands(
-            vk_context.device(),
-            command_pool,
-            transfer_queue,
-            |buffer| {
-                let mut barrier = vk::ImageMemoryBarrier::default()
-                    .image(image)
-                    .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
-                    .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
-                    .subresource_range(vk::ImageSubresourceRange {
-                        aspect_mask: vk::ImageAspectFlags::COLOR,
-                        base_array_layer: 0,
-                        layer_count,
-                        level_count: 1,
-                        ..Default::default()
-                    });
-
-                let mut mip_width = extent.width as i32;
-                let mut mip_height = extent.height as i32;
-                for level in 1..mip_levels {
-                    let next_mip_width = if mip_width > 1 {
-                        mip_width / 2
-                    } else {
-                        mip_width
-                    };
-                    let next_mip_height = if mip_height > 1 {
-                        mip_height / 2
-                    } else {
-                        mip_height
-                    };
-
-                    barrier.subresource_range.base_mip_level = level - 1;
-                    barrier.old_layout = vk::ImageLayout::TRANSFER_DST_OPTIMAL;
-                    barrier.new_layout = vk::ImageLayout::TRANSFER_SRC_OPTIMAL;
-                    barrier.src_access_mask = vk::AccessFlags::TRANSFER_WRITE;
-                    barrier.dst_access_mask = vk::AccessFlags::TRANSFER_READ;
-                    let barriers = [barrier];
-
-                    unsafe {
-                        vk_context.device().cmd_pipeline_barrier(
-                            buffer,
-                            vk::PipelineStageFlags::TRANSFER,
-                            vk::PipelineStageFlags::TRANSFER,
-                            vk::DependencyFlags::empty(),
-                            &[],
-                            &[],
-                            &barriers,
-                        )
-                    };
+        let mut barrier = vk::ImageMemoryBarrier::default()
+            .image(image)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .subresource_range(vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_array_layer: 0,
+                layer_count,
+                level_count: 1,
+                ..Default::default()
+            });
 
-                    let blit = vk::ImageBlit::default()
-                        .src_offsets([
-                            vk::Offset3D { x: 0, y: 0, z: 0 },
-                            vk::Offset3D {
-                                x: mip_width,
-                                y: mip_height,
-                                z: 1,
-                            },
-                        ])
-                        .src_subresource(vk::ImageSubresourceLayers {
-                            aspect_mask: vk::ImageAspectFlags::COLOR,
-                            mip_level: level - 1,
-                            base_array_layer: 0,
-                            layer_count,
-                        })
-                        .dst_offsets([
-                            vk::Offset3D { x: 0, y: 0, z: 0 },
-                            vk::Offset3D {
-                                x: next_mip_width,
-                                y: next_mip_height,
-                                z: 1,
-                            },
-                        ])
-                        .dst_subresource(vk::ImageSubresourceLayers {
-                            aspect_mask: vk::ImageAspectFlags::COLOR,
-                            mip_level: level,
-                            base_array_layer: 0,
-                            layer_count,
-                        });
-                    let blits = [blit];
-
-                    unsafe {
-                        vk_context.device().cmd_blit_image(
-                            buffer,
-                            image,
-                            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
-                            image,
-                            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-                            &blits,
-                            vk::Filter::LINEAR,
-                        )
-                    };
+        let mut mip_width = extent.width as i32;
+        let mut mip_height = extent.height as i32;
+        for level in 1..mip_levels {
+            let next_mip_width = if mip_width > 1 {
+                mip_width / 2
+            } else {
+                mip_width
+            };
+            let next_mip_height = if mip_height > 1 {
+                mip_height / 2
+            } else {
+                mip_height
+            };
 
-                    barrier.old_layout = vk::ImageLayout::TRANSFER_SRC_OPTIMAL;
-                    barrier.new_layout = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
-                    barrier.src_access_mask = vk::AccessFlags::TRANSFER_READ;
-                    barrier.dst_access_mask = vk::AccessFlags::SHADER_READ;
-                    let barriers = [barrier];
+            barrier.subresource_range.base_mip_level = level - 1;
+            barrier.old_layout = vk::ImageLayout::TRANSFER_DST_OPTIMAL;
+            barrier.new_layout = vk::ImageLayout::TRANSFER_SRC_OPTIMAL;
+            barrier.src_access_mask = vk::AccessFlags::TRANSFER_WRITE;
+            barrier.dst_access_mask = vk::AccessFlags::TRANSFER_READ;
+            let barriers = [barrier];
 
-                    unsafe {
-                        vk_context.device().cmd_pipeline_barrier(
-                            buffer,
-                            vk::PipelineStageFlags::TRANSFER,
-                            vk::PipelineStageFlags::FRAGMENT_SHADER,
-                            vk::DependencyFlags::empty(),
-                            &[],
-                            &[],
-                            &barriers,
-                        )
-                    };
+            unsafe {
+                vk_context.device().cmd_pipeline_barrier(
+                    buffer,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[],
+                    &barriers,
+                )
+            };
 
-                    mip_width = next_mip_width;
-                    mip_height = next_mip_height;
-                }
+            let blit = vk::ImageBlit::default()
+                .src_offsets([
+                    vk::Offset3D { x: 0, y: 0, z: 0 },
+                    vk::Offset3D {
+                        x: mip_width,
+                        y: mip_height,
+                        z: 1,
+                    },
+                ])
+                .src_subresource(vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: level - 1,
+                    base_array_layer: 0,
+                    layer_count,
+                })
+                .dst_offsets([
+                    vk::Offset3D { x: 0, y: 0, z: 0 },
+                    vk::Offset3D {
+                        x: next_mip_width,
+                        y: next_mip_height,
+                        z: 1,
+                    },
+                ])
+                .dst_subresource(vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: level,
+                    base_array_layer: 0,
+                    layer_count,
+                });
+            let blits = [blit];
 
-                barrier.subresource_range.base_mip_level = mip_levels - 1;
-                barrier.old_layout = vk::ImageLayout::TRANSFER_DST_OPTIMAL;
-                barrier.new_layout = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
-                barrier.src_access_mask = vk::AccessFlags::TRANSFER_WRITE;
-                barrier.dst_access_mask = vk::AccessFlags::SHADER_READ;
-                let barriers = [barrier];
+            unsafe {
+                vk_context.device().cmd_blit_image(
+                    buffer,
+                    image,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &blits,
+                    vk::Filter::LINEAR,
+                )
+            };
 
-                unsafe {
-                    vk_context.device().cmd_pipeline_barrier(
-                        buffer,
-                        vk::PipelineStageFlags::TRANSFER,
-                        vk::PipelineStageFlags::FRAGMENT_SHADER,
-                        vk::DependencyFlags::empty(),
-                        &[],
-                        &[],
-                        &barriers,
-                    )
-                };
-            },
-        );
+            barrier.old_layout = vk::ImageLayout::TRANSFER_SRC_OPTIMAL;
+            barrier.new_layout = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
+            barrier.src_access_mask = vk::AccessFlags::TRANSFER_READ;
+            barrier.dst_access_mask = vk::AccessFlags::SHADER_READ;
+            let barriers = [barrier];
+
+            unsafe {
+                vk_context.device().cmd_pipeline_barrier(
+                    buffer,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::PipelineStageFlags::FRAGMENT_SHADER,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[],
+                    &barriers,
+                )
+            };
+
+            mip_width = next_mip_width;
+            mip_height = next_mip_height;
+        }
+
+        barrier.subresource_range.base_mip_level = mip_levels - 1;
+        barrier.old_layout = vk::ImageLayout::TRANSFER_DST_OPTIMAL;
+        barrier.new_layout = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
+        barrier.src_access_mask = vk::AccessFlags::TRANSFER_WRITE;
+        barrier.dst_access_mask = vk::AccessFlags::SHADER_READ;
+        let barriers = [barrier];
+
+        unsafe {
+            vk_context.device().cmd_pipeline_barrier(
+                buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::FRAGMENT_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &barriers,
+            )
+        };
     }
 
-    fn load_model(nobj: NormalizedObj) -> (Vec<Vertex>, Vec<u32>, (Vector3, Vector3)) {
-        let mut min = Vector3::new(f32::MAX);
-        let mut max = Vector3::new(f32::MIN);
-        for vertex in &nobj.vertices {
-            for (i, &coord) in vertex.pos_coords.iter().enumerate() {
-                min[i] = min[i].min(coord);
-                max[i] = max[i].max(coord);
+    /// Creates a line list of the edges of all triangles and of a grid over the unit
+    /// square. The lines are meant to be drawn in texture space by the uv shaders.
+    fn load_uv_layout(vertices: &[Vertex], indices: &[u32]) -> (Vec<Vertex>, Vec<u32>) {
+        const GRID_LINES: u32 = 4;
+
+        let mut uv_vertices = Vec::with_capacity(vertices.len() + (GRID_LINES as usize + 1) * 4);
+        let mut uv_indices = Vec::with_capacity(indices.len() * 2);
+        for i in 0..=GRID_LINES {
+            let t = i as f32 / GRID_LINES as f32;
+            let color = if i == 0 || i == GRID_LINES { [0.8; 3] } else { [0.4; 3] };
+            for coords in [[t, 0.], [t, 1.], [0., t], [1., t]] {
+                uv_indices.push(uv_vertices.len() as u32);
+                uv_vertices.push(Vertex { pos: [0.; 3], color, coords, normal: [0.; 3], tangent: [0.; 4] });
             }
         }
-        let x_middle = (max.x() + min.x()) / 2.;
-        let vertices = nobj.vertices.iter().map(|vertex| {
-            let tex_coords = if nobj.has_tex_coords {
-                vertex.tex_coords
-            } else {
+
+        let offset = uv_vertices.len() as u32;
+        uv_vertices.extend(vertices.iter().map(|v| Vertex { color: [1.0, 0.8, 0.2], ..*v }));
+        for tri in indices.chunks_exact(3) {
+            let [a, b, c] = [tri[0], tri[1], tri[2]].map(|idx| idx + offset);
+            uv_indices.extend([a, b, b, c, c, a]);
+        }
+
+        (uv_vertices, uv_indices)
+    }
+
+    /// Creates the lines of a grid on the XZ plane and the world axes through the origin,
+    /// colored red, green and blue for X, Y and Z.
+    fn load_grid() -> (Vec<Vertex>, Vec<u32>) {
+        let steps = (GRID_EXTENT / GRID_SPACING).round() as i32;
+        let mut lines = Vec::new();
+        for i in -steps..=steps {
+            // the axes are drawn instead of the center lines
+            if i == 0 {
+                continue;
+            }
+            let t = i as f32 * GRID_SPACING;
+            let color = if i % 2 == 0 { [0.5; 3] } else { [0.3; 3] };
+            lines.push(([t, 0., -GRID_EXTENT], [t, 0., GRID_EXTENT], color));
+            lines.push(([-GRID_EXTENT, 0., t], [GRID_EXTENT, 0., t], color));
+        }
+        lines.push(([-GRID_EXTENT, 0., 0.], [GRID_EXTENT, 0., 0.], [1., 0.2, 0.2]));
+        lines.push(([0., 0., 0.], [0., GRID_EXTENT, 0.], [0.2, 1., 0.2]));
+        lines.push(([0., 0., -GRID_EXTENT], [0., 0., GRID_EXTENT], [0.2, 0.4, 1.]));
+
+        let vertices = lines.into_iter()
+            .flat_map(|(start, end, color)| [start, end].map(|pos| Vertex {
+                pos,
+                color,
+                coords: [0.; 2],
+                normal: [0.; 3],
+                tangent: [0.; 4],
+            }))
+            .collect::<Vec<_>>();
+        let indices = (0..vertices.len() as u32).collect();
+        (vertices, indices)
+    }
+
+    /// Creates the 12 edges of the axis aligned bounding box `aabb` in white.
+    fn load_bbox(aabb: Aabb) -> (Vec<Vertex>, Vec<u32>) {
+        // bit i of the index selects the max of axis i
+        let vertices = aabb.corners().into_iter()
+            .map(|corner| Vertex {
+                pos: corner.into(),
+                color: [1.; 3],
+                coords: [0.; 2],
+                normal: [0.; 3],
+                tangent: [0.; 4],
+            })
+            .collect();
+        // connect each corner to the corners differing in one axis
+        let indices = (0..8u32)
+            .flat_map(|corner| [1, 2, 4].map(|bit| (corner, corner | bit)))
+            .filter(|&(start, end)| start != end)
+            .flat_map(|(start, end)| [start, end])
+            .collect();
+        (vertices, indices)
+    }
+
+    /// Creates a line from each vertex along its normal, colored by the direction of the normal.
+    fn load_normals(vertices: &[Vertex], aabb: Aabb) -> (Vec<Vertex>, Vec<u32>) {
+        let length = aabb.extents().magnitude() * NORMAL_LENGTH;
+        let normal_vertices = vertices.iter()
+            .flat_map(|vertex| {
+                let pos = Vector3::from(vertex.pos);
+                let normal = Vector3::from(vertex.normal);
+                let color = vertex.normal.map(|n| n * 0.5 + 0.5);
+                [pos, pos + normal * length].map(|pos| Vertex {
+                    pos: pos.into(),
+                    color,
+                    coords: [0.; 2],
+                    normal: vertex.normal,
+                    tangent: [0.; 4],
+                })
+            })
+            .collect::<Vec<_>>();
+        let indices = (0..normal_vertices.len() as u32).collect();
+        (normal_vertices, indices)
+    }
+
+    fn load_model(nobj: NormalizedObj, uv_mode: UvMode, shading: Shading) -> (Vec<Vertex>, Vec<u32>, Aabb) {
+        let nobj = Self::display_obj(nobj, uv_mode, shading);
+        let (vertices, aabb) = Self::model_vertices(&nobj);
+        (vertices, nobj.indices, aabb)
+    }
+
+    /// Applies `shading` and `uv_mode` to `nobj`, the result has normals and texture
+    /// coordinates and is the model as it is displayed.
+    fn display_obj(nobj: NormalizedObj, uv_mode: UvMode, shading: Shading) -> NormalizedObj {
+        let mut nobj = match shading {
+            Shading::Smooth => nobj,
+            Shading::Flat => nobj.flat_shaded(),
+        };
+        let aabb = nobj.aabb();
+        let x_middle = aabb.center().x();
+        let normals = if nobj.has_normals {
+            nobj.vertices.iter().map(|vertex| vertex.normal).collect()
+        } else {
+            nobj.compute_normals()
+        };
+        let uv_mode = match uv_mode {
+            UvMode::File if !nobj.has_tex_coords => UvMode::Spherical,
+            mode => mode,
+        };
+        let tex_coords = match uv_mode {
+            UvMode::File => nobj.vertices.iter().map(|vertex| vertex.tex_coords).collect(),
+            UvMode::Planar => nobj.vertices.iter().map(|vertex| {
                 let mut coords = [
                     vertex.pos_coords[2],
                     vertex.pos_coords[1],
                 ];
                 if vertex.pos_coords[0] > x_middle {
-                    coords[0] += max.z() - min.z();
+                    coords[0] += aabb.extents().z();
                 }
                 coords
-            };
+            }).collect(),
+            UvMode::Spherical => nobj.spherical_tex_coords(),
+        };
+        for ((vertex, normal), coords) in nobj.vertices.iter_mut().zip(normals).zip(tex_coords) {
+            vertex.normal = normal;
+            vertex.tex_coords = coords;
+        }
+        nobj.has_normals = true;
+        nobj.has_tex_coords = true;
+        nobj
+    }
+
+    /// Returns the vertices to draw `nobj` returned by `display_obj` and their bounding box.
+    fn model_vertices(nobj: &NormalizedObj) -> (Vec<Vertex>, Aabb) {
+        let normals = nobj.vertices.iter().map(|vertex| vertex.normal).collect::<Vec<_>>();
+        let tex_coords = nobj.vertices.iter().map(|vertex| vertex.tex_coords).collect::<Vec<_>>();
+        let tangents = nobj.compute_tangents(&normals, &tex_coords);
+        let vertices = nobj.vertices.iter().zip(tangents).map(|(vertex, tangent)| {
             Vertex {
                 pos: vertex.pos_coords,
-                color: [1.0, 1.0, 1.0],
-                coords: tex_coords,
+                color: vertex.color,
+                coords: vertex.tex_coords,
+                normal: vertex.normal,
+                tangent,
             }
         }).collect();
 
-        (vertices, nobj.indices, (min, max))
+        (vertices, nobj.aabb())
+    }
+
+    /// Returns one index per vertex if the model has vertices but no faces,
+    /// so it can be drawn as point cloud by `pipeline_points`.
+    fn point_cloud_indices(vertices: &[Vertex], indices: &[u32]) -> Option<Vec<u32>> {
+        (indices.is_empty() && !vertices.is_empty()).then(|| (0..vertices.len() as u32).collect())
     }
 
     fn create_uniform_buffers(
         vk_context: &VkContext,
         count: usize,
-    ) -> (Vec<vk::Buffer>, Vec<vk::DeviceMemory>) {
+    ) -> (Vec<vk::Buffer>, Vec<Allocation>) {
         let size = size_of::<UniformBufferObject>() as vk::DeviceSize;
         let mut buffers = Vec::new();
         let mut memories = Vec::new();
 
         for _ in 0..count {
-            let (buffer, memory, _) = buffer::create_buffer(
+            let (buffer, memory) = buffer::create_buffer(
                 vk_context,
                 size,
                 vk::BufferUsageFlags::UNIFORM_BUFFER,
-                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+                MemoryLocation::CpuToGpu,
             );
             buffers.push(buffer);
             memories.push(memory);
@@ -1412,119 +2488,326 @@ impl VkApp {
         (buffers, memories)
     }
 
-    fn recreate_command_buffers(&mut self) {
-        let device = self.vk_context.device();
-        unsafe {
-            device.free_command_buffers(self.command_pool, &self.command_buffers);
+    /// Allocates one command buffer per frame in flight, they are recorded anew each frame.
+    fn allocate_command_buffers(device: &Device, pool: vk::CommandPool) -> Vec<vk::CommandBuffer> {
+        let allocate_info = vk::CommandBufferAllocateInfo::default()
+            .command_pool(pool)
+            .level(vk::CommandBufferLevel::PRIMARY)
+            .command_buffer_count(MAX_FRAMES_IN_FLIGHT);
+        unsafe { device.allocate_command_buffers(&allocate_info).unwrap() }
+    }
+
+    fn push_constants(&self) -> PushConstants {
+        Self::push_constants_for(self.model_matrix * self.initial_model_matrix)
+    }
+
+    fn push_constants_for(model: Matrix4) -> PushConstants {
+        PushConstants {
+            model,
+            // keeps normals perpendicular to the surface under non-uniform scale
+            normal_matrix: model.inverse().map(Matrix4::transpose).unwrap_or(model),
         }
+    }
+
+    /// Records the commands to render to the swapchain image `image_index` into `buffer`,
+    /// the command buffer of the frame in flight `frame`.
+    ///
+    /// The model is drawn first, with the groups of the scene if one is loaded,
+    /// then the skybox and overlays depending on the current settings.
+    fn record_command_buffer(
+        &self,
+        buffer: vk::CommandBuffer,
+        frame: usize,
+        image_index: usize,
+        target: &FrameTarget,
+    ) {
+        let device = self.vk_context.device();
+        let descriptor_sets = &self.descriptor_sets;
+        let push_constants = self.push_constants();
 
-        let pipelines: &[Pipeline] = if self.show_cubemap {
+        // a transparent model is blended over the opaque pipelines, so it is drawn after them
+        let mut pipelines = Vec::new();
+        if !self.transparent {
+            pipelines.push(&self.pipeline);
+        }
+        if self.pipeline_points.geometry.is_some() {
+            pipelines.push(&self.pipeline_points);
+        }
+        if self.show_grid {
+            pipelines.push(&self.pipeline_grid);
+        }
+        if self.show_normals {
+            pipelines.push(&self.pipeline_normals);
+        }
+        if self.show_bbox {
+            pipelines.push(&self.pipeline_bbox);
+        }
+        if self.show_cubemap {
             // render cubemap after object for performance gain
             // (avoids rendering the parts occluded by the object)
-            &[self.pipeline, self.pipeline_cubemap]
-        } else {
-            &[self.pipeline]
-        };
-        self.command_buffers = Self::create_and_register_command_buffers(
-            device,
-            self.command_pool,
-            &self.swapchain_framebuffers,
-            self.render_pass,
-            self.swapchain_properties,
-            &self.descriptor_sets,
-            pipelines,
-        );
-    }
-
-    #[allow(clippy::too_many_arguments)]
-    fn create_and_register_command_buffers(
-        device: &Device,
-        pool: vk::CommandPool,
-        framebuffers: &[vk::Framebuffer],
-        render_pass: vk::RenderPass,
-        swapchain_properties: SwapchainProperties,
-        descriptor_sets: &[vk::DescriptorSet],
-        pipelines: &[Pipeline],
-    ) -> Vec<vk::CommandBuffer> {
-        let allocate_info = vk::CommandBufferAllocateInfo::default()
-            .command_pool(pool)
-            .level(vk::CommandBufferLevel::PRIMARY)
-            .command_buffer_count(framebuffers.len() as _);
-        let buffers = unsafe { device.allocate_command_buffers(&allocate_info).unwrap() };
+            pipelines.push(&self.pipeline_cubemap);
+        }
+        if self.transparent {
+            pipelines.push(&self.pipeline);
+        }
+        if self.show_uv_layout {
+            // overlays must be rendered last
+            pipelines.push(&self.pipeline_uv);
+        }
 
-        for (i, &buffer) in buffers.iter().enumerate() {
-            // begin command buffer
-            let command_buffer_begin_info = vk::CommandBufferBeginInfo::default()
-                .flags(vk::CommandBufferUsageFlags::SIMULTANEOUS_USE);
-            unsafe {
-                device.begin_command_buffer(buffer, &command_buffer_begin_info).unwrap()
-            };
+        // begin command buffer
+        let command_buffer_begin_info = vk::CommandBufferBeginInfo::default()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        unsafe {
+            device.reset_command_buffer(buffer, vk::CommandBufferResetFlags::empty()).unwrap();
+            device.begin_command_buffer(buffer, &command_buffer_begin_info).unwrap();
+            self.gpu_timer.write_start(device, buffer, frame);
+        };
 
-            // begin render pass
-            let clear_values = [
-                vk::ClearValue {
-                    color: vk::ClearColorValue {
-                        float32: [0.0, 0.0, 0.0, 1.0],
-                    },
-                },
-                vk::ClearValue {
-                    depth_stencil: vk::ClearDepthStencilValue {
-                        depth: 1.0,
-                        stencil: 0,
-                    },
+        // begin render pass, without MSAA the third attachment is rendered to and cleared
+        let clear_color = vk::ClearValue {
+            color: vk::ClearColorValue {
+                float32: self.clear_color,
+            },
+        };
+        let clear_values = [
+            clear_color,
+            vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue {
+                    depth: 1.0,
+                    stencil: 0,
                 },
-            ];
-            let render_pass_begin_info = vk::RenderPassBeginInfo::default()
-                .render_pass(render_pass)
-                .framebuffer(framebuffers[i])
-                .render_area(vk::Rect2D {
-                    offset: vk::Offset2D { x: 0, y: 0 },
-                    extent: swapchain_properties.extent,
-                })
-                .clear_values(&clear_values);
+            },
+            clear_color,
+        ];
+        // with FXAA the scene is rendered to the texture of the FXAA pass
+        let framebuffer = match &self.fxaa {
+            Some(fxaa) => fxaa.scene_framebuffer(),
+            None => target.framebuffer,
+        };
+        let render_pass_begin_info = vk::RenderPassBeginInfo::default()
+            .render_pass(self.render_pass)
+            .framebuffer(framebuffer)
+            .render_area(vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent: self.swapchain_properties.extent,
+            })
+            .clear_values(&clear_values);
+        unsafe {
+            device.cmd_begin_render_pass(
+                buffer,
+                &render_pass_begin_info,
+                vk::SubpassContents::INLINE,
+            )
+        };
+
+        let i = image_index;
+        let (instances_behind, instances_in_front) = self.instance_draw_order();
+        for pipeline in pipelines {
+            // bind pipeline, descriptor set and push constants
             unsafe {
-                device.cmd_begin_render_pass(
+                device.cmd_bind_pipeline(buffer, vk::PipelineBindPoint::GRAPHICS, pipeline.pipeline);
+                pipeline.cmd_set_viewport(device, buffer, self.swapchain_properties.extent);
+                device.cmd_bind_descriptor_sets(
                     buffer,
-                    &render_pass_begin_info,
-                    vk::SubpassContents::INLINE,
-                )
+                    vk::PipelineBindPoint::GRAPHICS,
+                    pipeline.layout,
+                    0,
+                    &descriptor_sets[i..=i],
+                    &[],
+                );
+                device.cmd_push_constants(
+                    buffer,
+                    pipeline.layout,
+                    vk::ShaderStageFlags::VERTEX,
+                    0,
+                    push_constants.as_bytes(),
+                );
             };
 
-            for pipeline in pipelines {
-                // bind pipeline, vertex and index buffer
-                let mut index_count = 0;
-                unsafe {
-                    device.cmd_bind_pipeline(buffer, vk::PipelineBindPoint::GRAPHICS, pipeline.pipeline);
-                    if let Some(g) = pipeline.geometry {
-                        device.cmd_bind_vertex_buffers(buffer, 0, &[g.vertex_buffer], &[0]);
-                        device.cmd_bind_index_buffer(buffer, g.index_buffer, 0, vk::IndexType::UINT32);
-                        index_count = g.index_count;
+            let is_model = std::ptr::eq(pipeline, &self.pipeline);
+            if is_model {
+                unsafe { self.record_instances(buffer, i, &instances_behind) };
+            }
+            let Some(g) = &pipeline.geometry else {
+                if is_model {
+                    unsafe { self.record_instances(buffer, i, &instances_in_front) };
+                }
+                continue;
+            };
+            // A morph target with matching vertex count is bound as second vertex buffer
+            // and interpolated in the vertex shader. Otherwise it is drawn separately
+            // with the first instance set to 1 to tell the shaders to crossfade.
+            let (morph_buffer, crossfade) = match &pipeline.morph_geometry {
+                Some(m) if m.vertex_count == g.vertex_count => (m.vertex_buffer, None),
+                Some(m) => (g.vertex_buffer, Some(m)),
+                None => (g.vertex_buffer, None),
+            };
+            unsafe {
+                device.cmd_bind_vertex_buffers(buffer, 0, &[g.vertex_buffer, morph_buffer], &[0, 0]);
+                device.cmd_bind_index_buffer(buffer, g.index_buffer, 0, g.index_type);
+                match &self.scene {
+                    Some(scene) if std::ptr::eq(pipeline, &self.pipeline) => {
+                        for group in self.draw_order(scene) {
+                            let sets = group.material
+                                .map_or(descriptor_sets, |m| &scene.descriptor_sets[m]);
+                            device.cmd_bind_descriptor_sets(
+                                buffer,
+                                vk::PipelineBindPoint::GRAPHICS,
+                                pipeline.layout,
+                                0,
+                                &sets[i..=i],
+                                &[],
+                            );
+                            device.cmd_draw_indexed(
+                                buffer,
+                                group.index_count,
+                                1,
+                                group.first_index,
+                                0,
+                                0,
+                            );
+                        }
+                        device.cmd_bind_descriptor_sets(
+                            buffer,
+                            vk::PipelineBindPoint::GRAPHICS,
+                            pipeline.layout,
+                            0,
+                            &descriptor_sets[i..=i],
+                            &[],
+                        );
                     }
-                };
-
-                // bind descriptor set
-                unsafe {
-                    device.cmd_bind_descriptor_sets(
-                        buffer,
-                        vk::PipelineBindPoint::GRAPHICS,
-                        pipeline.layout,
-                        0,
-                        &descriptor_sets[i..=i],
-                        &[],
-                    )
-                };
-
-                unsafe { device.cmd_draw_indexed(buffer, index_count as _, 1, 0, 0, 0) };
+                    _ => device.cmd_draw_indexed(buffer, g.index_count as _, 1, 0, 0, 0),
+                }
+                if let Some(m) = crossfade {
+                    device.cmd_bind_vertex_buffers(buffer, 0, &[m.vertex_buffer, m.vertex_buffer], &[0, 0]);
+                    device.cmd_bind_index_buffer(buffer, m.index_buffer, 0, m.index_type);
+                    device.cmd_draw_indexed(buffer, m.index_count as _, 1, 0, 0, 1);
+                }
+            };
+            if is_model {
+                unsafe { self.record_instances(buffer, i, &instances_in_front) };
             }
+        }
 
-            // end render pass and command buffer
+        // the ui is drawn after FXAA, so the text is not blurred
+        if let Some(fxaa) = &self.fxaa {
             unsafe {
                 device.cmd_end_render_pass(buffer);
-                device.end_command_buffer(buffer).unwrap();
-            };
+                fxaa.record(device, buffer, target.framebuffer, self.swapchain_properties.extent);
+            }
+        }
+
+        #[cfg(feature = "ui")]
+        if target.ui {
+            unsafe { self.ui.record(device, buffer, frame, self.swapchain_properties.extent) };
+        }
+
+        // end render pass, then copy the image if requested and transition it for presenting
+        unsafe { device.cmd_end_render_pass(buffer) };
+        let format = self.swapchain_properties.format.format;
+        let mut layout = vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL;
+        if let Some(readback) = target.readback {
+            let copy_layout = vk::ImageLayout::TRANSFER_SRC_OPTIMAL;
+            Self::record_layout_transition(device, buffer, target.image, 1, format, layout, copy_layout, 1);
+            unsafe { Self::record_readback(device, buffer, target.image, readback, self.swapchain_properties.extent) };
+            layout = copy_layout;
+        }
+        if layout != target.layout {
+            Self::record_layout_transition(device, buffer, target.image, 1, format, layout, target.layout, 1);
+        }
+
+        // end command buffer
+        unsafe {
+            self.gpu_timer.write_end(device, buffer, frame);
+            device.end_command_buffer(buffer).unwrap();
+        };
+    }
+
+    /// Records the draws of `instances` with the model pipeline, which must be bound.
+    /// The descriptor sets and push constants of the main model are bound again after.
+    unsafe fn record_instances(&self, buffer: vk::CommandBuffer, image_index: usize, instances: &[&ModelInstance]) {
+        if instances.is_empty() {
+            return;
+        }
+        let device = self.vk_context.device();
+        let layout = self.pipeline.layout;
+        let i = image_index;
+        for instance in instances {
+            let model = instance.model_matrix();
+            let sets = instance.material.as_ref()
+                .map_or(&self.descriptor_sets, |material| &material.descriptor_sets);
+            device.cmd_bind_descriptor_sets(buffer, vk::PipelineBindPoint::GRAPHICS, layout, 0, &sets[i..=i], &[]);
+            let push_constants = Self::push_constants_for(model);
+            device.cmd_push_constants(buffer, layout, vk::ShaderStageFlags::VERTEX, 0, push_constants.as_bytes());
+            // without a morph target the second vertex buffer is the same as the first
+            let g = &instance.geometry;
+            device.cmd_bind_vertex_buffers(buffer, 0, &[g.vertex_buffer, g.vertex_buffer], &[0, 0]);
+            device.cmd_bind_index_buffer(buffer, g.index_buffer, 0, g.index_type);
+            device.cmd_draw_indexed(buffer, g.index_count as _, 1, 0, 0, 0);
+        }
+        let sets = &self.descriptor_sets[i..=i];
+        device.cmd_bind_descriptor_sets(buffer, vk::PipelineBindPoint::GRAPHICS, layout, 0, sets, &[]);
+        let push_constants = self.push_constants();
+        device.cmd_push_constants(buffer, layout, vk::ShaderStageFlags::VERTEX, 0, push_constants.as_bytes());
+    }
+
+    /// Returns the model instances inside the view frustum split into those drawn before
+    /// and after the main model. A transparent model is blended over what is behind it,
+    /// so then the instances are ordered back to front around it by their centers.
+    /// Otherwise all are drawn after the main model.
+    fn instance_draw_order(&self) -> (Vec<&ModelInstance>, Vec<&ModelInstance>) {
+        let frustum = Frustum::from_matrix(self.projection_matrix() * self.view_matrix);
+        let visible = self.instances.iter()
+            .filter(|instance| instance.aabb.transformed_by(&instance.model_matrix()).intersects_frustum(&frustum))
+            .collect::<Vec<_>>();
+        if !self.transparent {
+            return (Vec::new(), visible);
         }
+        // the camera looks along -z in view space, so the farthest model has the lowest z
+        let depth = |model: Matrix4, aabb: &Aabb| (self.view_matrix * model * aabb.center().to_point()).z();
+        let depths = visible.iter()
+            .map(|instance| depth(instance.model_matrix(), &instance.aabb))
+            .collect::<Vec<_>>();
+        let model_depth = depth(self.model_matrix * self.initial_model_matrix, &self.model_aabb);
+        let (behind, in_front) = back_to_front(&depths, model_depth);
+        (behind.into_iter().map(|i| visible[i]).collect(), in_front.into_iter().map(|i| visible[i]).collect())
+    }
 
-        buffers
+    /// Records the copy of `image` in the `TRANSFER_SRC_OPTIMAL` layout to `readback`,
+    /// which can be read on the CPU once the command buffer finished.
+    unsafe fn record_readback(
+        device: &Device,
+        buffer: vk::CommandBuffer,
+        image: vk::Image,
+        readback: vk::Buffer,
+        extent: vk::Extent2D,
+    ) {
+        let region = vk::BufferImageCopy::default()
+            .image_subresource(vk::ImageSubresourceLayers {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                mip_level: 0,
+                base_array_layer: 0,
+                layer_count: 1,
+            })
+            .image_extent(vk::Extent3D { width: extent.width, height: extent.height, depth: 1 });
+        device.cmd_copy_image_to_buffer(buffer, image, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, readback, &[region]);
+        let barrier = vk::BufferMemoryBarrier::default()
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::HOST_READ)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .buffer(readback)
+            .size(vk::WHOLE_SIZE);
+        device.cmd_pipeline_barrier(
+            buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::HOST,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[barrier],
+            &[],
+        );
     }
 
     fn create_sync_objects(device: &Device) -> InFlightFrames {
@@ -1557,30 +2840,56 @@ impl VkApp {
         InFlightFrames::new(sync_objects_vec)
     }
 
+    /// Waits until the GPU finished all work. A lost device is only logged,
+    /// so the app can still be dropped afterwards.
     pub fn wait_gpu_idle(&self) {
-        unsafe { self.vk_context.device().device_wait_idle().unwrap() };
+        if let Err(err) = unsafe { self.vk_context.device().device_wait_idle() } {
+            log::error!("Failed to wait for the GPU: {err}");
+        }
+    }
+
+    /// Waits until the GPU finished the frames in flight, unlike `wait_gpu_idle`
+    /// other work like background uploads is not waited for.
+    fn wait_frames_in_flight(&self) {
+        let fences = self.in_flight_frames.sync_objects.iter().map(|o| o.fence).collect::<Vec<_>>();
+        if let Err(err) = unsafe { self.vk_context.device().wait_for_fences(&fences, true, u64::MAX) } {
+            log::error!("Failed to wait for the frames in flight: {err}");
+        }
     }
 
     /// Draws a frame.
     ///
     /// #Returns
     ///
-    /// True if the swapchain is dirty and needs to be recreated.
-    pub fn draw_frame(&mut self) -> bool {
+    /// True if the swapchain is dirty and needs to be recreated. An error if the frame
+    /// could not be drawn, after `DrawError::DeviceLost` the app has to be recreated.
+    pub fn draw_frame(&mut self) -> Result<bool, DrawError> {
         log::trace!("Drawing frame.");
+        self.process_model_loads(false);
+        self.process_texture_loads();
+
+        // the loader is cloned as the frame is recorded with a mutable borrow
+        let (swapchain, swapchain_khr) = match &self.render_target {
+            RenderTarget::Swapchain { loader, swapchain_khr } => (loader.clone(), *swapchain_khr),
+            RenderTarget::Offscreen { .. } => {
+                self.render_offscreen(self.images[0], self.swapchain_framebuffers[0], true)?;
+                return Ok(false);
+            }
+        };
+
+        let frame = self.in_flight_frames.current_frame;
+        let command_buffer = self.command_buffers[frame];
         let sync_objects = self.in_flight_frames.next().unwrap();
         let image_available_semaphore = sync_objects.image_available_semaphore;
         let render_finished_semaphore = sync_objects.render_finished_semaphore;
         let in_flight_fence = sync_objects.fence;
         let wait_fences = [in_flight_fence];
 
-        unsafe {
-            self.vk_context.device().wait_for_fences(&wait_fences, true, u64::MAX).unwrap()
-        };
+        unsafe { self.vk_context.device().wait_for_fences(&wait_fences, true, u64::MAX)? };
 
         let result = unsafe {
-            self.swapchain.acquire_next_image(
-                self.swapchain_khr,
+            swapchain.acquire_next_image(
+                swapchain_khr,
                 u64::MAX,
                 image_available_semaphore,
                 vk::Fence::null(),
@@ -1590,15 +2899,31 @@ impl VkApp {
             // ignore suboptimal swap chain here because we already aquired an image
             Ok((image_index, _suboptimal)) => image_index,
             Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
-                return true;
+                return Ok(true);
+            }
+            Err(error) => {
+                log::error!("Error while acquiring next image. Cause: {error}");
+                return Err(error.into());
             }
-            Err(error) => panic!("Error while acquiring next image. Cause: {}", error),
         };
 
         // it is important to only reset the fence when we know that we are going to do work
-        unsafe { self.vk_context.device().reset_fences(&wait_fences).unwrap() };
+        unsafe { self.vk_context.device().reset_fences(&wait_fences)? };
 
+        self.gpu_timer.read(self.vk_context.device(), frame);
+        self.read_back(frame);
+        self.release_retired(frame);
+        #[cfg(feature = "ui")]
+        self.ui.prepare(&self.vk_context, frame);
         self.update_uniform_buffers(image_index);
+        let target = FrameTarget {
+            framebuffer: self.swapchain_framebuffers[image_index as usize],
+            image: self.images[image_index as usize],
+            layout: vk::ImageLayout::PRESENT_SRC_KHR,
+            readback: self.take_capture_request(frame),
+            ui: true,
+        };
+        self.record_command_buffer(command_buffer, frame, image_index as usize, &target);
 
         let device = self.vk_context.device();
         let wait_semaphores = [image_available_semaphore];
@@ -1607,19 +2932,18 @@ impl VkApp {
         // Submit command buffer
         {
             let wait_stages = [vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT];
-            let command_buffers = [self.command_buffers[image_index as usize]];
+            let command_buffers = [command_buffer];
             let submit_info = vk::SubmitInfo::default()
                 .wait_semaphores(&wait_semaphores)
                 .wait_dst_stage_mask(&wait_stages)
                 .command_buffers(&command_buffers)
                 .signal_semaphores(&signal_semaphores);
             let submit_infos = [submit_info];
-            unsafe {
-                device.queue_submit(self.graphics_queue, &submit_infos, in_flight_fence).unwrap()
-            };
+            unsafe { device.queue_submit(self.graphics_queue, &submit_infos, in_flight_fence)? };
         }
+        self.gpu_timer.submitted[frame] = true;
 
-        let swapchains = [self.swapchain_khr];
+        let swapchains = [swapchain_khr];
         let images_indices = [image_index];
         let present_info = vk::PresentInfoKHR::default()
             .wait_semaphores(&signal_semaphores)
@@ -1627,214 +2951,2104 @@ impl VkApp {
             .image_indices(&images_indices);
         // .results() null since we only have one swapchain
         let result = unsafe {
-            self.swapchain.queue_present(self.present_queue, &present_info)
+            swapchain.queue_present(self.present_queue, &present_info)
         };
         match result {
-            Ok(value) => value,
-            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => true,
-            Err(error) => panic!("Failed to present queue. Cause: {}", error),
+            Ok(suboptimal) => Ok(suboptimal),
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => Ok(true),
+            Err(error) => {
+                log::error!("Failed to present queue. Cause: {error}");
+                Err(error.into())
+            }
         }
     }
 
-    pub fn load_new_texture<P: AsRef<Path>>(&mut self, path: P) -> Result<(), anyhow::Error> {
-        log::info!("Loading image {:?}", path.as_ref().as_os_str());
-        self.wait_gpu_idle();
-
-        let texture = Self::create_texture_image(
-            &self.vk_context,
-            self.command_pool,
-            self.graphics_queue,
-            path,
-        )?;
+    /// Renders a frame into `image` with `framebuffer`, which must not be a swapchain image,
+    /// and waits until it is finished. A requested capture is read back right away.
+    fn render_offscreen(&mut self, image: vk::Image, framebuffer: vk::Framebuffer, ui: bool) -> Result<(), DrawError> {
+        let frame = self.in_flight_frames.current_frame;
+        let command_buffer = self.command_buffers[frame];
+        let fence = self.in_flight_frames.next().unwrap().fence;
         let device = self.vk_context.device();
-
-        for set in self.descriptor_sets.iter() {
-            let image_info = vk::DescriptorImageInfo::default()
-                .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
-                .image_view(texture.view)
-                .sampler(texture.sampler.unwrap());
-            let image_infos = [image_info];
-            let sampler_descriptor_write = vk::WriteDescriptorSet::default()
-                .dst_set(*set)
-                .dst_binding(1)
-                .dst_array_element(0)
-                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-                .image_info(&image_infos);
-            unsafe { device.update_descriptor_sets(&[sampler_descriptor_write], &[]) }
+        unsafe {
+            device.wait_for_fences(&[fence], true, u64::MAX)?;
+            device.reset_fences(&[fence])?;
         }
 
-        self.recreate_command_buffers();
-        Ok(())
-    }
+        self.gpu_timer.read(device, frame);
+        self.release_retired(frame);
+        #[cfg(feature = "ui")]
+        self.ui.prepare(&self.vk_context, frame);
+        self.update_uniform_buffers(0);
+        let target = FrameTarget {
+            framebuffer,
+            image,
+            layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            readback: self.take_capture_request(frame),
+            ui,
+        };
+        self.record_command_buffer(command_buffer, frame, 0, &target);
 
-    pub fn load_new_model(&mut self, nobj: NormalizedObj) {
         let device = self.vk_context.device();
-        let (vertices, indices, model_extent) = Self::load_model(nobj);
-        self.initial_model_matrix = UniformBufferObject::model_matrix(
-            model_extent.0,
-            model_extent.1,
-        );
-        self.model_extent = model_extent;
-
-        self.wait_gpu_idle();
-
-        if let Some(g) = self.pipeline.geometry.take() {
-            unsafe { g.cleanup(device) };
+        let command_buffers = [command_buffer];
+        let submit_info = vk::SubmitInfo::default().command_buffers(&command_buffers);
+        unsafe {
+            device.queue_submit(self.graphics_queue, &[submit_info], fence)?;
+            device.wait_for_fences(&[fence], true, u64::MAX)?;
         }
-        self.pipeline.geometry = Some(Geometry::new(
-            &self.vk_context,
-            self.transient_command_pool,
-            self.graphics_queue,
-            &vertices,
-            &indices,
-        ));
+        self.gpu_timer.submitted[frame] = true;
+        self.read_back(frame);
+        Ok(())
+    }
 
-        self.recreate_command_buffers();
+    /// Destroys the retired resources once the fence of every frame in flight was waited on
+    /// since they were replaced, called after waiting on the fence of `frame`.
+    fn release_retired(&mut self, frame: usize) {
+        for retired in self.retired.iter_mut() {
+            retired.pending_frames &= !(1 << frame);
+        }
+        let (done, pending) = std::mem::take(&mut self.retired).into_iter()
+            .partition::<Vec<_>, _>(|retired| retired.pending_frames == 0);
+        self.retired = pending;
+        for retired in done {
+            retired.destroy(&self.vk_context);
+        }
     }
 
-    /// Recreates the swapchain with new dimensions.
+    /// Renders a frame and returns it, only for apps created with `new_headless`.
     ///
-    /// # Panics
+    /// The result is the same as a window of the same size would show.
+    pub fn render_to_image(&mut self) -> Result<image::RgbaImage, anyhow::Error> {
+        if !matches!(self.render_target, RenderTarget::Offscreen { .. }) {
+            anyhow::bail!("Rendering to an image needs a headless app, use request_capture instead");
+        }
+        // unlike windows there are no further frames to finish a load
+        self.process_model_loads(true);
+        self.process_texture_loads();
+        self.request_capture()?;
+        self.render_offscreen(self.images[0], self.swapchain_framebuffers[0], true)?;
+        self.take_captured_frame().context("Failed to read back the frame")
+    }
+
+    /// Renders the model from `count` evenly spaced angles around the y axis and saves
+    /// the frames as `turntable_<index>.png` in `out_dir`, which is created if needed.
+    /// Returns the paths of the saved images.
     ///
-    /// Panics if either `width` or `height` is zero.
-    pub fn recreate_swapchain(&mut self, width: u32, height: u32) {
-        log::debug!("Recreating swapchain");
-        if width == 0 || height == 0 {
-            panic!("invalid dimensions: ({width}, {height})");
+    /// The views are rendered into an offscreen image without the ui, also for apps with
+    /// a window, which keeps showing the last frame. The model matrix is restored afterwards.
+    pub fn render_turntable<P: AsRef<Path>>(
+        &mut self,
+        count: u32,
+        out_dir: P,
+    ) -> Result<Vec<PathBuf>, anyhow::Error> {
+        let out_dir = out_dir.as_ref();
+        if count == 0 {
+            anyhow::bail!("A turntable needs at least one view");
         }
+        std::fs::create_dir_all(out_dir)
+            .with_context(|| format!("Failed to create {}", out_dir.display()))?;
+
+        let format = self.swapchain_properties.format.format;
+        Readback::swizzle(format)?;
+        // the frames in flight use the uniform buffers, pending captures are kept
+        self.finish_captures();
+        self.process_model_loads(true);
+        self.process_texture_loads();
+
+        let (mut target, framebuffer) = self.create_offscreen_target();
+        let model_matrix = self.model_matrix;
+        let result = (0..count).map(|i| {
+            let angle = Deg(360. * i as f32 / count as f32);
+            self.model_matrix = Matrix4::from_angle_y(angle) * model_matrix;
+            self.capture_requested = true;
+            self.render_offscreen(target.image, framebuffer, false)?;
+            // the view was read back last, after older captures of the window
+            let image = self.captured_frames.pop_back().context("Failed to read back the view")?;
+            let path = out_dir.join(format!("turntable_{i:03}.png"));
+            image.save(&path).with_context(|| format!("Failed to save {}", path.display()))?;
+            Ok(path)
+        }).collect();
+        self.model_matrix = model_matrix;
 
         self.wait_gpu_idle();
+        unsafe { self.vk_context.device().destroy_framebuffer(framebuffer, None) };
+        target.destroy(&self.vk_context);
+        result
+    }
 
-        let geometry = self.pipeline.geometry.take();
-        let geometry_cubemap = self.pipeline_cubemap.geometry.take();
-        self.cleanup_swapchain();
+    /// Creates an image with the format and size of the swapchain images and a framebuffer
+    /// with it that can be rendered to with `render_offscreen`.
+    fn create_offscreen_target(&self) -> (Texture, vk::Framebuffer) {
+        let device = self.vk_context.device();
+        let format = self.swapchain_properties.format.format;
+        let extent = self.swapchain_properties.extent;
+        let (image, memory) = Self::create_image(
+            &self.vk_context,
+            MemoryLocation::GpuOnly,
+            extent,
+            1,
+            vk::SampleCountFlags::TYPE_1,
+            format,
+            vk::ImageTiling::OPTIMAL,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC,
+        );
+        let view = Self::create_image_view(device, image, 1, format, vk::ImageAspectFlags::COLOR);
+        let attachments = [self.color_texture.view, self.depth_texture.view, view];
+        let framebuffer_info = vk::FramebufferCreateInfo::default()
+            .render_pass(self.render_pass)
+            .attachments(&attachments)
+            .width(extent.width)
+            .height(extent.height)
+            .layers(1);
+        let framebuffer = unsafe { device.create_framebuffer(&framebuffer_info, None).unwrap() };
+        (Texture::new(image, memory, view, None, 1), framebuffer)
+    }
+
+    pub fn load_new_texture<P: AsRef<Path>>(&mut self, path: P) -> Result<(), anyhow::Error> {
+        log::info!("Loading image {:?}", path.as_ref().as_os_str());
+        self.wait_gpu_idle();
+
+        let texture = Self::create_texture_image(
+            &self.vk_context,
+            &mut self.staging,
+            self.command_pool,
+            self.graphics_queue,
+            &path,
+            COLOR_TEXTURE_FORMAT,
+            self.texture_filter,
+            self.anisotropy,
+            self.generate_mips,
+        )?;
+        self.cancel_texture_loads(TextureSlot::Color);
+        self.texture_path = path.as_ref().to_path_buf();
+        self.texture_source = TextureSource::File;
+        self.replace_texture(texture);
+        Ok(())
+    }
+
+    /// Replaces the texture with 8 bit RGBA `pixels` of an image in memory, e.g. generated
+    /// on the CPU, with the first row at v = 0. `texture_path` keeps the last loaded file.
+    pub fn load_texture_from_rgba(&mut self, pixels: &[u8], width: u32, height: u32) -> Result<(), anyhow::Error> {
+        let image = image::RgbaImage::from_raw(width, height, pixels.to_vec()).with_context(|| {
+            let expected = width as usize * height as usize * 4;
+            format!("Expected {expected} bytes for a {width}x{height} RGBA image, got {}", pixels.len())
+        })?;
+        self.replace_texture_with(TexturePixels::Rgba8(image), TextureSource::Memory)
+    }
+
+    /// Replaces the texture with a decoded image, e.g. embedded in the binary. Like image
+    /// files its first row is the top, at v = 1. `texture_path` keeps the last loaded file.
+    pub fn load_texture_from_image(&mut self, image: DynamicImage) -> Result<(), anyhow::Error> {
+        self.replace_texture_with(Self::texture_pixels(image), TextureSource::Memory)
+    }
+
+    /// Replaces the texture with an image file read into memory, in any format that
+    /// can be loaded from disk. `texture_path` keeps the last loaded file.
+    pub fn load_texture_from_memory(&mut self, bytes: &[u8]) -> Result<(), anyhow::Error> {
+        let image = image::load_from_memory(bytes).context("Failed to decode image")?;
+        self.load_texture_from_image(image)
+    }
+
+    fn replace_texture_with(&mut self, pixels: TexturePixels, source: TextureSource) -> Result<(), anyhow::Error> {
+        self.wait_gpu_idle();
+        let texture = Self::create_texture_from_image(
+            &self.vk_context,
+            &mut self.staging,
+            self.command_pool,
+            self.graphics_queue,
+            pixels,
+            COLOR_TEXTURE_FORMAT,
+            self.texture_filter,
+            self.anisotropy,
+            self.generate_mips,
+        )?;
+        self.cancel_texture_loads(TextureSlot::Color);
+        self.texture_source = source;
+        self.replace_texture(texture);
+        Ok(())
+    }
+
+    /// Replaces the texture with a colored checkerboard to check the texture coordinates.
+    pub fn load_checkerboard(&mut self) -> Result<(), anyhow::Error> {
+        log::info!("Loading checkerboard texture");
+        let image = texture::uv_checkerboard(CHECKERBOARD_SIZE, CHECKERBOARD_CELLS);
+        self.replace_texture_with(TexturePixels::Rgba8(image), TextureSource::Checkerboard)
+    }
+
+    /// Replaces the texture with the white one used when no image could be loaded.
+    pub fn load_white_texture(&mut self) -> Result<(), anyhow::Error> {
+        self.replace_texture_with(TexturePixels::Rgba8(Self::white_image()), TextureSource::White)
+    }
+
+    /// Returns where the texture comes from, `texture_path` is only used for `TextureSource::File`.
+    pub fn texture_source(&self) -> TextureSource {
+        self.texture_source
+    }
+
+    fn replace_texture(&mut self, texture: Texture) {
+        let device = self.vk_context.device();
+        for set in self.descriptor_sets.iter() {
+            let image_info = vk::DescriptorImageInfo::default()
+                .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                .image_view(texture.view)
+                .sampler(texture.sampler.unwrap());
+            let image_infos = [image_info];
+            let sampler_descriptor_write = vk::WriteDescriptorSet::default()
+                .dst_set(*set)
+                .dst_binding(1)
+                .dst_array_element(0)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .image_info(&image_infos);
+            unsafe { device.update_descriptor_sets(&[sampler_descriptor_write], &[]) }
+        }
+        let old_texture = std::mem::replace(&mut self.textures[0], texture);
+        self.retire_texture(old_texture);
+    }
+
+    /// Loads a tangent-space normal map with y pointing up, in the direction of increasing v.
+    ///
+    /// The normal map applies to the whole model including all materials of a scene.
+    pub fn load_normal_map<P: AsRef<Path>>(&mut self, path: P) -> Result<(), anyhow::Error> {
+        log::info!("Loading normal map {:?}", path.as_ref().as_os_str());
+        self.wait_gpu_idle();
+
+        let texture = Self::create_texture_image(
+            &self.vk_context,
+            &mut self.staging,
+            self.command_pool,
+            self.graphics_queue,
+            &path,
+            DATA_TEXTURE_FORMAT,
+            self.texture_filter,
+            self.anisotropy,
+            self.generate_mips,
+        )?;
+        self.cancel_texture_loads(TextureSlot::NormalMap);
+        self.replace_normal_map(texture);
+        Ok(())
+    }
+
+    /// Removes the normal map, so the geometric normals are used again.
+    ///
+    /// Normal maps still loading from `request_normal_map_load` are discarded.
+    pub fn clear_normal_map(&mut self) -> Result<(), anyhow::Error> {
+        self.cancel_texture_loads(TextureSlot::NormalMap);
+        self.wait_gpu_idle();
+        let texture = Self::create_flat_normal_map(
+            &self.vk_context,
+            &mut self.staging,
+            self.command_pool,
+            self.graphics_queue,
+            self.anisotropy,
+        )?;
+        self.replace_normal_map(texture);
+        Ok(())
+    }
+
+    fn replace_normal_map(&mut self, texture: Texture) {
+        let device = self.vk_context.device();
+        for set in self.descriptor_sets.iter().chain(self.material_descriptor_sets()) {
+            let image_info = vk::DescriptorImageInfo::default()
+                .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                .image_view(texture.view)
+                .sampler(texture.sampler.unwrap());
+            let image_infos = [image_info];
+            let sampler_descriptor_write = vk::WriteDescriptorSet::default()
+                .dst_set(*set)
+                .dst_binding(3)
+                .dst_array_element(0)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .image_info(&image_infos);
+            unsafe { device.update_descriptor_sets(&[sampler_descriptor_write], &[]) }
+        }
+        let old_texture = std::mem::replace(&mut self.textures[2], texture);
+        self.retire_texture(old_texture);
+    }
+
+    /// Destroys `texture` once no frame in flight uses it anymore.
+    fn retire_texture(&mut self, texture: Texture) {
+        self.retired.push(Retired {
+            geometries: Vec::new(),
+            scene: None,
+            textures: vec![texture],
+            pending_frames: (1 << MAX_FRAMES_IN_FLIGHT) - 1,
+        });
+    }
+
+    /// Loads a new cubemap for the skybox and updates binding 2 of all descriptor sets.
+    pub fn load_new_cubemap(&mut self, paths: &CubemapPaths) -> Result<(), anyhow::Error> {
+        log::info!("Loading cubemap {paths:?}");
+        self.wait_gpu_idle();
+
+        let texture = Self::create_cubemap(
+            &self.vk_context,
+            &mut self.staging,
+            self.command_pool,
+            self.graphics_queue,
+            paths,
+            self.generate_mips,
+        )?;
+        let device = self.vk_context.device();
+
+        for set in self.descriptor_sets.iter().chain(self.material_descriptor_sets()) {
+            let image_info = vk::DescriptorImageInfo::default()
+                .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                .image_view(texture.view)
+                .sampler(texture.sampler.unwrap());
+            let image_infos = [image_info];
+            let sampler_descriptor_write = vk::WriteDescriptorSet::default()
+                .dst_set(*set)
+                .dst_binding(2)
+                .dst_array_element(0)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .image_info(&image_infos);
+            unsafe { device.update_descriptor_sets(&[sampler_descriptor_write], &[]) }
+        }
+        let mut old_texture = std::mem::replace(&mut self.textures[1], texture);
+        old_texture.destroy(&self.vk_context);
+        self.cubemap_paths = paths.clone();
+
+        Ok(())
+    }
+
+    /// Loads the current cubemap again, e.g. to apply a change of `generate_mips`.
+    pub fn reload_cubemap(&mut self) -> Result<(), anyhow::Error> {
+        let paths = self.cubemap_paths.clone();
+        self.load_new_cubemap(&paths)
+    }
+
+    /// Returns the path of the currently displayed texture image, it is empty
+    /// if no image was loaded yet.
+    pub fn texture_path(&self) -> &Path {
+        &self.texture_path
+    }
+
+    /// Returns the statistics of the currently displayed model.
+    pub fn model_stats(&self) -> &ModelStats {
+        &self.model_stats
+    }
+
+    /// Writes the model as displayed, with the shading and texture coordinates
+    /// applied, as obj file, see `NormalizedObj::write`.
+    pub fn write_model<W: io::Write>(&self, writer: W) -> io::Result<()> {
+        self.model_obj.write(writer)
+    }
+
+    /// Returns the bounding box of the current model in model space, before
+    /// `initial_model_matrix` centers and scales it.
+    pub fn model_aabb(&self) -> Aabb {
+        self.model_aabb
+    }
+
+    /// Replaces the model, pending background loads started by `request_model_load` are cancelled.
+    pub fn load_new_model(&mut self, nobj: NormalizedObj) {
+        self.cancel_model_loads();
+        self.model_stats = nobj.stats();
+        self.replace_model(&ModelMeshes::load(nobj, self.uv_mode, self.shading));
+        self.frame_model();
+    }
+
+    /// Returns the camera distance from the model center at which the bounding sphere
+    /// of the model fits into the view, including the rotation and scale of `model_matrix`.
+    pub fn framing_distance(&self) -> f32 {
+        let area = self.render_area_for(self.get_extent()).extent;
+        let aspect = area.width as f32 / area.height as f32;
+        let half_fov_y = FOV_Y.to_radians().0 / 2.;
+        // on narrow windows the horizontal field of view is the limiting one
+        let half_fov = half_fov_y.min((half_fov_y.tan() * aspect).atan());
+        self.bounding_radius() / half_fov.sin() * FRAME_MARGIN
+    }
+
+    /// Returns the distance of the camera from the model center.
+    fn camera_distance(&self) -> f32 {
+        let center = self.model_matrix[3].truncate();
+        (UniformBufferObject::view_position(self.view_matrix) - center).magnitude()
+    }
+
+    /// Returns the radius of the bounding sphere of the model in world space.
+    fn bounding_radius(&self) -> f32 {
+        // initial_model_matrix scales the largest side to 1
+        let radius = self.model_radius / self.model_aabb.max_extent();
+        let scale = (0..3)
+            .map(|i| self.model_matrix[i].truncate())
+            .map(|column| column.magnitude())
+            .fold(0., f32::max);
+        radius * scale
+    }
+
+    /// Returns the radius of a sphere around the center of `aabb` containing `vertices`.
+    ///
+    /// The tight bounding sphere is usually smaller than the one around the bounding box,
+    /// but the model is framed around the box center, so the offset of the centers is added.
+    fn model_radius(aabb: Aabb, vertices: &[Vertex]) -> f32 {
+        let (center, radius) = math::bounding_sphere(vertices.iter().map(|vertex| Vector3::from(vertex.pos)));
+        let box_radius = aabb.extents().magnitude() / 2.;
+        box_radius.min((center - aabb.center()).magnitude() + radius)
+    }
+
+    /// Moves the camera towards or away from the model center so that the whole model
+    /// is visible. The direction the model is viewed from is kept.
+    pub fn frame_model(&mut self) {
+        let center = self.model_matrix[3].truncate();
+        let eye = UniformBufferObject::view_position(self.view_matrix);
+        let dir = if (eye - center).magnitude() > f32::EPSILON {
+            eye - center
+        } else {
+            Vector3::from([0., 0., 1.])
+        };
+        self.view_model_from(dir);
+    }
+
+    /// Moves the camera to look at the model center from `dir`, e.g. `[0., 1., 0.]`
+    /// for a view from the top, at the distance at which the whole model is visible.
+    pub fn view_model_from(&mut self, dir: Vector3) {
+        let center = self.model_matrix[3].truncate();
+        let dir = dir.normalize();
+        let up = if dir.y().abs() > 0.999 { [0., 0., -dir.y()] } else { [0., 1., 0.] };
+        self.view_matrix = Matrix4::look_at_rh(
+            center + dir * self.framing_distance(),
+            center,
+            Vector3::from(up),
+        );
+    }
+
+    /// Replaces the geometry of the model and removes a loaded scene.
+    fn replace_model(&mut self, meshes: &ModelMeshes) {
+        self.wait_gpu_idle();
+        let geometries = meshes.geometries(|vertices, indices| Geometry::new(
+            &self.vk_context,
+            &mut self.staging,
+            self.transient_command_pool,
+            self.graphics_queue,
+            vertices,
+            indices,
+        ));
+        self.set_model(meshes, geometries);
+    }
+
+    /// Swaps in the uploaded `geometries` of `meshes`, the GPU must be idle.
+    fn set_model(&mut self, meshes: &ModelMeshes, geometries: ModelGeometries) {
+        self.initial_model_matrix = UniformBufferObject::model_matrix(meshes.aabb);
+        self.model_aabb = meshes.aabb;
+        self.model_radius = Self::model_radius(meshes.aabb, &meshes.vertices);
+        self.model_obj = meshes.obj.clone();
+
+        let old_geometries = [
+            std::mem::replace(&mut self.pipeline.geometry, geometries.model),
+            self.pipeline.morph_geometry.take(),
+            std::mem::replace(&mut self.pipeline_points.geometry, geometries.points),
+            self.pipeline_uv.geometry.replace(geometries.uv_layout),
+            std::mem::replace(&mut self.pipeline_normals.geometry, geometries.normals),
+            self.pipeline_bbox.geometry.replace(geometries.bbox),
+        ];
+        // the old model may still be used by frames in flight
+        self.retired.push(Retired {
+            geometries: old_geometries.into_iter().flatten().collect(),
+            scene: self.scene.take(),
+            textures: Vec::new(),
+            pending_frames: (1 << MAX_FRAMES_IN_FLIGHT) - 1,
+        });
+        self.morph_weight = 0.;
+    }
+
+    /// Returns the triangle of the main model or of a model added with `add_model`
+    /// closest to the camera under the pixel `x`, `y` of the window, or `None` if there
+    /// is none. A loaded morph target is not considered.
+    pub fn pick(&self, x: f32, y: f32) -> Option<PickResult> {
+        let area = self.render_area_for(self.get_extent());
+        let ndc_x = (x - area.offset.x as f32) / area.extent.width as f32 * 2. - 1.;
+        let ndc_y = (y - area.offset.y as f32) / area.extent.height as f32 * 2. - 1.;
+        if !(-1. ..=1.).contains(&ndc_x) || !(-1. ..=1.).contains(&ndc_y) {
+            return None;
+        }
+        // unproject straight into model space, so the vertices need no transformation. Each
+        // ray goes from the near to the far plane, so distances along them are comparable.
+        let view_proj = self.projection_matrix() * self.view_matrix;
+        let model_positions = self.model_obj.vertices.iter()
+            .map(|vertex| Vector3::from(vertex.pos_coords))
+            .collect::<Vec<_>>();
+        let main = (None, self.model_matrix * self.initial_model_matrix, &model_positions, &self.model_obj.indices);
+        let instances = self.instances.iter().map(|instance| {
+            (Some(instance.id), instance.model_matrix(), &instance.positions, &instance.indices)
+        });
+        std::iter::once(main).chain(instances)
+            .filter_map(|(model, matrix, positions, indices)| {
+                let ray = Ray::from_ndc((view_proj * matrix).inverse()?, ndc_x, ndc_y);
+                let (triangle, hit) = ray.intersect_mesh(positions, indices)?;
+                let vertices = [0, 1, 2].map(|i| indices[triangle * 3 + i]);
+                let pick = PickResult {
+                    model,
+                    triangle,
+                    vertices,
+                    barycentric: hit.barycentric,
+                    position: ray.at(hit.t),
+                };
+                Some((pick, hit.t))
+            })
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(pick, _)| pick)
+    }
+
+    /// Loads the first obj file in `dir` together with its materials and their
+    /// diffuse textures and draws each material group with its own texture.
+    /// `dir` can also be a zip archive, then the paths in the obj and mtl files
+    /// are entries in the archive.
+    ///
+    /// Missing material libraries and textures are reported as warnings,
+    /// the affected groups are drawn with the current texture instead.
+    pub fn load_scene<P: AsRef<Path>>(&mut self, dir: P) -> Result<(), anyhow::Error> {
+        let dir = dir.as_ref();
+        log::info!("Loading scene {:?}", dir.as_os_str());
+        // files are named relative to the directory or archive with / as separator
+        let mut source = fs::SceneSource::open(dir).context("Failed to open scene")?;
+        let obj_names = source.names()
+            .context("Failed to read scene directory")?
+            .into_iter()
+            .filter(|name| fs::has_extension(Path::new(name), "obj"))
+            .collect::<Vec<_>>();
+        let obj_name = obj_names.first().context("No obj file found in scene directory")?;
+        let reader = source.load(obj_name).with_context(|| format!("Failed to open {obj_name}"))?;
+        let nobj = NormalizedObj::from_reader(reader)
+            .with_context(|| format!("Failed to parse {obj_name}"))?;
+
+        let mut materials = HashMap::<String, Material>::new();
+        for mtllib in &nobj.mtllibs {
+            let name = fs::resolve_entry(obj_name, mtllib);
+            let mtl = match source.load(&name) {
+                Ok(reader) => Mtl::from_reader(reader).map_err(anyhow::Error::from),
+                Err(err) => Err(err.into()),
+            };
+            match mtl {
+                Ok(mtl) => materials.extend(mtl.materials.into_iter().map(|m| (m.name.clone(), m))),
+                Err(err) => log::warn!("Failed to load material library {name}: {err}"),
+            }
+        }
+
+        let groups = nobj.groups.iter().map(|group| {
+            let material = group.material.as_ref().and_then(|name| {
+                let material = materials.get(name);
+                if material.is_none() {
+                    log::warn!("Material {name} not found");
+                }
+                material
+            });
+            (group.first_index, group.index_count, material)
+        }).collect::<Vec<_>>();
+        self.model_stats = nobj.stats();
+        let mut model_obj = Self::display_obj(nobj, self.uv_mode, self.shading);
+        // vertices are not shared between groups, so the diffuse color can be stored in them
+        for &(first_index, index_count, material) in &groups {
+            let Some(material) = material else { continue };
+            for &idx in &model_obj.indices[first_index as usize..(first_index + index_count) as usize] {
+                model_obj.vertices[idx as usize].color = material.diffuse;
+            }
+        }
+        let (vertices, model_aabb) = Self::model_vertices(&model_obj);
+        let meshes = ModelMeshes::new(model_obj, vertices, model_aabb);
+        self.cancel_model_loads();
+        self.replace_model(&meshes);
+        let (vertices, indices) = (&meshes.vertices, &meshes.obj.indices);
+
+        let mut textures = Vec::new();
+        let mut texture_indices = HashMap::<String, Option<usize>>::new();
+        let mut draw_groups = Vec::with_capacity(groups.len());
+        for (first_index, index_count, material) in groups {
+            let texture_name = material
+                .and_then(|material| material.diffuse_map.as_ref())
+                .map(|map| fs::resolve_entry(obj_name, map));
+            let material = texture_name.and_then(|name| {
+                *texture_indices.entry(name).or_insert_with_key(|name| {
+                    let texture = source.load(name)
+                        .context("Failed to open image")
+                        .and_then(|reader| {
+                            let image = texture::decode_image(reader, Path::new(name))
+                                .context("Failed to decode image")?;
+                            Ok(Self::texture_pixels(image))
+                        })
+                        .and_then(|pixels| Self::create_texture_from_image(
+                            &self.vk_context,
+                            &mut self.staging,
+                            self.command_pool,
+                            self.graphics_queue,
+                            pixels,
+                            COLOR_TEXTURE_FORMAT,
+                            self.texture_filter,
+                            self.anisotropy,
+                            self.generate_mips,
+                        ));
+                    match texture {
+                        Ok(texture) => {
+                            textures.push(texture);
+                            Some(textures.len() - 1)
+                        }
+                        Err(err) => {
+                            log::warn!("Failed to load texture {name}: {err:#}");
+                            None
+                        }
+                    }
+                })
+            });
+            let group_indices = &indices[first_index as usize..(first_index + index_count) as usize];
+            let aabb = Self::group_aabb(vertices, group_indices);
+            draw_groups.push(DrawGroup { first_index, index_count, material, aabb });
+        }
+        if draw_groups.is_empty() {
+            draw_groups.push(DrawGroup {
+                first_index: 0,
+                index_count: indices.len() as u32,
+                material: None,
+                aabb: model_aabb,
+            });
+        }
+
+        let device = self.vk_context.device();
+        let set_count = self.uniform_buffers.len() as u32;
+        let descriptor_pool = Self::create_descriptor_pool(
+            device,
+            set_count * textures.len().max(1) as u32,
+        );
+        let descriptor_sets = textures.iter().map(|texture| {
+            Self::create_descriptor_sets(
+                device,
+                descriptor_pool,
+                self.descriptor_set_layout,
+                &self.uniform_buffers,
+                [texture, &self.textures[1], &self.textures[2]],
+            )
+        }).collect();
+        log::info!(
+            "Loaded scene with {} groups and {} textures",
+            draw_groups.len(),
+            textures.len(),
+        );
+        self.scene = Some(Scene {
+            textures,
+            descriptor_pool,
+            descriptor_sets,
+            groups: draw_groups,
+        });
+
+        Ok(())
+    }
+
+    /// Adds a model that is displayed next to the main one and returns its id.
+    ///
+    /// It is centered and scaled like the main model, then placed with `transform`
+    /// instead of the model matrix, so it does not rotate with the main model.
+    /// It uses the texture of the main model until `set_model_texture` is called.
+    pub fn add_model(&mut self, nobj: NormalizedObj, transform: Matrix4) -> ModelId {
+        let (vertices, indices, aabb) = Self::load_model(nobj, self.uv_mode, self.shading);
+        let geometry = Geometry::new(
+            &self.vk_context,
+            &mut self.staging,
+            self.transient_command_pool,
+            self.graphics_queue,
+            &vertices,
+            &indices,
+        );
+        let id = ModelId(self.next_model_id);
+        self.next_model_id += 1;
+        self.instances.push(ModelInstance {
+            id,
+            geometry,
+            positions: vertices.iter().map(|vertex| Vector3::from(vertex.pos)).collect(),
+            indices,
+            transform,
+            initial_model_matrix: UniformBufferObject::model_matrix(aabb),
+            aabb,
+            material: None,
+        });
+        log::info!("Added model {id:?} with {} vertices", vertices.len());
+        id
+    }
+
+    /// Removes a model added with `add_model`, returns false if there is none with `id`.
+    pub fn remove_model(&mut self, id: ModelId) -> bool {
+        let Some(idx) = self.instances.iter().position(|instance| instance.id == id) else {
+            return false;
+        };
+        self.wait_gpu_idle();
+        self.instances.remove(idx).destroy(&self.vk_context);
+        true
+    }
+
+    /// Returns the ids of the models added with `add_model` in the order they were added.
+    pub fn model_ids(&self) -> Vec<ModelId> {
+        self.instances.iter().map(|instance| instance.id).collect()
+    }
+
+    pub fn model_transform(&self, id: ModelId) -> Option<Matrix4> {
+        self.instance(id).map(|instance| instance.transform)
+    }
+
+    /// Sets the transform of a model added with `add_model`, returns false if there is none with `id`.
+    pub fn set_model_transform(&mut self, id: ModelId, transform: Matrix4) -> bool {
+        let Some(instance) = self.instances.iter_mut().find(|instance| instance.id == id) else {
+            return false;
+        };
+        instance.transform = transform;
+        true
+    }
+
+    /// Loads the texture of a model added with `add_model`, replacing its previous one.
+    pub fn set_model_texture<P: AsRef<Path>>(&mut self, id: ModelId, path: P) -> Result<(), anyhow::Error> {
+        let idx = self.instances.iter().position(|instance| instance.id == id)
+            .with_context(|| format!("No model with id {id:?}"))?;
+        log::info!("Loading texture {:?} for model {id:?}", path.as_ref().as_os_str());
+        self.wait_gpu_idle();
+
+        let texture = Self::create_texture_image(
+            &self.vk_context,
+            &mut self.staging,
+            self.command_pool,
+            self.graphics_queue,
+            &path,
+            COLOR_TEXTURE_FORMAT,
+            self.texture_filter,
+            self.anisotropy,
+            self.generate_mips,
+        )?;
+        let device = self.vk_context.device();
+        let descriptor_pool = Self::create_descriptor_pool(device, self.uniform_buffers.len() as u32);
+        let descriptor_sets = Self::create_descriptor_sets(
+            device,
+            descriptor_pool,
+            self.descriptor_set_layout,
+            &self.uniform_buffers,
+            [&texture, &self.textures[1], &self.textures[2]],
+        );
+        let material = InstanceMaterial { texture, descriptor_pool, descriptor_sets };
+        if let Some(old_material) = self.instances[idx].material.replace(material) {
+            old_material.destroy(&self.vk_context);
+        }
+        Ok(())
+    }
+
+    fn instance(&self, id: ModelId) -> Option<&ModelInstance> {
+        self.instances.iter().find(|instance| instance.id == id)
+    }
+
+    /// Requests that the next frame is copied to the CPU, it can be taken with
+    /// `take_captured_frame` once the GPU finished it, usually a few frames later.
+    ///
+    /// The copy is recorded after the frame is rendered, before it is presented,
+    /// so it works with any MSAA setting and includes the ui.
+    pub fn request_capture(&mut self) -> Result<(), anyhow::Error> {
+        if let Some(details) = self.vk_context.swapchain_support_details() {
+            if !Self::swapchain_image_usage(&details).contains(vk::ImageUsageFlags::TRANSFER_SRC) {
+                anyhow::bail!("Swapchain images do not support being copied from");
+            }
+        }
+        Readback::swizzle(self.swapchain_properties.format.format)?;
+        self.capture_requested = true;
+        Ok(())
+    }
+
+    /// Returns the oldest captured frame that was not taken yet, see `request_capture`.
+    pub fn take_captured_frame(&mut self) -> Option<image::RgbaImage> {
+        self.captured_frames.pop_front()
+    }
+
+    /// Waits until the GPU finished all frames and reads their captures,
+    /// so `take_captured_frame` returns all requested frames.
+    pub fn finish_captures(&mut self) {
+        self.wait_gpu_idle();
+        // the oldest frame in flight is the next one to be recorded
+        let count = self.readbacks.len();
+        for i in 0..count {
+            self.read_back((self.in_flight_frames.current_frame + i) % count);
+        }
+    }
+
+    /// Returns the readback buffer of `frame` if a capture was requested,
+    /// which is created or resized first. The request is cleared.
+    fn take_capture_request(&mut self, frame: usize) -> Option<vk::Buffer> {
+        if !std::mem::take(&mut self.capture_requested) {
+            return None;
+        }
+        let extent = self.swapchain_properties.extent;
+        let format = self.swapchain_properties.format.format;
+        let readback = &mut self.readbacks[frame];
+        if readback.as_ref().is_some_and(|readback| readback.extent != extent || readback.format != format) {
+            readback.take().unwrap().destroy(&self.vk_context);
+        }
+        let readback = readback.get_or_insert_with(|| Readback::new(&self.vk_context, extent, format));
+        readback.pending = true;
+        Some(readback.buffer)
+    }
+
+    /// Reads the frame copied with the command buffer of `frame` into `captured_frames`.
+    /// Must be called after waiting for the fence of the frame and before recording it again.
+    fn read_back(&mut self, frame: usize) {
+        let Some(readback) = self.readbacks[frame].as_mut().filter(|readback| readback.pending) else {
+            return;
+        };
+        readback.pending = false;
+        match readback.read() {
+            Ok(image) => self.captured_frames.push_back(image),
+            Err(err) => log::warn!("Failed to read back frame: {err:#}"),
+        }
+    }
+
+    /// Loads the model at `path` in the background, the current model is displayed
+    /// until the new one is uploaded. Older requests that are not done yet are cancelled.
+    ///
+    /// The model is parsed on a worker thread and uploaded without waiting for the GPU,
+    /// it is swapped in during the first `draw_frame` after the upload completed.
+    /// Unlike `load_new_model` the camera is not moved, call `frame_model` once
+    /// the ticket is done to fit the model into the view.
+    pub fn request_model_load<P: Into<PathBuf>>(&self, path: P) -> LoadTicket {
+        self.load_queue.request(path)
+    }
+
+    /// Returns a handle to the load queue that can be sent to other threads
+    /// to request model loads with `ModelLoadQueue::request`.
+    pub fn model_load_queue(&self) -> ModelLoadQueue {
+        self.load_queue.clone()
+    }
+
+    /// Loads the texture at `path` in the background, the current texture is displayed
+    /// until the new one is uploaded. Older requests that are not done yet are cancelled.
+    ///
+    /// The image is decoded on a worker thread and uploaded without waiting for the GPU,
+    /// the texture is swapped during the first `draw_frame` after the upload completed.
+    pub fn request_texture_load<P: Into<PathBuf>>(&mut self, path: P) -> LoadTicket {
+        self.request_slot_load(TextureSlot::Color, path.into())
+    }
+
+    /// Loads a normal map in the background like `request_texture_load`,
+    /// see `load_normal_map` for the expected normals.
+    pub fn request_normal_map_load<P: Into<PathBuf>>(&mut self, path: P) -> LoadTicket {
+        self.request_slot_load(TextureSlot::NormalMap, path.into())
+    }
+
+    fn request_slot_load(&mut self, slot: TextureSlot, path: PathBuf) -> LoadTicket {
+        self.cancel_texture_loads(slot);
+        let ticket = LoadTicket::new(self.next_texture_load_id);
+        self.next_texture_load_id += 1;
+        log::info!("Loading {slot:?} texture {} in the background", path.display());
+
+        let worker_path = path.clone();
+        let decoder = thread::spawn(move || Self::decode_texture(worker_path));
+        self.texture_loads.push(TextureLoad {
+            slot,
+            path,
+            ticket: ticket.clone(),
+            cancelled: false,
+            state: TextureLoadState::Decoding(decoder),
+        });
+        ticket
+    }
+
+    /// Cancels the pending loads of `slot`, uploads already submitted are destroyed once they complete.
+    fn cancel_texture_loads(&mut self, slot: TextureSlot) {
+        for load in self.texture_loads.iter_mut().filter(|load| load.slot == slot && !load.cancelled) {
+            load.cancelled = true;
+            load.ticket.finish(Err(anyhow::anyhow!("Cancelled by a newer request")));
+        }
+    }
+
+    /// Advances the background texture loads and swaps in the textures whose upload completed.
+    ///
+    /// Normal maps are swapped in only after the pending color textures, and are cancelled
+    /// when one of those fails, so they never end up on a texture they do not belong to.
+    fn process_texture_loads(&mut self) {
+        let (colors, mut normal_maps) = std::mem::take(&mut self.texture_loads).into_iter()
+            .partition::<Vec<_>, _>(|load| load.slot == TextureSlot::Color);
+        let mut failed = false;
+        for load in colors {
+            match self.advance_texture_load(load, false) {
+                Ok(load) => self.texture_loads.extend(load),
+                Err(()) => failed = true,
+            }
+        }
+        if failed {
+            for load in normal_maps.iter_mut().filter(|load| !load.cancelled) {
+                load.cancelled = true;
+                load.ticket.finish(Err(anyhow::anyhow!("Cancelled because the texture failed to load")));
+            }
+        }
+        let hold = self.texture_loads.iter().any(|load| !load.cancelled);
+        for load in normal_maps {
+            if let Ok(Some(load)) = self.advance_texture_load(load, hold) {
+                self.texture_loads.push(load);
+            }
+        }
+    }
+
+    fn fail_texture_load(path: &Path, ticket: &LoadTicket, err: anyhow::Error) -> Result<Option<TextureLoad>, ()> {
+        log::warn!("Failed to load texture {}: {err:#}", path.display());
+        ticket.finish(Err(err));
+        Err(())
+    }
+
+    /// Returns `load` again if it is not done yet and `Err` if it failed. With `hold`
+    /// the texture is not swapped in yet once its upload completed.
+    fn advance_texture_load(&mut self, mut load: TextureLoad, hold: bool) -> Result<Option<TextureLoad>, ()> {
+        let device = self.vk_context.device();
+        match load.state {
+            // the decoder thread is detached, its result is dropped when it finishes
+            TextureLoadState::Decoding(_) if load.cancelled => Ok(None),
+            TextureLoadState::Decoding(decoder) if !decoder.is_finished() => {
+                load.state = TextureLoadState::Decoding(decoder);
+                Ok(Some(load))
+            }
+            TextureLoadState::Decoding(decoder) => {
+                let pixels = match decoder.join() {
+                    Ok(Ok(pixels)) => pixels,
+                    Ok(Err(err)) => return Self::fail_texture_load(&load.path, &load.ticket, err),
+                    Err(_) => {
+                        let err = anyhow::anyhow!("Decoder thread panicked");
+                        return Self::fail_texture_load(&load.path, &load.ticket, err);
+                    }
+                };
+                let mut staging = StagingBuffer::default();
+                let (texture, commands) = cmd::submit_one_time_commands(
+                    device,
+                    self.transient_command_pool,
+                    self.graphics_queue,
+                    |command_buffer| Self::record_texture_upload(
+                        &self.vk_context,
+                        &mut staging,
+                        command_buffer,
+                        pixels,
+                        load.slot.format(),
+                        self.texture_filter,
+                        self.anisotropy,
+                        self.generate_mips,
+                    ),
+                );
+                match texture {
+                    Ok(texture) => {
+                        load.state = TextureLoadState::Uploading { texture, staging, commands };
+                        Ok(Some(load))
+                    }
+                    Err(err) => {
+                        commands.wait(device);
+                        unsafe {
+                            commands.destroy(device, self.transient_command_pool);
+                            staging.destroy(&self.vk_context);
+                        }
+                        Self::fail_texture_load(&load.path, &load.ticket, err)
+                    }
+                }
+            }
+            TextureLoadState::Uploading { texture, staging, commands }
+                if !commands.is_done(device) || (hold && !load.cancelled) =>
+            {
+                load.state = TextureLoadState::Uploading { texture, staging, commands };
+                Ok(Some(load))
+            }
+            TextureLoadState::Uploading { mut texture, mut staging, commands } => {
+                unsafe {
+                    commands.destroy(device, self.transient_command_pool);
+                    staging.destroy(&self.vk_context);
+                }
+                if load.cancelled {
+                    texture.destroy(&self.vk_context);
+                    return Ok(None);
+                }
+
+                // descriptor sets must not be updated while frames in flight use them,
+                // the old texture itself is retired and other uploads keep running
+                self.wait_frames_in_flight();
+                match load.slot {
+                    TextureSlot::Color => {
+                        self.replace_texture(texture);
+                        self.texture_path = load.path;
+                        self.texture_source = TextureSource::File;
+                    }
+                    TextureSlot::NormalMap => self.replace_normal_map(texture),
+                }
+                log::info!("Finished texture load request {}", load.ticket.id());
+                load.ticket.finish(Ok(()));
+                Ok(None)
+            }
+        }
+    }
+
+    /// Starts loading the models of the pending load requests in the background,
+    /// each request cancels the loads started before it.
+    fn process_model_load_requests(&mut self) {
+        while let Some((path, ticket)) = self.load_queue.pop() {
+            self.cancel_model_loads();
+            log::info!("Processing load request {} for {}", ticket.id(), path.display());
+
+            let worker_path = path.clone();
+            let (uv_mode, shading) = (self.uv_mode, self.shading);
+            let parser = thread::spawn(move || {
+                let nobj = fs::load_model(&worker_path)?;
+                let stats = nobj.stats();
+                Ok((stats, ModelMeshes::load(nobj, uv_mode, shading)))
+            });
+            self.model_loads.push(ModelLoad {
+                path,
+                ticket,
+                cancelled: false,
+                state: ModelLoadState::Parsing(parser),
+            });
+        }
+    }
+
+    /// Cancels the pending model loads, uploads already submitted are destroyed once they complete.
+    fn cancel_model_loads(&mut self) {
+        for load in self.model_loads.iter_mut().filter(|load| !load.cancelled) {
+            load.cancelled = true;
+            load.ticket.finish(Err(anyhow::anyhow!("Cancelled by a newer request")));
+        }
+    }
+
+    /// Advances the background model loads and swaps in the model whose upload completed.
+    /// With `wait` this blocks until all loads are done.
+    fn process_model_loads(&mut self, wait: bool) {
+        self.process_model_load_requests();
+        for load in std::mem::take(&mut self.model_loads) {
+            if let Some(load) = self.advance_model_load(load, wait) {
+                self.model_loads.push(load);
+            }
+        }
+    }
+
+    fn fail_model_load(path: &Path, ticket: &LoadTicket, err: anyhow::Error) -> Option<ModelLoad> {
+        log::warn!("Failed to load model {}: {err:#}", path.display());
+        ticket.finish(Err(err));
+        None
+    }
+
+    /// Returns `load` again if it is not done yet.
+    fn advance_model_load(&mut self, mut load: ModelLoad, wait: bool) -> Option<ModelLoad> {
+        let device = self.vk_context.device();
+        match load.state {
+            // the parser thread is detached, its result is dropped when it finishes
+            ModelLoadState::Parsing(_) if load.cancelled => None,
+            ModelLoadState::Parsing(parser) if !wait && !parser.is_finished() => {
+                load.state = ModelLoadState::Parsing(parser);
+                Some(load)
+            }
+            ModelLoadState::Parsing(parser) => {
+                let (stats, meshes) = match parser.join() {
+                    Ok(Ok(model)) => model,
+                    Ok(Err(err)) => return Self::fail_model_load(&load.path, &load.ticket, err),
+                    Err(_) => {
+                        let err = anyhow::anyhow!("Parser thread panicked");
+                        return Self::fail_model_load(&load.path, &load.ticket, err);
+                    }
+                };
+                let mut stagings = Vec::new();
+                let (geometries, commands) = cmd::submit_one_time_commands(
+                    device,
+                    self.transient_command_pool,
+                    self.graphics_queue,
+                    |command_buffer| meshes.geometries(|vertices, indices| Geometry::record_upload(
+                        &self.vk_context,
+                        &mut stagings,
+                        command_buffer,
+                        vertices,
+                        indices,
+                    )),
+                );
+                let upload = ModelUpload { stats, meshes, geometries, stagings, commands };
+                load.state = ModelLoadState::Uploading(Box::new(upload));
+                self.advance_model_load(load, wait)
+            }
+            ModelLoadState::Uploading(upload) if !wait && !upload.commands.is_done(device) => {
+                load.state = ModelLoadState::Uploading(upload);
+                Some(load)
+            }
+            ModelLoadState::Uploading(upload) => {
+                let ModelUpload { stats, meshes, geometries, mut stagings, commands } = *upload;
+                commands.wait(device);
+                unsafe {
+                    commands.destroy(device, self.transient_command_pool);
+                    for staging in stagings.iter_mut() {
+                        staging.destroy(&self.vk_context);
+                    }
+                }
+                if load.cancelled {
+                    geometries.destroy(&self.vk_context);
+                    return None;
+                }
+
+                self.model_stats = stats;
+                self.set_model(&meshes, geometries);
+                log::info!("Finished load request {} for {}", load.ticket.id(), load.path.display());
+                load.ticket.finish(Ok(()));
+                None
+            }
+        }
+    }
+
+    /// Bounding box of the vertices referenced by `indices`.
+    fn group_aabb(vertices: &[Vertex], indices: &[u32]) -> Aabb {
+        Aabb::from_points(indices.iter().map(|&idx| Vector3::from(vertices[idx as usize].pos)))
+    }
+
+    /// Loads a second model to morph the current one into with `morph_weight`.
+    ///
+    /// The morph target is scaled and moved so that its bounding box matches the
+    /// one of the current model. If both have the same vertex count the vertex
+    /// positions are interpolated, otherwise both models are crossfaded.
+    ///
+    /// Fails if either model is a point cloud, as points are not morphed.
+    pub fn load_morph_target(&mut self, nobj: NormalizedObj) -> Result<(), anyhow::Error> {
+        if self.pipeline.geometry.is_none() || nobj.indices.is_empty() {
+            anyhow::bail!("Point clouds can not be morphed");
+        }
+        let (mut vertices, indices, aabb) = Self::load_model(nobj, self.uv_mode, self.shading);
+        let scale = self.model_aabb.max_extent() / aabb.max_extent();
+        let center = aabb.center();
+        let base_center = self.model_aabb.center();
+        for vertex in vertices.iter_mut() {
+            vertex.pos = ((Vector3::from(vertex.pos) - center) * scale + base_center).into();
+        }
+        if vertices.len() == self.pipeline.geometry.as_ref().map(|g| g.vertex_count).unwrap_or_default() {
+            log::debug!("Morph target has matching vertex count, interpolating positions");
+        } else {
+            log::debug!("Morph target vertex count differs, using crossfade");
+        }
+
+        self.wait_gpu_idle();
+
+        if let Some(g) = self.pipeline.morph_geometry.take() {
+            unsafe { g.cleanup(&self.vk_context) };
+        }
+        self.pipeline.morph_geometry = Some(Geometry::new(
+            &self.vk_context,
+            &mut self.staging,
+            self.transient_command_pool,
+            self.graphics_queue,
+            &vertices,
+            &indices,
+        ));
+        Ok(())
+    }
+
+    pub fn has_morph_target(&self) -> bool {
+        self.pipeline.morph_geometry.is_some()
+    }
+
+    /// Whether a scene loaded with `load_scene` is displayed instead of a single model.
+    pub fn has_scene(&self) -> bool {
+        self.scene.is_some()
+    }
+
+    pub fn show_cubemap(&self) -> bool {
+        self.show_cubemap
+    }
+
+    /// Shows or hides the skybox starting with the next frame.
+    pub fn set_show_cubemap(&mut self, show: bool) {
+        self.show_cubemap = show;
+    }
+
+    pub fn show_uv_layout(&self) -> bool {
+        self.show_uv_layout
+    }
+
+    /// Shows or hides the overlay displaying the texture coordinates of the model.
+    pub fn set_show_uv_layout(&mut self, show: bool) {
+        self.show_uv_layout = show;
+    }
+
+    pub fn show_grid(&self) -> bool {
+        self.show_grid
+    }
+
+    /// Shows or hides the ground grid and the world axes.
+    pub fn set_show_grid(&mut self, show: bool) {
+        self.show_grid = show;
+    }
+
+    pub fn show_normals(&self) -> bool {
+        self.show_normals
+    }
+
+    /// Shows or hides a line along the normal of each vertex of the model.
+    pub fn set_show_normals(&mut self, show: bool) {
+        self.show_normals = show;
+    }
+
+    pub fn show_bbox(&self) -> bool {
+        self.show_bbox
+    }
+
+    /// Shows or hides the bounding box the model is normalized with.
+    pub fn set_show_bbox(&mut self, show: bool) {
+        self.show_bbox = show;
+    }
+
+    pub fn show_depth(&self) -> bool {
+        self.show_depth
+    }
+
+    /// Colors the model by its distance from the camera between the clip planes instead
+    /// of lighting it, from white at the near to black at the far plane. Fragments close
+    /// to the near plane are tinted red and close to the far plane blue.
+    pub fn set_show_depth(&mut self, show: bool) {
+        self.show_depth = show;
+    }
+
+    /// Returns the near and far plane of the projection.
+    ///
+    /// Unless they are set with `set_clip_planes` they enclose the bounding sphere of
+    /// the model as seen from the camera. The near plane is never further than `NEAR`
+    /// and the far plane never closer than `FAR`, so other objects close to the camera
+    /// and the skybox stay visible.
+    pub fn clip_planes(&self) -> (f32, f32) {
+        if let Some(planes) = self.clip_planes {
+            return planes;
+        }
+        clip_planes_around(self.camera_distance(), self.bounding_radius())
+    }
+
+    /// Sets the near and far plane of the projection instead of deriving them from the model.
+    /// `near` must be positive and smaller than `far`, otherwise the planes are not changed.
+    pub fn set_clip_planes(&mut self, near: f32, far: f32) {
+        if near > 0. && near < far {
+            self.clip_planes = Some((near, far));
+        } else {
+            log::warn!("Invalid clip planes, near {near} must be positive and smaller than far {far}");
+        }
+    }
+
+    /// Derives the clip planes from the model again, see `clip_planes`.
+    pub fn reset_clip_planes(&mut self) {
+        self.clip_planes = None;
+    }
+
+    /// Returns true if the clip planes were set with `set_clip_planes`.
+    pub fn has_custom_clip_planes(&self) -> bool {
+        self.clip_planes.is_some()
+    }
+
+    pub fn clear_color(&self) -> [f32; 4] {
+        self.clear_color
+    }
+
+    /// Sets the background color, which is only visible if the skybox is hidden.
+    pub fn set_clear_color(&mut self, color: [f32; 4]) {
+        self.clear_color = color;
+    }
+
+    /// Returns the target aspect ratio if letterboxing is enabled.
+    pub fn letterbox(&self) -> Option<f32> {
+        self.letterbox
+    }
+
+    /// Restricts rendering to the largest centered area with the aspect ratio `aspect`,
+    /// the rest of the window is filled with the clear color.
+    /// Disables letterboxing if `aspect` is `None`.
+    pub fn set_letterbox(&mut self, aspect: Option<f32>) {
+        self.letterbox = aspect.filter(|aspect| aspect.is_finite() && *aspect > 0.);
+        self.update_viewports();
+    }
+
+    /// Returns the present mode of the current swapchain, which may differ
+    /// from the preferred one if that is not supported.
+    pub fn present_mode(&self) -> vk::PresentModeKHR {
+        self.swapchain_properties.present_mode
+    }
+
+    /// Returns false for all modes if there is no window.
+    pub fn supports_present_mode(&self, mode: vk::PresentModeKHR) -> bool {
+        self.vk_context.swapchain_support_details()
+            .is_some_and(|details| details.present_modes.contains(&mode))
+    }
+
+    /// Sets the preferred present mode and recreates the swapchain,
+    /// FIFO is used instead if `mode` is not supported.
+    pub fn set_present_mode(&mut self, mode: vk::PresentModeKHR) {
+        self.present_mode_preference = mode;
+        self.dirty_swapchain = true;
+    }
+
+    /// Returns the time in milliseconds the GPU spent on the last finished frame.
+    ///
+    /// `None` if the device does not support timestamps or no frame finished yet.
+    pub fn last_gpu_time(&self) -> Option<f32> {
+        self.gpu_timer.last_time
+    }
+
+    /// Sets the egui output to draw on top of the next frames.
+    ///
+    /// `primitives` are the tessellated shapes of the output, textures changes are applied immediately.
+    #[cfg(feature = "ui")]
+    pub fn set_ui(
+        &mut self,
+        textures_delta: &egui::TexturesDelta,
+        primitives: Vec<egui::ClippedPrimitive>,
+        pixels_per_point: f32,
+    ) -> Result<(), anyhow::Error> {
+        self.ui.update(
+            &self.vk_context,
+            self.command_pool,
+            self.graphics_queue,
+            textures_delta,
+            primitives,
+            pixels_per_point,
+        )
+    }
+
+    pub fn msaa_samples(&self) -> vk::SampleCountFlags {
+        self.msaa_samples
+    }
+
+    /// Sets the number of MSAA samples and recreates the swapchain, `TYPE_1` disables MSAA.
+    ///
+    /// Returns an error and keeps the current setting if the device does not support `samples`.
+    pub fn set_msaa(&mut self, samples: vk::SampleCountFlags) -> Result<(), anyhow::Error> {
+        if samples.as_raw().count_ones() != 1 || !self.vk_context.supported_sample_counts().contains(samples) {
+            anyhow::bail!("MSAA sample count {samples:?} is not supported by the device");
+        }
+        if samples != self.msaa_samples {
+            self.msaa_samples = samples;
+            self.rebuild_pipelines = true;
+            self.dirty_swapchain = true;
+        }
+        if samples != vk::SampleCountFlags::TYPE_1 && self.fxaa_enabled {
+            log::info!("Disabling FXAA for MSAA {samples:?}");
+            self.fxaa_enabled = false;
+            self.rebuild_pipelines = true;
+            self.dirty_swapchain = true;
+        }
+        Ok(())
+    }
+
+    pub fn fxaa(&self) -> bool {
+        self.fxaa_enabled
+    }
+
+    /// Enables or disables FXAA and recreates the swapchain. FXAA smooths the edges of the
+    /// rendered image in a post-process pass, which is cheaper than MSAA but blurs details
+    /// like textures a little. MSAA is disabled while FXAA is enabled.
+    pub fn set_fxaa(&mut self, enabled: bool) {
+        if enabled == self.fxaa_enabled {
+            return;
+        }
+        self.fxaa_enabled = enabled;
+        if enabled {
+            self.msaa_samples = vk::SampleCountFlags::TYPE_1;
+        }
+        self.rebuild_pipelines = true;
+        self.dirty_swapchain = true;
+    }
+
+    pub fn polygon_mode(&self) -> vk::PolygonMode {
+        self.polygon_mode
+    }
+
+    /// Sets the polygon mode of the model, e.g. `LINE` for wireframe rendering.
+    /// Falls back to `FILL` if the device does not support `fillModeNonSolid`.
+    pub fn set_polygon_mode(&mut self, mode: vk::PolygonMode) {
+        let mode = if mode != vk::PolygonMode::FILL && !self.vk_context.supports_fill_mode_non_solid() {
+            log::warn!("Polygon mode {mode:?} is not supported by the device, using FILL");
+            vk::PolygonMode::FILL
+        } else {
+            mode
+        };
+        if mode != self.polygon_mode {
+            self.polygon_mode = mode;
+            self.recreate_model_pipeline();
+        }
+    }
+
+    pub fn texture_filter(&self) -> TextureFilter {
+        self.texture_filter
+    }
+
+    /// Recreates the samplers of the model texture, the normal map and the textures
+    /// of a scene with `filter` and updates the descriptor sets using them.
+    pub fn set_texture_filter(&mut self, filter: TextureFilter) -> Result<(), anyhow::Error> {
+        if filter == self.texture_filter {
+            return Ok(());
+        }
+        self.recreate_samplers(filter, self.anisotropy)?;
+        self.texture_filter = filter;
+        Ok(())
+    }
+
+    pub fn anisotropy(&self) -> f32 {
+        self.anisotropy
+    }
+
+    /// Sets the maximum anisotropy of the model textures and recreates their samplers,
+    /// values below 1 disable anisotropic filtering.
+    ///
+    /// It is clamped to the device limit and has no effect if the device does not
+    /// support anisotropic filtering or with `TextureFilter::Nearest`.
+    pub fn set_anisotropy(&mut self, anisotropy: f32) -> Result<(), anyhow::Error> {
+        if anisotropy == self.anisotropy {
+            return Ok(());
+        }
+        self.recreate_samplers(self.texture_filter, anisotropy)?;
+        self.anisotropy = anisotropy;
+        Ok(())
+    }
+
+    /// Replaces the samplers of all model textures and updates the descriptor sets.
+    fn recreate_samplers(&mut self, filter: TextureFilter, anisotropy: f32) -> Result<(), anyhow::Error> {
+        self.wait_gpu_idle();
+        let device = self.vk_context.device();
+        let scene_textures = self.scene.iter_mut().flat_map(|scene| scene.textures.iter_mut());
+        let instance_textures = self.instances.iter_mut()
+            .filter_map(|instance| Some(&mut instance.material.as_mut()?.texture));
+        let [texture, _, normal_map] = &mut self.textures;
+        let textures = [texture, normal_map].into_iter()
+            .chain(scene_textures)
+            .chain(instance_textures)
+            .collect::<Vec<_>>();
+        // all samplers are created before any is replaced, so on failure the old ones stay in use
+        let mut samplers = Vec::with_capacity(textures.len());
+        for texture in &textures {
+            match Self::create_texture_sampler(&self.vk_context, filter, anisotropy, texture.mip_levels) {
+                Ok(sampler) => samplers.push(sampler),
+                Err(err) => {
+                    for sampler in samplers {
+                        unsafe { device.destroy_sampler(sampler, None) };
+                    }
+                    return Err(err);
+                }
+            }
+        }
+        for (texture, sampler) in textures.into_iter().zip(samplers) {
+            if let Some(old_sampler) = texture.sampler.replace(sampler) {
+                unsafe { device.destroy_sampler(old_sampler, None) };
+            }
+        }
+
+        for &set in self.descriptor_sets.iter().chain(self.material_descriptor_sets()) {
+            Self::write_texture_descriptor(device, set, 3, &self.textures[2]);
+        }
+        for &set in &self.descriptor_sets {
+            Self::write_texture_descriptor(device, set, 1, &self.textures[0]);
+        }
+        if let Some(scene) = &self.scene {
+            for (texture, sets) in scene.textures.iter().zip(&scene.descriptor_sets) {
+                for &set in sets {
+                    Self::write_texture_descriptor(device, set, 1, texture);
+                }
+            }
+        }
+        for material in self.instances.iter().filter_map(|instance| instance.material.as_ref()) {
+            for &set in &material.descriptor_sets {
+                Self::write_texture_descriptor(device, set, 1, &material.texture);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the descriptor sets of the scene materials and model instances with
+    /// their own texture. They share all bindings but the texture with `descriptor_sets`.
+    fn material_descriptor_sets(&self) -> impl Iterator<Item = &vk::DescriptorSet> {
+        let scene_sets = self.scene.iter().flat_map(|scene| scene.descriptor_sets.iter().flatten());
+        let instance_sets = self.instances.iter()
+            .filter_map(|instance| instance.material.as_ref())
+            .flat_map(|material| material.descriptor_sets.iter());
+        scene_sets.chain(instance_sets)
+    }
+
+    /// Returns the groups of `scene` in the order to draw them, back to front
+    /// by their center if the model is transparent.
+    ///
+    /// Groups whose bounding box is outside of the view frustum are left out.
+    fn draw_order<'a>(&self, scene: &'a Scene) -> Vec<&'a DrawGroup> {
+        let model = self.model_matrix * self.initial_model_matrix;
+        let frustum = Frustum::from_matrix(self.projection_matrix() * self.view_matrix);
+        let mut groups = scene.groups.iter()
+            .filter(|group| group.aabb.transformed_by(&model).intersects_frustum(&frustum))
+            .collect::<Vec<_>>();
+        if self.transparent {
+            let model_view = self.view_matrix * model;
+            // the camera looks along -z in view space, so the farthest group has the lowest z
+            let depth = |group: &DrawGroup| (model_view * group.aabb.center().to_point()).z();
+            groups.sort_by(|a, b| depth(a).total_cmp(&depth(b)));
+        }
+        groups
+    }
+
+    /// Points the sampler at `binding` of the descriptor `set` to `texture`.
+    fn write_texture_descriptor(device: &Device, set: vk::DescriptorSet, binding: u32, texture: &Texture) {
+        let image_info = vk::DescriptorImageInfo::default()
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .image_view(texture.view)
+            .sampler(texture.sampler.unwrap());
+        let image_infos = [image_info];
+        let write = vk::WriteDescriptorSet::default()
+            .dst_set(set)
+            .dst_binding(binding)
+            .dst_array_element(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(&image_infos);
+        unsafe { device.update_descriptor_sets(&[write], &[]) }
+    }
+
+    pub fn transparent(&self) -> bool {
+        self.transparent
+    }
+
+    /// Blends the model with what is behind it using the alpha of its texture.
+    ///
+    /// The model is drawn after the opaque overlays and the skybox then, and the groups
+    /// of a scene are sorted back to front. Triangles within a group are not sorted,
+    /// so overlapping transparent parts of one group may blend in the wrong order.
+    pub fn set_transparent(&mut self, transparent: bool) {
+        if transparent != self.transparent {
+            self.transparent = transparent;
+            self.recreate_model_pipeline();
+        }
+    }
+
+    pub fn depth_test(&self) -> bool {
+        self.depth_test
+    }
+
+    /// Enables or disables the depth test of the model, without it later drawn triangles
+    /// are always in front. The model still writes its depth, so the skybox drawn after
+    /// it does not cover it.
+    pub fn set_depth_test(&mut self, depth_test: bool) {
+        if depth_test != self.depth_test {
+            self.depth_test = depth_test;
+            self.recreate_model_pipeline();
+        }
+    }
+
+    /// Sets the cull mode of the model and recreates only the model pipeline.
+    pub fn set_cull_mode(&mut self, mode: vk::CullModeFlags) {
+        self.cull_mode = mode;
+        self.recreate_model_pipeline();
+    }
+
+    /// Sets the depth bias of the model and recreates its pipeline.
+    pub fn set_depth_bias(&mut self, constant: f32, slope: f32) {
+        self.depth_bias_constant = constant;
+        self.depth_bias_slope = slope;
+        self.recreate_model_pipeline();
+    }
+
+    pub fn rotation_speed(&self) -> f32 {
+        self.rotation_speed
+    }
+
+    /// Sets the speed of `auto_rotate` in degrees per second,
+    /// negative speeds turn the model the other way.
+    pub fn set_rotation_speed(&mut self, speed: f32) {
+        self.rotation_speed = speed;
+    }
+
+    /// Turns the model around `rotation_axis` by the rotation speed for `delta` seconds,
+    /// clockwise when looking down the axis for positive speeds.
+    pub fn auto_rotate(&mut self, delta: f32) {
+        let angle = Deg(delta * -self.rotation_speed);
+        let rotation = match self.rotation_axis {
+            RotationAxis::X => Matrix4::from_angle_x(angle),
+            RotationAxis::Y => Matrix4::from_angle_y(angle),
+            RotationAxis::Z => Matrix4::from_angle_z(angle),
+        };
+        self.model_matrix = rotation * self.model_matrix;
+    }
+
+    /// Recreates only the model pipeline with the current settings.
+    ///
+    /// This is cheaper than recreating the whole swapchain and keeps the geometry.
+    fn recreate_model_pipeline(&mut self) {
+        self.wait_gpu_idle();
 
         let device = self.vk_context.device();
+        let mut pipeline = Pipeline::new(
+            device,
+            self.model_pipeline_state(),
+            self.msaa_samples,
+            self.render_pass,
+            self.descriptor_set_layout,
+            self.pipeline_cache,
+            self.shader_spv,
+        );
+        pipeline.viewport = self.pipeline.viewport;
+        pipeline.geometry = self.pipeline.geometry.take();
+        pipeline.morph_geometry = self.pipeline.morph_geometry.take();
+        unsafe { self.pipeline.cleanup(&self.vk_context) };
+        self.pipeline = pipeline;
+    }
+
+    /// Recreates the swapchain with new dimensions.
+    ///
+    /// Only the swapchain and the attachments depending on its size are recreated,
+    /// the pipelines use dynamic viewports and are kept unless the format or the MSAA
+    /// sample count changed.
+    ///
+    /// Returns `Ok(false)` without recreating anything if `width`, `height` or the extent
+    /// of the surface is zero, e.g. while the window is minimized. `dirty_swapchain` is
+    /// set in that case, so the swapchain can be recreated once the window has a size again.
+    ///
+    /// On error the old swapchain is kept and `dirty_swapchain` stays set, so the
+    /// recreation is tried again.
+    pub fn recreate_swapchain(&mut self, width: u32, height: u32) -> Result<bool, anyhow::Error> {
+        let surface_extent = self.vk_context.swapchain_support_details()
+            .map(|details| details.capabilities.current_extent);
+        let empty_surface = surface_extent.is_some_and(|extent| extent.width == 0 || extent.height == 0);
+        if width == 0 || height == 0 || empty_surface {
+            log::debug!("Skipping swapchain recreation for empty extent ({width}, {height})");
+            self.dirty_swapchain = true;
+            return Ok(false);
+        }
+        log::debug!("Recreating swapchain");
+        self.dirty_swapchain = true;
+        self.wait_gpu_idle();
 
+        let old_swapchain = match self.render_target {
+            RenderTarget::Swapchain { swapchain_khr, .. } => swapchain_khr,
+            RenderTarget::Offscreen { .. } => vk::SwapchainKHR::null(),
+        };
         let dimensions = [width, height];
-        let (swapchain, swapchain_khr, properties, images) = Self::create_swapchain_and_images(
+        let (render_target, properties, images) = Self::create_render_target(
             &self.vk_context,
             dimensions,
+            self.present_mode_preference,
+            old_swapchain,
+        )?;
+        self.cleanup_swapchain();
+        let swapchain_image_views = Self::create_swapchain_image_views(self.vk_context.device(), &images, properties);
+        let format_changed = properties.format != self.swapchain_properties.format;
+
+        self.render_target = render_target;
+        self.swapchain_properties = properties;
+        self.images = images;
+        self.swapchain_image_views = swapchain_image_views;
+        if format_changed || self.rebuild_pipelines {
+            self.recreate_pipelines();
+        }
+
+        let device = self.vk_context.device();
+        let color_texture = Self::create_color_texture(
+            &self.vk_context,
+            self.command_pool,
+            self.graphics_queue,
+            properties,
+            self.msaa_samples,
+        );
+
+        let depth_texture = Self::create_depth_texture(
+            &self.vk_context,
+            self.command_pool,
+            self.graphics_queue,
+            self.depth_format,
+            properties.extent,
+            self.msaa_samples,
+        );
+
+        let swapchain_framebuffers = Self::create_framebuffers(
+            device,
+            &self.swapchain_image_views,
+            &color_texture,
+            &depth_texture,
+            self.render_pass,
+            properties,
+        );
+
+        if let Some(fxaa) = &mut self.fxaa {
+            fxaa.create_target(&self.vk_context, self.render_pass, properties, &color_texture, &depth_texture);
+        }
+        self.color_texture = color_texture;
+        self.depth_texture = depth_texture;
+        self.swapchain_framebuffers = swapchain_framebuffers;
+        self.update_viewports();
+        self.dirty_swapchain = false;
+        Ok(true)
+    }
+
+    /// Recreates the render pass and all pipelines for the current swapchain format
+    /// and MSAA sample count, keeping their geometry.
+    fn recreate_pipelines(&mut self) {
+        log::debug!("Recreating render pass and pipelines");
+        let geometry = self.pipeline.geometry.take();
+        let morph_geometry = self.pipeline.morph_geometry.take();
+        let geometry_cubemap = self.pipeline_cubemap.geometry.take();
+        let geometry_uv = self.pipeline_uv.geometry.take();
+        let geometry_grid = self.pipeline_grid.geometry.take();
+        let geometry_normals = self.pipeline_normals.geometry.take();
+        let geometry_bbox = self.pipeline_bbox.geometry.take();
+        let geometry_points = self.pipeline_points.geometry.take();
+        self.cleanup_pipelines();
+
+        let device = self.vk_context.device();
+        // with FXAA the scene is rendered to a texture sampled by the FXAA pass,
+        // otherwise the image is transitioned after rendering, see `record_command_buffer`
+        let final_layout = if self.fxaa_enabled {
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+        } else {
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+        };
+        let render_pass = Self::create_render_pass(
+            device,
+            self.swapchain_properties,
+            self.msaa_samples,
+            self.depth_format,
+            final_layout,
         );
-        let swapchain_image_views = Self::create_swapchain_image_views(device, &images, properties);
-
-        let render_pass =
-            Self::create_render_pass(device, properties, self.msaa_samples, self.depth_format);
         let mut pipeline = Pipeline::new(
             device,
-            properties,
-            self.cull_mode,
+            self.model_pipeline_state(),
             self.msaa_samples,
             render_pass,
             self.descriptor_set_layout,
+            self.pipeline_cache,
             self.shader_spv,
         );
         pipeline.geometry = geometry;
+        pipeline.morph_geometry = morph_geometry;
 
         let mut pipeline_cubemap = Pipeline::new(
             device,
-            properties,
-            vk::CullModeFlags::BACK,
+            Self::cubemap_pipeline_state(),
             self.msaa_samples,
             render_pass,
             self.descriptor_set_layout,
+            self.pipeline_cache,
             self.cubemap_spv,
         );
         pipeline_cubemap.geometry = geometry_cubemap;
 
-        let color_texture = Self::create_color_texture(
-            &self.vk_context,
-            self.command_pool,
-            self.graphics_queue,
-            properties,
+        let mut pipeline_uv = Pipeline::new(
+            device,
+            Self::uv_layout_pipeline_state(),
             self.msaa_samples,
+            render_pass,
+            self.descriptor_set_layout,
+            self.pipeline_cache,
+            self.uv_spv,
         );
+        pipeline_uv.geometry = geometry_uv;
 
-        let depth_texture = Self::create_depth_texture(
-            &self.vk_context,
-            self.command_pool,
-            self.graphics_queue,
-            self.depth_format,
-            properties.extent,
+        let mut pipeline_grid = Pipeline::new(
+            device,
+            Self::lines_pipeline_state(),
             self.msaa_samples,
+            render_pass,
+            self.descriptor_set_layout,
+            self.pipeline_cache,
+            self.grid_spv,
         );
+        pipeline_grid.geometry = geometry_grid;
 
-        let swapchain_framebuffers = Self::create_framebuffers(
+        let mut pipeline_normals = Pipeline::new(
             device,
-            &swapchain_image_views,
-            color_texture,
-            depth_texture,
+            Self::lines_pipeline_state(),
+            self.msaa_samples,
             render_pass,
-            properties,
+            self.descriptor_set_layout,
+            self.pipeline_cache,
+            self.normals_spv,
         );
+        pipeline_normals.geometry = geometry_normals;
+
+        let mut pipeline_bbox = Pipeline::new(
+            device,
+            Self::lines_pipeline_state(),
+            self.msaa_samples,
+            render_pass,
+            self.descriptor_set_layout,
+            self.pipeline_cache,
+            self.normals_spv,
+        );
+        pipeline_bbox.geometry = geometry_bbox;
+
+        let mut pipeline_points = Pipeline::new(
+            device,
+            Self::points_pipeline_state(),
+            self.msaa_samples,
+            render_pass,
+            self.descriptor_set_layout,
+            self.pipeline_cache,
+            self.points_spv,
+        );
+        pipeline_points.geometry = geometry_points;
+
+        self.fxaa = self.fxaa_enabled.then(|| FxaaPass::new(
+            device,
+            self.swapchain_properties,
+            self.depth_format,
+            self.pipeline_cache,
+            self.fxaa_spv,
+        ));
+
+        // the FXAA pass is compatible with the main render pass and draws the ui
+        #[cfg(feature = "ui")]
+        self.ui.recreate_pipeline(device, render_pass, self.msaa_samples, self.pipeline_cache);
 
-        self.swapchain = swapchain;
-        self.swapchain_khr = swapchain_khr;
-        self.swapchain_properties = properties;
-        self.images = images;
-        self.swapchain_image_views = swapchain_image_views;
         self.render_pass = render_pass;
         self.pipeline = pipeline;
         self.pipeline_cubemap = pipeline_cubemap;
-        self.color_texture = color_texture;
-        self.depth_texture = depth_texture;
-        self.swapchain_framebuffers = swapchain_framebuffers;
-        self.recreate_command_buffers();
+        self.pipeline_uv = pipeline_uv;
+        self.pipeline_grid = pipeline_grid;
+        self.pipeline_normals = pipeline_normals;
+        self.pipeline_bbox = pipeline_bbox;
+        self.pipeline_points = pipeline_points;
+        self.rebuild_pipelines = false;
     }
 
-    /// Clean up the swapchain and all resources that depend on it.
+    /// Clean up the swapchain and all resources that depend on its size.
     fn cleanup_swapchain(&mut self) {
         let device = self.vk_context.device();
         unsafe {
-            self.depth_texture.destroy(device);
-            self.color_texture.destroy(device);
+            if let Some(fxaa) = &mut self.fxaa {
+                fxaa.destroy_target(&self.vk_context);
+            }
+            self.depth_texture.destroy(&self.vk_context);
+            self.color_texture.destroy(&self.vk_context);
             for framebuffer in self.swapchain_framebuffers.iter() {
                 device.destroy_framebuffer(*framebuffer, None);
             }
-            self.pipeline.cleanup(device);
-            self.pipeline_cubemap.cleanup(device);
-            device.destroy_render_pass(self.render_pass, None);
             for image_view in self.swapchain_image_views.iter() {
                 device.destroy_image_view(*image_view, None);
             }
-            self.swapchain.destroy_swapchain(self.swapchain_khr, None);
+            self.render_target.destroy(&self.vk_context);
+        }
+    }
+
+    /// Clean up the render pass and the pipelines including their geometry.
+    fn cleanup_pipelines(&mut self) {
+        let device = self.vk_context.device();
+        unsafe {
+            self.pipeline.cleanup(&self.vk_context);
+            self.pipeline_cubemap.cleanup(&self.vk_context);
+            self.pipeline_uv.cleanup(&self.vk_context);
+            self.pipeline_grid.cleanup(&self.vk_context);
+            self.pipeline_normals.cleanup(&self.vk_context);
+            self.pipeline_bbox.cleanup(&self.vk_context);
+            self.pipeline_points.cleanup(&self.vk_context);
+            if let Some(mut fxaa) = self.fxaa.take() {
+                fxaa.destroy(&self.vk_context);
+            }
+            #[cfg(feature = "ui")]
+            self.ui.cleanup_pipeline(device);
+            device.destroy_render_pass(self.render_pass, None);
+        }
+    }
+
+    /// Returns the projection matrix for the current render area.
+    fn projection_matrix(&self) -> Matrix4 {
+        let area = self.render_area_for(self.get_extent()).extent;
+        let aspect = area.width as f32 / area.height as f32;
+        let (near, far) = self.clip_planes();
+        match self.projection_mode {
+            ProjectionMode::Perspective => math::perspective(FOV_Y, aspect, near, far),
+            ProjectionMode::Orthographic => {
+                // zooming changes the camera distance, so scale the view by it relative to
+                // the distance at which the model is framed like in perspective mode
+                let zoom = self.camera_distance() / self.framing_distance();
+                let zoom = if zoom.is_finite() && zoom > 0. { zoom } else { 1. };
+                let half_height = self.bounding_radius() * FRAME_MARGIN * zoom;
+                let half_width = half_height * aspect.max(1.);
+                let half_height = half_height / aspect.min(1.);
+                math::orthographic(-half_width, half_width, -half_height, half_height, near, far)
+            }
         }
     }
 
-    fn update_uniform_buffers(&mut self, current_image: u32) {
-        let aspect = self.get_extent().width as f32 / self.get_extent().height as f32;
+    fn update_uniform_buffers(&self, current_image: u32) {
+        let (near, far) = self.clip_planes();
         let ubo = UniformBufferObject {
-            model: self.model_matrix * self.initial_model_matrix,
             view: self.view_matrix,
-            proj: math::perspective(Deg(75.0), aspect, 0.1, 20.0),
+            proj: self.projection_matrix(),
+            light_dir: [self.light_dir.x(), self.light_dir.y(), self.light_dir.z(), 0.],
+            view_pos: {
+                let pos = UniformBufferObject::view_position(self.view_matrix);
+                [pos.x(), pos.y(), pos.z(), 1.]
+            },
             texture_weight: self.texture_weight,
+            reflectivity: self.reflectivity,
+            morph_weight: self.morph_weight,
+            morph_crossfade: match (&self.pipeline.geometry, &self.pipeline.morph_geometry) {
+                (Some(g), Some(m)) => (g.vertex_count != m.vertex_count) as u32,
+                _ => 0,
+            },
+            near,
+            far,
+            show_depth: self.show_depth as u32,
+            vertex_colors: self.model_stats.has_colors as u32,
         };
         let ubos = [ubo];
 
-        let buffer_mem = self.uniform_buffer_memories[current_image as usize];
-        let size = size_of::<UniformBufferObject>() as vk::DeviceSize;
-        unsafe {
-            let device = self.vk_context.device();
-            let data_ptr = device
-                .map_memory(buffer_mem, 0, size, vk::MemoryMapFlags::empty())
-                .unwrap();
-            let mut align = ash::util::Align::new(data_ptr, align_of::<f32>() as _, size);
-            align.copy_from_slice(&ubos);
-            device.unmap_memory(buffer_mem);
+        buffer::write_mapped::<f32, _>(&self.uniform_buffer_memories[current_image as usize], &ubos);
+    }
+
+    /// Returns the area of an image of size `extent` the scene is rendered to.
+    ///
+    /// This is the whole image unless a `letterbox` aspect ratio is given.
+    /// Then it is the largest centered area with this aspect ratio.
+    fn render_area(extent: vk::Extent2D, letterbox: Option<f32>) -> vk::Rect2D {
+        let full = vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent,
+        };
+        let Some(aspect) = letterbox else { return full };
+        let (width, height) = (extent.width as f32, extent.height as f32);
+        let extent = if width / height > aspect {
+            vk::Extent2D { width: ((height * aspect) as u32).max(1), height: extent.height }
+        } else {
+            vk::Extent2D { width: extent.width, height: ((width / aspect) as u32).max(1) }
+        };
+        vk::Rect2D {
+            offset: vk::Offset2D {
+                x: (full.extent.width - extent.width) as i32 / 2,
+                y: (full.extent.height - extent.height) as i32 / 2,
+            },
+            extent,
+        }
+    }
+
+    fn render_area_for(&self, extent: vk::Extent2D) -> vk::Rect2D {
+        Self::render_area(extent, self.letterbox)
+    }
+
+    fn model_pipeline_state(&self) -> PipelineState {
+        PipelineState {
+            polygon_mode: self.polygon_mode,
+            cull_mode: self.cull_mode,
+            depth_bias: (self.depth_bias_constant, self.depth_bias_slope),
+            depth_test: self.depth_test,
+            blend: self.transparent,
+            ..Default::default()
+        }
+    }
+
+    fn cubemap_pipeline_state() -> PipelineState {
+        PipelineState {
+            cull_mode: vk::CullModeFlags::BACK,
+            ..Default::default()
+        }
+    }
+
+    /// State of the depth tested line overlays, the grid, the normals and the bounding box.
+    fn lines_pipeline_state() -> PipelineState {
+        PipelineState {
+            topology: vk::PrimitiveTopology::LINE_LIST,
+            ..Default::default()
+        }
+    }
+
+    /// State of point clouds, the point size is set by the vertex shader.
+    fn points_pipeline_state() -> PipelineState {
+        PipelineState {
+            topology: vk::PrimitiveTopology::POINT_LIST,
+            ..Default::default()
         }
     }
 
+    fn uv_layout_pipeline_state() -> PipelineState {
+        PipelineState {
+            topology: vk::PrimitiveTopology::LINE_LIST,
+            depth_test: false,
+            ..Default::default()
+        }
+    }
+
+    /// The uv layout is drawn in a square in the bottom right corner of `area`.
+    fn uv_layout_viewport(area: vk::Rect2D) -> vk::Rect2D {
+        let margin = 10;
+        let size = (area.extent.width.min(area.extent.height) / 3).max(1);
+        vk::Rect2D {
+            offset: vk::Offset2D {
+                x: area.offset.x + area.extent.width.saturating_sub(size + margin) as i32,
+                y: area.offset.y + area.extent.height.saturating_sub(size + margin) as i32,
+            },
+            extent: vk::Extent2D { width: size, height: size },
+        }
+    }
+
+    /// Sets the areas the pipelines render to after the extent or the letterbox changed.
+    fn update_viewports(&mut self) {
+        let area = self.render_area_for(self.get_extent());
+        for pipeline in [
+            &mut self.pipeline,
+            &mut self.pipeline_cubemap,
+            &mut self.pipeline_grid,
+            &mut self.pipeline_normals,
+            &mut self.pipeline_bbox,
+            &mut self.pipeline_points,
+        ] {
+            pipeline.viewport = Some(area);
+        }
+        self.pipeline_uv.viewport = Some(Self::uv_layout_viewport(area));
+    }
+
     pub fn get_extent(&self) -> vk::Extent2D {
         self.swapchain_properties.extent
     }
 
+    /// Logs the device, swapchain, model and camera state as one readable block.
+    ///
+    /// The `extra` lines come first, for state the app does not know about like
+    /// the path of the model.
+    pub fn log_state(&self, extra: &[(&str, String)]) {
+        let props = self.vk_context.physical_device_properties();
+        let device_name = unsafe { CStr::from_ptr(props.device_name.as_ptr()) };
+        let api_version = props.api_version;
+        let (vertex_count, triangle_count) = self.pipeline.geometry.as_ref()
+            .or(self.pipeline_points.geometry.as_ref())
+            .map(|g| (g.vertex_count, g.index_count / 3))
+            .unwrap_or_default();
+        let lines = [
+            ("Device", format!("{device_name:?} ({:?})", props.device_type)),
+            ("Vulkan API", format!(
+                "{}.{}.{}",
+                vk::api_version_major(api_version),
+                vk::api_version_minor(api_version),
+                vk::api_version_patch(api_version),
+            )),
+            ("Driver version", format!("{} ({:#x})", props.driver_version, props.driver_version)),
+            ("MSAA", format!("{:?}", self.msaa_samples)),
+            ("PresentMode", format!("{:?}", self.swapchain_properties.present_mode)),
+            ("Format", format!("{:?}", self.swapchain_properties.format.format)),
+            ("ColorSpace", format!("{:?}", self.swapchain_properties.format.color_space)),
+            ("Extent", format!(
+                "{}x{}",
+                self.swapchain_properties.extent.width,
+                self.swapchain_properties.extent.height,
+            )),
+            ("Letterbox", format!("{:?}", self.letterbox)),
+            ("Texture", format!(
+                "{} ({:?} filter, {} anisotropy, {} mip levels)",
+                self.texture_path.display(),
+                self.texture_filter,
+                self.anisotropy,
+                self.textures[0].mip_levels,
+            )),
+            ("Model", format!(
+                "{vertex_count} vertices, {triangle_count} triangles, extent {:?} to {:?}",
+                <[f32; 3]>::from(self.model_aabb.min),
+                <[f32; 3]>::from(self.model_aabb.max),
+            )),
+            ("Model stats", self.model_stats.to_string()),
+            ("Model matrix", format!("{:?}", <[[f32; 4]; 4]>::from(self.model_matrix))),
+            ("Initial model matrix", format!("{:?}", <[[f32; 4]; 4]>::from(self.initial_model_matrix))),
+            ("View matrix", format!("{:?}", <[[f32; 4]; 4]>::from(self.view_matrix))),
+            ("Projection", format!("{:?}", self.projection_mode)),
+            ("Cull mode", format!("{:?}", self.cull_mode)),
+            ("Polygon mode", format!("{:?}", self.polygon_mode)),
+            ("Transparent", self.transparent.to_string()),
+            ("Depth test", self.depth_test.to_string()),
+            ("Depth bias", format!("{} constant, {} slope", self.depth_bias_constant, self.depth_bias_slope)),
+            ("Show cubemap", self.show_cubemap.to_string()),
+            ("Light direction", format!("{:?}", <[f32; 3]>::from(self.light_dir))),
+            ("Texture weight", self.texture_weight.to_string()),
+            ("Reflectivity", self.reflectivity.to_string()),
+            ("Morph weight", format!("{} (target loaded: {})", self.morph_weight, self.has_morph_target())),
+            ("Clip planes", format!("{:?} (custom: {})", self.clip_planes(), self.has_custom_clip_planes())),
+            ("Show depth", self.show_depth.to_string()),
+            ("FXAA", self.fxaa.is_some().to_string()),
+        ];
+        let mut dump = String::from("State dump:");
+        for (label, value) in extra.iter().chain(&lines) {
+            dump.push_str(&format!("\n\t{label}: {value}"));
+        }
+        log::info!("{dump}");
+    }
+
     pub fn reset_ubo(&mut self) {
         self.view_matrix = UniformBufferObject::view_matrix();
         self.model_matrix = Matrix4::unit();
-        self.initial_model_matrix = UniformBufferObject::model_matrix(
-            self.model_extent.0,
-            self.model_extent.1,
-        );
+        self.initial_model_matrix = UniformBufferObject::model_matrix(self.model_aabb);
     }
 }
 
@@ -1842,24 +5056,333 @@ impl Drop for VkApp {
     fn drop(&mut self) {
         log::debug!("Dropping application.");
         self.cleanup_swapchain();
+        self.cleanup_pipelines();
 
         let device = self.vk_context.device();
+        if let Some(path) = pipeline::pipeline_cache_path() {
+            match pipeline::save_pipeline_cache(device, self.pipeline_cache, &path) {
+                Ok(()) => log::debug!("Saved pipeline cache to {}", path.display()),
+                Err(err) => log::warn!("Failed to save pipeline cache to {}: {err}", path.display()),
+            }
+        }
         self.in_flight_frames.destroy(device);
+        self.gpu_timer.destroy(device);
+        for readback in self.readbacks.drain(..).flatten() {
+            readback.destroy(&self.vk_context);
+        }
         unsafe {
+            #[cfg(feature = "ui")]
+            self.ui.destroy(&self.vk_context);
+            device.destroy_pipeline_cache(self.pipeline_cache, None);
             device.destroy_descriptor_pool(self.descriptor_pool, None);
             device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
-            for &mem in &self.uniform_buffer_memories {
-                device.free_memory(mem, None);
+            let memories = self.uniform_buffer_memories.drain(..);
+            for (&buffer, memory) in self.uniform_buffers.iter().zip(memories) {
+                buffer::destroy_buffer(&self.vk_context, buffer, memory);
+            }
+            for texture in self.textures.iter_mut() {
+                texture.destroy(&self.vk_context);
+            }
+            for load in self.model_loads.drain(..) {
+                if let ModelLoadState::Uploading(upload) = load.state {
+                    let ModelUpload { geometries, mut stagings, commands, .. } = *upload;
+                    commands.wait(device);
+                    commands.destroy(device, self.transient_command_pool);
+                    for staging in stagings.iter_mut() {
+                        staging.destroy(&self.vk_context);
+                    }
+                    geometries.destroy(&self.vk_context);
+                }
+            }
+            for load in self.texture_loads.drain(..) {
+                if let TextureLoadState::Uploading { mut texture, mut staging, commands } = load.state {
+                    commands.wait(device);
+                    commands.destroy(device, self.transient_command_pool);
+                    staging.destroy(&self.vk_context);
+                    texture.destroy(&self.vk_context);
+                }
+            }
+            if let Some(mut scene) = self.scene.take() {
+                scene.destroy(&self.vk_context);
             }
-            for &buffer in &self.uniform_buffers {
-                device.destroy_buffer(buffer, None);
+            for retired in self.retired.drain(..) {
+                retired.destroy(&self.vk_context);
             }
-            for mut texture in self.textures {
-                texture.destroy(device);
+            for instance in self.instances.drain(..) {
+                instance.destroy(&self.vk_context);
             }
             device.free_command_buffers(self.command_pool, &self.command_buffers);
             device.destroy_command_pool(self.transient_command_pool, None);
             device.destroy_command_pool(self.command_pool, None);
+            self.staging.destroy(&self.vk_context);
+        }
+    }
+}
+
+/// Textures and descriptor sets of the materials of a scene loaded with `load_scene`.
+struct Scene {
+    textures: Vec<Texture>,
+    descriptor_pool: vk::DescriptorPool,
+    /// Descriptor sets for each texture, one per swapchain image.
+    descriptor_sets: Vec<Vec<vk::DescriptorSet>>,
+    groups: Vec<DrawGroup>,
+}
+
+impl Scene {
+    fn destroy(&mut self, vk_context: &VkContext) {
+        unsafe { vk_context.device().destroy_descriptor_pool(self.descriptor_pool, None) };
+        for texture in self.textures.iter_mut() {
+            texture.destroy(vk_context);
+        }
+    }
+}
+
+/// Returns the near and far plane enclosing a sphere with `radius` at `distance` from the
+/// camera, see `VkApp::clip_planes`.
+fn clip_planes_around(distance: f32, radius: f32) -> (f32, f32) {
+    let radius = radius * CLIP_MARGIN;
+    let far = (distance + radius).max(FAR);
+    let near = (distance - radius).min(NEAR).max(far * MIN_NEAR_RATIO);
+    (near, far)
+}
+
+/// Splits the indices of `depths`, view space z coordinates of models, into those behind
+/// a model at `model_depth` and those in front of it, both ordered back to front.
+fn back_to_front(depths: &[f32], model_depth: f32) -> (Vec<usize>, Vec<usize>) {
+    let mut order = (0..depths.len()).collect::<Vec<_>>();
+    order.sort_by(|&a, &b| depths[a].total_cmp(&depths[b]));
+    let split = order.partition_point(|&i| depths[i] < model_depth);
+    let in_front = order.split_off(split);
+    (order, in_front)
+}
+
+/// A model added with `VkApp::add_model`, drawn with the model pipeline.
+struct ModelInstance {
+    id: ModelId,
+    geometry: Geometry,
+    /// Positions and indices on the CPU for picking.
+    positions: Vec<Vector3>,
+    indices: Vec<u32>,
+    /// Places the model in world space after `initial_model_matrix`.
+    transform: Matrix4,
+    initial_model_matrix: Matrix4,
+    /// Bounding box in model space, used to cull the model.
+    aabb: Aabb,
+    /// Own texture of the model, the one of the main model is used if `None`.
+    material: Option<InstanceMaterial>,
+}
+
+impl ModelInstance {
+    fn model_matrix(&self) -> Matrix4 {
+        self.transform * self.initial_model_matrix
+    }
+
+    fn destroy(self, vk_context: &VkContext) {
+        unsafe { self.geometry.cleanup(vk_context) };
+        if let Some(material) = self.material {
+            material.destroy(vk_context);
+        }
+    }
+}
+
+/// Texture of a `ModelInstance` and the descriptor sets using it, one per swapchain image.
+struct InstanceMaterial {
+    texture: Texture,
+    descriptor_pool: vk::DescriptorPool,
+    descriptor_sets: Vec<vk::DescriptorSet>,
+}
+
+impl InstanceMaterial {
+    fn destroy(mut self, vk_context: &VkContext) {
+        unsafe { vk_context.device().destroy_descriptor_pool(self.descriptor_pool, None) };
+        self.texture.destroy(vk_context);
+    }
+}
+
+/// Vertices and indices of a model and of the helpers drawn with it.
+struct ModelMeshes {
+    /// Bounding box of the model vertices.
+    aabb: Aabb,
+    /// The model as displayed, its indices are used with `vertices`.
+    obj: NormalizedObj,
+    vertices: Vec<Vertex>,
+    uv_layout: (Vec<Vertex>, Vec<u32>),
+    /// Empty if the model has no normals to draw.
+    normals: (Vec<Vertex>, Vec<u32>),
+    bbox: (Vec<Vertex>, Vec<u32>),
+}
+
+impl ModelMeshes {
+    /// Creates the meshes of `nobj` displayed with `uv_mode` and `shading`.
+    fn load(nobj: NormalizedObj, uv_mode: UvMode, shading: Shading) -> Self {
+        let obj = VkApp::display_obj(nobj, uv_mode, shading);
+        let (vertices, aabb) = VkApp::model_vertices(&obj);
+        Self::new(obj, vertices, aabb)
+    }
+
+    fn new(obj: NormalizedObj, vertices: Vec<Vertex>, aabb: Aabb) -> Self {
+        Self {
+            aabb,
+            uv_layout: VkApp::load_uv_layout(&vertices, &obj.indices),
+            normals: VkApp::load_normals(&vertices, aabb),
+            bbox: VkApp::load_bbox(aabb),
+            obj,
+            vertices,
+        }
+    }
+
+    /// Creates the geometries of the meshes with `create`.
+    fn geometries(&self, mut create: impl FnMut(&[Vertex], &[u32]) -> Geometry) -> ModelGeometries {
+        let (vertices, indices) = (&self.vertices, &self.obj.indices);
+        let point_indices = VkApp::point_cloud_indices(vertices, indices);
+        ModelGeometries {
+            model: point_indices.is_none().then(|| create(vertices, indices)),
+            points: point_indices.map(|indices| create(vertices, &indices)),
+            uv_layout: create(&self.uv_layout.0, &self.uv_layout.1),
+            normals: (!self.normals.0.is_empty()).then(|| create(&self.normals.0, &self.normals.1)),
+            bbox: create(&self.bbox.0, &self.bbox.1),
+        }
+    }
+}
+
+/// Geometries of the meshes of a `ModelMeshes`.
+struct ModelGeometries {
+    /// `None` for point clouds, which are drawn with `points` instead.
+    model: Option<Geometry>,
+    points: Option<Geometry>,
+    uv_layout: Geometry,
+    normals: Option<Geometry>,
+    bbox: Geometry,
+}
+
+impl ModelGeometries {
+    fn destroy(self, vk_context: &VkContext) {
+        for g in [self.model, self.points, Some(self.uv_layout), self.normals, Some(self.bbox)].into_iter().flatten() {
+            unsafe { g.cleanup(vk_context) };
+        }
+    }
+}
+
+/// Resources replaced while frames in flight may still use them.
+struct Retired {
+    geometries: Vec<Geometry>,
+    scene: Option<Scene>,
+    textures: Vec<Texture>,
+    /// One bit per frame in flight whose fence was not waited on since.
+    pending_frames: u32,
+}
+
+impl Retired {
+    fn destroy(self, vk_context: &VkContext) {
+        for g in self.geometries {
+            unsafe { g.cleanup(vk_context) };
+        }
+        if let Some(mut scene) = self.scene {
+            scene.destroy(vk_context);
+        }
+        for mut texture in self.textures {
+            texture.destroy(vk_context);
+        }
+    }
+}
+
+/// A model loaded in the background by `VkApp::request_model_load`.
+struct ModelLoad {
+    path: PathBuf,
+    ticket: LoadTicket,
+    /// Whether the model is dropped instead of swapped in when done.
+    cancelled: bool,
+    state: ModelLoadState,
+}
+
+enum ModelLoadState {
+    /// The model is parsed on a worker thread.
+    Parsing(JoinHandle<Result<(ModelStats, ModelMeshes), anyhow::Error>>),
+    Uploading(Box<ModelUpload>),
+}
+
+/// The copies into `geometries` were submitted and are done when `commands` are.
+struct ModelUpload {
+    stats: ModelStats,
+    meshes: ModelMeshes,
+    geometries: ModelGeometries,
+    stagings: Vec<StagingBuffer>,
+    commands: PendingCommands,
+}
+
+/// Texture of the model replaced by a `TextureLoad`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TextureSlot {
+    Color,
+    NormalMap,
+}
+
+impl TextureSlot {
+    fn format(self) -> vk::Format {
+        match self {
+            Self::Color => COLOR_TEXTURE_FORMAT,
+            Self::NormalMap => DATA_TEXTURE_FORMAT,
+        }
+    }
+}
+
+/// A texture loaded in the background by `VkApp::request_texture_load`.
+struct TextureLoad {
+    slot: TextureSlot,
+    path: PathBuf,
+    ticket: LoadTicket,
+    /// Whether the texture is dropped instead of swapped in when done.
+    cancelled: bool,
+    state: TextureLoadState,
+}
+
+enum TextureLoadState {
+    /// The image is decoded on a worker thread.
+    Decoding(JoinHandle<Result<TexturePixels, anyhow::Error>>),
+    /// The copy into `texture` was submitted and is done when `commands` are.
+    Uploading {
+        texture: Texture,
+        staging: StagingBuffer,
+        commands: PendingCommands,
+    },
+}
+
+/// Range of indices drawn with the descriptor sets of a material texture
+/// or with the default descriptor sets if `material` is `None`.
+struct DrawGroup {
+    first_index: u32,
+    index_count: u32,
+    material: Option<usize>,
+    /// Bounding box of the group in model space, used to cull and sort transparent groups.
+    aabb: Aabb,
+}
+
+/// Images that are rendered to.
+enum RenderTarget {
+    /// Images of a swapchain that are presented to the window.
+    Swapchain {
+        loader: khr_swapchain::Device,
+        swapchain_khr: vk::SwapchainKHR,
+    },
+    /// A single image of a headless app that is copied from with `request_capture`.
+    Offscreen {
+        image: vk::Image,
+        /// `None` after the image was destroyed.
+        memory: Option<Allocation>,
+    },
+}
+
+impl RenderTarget {
+    /// Destroys the swapchain or the offscreen image, but not the image views.
+    unsafe fn destroy(&mut self, vk_context: &VkContext) {
+        match self {
+            Self::Swapchain { loader, swapchain_khr } => loader.destroy_swapchain(*swapchain_khr, None),
+            Self::Offscreen { image, memory } => {
+                vk_context.device().destroy_image(*image, None);
+                if let Some(memory) = memory.take() {
+                    vk_context.free(memory);
+                }
+            }
         }
     }
 }
@@ -1881,6 +5404,74 @@ impl SyncObjects {
     }
 }
 
+/// Image a frame is rendered to by `record_command_buffer`.
+struct FrameTarget {
+    /// Framebuffer with the image as last attachment, compatible with the render pass.
+    framebuffer: vk::Framebuffer,
+    image: vk::Image,
+    /// Layout the image is transitioned to after rendering.
+    layout: vk::ImageLayout,
+    /// Buffer the image is copied to after rendering, see `VkApp::request_capture`.
+    readback: Option<vk::Buffer>,
+    /// Whether the ui is drawn on top of the scene.
+    #[cfg_attr(not(feature = "ui"), allow(dead_code))]
+    ui: bool,
+}
+
+/// Host visible buffer the image of a frame in flight is copied to when it is captured.
+struct Readback {
+    buffer: vk::Buffer,
+    memory: Allocation,
+    extent: vk::Extent2D,
+    format: vk::Format,
+    /// Whether a copy was submitted that was not read yet.
+    pending: bool,
+}
+
+impl Readback {
+    fn new(vk_context: &VkContext, extent: vk::Extent2D, format: vk::Format) -> Self {
+        let (buffer, memory) = buffer::create_buffer(
+            vk_context,
+            Self::size(extent),
+            vk::BufferUsageFlags::TRANSFER_DST,
+            MemoryLocation::GpuToCpu,
+        );
+        Self { buffer, memory, extent, format, pending: false }
+    }
+
+    fn size(extent: vk::Extent2D) -> vk::DeviceSize {
+        extent.width as vk::DeviceSize * extent.height as vk::DeviceSize * 4
+    }
+
+    /// Returns whether the red and blue channels of `format` must be swapped to get RGBA,
+    /// or an error if frames with `format` cannot be captured.
+    fn swizzle(format: vk::Format) -> Result<bool, anyhow::Error> {
+        match format {
+            vk::Format::B8G8R8A8_UNORM | vk::Format::B8G8R8A8_SRGB => Ok(true),
+            vk::Format::R8G8B8A8_UNORM | vk::Format::R8G8B8A8_SRGB => Ok(false),
+            other => anyhow::bail!("Capturing swapchain format {other:?} is not supported"),
+        }
+    }
+
+    /// Copies the pixels out of the buffer, the copy to it must be finished.
+    fn read(&self) -> Result<image::RgbaImage, anyhow::Error> {
+        let size = Self::size(self.extent) as usize;
+        let mapped = self.memory.mapped_slice().context("Failed to map memory for frame capture")?;
+        let mut pixels = mapped[..size].to_vec();
+        if Self::swizzle(self.format)? {
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+        image::RgbaImage::from_raw(self.extent.width, self.extent.height, pixels)
+            .context("Failed to create image from frame")
+    }
+
+    fn destroy(self, vk_context: &VkContext) {
+        unsafe { buffer::destroy_buffer(vk_context, self.buffer, self.memory) };
+    }
+}
+
 struct InFlightFrames {
     sync_objects: Vec<SyncObjects>,
     current_frame: usize,
@@ -1899,6 +5490,90 @@ impl InFlightFrames {
     }
 }
 
+/// Timestamp queries at the start and the end of the command buffer of each frame in flight.
+struct GpuTimer {
+    query_pool: vk::QueryPool,
+    /// Nanoseconds per timestamp tick, `None` if timestamps are not supported.
+    period: Option<f32>,
+    /// Whether the command buffer of a frame was submitted before, otherwise its queries are unset.
+    /// Set after each submission of the frame.
+    submitted: Vec<bool>,
+    last_time: Option<f32>,
+}
+
+impl GpuTimer {
+    fn new(device: &Device, props: vk::PhysicalDeviceProperties) -> Self {
+        let mut timer = Self {
+            query_pool: vk::QueryPool::null(),
+            period: None,
+            submitted: vec![false; MAX_FRAMES_IN_FLIGHT as usize],
+            last_time: None,
+        };
+        if props.limits.timestamp_compute_and_graphics == vk::FALSE {
+            log::info!("Device does not support timestamps, GPU times are not available");
+            return timer;
+        }
+        let create_info = vk::QueryPoolCreateInfo::default()
+            .query_type(vk::QueryType::TIMESTAMP)
+            .query_count(2 * MAX_FRAMES_IN_FLIGHT);
+        match unsafe { device.create_query_pool(&create_info, None) } {
+            Ok(pool) => {
+                timer.query_pool = pool;
+                timer.period = Some(props.limits.timestamp_period);
+            }
+            Err(err) => log::warn!("Failed to create query pool: {err}"),
+        }
+        timer
+    }
+
+    unsafe fn write_start(&self, device: &Device, buffer: vk::CommandBuffer, frame: usize) {
+        if self.period.is_none() {
+            return;
+        }
+        let first = 2 * frame as u32;
+        device.cmd_reset_query_pool(buffer, self.query_pool, first, 2);
+        device.cmd_write_timestamp(buffer, vk::PipelineStageFlags::TOP_OF_PIPE, self.query_pool, first);
+    }
+
+    unsafe fn write_end(&self, device: &Device, buffer: vk::CommandBuffer, frame: usize) {
+        if self.period.is_none() {
+            return;
+        }
+        let query = 2 * frame as u32 + 1;
+        device.cmd_write_timestamp(buffer, vk::PipelineStageFlags::BOTTOM_OF_PIPE, self.query_pool, query);
+    }
+
+    /// Reads the timestamps of the last frame submitted with the command buffer of `frame`.
+    /// Must be called after waiting for the fence of the frame and before recording it again.
+    fn read(&mut self, device: &Device, frame: usize) {
+        let Some(period) = self.period else { return };
+        if !self.submitted[frame] {
+            return;
+        }
+        let mut timestamps = [0u64; 2];
+        let result = unsafe {
+            device.get_query_pool_results(
+                self.query_pool,
+                2 * frame as u32,
+                &mut timestamps,
+                vk::QueryResultFlags::TYPE_64,
+            )
+        };
+        match result {
+            Ok(()) => {
+                let ticks = timestamps[1].wrapping_sub(timestamps[0]);
+                self.last_time = Some(ticks as f32 * period / 1_000_000.);
+            }
+            Err(vk::Result::NOT_READY) => {}
+            Err(err) => log::warn!("Failed to read timestamps: {err}"),
+        }
+    }
+
+    fn destroy(&self, device: &Device) {
+        unsafe { device.destroy_query_pool(self.query_pool, None) };
+    }
+}
+
 impl Iterator for InFlightFrames {
     type Item = SyncObjects;
 
@@ -1910,3 +5585,43 @@ impl Iterator for InFlightFrames {
         Some(next)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_obj() {
+        let file = "v 0 0 0\nv 1 0 0\nv 0 1 0\nv 0 0 1\nf 1 2 3\nf 1 3 4";
+        let nobj = NormalizedObj::from_reader(std::io::Cursor::new(file.as_bytes())).unwrap();
+        let obj = VkApp::display_obj(nobj, UvMode::Planar, Shading::Flat);
+        // the shared vertices are split and the generated normals and coordinates are kept
+        assert_eq!(obj.vertices.len(), 6);
+        assert!(obj.has_normals && obj.has_tex_coords);
+        assert_eq!(obj.vertices[0].normal, [0., 0., 1.]);
+        assert_eq!(obj.vertices[3].normal, [1., 0., 0.]);
+        assert_eq!(obj.vertices[2].tex_coords, [0., 1.]);
+    }
+
+    #[test]
+    fn instances_back_to_front() {
+        let depths = [-2., -5., -1., -4.];
+        assert_eq!(back_to_front(&depths, -3.), (vec![1, 3], vec![0, 2]));
+        assert_eq!(back_to_front(&depths, -10.), (vec![], vec![1, 3, 0, 2]));
+        assert_eq!(back_to_front(&[], -3.), (vec![], vec![]));
+    }
+
+    #[test]
+    fn clip_planes() {
+        // a tiny model keeps the default planes
+        assert_eq!(clip_planes_around(5., 0.001), (NEAR, FAR));
+        // a huge far away model moves the far plane and with it the smallest near plane
+        let (near, far) = clip_planes_around(1000., 400.);
+        assert_eq!(far, 1000. + 400. * CLIP_MARGIN);
+        assert_eq!(near, far * MIN_NEAR_RATIO);
+        // inside the model the near plane stays positive
+        let (near, far) = clip_planes_around(0.5, 2.);
+        assert_eq!((near, far), (FAR * MIN_NEAR_RATIO, FAR));
+        assert!(near > 0.);
+    }
+}
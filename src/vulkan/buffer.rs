@@ -2,19 +2,18 @@ use super::cmd;
 use super::context::VkContext;
 
 use ash::{vk, Device};
+use gpu_allocator::{vulkan::Allocation, MemoryLocation};
 
-/// Create a buffer and allocate its memory.
+/// Create a buffer and allocate its memory at `location`.
 ///
-/// # Returns
-///
-/// The buffer, its memory and the actual size in bytes of the
-/// allocated memory since in may differ from the requested size.
+/// The memory of host visible locations stays mapped, see `write_mapped`.
+/// The returned allocation must be freed with `VkContext::free`.
 pub fn create_buffer(
     vk_context: &VkContext,
     size: vk::DeviceSize,
     usage: vk::BufferUsageFlags,
-    mem_properties: vk::MemoryPropertyFlags,
-) -> (vk::Buffer, vk::DeviceMemory, vk::DeviceSize) {
+    location: MemoryLocation,
+) -> (vk::Buffer, Allocation) {
     let device = vk_context.device();
     let buffer = {
         let buffer_info = vk::BufferCreateInfo::default()
@@ -25,17 +24,29 @@ pub fn create_buffer(
     };
 
     let mem_requirements = unsafe { device.get_buffer_memory_requirements(buffer) };
-    let memory = {
-        let mem_type_index = vk_context.find_memory_type(mem_requirements, mem_properties);
-        let alloc_info = vk::MemoryAllocateInfo::default()
-            .allocation_size(mem_requirements.size)
-            .memory_type_index(mem_type_index);
-        unsafe { device.allocate_memory(&alloc_info, None).unwrap() }
+    let allocation = vk_context.allocate("buffer", mem_requirements, location, true);
+
+    unsafe {
+        device.bind_buffer_memory(buffer, allocation.memory(), allocation.offset()).unwrap()
     };
 
-    unsafe { device.bind_buffer_memory(buffer, memory, 0).unwrap() };
+    (buffer, allocation)
+}
+
+/// Destroy a buffer created with `create_buffer` and free its memory.
+pub unsafe fn destroy_buffer(vk_context: &VkContext, buffer: vk::Buffer, allocation: Allocation) {
+    vk_context.device().destroy_buffer(buffer, None);
+    vk_context.free(allocation);
+}
 
-    (buffer, memory, mem_requirements.size)
+/// Copy `data` to the start of the mapped memory of `allocation`,
+/// the elements are aligned to the alignment of `A`.
+pub fn write_mapped<A, T: Copy>(allocation: &Allocation, data: &[T]) {
+    let ptr = allocation.mapped_ptr().expect("Memory is not host visible");
+    unsafe {
+        let mut align = ash::util::Align::new(ptr.as_ptr(), align_of::<A>() as _, allocation.size());
+        align.copy_from_slice(data);
+    }
 }
 
 /// Copy the `size` first bytes of `src` into `dst`.
@@ -51,13 +62,65 @@ pub fn copy_buffer(
     size: vk::DeviceSize,
 ) {
     cmd::execute_one_time_commands(device, command_pool, transfer_queue, |buffer| {
-        let region = vk::BufferCopy {
-            src_offset: 0,
-            dst_offset: 0,
-            size,
-        };
-        let regions = [region];
-
-        unsafe { device.cmd_copy_buffer(buffer, src, dst, &regions) };
+        record_copy_buffer(device, buffer, src, dst, size);
     });
 }
+
+/// Record copying the `size` first bytes of `src` into `dst` into `command_buffer`.
+pub fn record_copy_buffer(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    src: vk::Buffer,
+    dst: vk::Buffer,
+    size: vk::DeviceSize,
+) {
+    let region = vk::BufferCopy {
+        src_offset: 0,
+        dst_offset: 0,
+        size,
+    };
+    let regions = [region];
+
+    unsafe { device.cmd_copy_buffer(command_buffer, src, dst, &regions) };
+}
+
+/// Host visible buffer the data of uploads to device local memory is first
+/// copied to. It is reused for all uploads and only grows, so rapidly loading
+/// models and textures does not allocate and free memory every time.
+/// The default is empty, memory is allocated by the first `write`.
+#[derive(Default)]
+pub struct StagingBuffer {
+    buffer: Option<(vk::Buffer, Allocation)>,
+}
+
+impl StagingBuffer {
+    /// Copies `data` to the start of the buffer, aligning its elements to `A`,
+    /// and returns the buffer to copy from. The buffer is recreated first if it is too small.
+    ///
+    /// The previous content must not be in use anymore, which is the case
+    /// after `copy_buffer` since it waits for the copy to finish.
+    pub fn write<A, T: Copy>(&mut self, vk_context: &VkContext, data: &[T]) -> vk::Buffer {
+        let size = size_of_val(data) as vk::DeviceSize;
+        if self.buffer.as_ref().is_none_or(|(_, allocation)| allocation.size() < size) {
+            unsafe { self.destroy(vk_context) };
+            log::debug!("Growing staging buffer to {size} bytes");
+            self.buffer = Some(create_buffer(
+                vk_context,
+                size,
+                vk::BufferUsageFlags::TRANSFER_SRC,
+                MemoryLocation::CpuToGpu,
+            ));
+        }
+
+        let (buffer, allocation) = self.buffer.as_ref().unwrap();
+        write_mapped::<A, _>(allocation, data);
+        *buffer
+    }
+
+    /// Frees the buffer, it is empty afterwards.
+    pub unsafe fn destroy(&mut self, vk_context: &VkContext) {
+        if let Some((buffer, allocation)) = self.buffer.take() {
+            destroy_buffer(vk_context, buffer, allocation);
+        }
+    }
+}
@@ -1,33 +1,19 @@
 use ash::{vk, Device};
 
 /// Create a one time use command buffer and pass it to `executor`.
-pub fn execute_one_time_commands<F: FnOnce(vk::CommandBuffer)>(
+///
+/// Waits until the commands are executed and returns the result of `executor`.
+pub fn execute_one_time_commands<R, F: FnOnce(vk::CommandBuffer) -> R>(
     device: &Device,
     command_pool: vk::CommandPool,
     queue: vk::Queue,
     executor: F,
-) {
-    let command_buffer = {
-        let alloc_info = vk::CommandBufferAllocateInfo::default()
-            .level(vk::CommandBufferLevel::PRIMARY)
-            .command_pool(command_pool)
-            .command_buffer_count(1);
-
-        unsafe { device.allocate_command_buffers(&alloc_info).unwrap()[0] }
-    };
+) -> R {
+    let command_buffer = begin_one_time_commands(device, command_pool);
     let command_buffers = [command_buffer];
 
-    // Begin recording
-    {
-        let begin_info = vk::CommandBufferBeginInfo::default()
-            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
-        unsafe {
-            device.begin_command_buffer(command_buffer, &begin_info).unwrap()
-        };
-    }
-
     // Execute user function
-    executor(command_buffer);
+    let result = executor(command_buffer);
 
     // End recording
     unsafe { device.end_command_buffer(command_buffer).unwrap() };
@@ -46,4 +32,68 @@ pub fn execute_one_time_commands<F: FnOnce(vk::CommandBuffer)>(
 
     // Free
     unsafe { device.free_command_buffers(command_pool, &command_buffers) };
+    result
+}
+
+/// Like `execute_one_time_commands` but does not wait for the commands to be executed.
+/// The returned `PendingCommands` tell when they are done.
+pub fn submit_one_time_commands<R, F: FnOnce(vk::CommandBuffer) -> R>(
+    device: &Device,
+    command_pool: vk::CommandPool,
+    queue: vk::Queue,
+    executor: F,
+) -> (R, PendingCommands) {
+    let command_buffer = begin_one_time_commands(device, command_pool);
+    let result = executor(command_buffer);
+    unsafe { device.end_command_buffer(command_buffer).unwrap() };
+
+    let fence = unsafe { device.create_fence(&vk::FenceCreateInfo::default(), None).unwrap() };
+    let command_buffers = [command_buffer];
+    let submit_info = vk::SubmitInfo::default().command_buffers(&command_buffers);
+    unsafe { device.queue_submit(queue, &[submit_info], fence).unwrap() };
+    (result, PendingCommands { command_buffer, fence })
+}
+
+fn begin_one_time_commands(device: &Device, command_pool: vk::CommandPool) -> vk::CommandBuffer {
+    let command_buffer = {
+        let alloc_info = vk::CommandBufferAllocateInfo::default()
+            .level(vk::CommandBufferLevel::PRIMARY)
+            .command_pool(command_pool)
+            .command_buffer_count(1);
+
+        unsafe { device.allocate_command_buffers(&alloc_info).unwrap()[0] }
+    };
+
+    let begin_info = vk::CommandBufferBeginInfo::default()
+        .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+    unsafe {
+        device.begin_command_buffer(command_buffer, &begin_info).unwrap()
+    };
+    command_buffer
+}
+
+/// A command buffer submitted by `submit_one_time_commands` and the fence signaled
+/// when it is executed.
+pub struct PendingCommands {
+    command_buffer: vk::CommandBuffer,
+    fence: vk::Fence,
+}
+
+impl PendingCommands {
+    pub fn is_done(&self, device: &Device) -> bool {
+        matches!(unsafe { device.get_fence_status(self.fence) }, Ok(true))
+    }
+
+    /// Waits until the commands are executed, a lost device is only logged.
+    pub fn wait(&self, device: &Device) {
+        if let Err(err) = unsafe { device.wait_for_fences(&[self.fence], true, u64::MAX) } {
+            log::error!("Failed to wait for commands: {err}");
+        }
+    }
+
+    /// Frees the command buffer, which must not be executing anymore.
+    pub unsafe fn destroy(self, device: &Device, command_pool: vk::CommandPool) {
+        device.destroy_fence(self.fence, None);
+        device.free_command_buffers(command_pool, &[self.command_buffer]);
+    }
 }
@@ -0,0 +1,205 @@
+use super::structs::{CubemapPaths, ShaderSpv};
+
+use ash::vk;
+use std::path::PathBuf;
+
+/// Options to create a `VkApp` with.
+///
+/// The default uses the shaders built with the crate and the assets directory
+/// relative to the working directory. Change options with the builder methods:
+///
+/// ```
+/// use scop_lib::vulkan::VkAppConfig;
+///
+/// let config = VkAppConfig::default()
+///     .size(1024, 768)
+///     .model_path("assets/models/teapot.obj")
+///     .device_index(Some(1));
+/// assert_eq!(config.width, 1024);
+/// ```
+#[derive(Debug, Clone)]
+pub struct VkAppConfig {
+    pub width: u32,
+    pub height: u32,
+    /// Model to display first, see `fs::load_model` for the supported formats.
+    pub model_path: PathBuf,
+    /// Texture of the model, a white texture is used if it is `None` or cannot be loaded.
+    pub image_path: Option<PathBuf>,
+    pub cubemap_paths: CubemapPaths,
+    pub shader_spv: ShaderSpv,
+    pub cubemap_spv: ShaderSpv,
+    pub uv_spv: ShaderSpv,
+    pub grid_spv: ShaderSpv,
+    pub normals_spv: ShaderSpv,
+    /// Shaders of point clouds, models without faces.
+    pub points_spv: ShaderSpv,
+    /// Shaders of the anti-aliasing pass, see `VkApp::set_fxaa`.
+    pub fxaa_spv: ShaderSpv,
+    #[cfg(feature = "ui")]
+    pub ui_spv: ShaderSpv,
+    /// Maximum number of MSAA samples, the highest supported count is used if `None`.
+    pub msaa_samples: Option<vk::SampleCountFlags>,
+    /// Linear color the frame is cleared with, visible where the skybox is hidden.
+    pub clear_color: [f32; 4],
+    /// Preferred present mode, FIFO is used if it is not supported.
+    pub present_mode: vk::PresentModeKHR,
+    /// Index of the GPU to use, the first suitable one is used if `None`.
+    pub device_index: Option<usize>,
+    /// Whether textures and the skybox get mipmaps, see `VkApp::generate_mips`.
+    pub generate_mips: bool,
+    /// Maximum anisotropy of the texture samplers, see `VkApp::set_anisotropy`.
+    pub anisotropy: f32,
+}
+
+impl Default for VkAppConfig {
+    fn default() -> Self {
+        Self {
+            width: 800,
+            height: 600,
+            model_path: PathBuf::from("assets/models/42.obj"),
+            image_path: Some(PathBuf::from("assets/images/cat.png")),
+            cubemap_paths: CubemapPaths::from_dir("assets/cubemap"),
+            shader_spv: ShaderSpv {
+                vert: include_bytes!(concat!(env!("OUT_DIR"), "/shader.vert.spv")),
+                frag: include_bytes!(concat!(env!("OUT_DIR"), "/shader.frag.spv")),
+            },
+            cubemap_spv: ShaderSpv {
+                vert: include_bytes!(concat!(env!("OUT_DIR"), "/cubemap.vert.spv")),
+                frag: include_bytes!(concat!(env!("OUT_DIR"), "/cubemap.frag.spv")),
+            },
+            uv_spv: ShaderSpv {
+                vert: include_bytes!(concat!(env!("OUT_DIR"), "/uv.vert.spv")),
+                frag: include_bytes!(concat!(env!("OUT_DIR"), "/uv.frag.spv")),
+            },
+            // the grid only passes through vertex colors like the uv layout
+            grid_spv: ShaderSpv {
+                vert: include_bytes!(concat!(env!("OUT_DIR"), "/grid.vert.spv")),
+                frag: include_bytes!(concat!(env!("OUT_DIR"), "/uv.frag.spv")),
+            },
+            normals_spv: ShaderSpv {
+                vert: include_bytes!(concat!(env!("OUT_DIR"), "/normals.vert.spv")),
+                frag: include_bytes!(concat!(env!("OUT_DIR"), "/uv.frag.spv")),
+            },
+            points_spv: ShaderSpv {
+                vert: include_bytes!(concat!(env!("OUT_DIR"), "/points.vert.spv")),
+                frag: include_bytes!(concat!(env!("OUT_DIR"), "/points.frag.spv")),
+            },
+            fxaa_spv: ShaderSpv {
+                vert: include_bytes!(concat!(env!("OUT_DIR"), "/fxaa.vert.spv")),
+                frag: include_bytes!(concat!(env!("OUT_DIR"), "/fxaa.frag.spv")),
+            },
+            #[cfg(feature = "ui")]
+            ui_spv: ShaderSpv {
+                vert: include_bytes!(concat!(env!("OUT_DIR"), "/ui.vert.spv")),
+                frag: include_bytes!(concat!(env!("OUT_DIR"), "/ui.frag.spv")),
+            },
+            msaa_samples: None,
+            clear_color: [0., 0., 0., 1.],
+            present_mode: vk::PresentModeKHR::MAILBOX,
+            device_index: None,
+            generate_mips: true,
+            anisotropy: 16.,
+        }
+    }
+}
+
+impl VkAppConfig {
+    /// Sets the initial size of the swapchain images.
+    pub fn size(mut self, width: u32, height: u32) -> Self {
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    pub fn model_path<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.model_path = path.into();
+        self
+    }
+
+    pub fn image_path<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.image_path = Some(path.into());
+        self
+    }
+
+    /// Shows the model with a white texture until one is loaded.
+    pub fn no_image(mut self) -> Self {
+        self.image_path = None;
+        self
+    }
+
+    pub fn cubemap_paths(mut self, paths: CubemapPaths) -> Self {
+        self.cubemap_paths = paths;
+        self
+    }
+
+    pub fn shader_spv(mut self, spv: ShaderSpv) -> Self {
+        self.shader_spv = spv;
+        self
+    }
+
+    pub fn cubemap_spv(mut self, spv: ShaderSpv) -> Self {
+        self.cubemap_spv = spv;
+        self
+    }
+
+    pub fn uv_spv(mut self, spv: ShaderSpv) -> Self {
+        self.uv_spv = spv;
+        self
+    }
+
+    pub fn grid_spv(mut self, spv: ShaderSpv) -> Self {
+        self.grid_spv = spv;
+        self
+    }
+
+    pub fn normals_spv(mut self, spv: ShaderSpv) -> Self {
+        self.normals_spv = spv;
+        self
+    }
+
+    pub fn points_spv(mut self, spv: ShaderSpv) -> Self {
+        self.points_spv = spv;
+        self
+    }
+
+    pub fn fxaa_spv(mut self, spv: ShaderSpv) -> Self {
+        self.fxaa_spv = spv;
+        self
+    }
+
+    #[cfg(feature = "ui")]
+    pub fn ui_spv(mut self, spv: ShaderSpv) -> Self {
+        self.ui_spv = spv;
+        self
+    }
+
+    pub fn msaa_samples(mut self, samples: Option<vk::SampleCountFlags>) -> Self {
+        self.msaa_samples = samples;
+        self
+    }
+
+    pub fn clear_color(mut self, color: [f32; 4]) -> Self {
+        self.clear_color = color;
+        self
+    }
+
+    pub fn present_mode(mut self, mode: vk::PresentModeKHR) -> Self {
+        self.present_mode = mode;
+        self
+    }
+
+    pub fn device_index(mut self, index: Option<usize>) -> Self {
+        self.device_index = index;
+        self
+    }
+
+    pub fn generate_mips(mut self, generate_mips: bool) -> Self {
+        self.generate_mips = generate_mips;
+        self
+    }
+
+    pub fn anisotropy(mut self, anisotropy: f32) -> Self {
+        self.anisotropy = anisotropy;
+        self
+    }
+}
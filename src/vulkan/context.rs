@@ -1,13 +1,20 @@
 use super::debug::setup_debug_messenger;
 use super::swapchain::SwapchainSupportDetails;
 
-use anyhow::anyhow;
+use anyhow::{anyhow, Context};
 use ash::{
     ext::debug_utils,
     khr::{surface, swapchain as khr_swapchain},
     vk, Device, Entry, Instance
 };
-use std::ffi::CStr;
+use gpu_allocator::{
+    vulkan::{Allocation, AllocationCreateDesc, AllocationScheme, Allocator, AllocatorCreateDesc},
+    MemoryLocation,
+};
+use std::{
+    ffi::CStr,
+    sync::{Mutex, MutexGuard},
+};
 
 #[derive(Debug, Clone, Copy)]
 pub struct QueueFamiliesIndices {
@@ -19,41 +26,74 @@ pub struct VkContext {
     _entry: Entry,
     instance: Instance,
     debug_report_callback: Option<(debug_utils::Instance, vk::DebugUtilsMessengerEXT)>,
-    surface: surface::Instance,
-    surface_khr: vk::SurfaceKHR,
+    /// `None` when rendering offscreen without a window.
+    surface: Option<(surface::Instance, vk::SurfaceKHR)>,
     physical_device: vk::PhysicalDevice,
     device: Device,
+    /// Sub-allocates the memory of all buffers and images, so the number of actual
+    /// allocations stays below `maxMemoryAllocationCount`. Only `None` while dropping,
+    /// it must be dropped before the device.
+    allocator: Option<Mutex<Allocator>>,
     queue_families_indices: QueueFamiliesIndices,
+    fill_mode_non_solid: bool,
+    sampler_anisotropy: bool,
 }
 
 impl VkContext {
+    /// Creates the context, a device that can present to `surface` is picked if
+    /// one is given, otherwise any device that supports graphics.
     pub fn new(
         entry: Entry,
         instance: Instance,
-        surface: surface::Instance,
-        surface_khr: vk::SurfaceKHR,
+        surface: Option<(surface::Instance, vk::SurfaceKHR)>,
+        device_index: Option<usize>,
     ) -> Result<Self, anyhow::Error> {
         let debug_report_callback = setup_debug_messenger(&entry, &instance);
 
+        let surface_ref = surface.as_ref().map(|(surface, surface_khr)| (surface, *surface_khr));
         let (physical_device, queue_families_indices) =
-            Self::pick_physical_device(&instance, &surface, surface_khr)
+            Self::pick_physical_device(&instance, surface_ref, device_index)
             .ok_or(anyhow!("No suitable physical device found"))?;
 
+        let features = unsafe { instance.get_physical_device_features(physical_device) };
+        let fill_mode_non_solid = features.fill_mode_non_solid == vk::TRUE;
+        let sampler_anisotropy = features.sampler_anisotropy == vk::TRUE;
+        let large_points = features.large_points == vk::TRUE;
+        if !large_points {
+            log::debug!("The device does not support large points, point clouds are drawn with single pixels");
+        }
+        if !sampler_anisotropy {
+            log::warn!("The device does not support anisotropic filtering, it is disabled");
+        }
         let device = Self::create_logical_device(
             &instance,
             physical_device,
             queue_families_indices,
+            fill_mode_non_solid,
+            sampler_anisotropy,
+            large_points,
+            surface.is_some(),
         )?;
+        let allocator = Allocator::new(&AllocatorCreateDesc {
+            instance: instance.clone(),
+            device: device.clone(),
+            physical_device,
+            debug_settings: Default::default(),
+            buffer_device_address: false,
+            allocation_sizes: Default::default(),
+        }).context("Failed to create memory allocator")?;
 
         Ok(VkContext {
             _entry: entry,
             instance,
             debug_report_callback,
             surface,
-            surface_khr,
             physical_device,
             device,
+            allocator: Some(Mutex::new(allocator)),
             queue_families_indices,
+            fill_mode_non_solid,
+            sampler_anisotropy,
         })
     }
 
@@ -61,12 +101,16 @@ impl VkContext {
         &self.instance
     }
 
-    pub fn surface(&self) -> &surface::Instance {
-        &self.surface
+    /// Returns the surface or `None` if the context was created without a window.
+    pub fn surface(&self) -> Option<(&surface::Instance, vk::SurfaceKHR)> {
+        self.surface.as_ref().map(|(surface, surface_khr)| (surface, *surface_khr))
     }
 
-    pub fn surface_khr(&self) -> vk::SurfaceKHR {
-        self.surface_khr
+    /// Returns the swapchain support of the surface or `None` without a window.
+    pub fn swapchain_support_details(&self) -> Option<SwapchainSupportDetails> {
+        self.surface().map(|(surface, surface_khr)| {
+            SwapchainSupportDetails::new(self.physical_device, surface, surface_khr)
+        })
     }
 
     pub fn physical_device(&self) -> vk::PhysicalDevice {
@@ -85,40 +129,55 @@ impl VkContext {
         self.queue_families_indices.present_index
     }
 
+    /// Returns true if the `fillModeNonSolid` feature is enabled,
+    /// which is needed for wireframe and point polygon modes.
+    pub fn supports_fill_mode_non_solid(&self) -> bool {
+        self.fill_mode_non_solid
+    }
+
+    /// Returns true if the `samplerAnisotropy` feature is enabled.
+    pub fn supports_sampler_anisotropy(&self) -> bool {
+        self.sampler_anisotropy
+    }
+
     pub fn physical_device_properties(&self) -> vk::PhysicalDeviceProperties {
         unsafe {
             self.instance.get_physical_device_properties(self.physical_device)
         }
     }
 
-    pub fn get_mem_properties(&self) -> vk::PhysicalDeviceMemoryProperties {
-        unsafe {
-            self.instance.get_physical_device_memory_properties(self.physical_device)
-        }
+    fn allocator(&self) -> MutexGuard<'_, Allocator> {
+        self.allocator.as_ref().unwrap().lock().unwrap()
     }
 
-    /// Find a memory type in `mem_properties` that is suitable
-    /// for `requirements` and supports `required_properties`.
+    /// Sub-allocates memory for a resource with `requirements`, memory at a host visible
+    /// `location` stays mapped. `linear` is false for images with optimal tiling.
     ///
-    /// # Returns
-    ///
-    /// The index of the memory type from `mem_properties`.
-    pub fn find_memory_type(
+    /// The resource must be bound to `memory()` at `offset()` of the allocation
+    /// and the allocation must be given back to `free` after destroying it.
+    pub fn allocate(
         &self,
+        name: &str,
         requirements: vk::MemoryRequirements,
-        required_properties: vk::MemoryPropertyFlags,
-    ) -> u32 {
-        let mem_properties = self.get_mem_properties();
-        for i in 0..mem_properties.memory_type_count {
-            if requirements.memory_type_bits & (1 << i) != 0
-                && mem_properties.memory_types[i as usize]
-                    .property_flags
-                    .contains(required_properties)
-            {
-                return i;
-            }
+        location: MemoryLocation,
+        linear: bool,
+    ) -> Allocation {
+        let desc = AllocationCreateDesc {
+            name,
+            requirements,
+            location,
+            linear,
+            allocation_scheme: AllocationScheme::GpuAllocatorManaged,
+        };
+        self.allocator().allocate(&desc)
+            .unwrap_or_else(|err| panic!("Failed to allocate memory for {name}: {err}"))
+    }
+
+    /// Gives the memory of a destroyed resource back to the allocator.
+    pub fn free(&self, allocation: Allocation) {
+        if let Err(err) = self.allocator().free(allocation) {
+            log::warn!("Failed to free memory: {err}");
         }
-        panic!("Failed to find suitable memory type.")
     }
 
     pub fn create_command_pool(&self, create_flags: vk::CommandPoolCreateFlags) -> vk::CommandPool {
@@ -148,12 +207,15 @@ impl VkContext {
         })
     }
 
+    /// Return the sample counts supported by both color and depth attachments.
+    pub fn supported_sample_counts(&self) -> vk::SampleCountFlags {
+        let props = self.physical_device_properties();
+        props.limits.framebuffer_color_sample_counts & props.limits.framebuffer_depth_sample_counts
+    }
+
     /// Return the maximum sample count supported.
     pub fn get_max_usable_sample_count(&self) -> vk::SampleCountFlags {
-        let props = self.physical_device_properties();
-        let color_sample_counts = props.limits.framebuffer_color_sample_counts;
-        let depth_sample_counts = props.limits.framebuffer_depth_sample_counts;
-        let sample_counts = color_sample_counts.min(depth_sample_counts);
+        let sample_counts = self.supported_sample_counts();
 
         if sample_counts.contains(vk::SampleCountFlags::TYPE_64) {
             vk::SampleCountFlags::TYPE_64
@@ -172,49 +234,76 @@ impl VkContext {
         }
     }
 
-    /// Pick the first suitable physical device.
+    /// Returns the name and type of all physical devices in the order
+    /// used for the `device_index` of `new`.
+    pub fn list_physical_devices(instance: &Instance) -> Vec<(String, vk::PhysicalDeviceType)> {
+        let devices = unsafe { instance.enumerate_physical_devices().unwrap_or_default() };
+        devices.into_iter()
+            .map(|device| {
+                let props = unsafe { instance.get_physical_device_properties(device) };
+                let name = unsafe { CStr::from_ptr(props.device_name.as_ptr()) };
+                (name.to_string_lossy().into_owned(), props.device_type)
+            })
+            .collect()
+    }
+
+    /// Pick the physical device at `device_index` or the first suitable one if
+    /// no index is given or the device at the index is not suitable.
     ///
     /// # Requirements
     /// - At least one queue family with one queue supportting graphics.
-    /// - At least one queue family with one queue supporting presentation to `surface_khr`.
+    /// - At least one queue family with one queue supporting presentation to `surface`.
     /// - Swapchain extension support.
     ///
+    /// The last two are not required without a surface.
+    ///
     /// # Returns
     ///
     /// None if no suitable device is found.
     fn pick_physical_device(
         instance: &Instance,
-        surface: &surface::Instance,
-        surface_khr: vk::SurfaceKHR,
+        surface: Option<(&surface::Instance, vk::SurfaceKHR)>,
+        device_index: Option<usize>,
     ) -> Option<(vk::PhysicalDevice, QueueFamiliesIndices)> {
         let devices = unsafe { instance.enumerate_physical_devices().ok()? };
-        let (device, queue_families_indices) = devices
-            .into_iter()
-            .filter_map(|device| {
-                if !Self::check_device_extension_support(instance, device) {
-                    return None;
-                }
+        let is_suitable = |&device: &vk::PhysicalDevice| {
+            if !Self::check_device_extension_support(instance, device, surface.is_some()) {
+                return None;
+            }
 
+            if let Some((surface, surface_khr)) = surface {
                 let details = SwapchainSupportDetails::new(device, surface, surface_khr);
                 if details.formats.is_empty() || details.present_modes.is_empty() {
                     return None;
                 }
+            }
 
-                let features = unsafe { instance.get_physical_device_features(device) };
-                if features.sampler_anisotropy != vk::TRUE
-                    || features.geometry_shader != vk::TRUE
-                {
-                    return None;
-                }
+            let features = unsafe { instance.get_physical_device_features(device) };
+            if features.geometry_shader != vk::TRUE {
+                return None;
+            }
 
-                let queue_families_indices =
-                    Self::find_queue_families(instance, surface, surface_khr, device)?;
-                Some((device, queue_families_indices))
-            })
-            .next()?;
+            let queue_families_indices =
+                Self::find_queue_families(instance, surface, device)?;
+            Some((device, queue_families_indices))
+        };
+
+        let requested = device_index.and_then(|index| {
+            let Some(device) = devices.get(index) else {
+                log::warn!("There is no GPU with index {index}, only {} found", devices.len());
+                return None;
+            };
+            let picked = is_suitable(device);
+            if picked.is_none() {
+                log::warn!("GPU {index} is not suitable, falling back to the first suitable one");
+            }
+            picked
+        });
+        let (device, queue_families_indices) = requested
+            .or_else(|| devices.iter().find_map(is_suitable))?;
 
         let props = unsafe { instance.get_physical_device_properties(device) };
-        log::debug!("Selected physical device: {:?}", unsafe {
+        log::info!("Selected physical device: {:?}", unsafe {
             CStr::from_ptr(props.device_name.as_ptr())
         });
 
@@ -226,6 +315,10 @@ impl VkContext {
         instance: &Instance,
         device: vk::PhysicalDevice,
         queue_families_indices: QueueFamiliesIndices,
+        fill_mode_non_solid: bool,
+        sampler_anisotropy: bool,
+        large_points: bool,
+        with_swapchain: bool,
     ) -> Result<Device, anyhow::Error> {
         let graphics_family_index = queue_families_indices.graphics_index;
         let present_family_index = queue_families_indices.present_index;
@@ -249,14 +342,16 @@ impl VkContext {
                 .collect::<Vec<_>>()
         };
 
-        let device_extensions = Self::get_required_device_extensions();
+        let device_extensions = Self::get_required_device_extensions(with_swapchain);
         let device_extensions_ptrs = device_extensions.iter()
             .map(|ext| ext.as_ptr())
             .collect::<Vec<_>>();
 
         let device_features = vk::PhysicalDeviceFeatures::default()
             .geometry_shader(true)
-            .sampler_anisotropy(true);
+            .sampler_anisotropy(sampler_anisotropy)
+            .fill_mode_non_solid(fill_mode_non_solid)
+            .large_points(large_points);
 
         let device_create_info = vk::DeviceCreateInfo::default()
             .queue_create_infos(&queue_create_infos)
@@ -270,12 +365,16 @@ impl VkContext {
         Ok(device)
     }
 
-    fn check_device_extension_support(instance: &Instance, device: vk::PhysicalDevice) -> bool {
+    fn check_device_extension_support(
+        instance: &Instance,
+        device: vk::PhysicalDevice,
+        with_swapchain: bool,
+    ) -> bool {
         let extension_props = unsafe {
             instance.enumerate_device_extension_properties(device).unwrap()
         };
 
-        Self::get_required_device_extensions().into_iter().all(|required_ext| {
+        Self::get_required_device_extensions(with_swapchain).into_iter().all(|required_ext| {
             extension_props.iter().any(|ext| {
                 let name = unsafe { CStr::from_ptr(ext.extension_name.as_ptr()) };
                 required_ext == name
@@ -283,22 +382,22 @@ impl VkContext {
         })
     }
 
-    fn get_required_device_extensions() -> [&'static CStr; 1] {
-        #[cfg(not(any(target_os = "macos", target_os = "ios")))]
-        [khr_swapchain::NAME]
-    }
-
-    #[cfg(any(target_os = "macos", target_os = "ios"))]
-    fn get_required_device_extensions() -> [&'static CStr; 2] {
-        [khr_swapchain::NAME, ash::khr::portability_subset::NAME]
+    fn get_required_device_extensions(with_swapchain: bool) -> Vec<&'static CStr> {
+        let mut extensions = Vec::new();
+        if with_swapchain {
+            extensions.push(khr_swapchain::NAME);
+        }
+        #[cfg(any(target_os = "macos", target_os = "ios"))]
+        extensions.push(ash::khr::portability_subset::NAME);
+        extensions
     }
 
     /// Find a queue family with at least one graphics queue and one with
     /// at least one presentation queue from `device`.
+    /// Without a surface the graphics queue is used as presentation queue.
     fn find_queue_families(
         instance: &Instance,
-        surface: &surface::Instance,
-        surface_khr: vk::SurfaceKHR,
+        surface: Option<(&surface::Instance, vk::SurfaceKHR)>,
         device: vk::PhysicalDevice,
     ) -> Option<QueueFamiliesIndices> {
         let mut graphics = None;
@@ -316,10 +415,14 @@ impl VkContext {
             if family.queue_flags.contains(vk::QueueFlags::GRAPHICS) && graphics.is_none() {
                 graphics = Some(index);
             }
-            let present_support = unsafe {
-                surface.get_physical_device_surface_support(device, index, surface_khr)
+            let present_support = match surface {
+                Some((surface, surface_khr)) => unsafe {
+                    surface.get_physical_device_surface_support(device, index, surface_khr)
+                        .unwrap_or(false)
+                },
+                None => graphics == Some(index),
             };
-            if present_support.unwrap_or(false) && present.is_none() {
+            if present_support && present.is_none() {
                 present = Some(index);
             }
 
@@ -337,9 +440,13 @@ impl VkContext {
 
 impl Drop for VkContext {
     fn drop(&mut self) {
+        // frees the memory blocks and reports leaked allocations
+        self.allocator.take();
         unsafe {
             self.device.destroy_device(None);
-            self.surface.destroy_surface(self.surface_khr, None);
+            if let Some((surface, surface_khr)) = self.surface.take() {
+                surface.destroy_surface(surface_khr, None);
+            }
             if let Some((utils, messenger)) = self.debug_report_callback.take() {
                 utils.destroy_debug_utils_messenger(messenger, None);
             }
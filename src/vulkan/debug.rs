@@ -5,15 +5,58 @@ use ash::{
 use std::{
     ffi::{CStr, CString},
     os::raw::{c_char, c_void},
+    sync::OnceLock,
 };
 
+/// Whether validation layers are enabled unless `VALIDATION_ENV_VAR` is set.
 #[cfg(debug_assertions)]
 pub const ENABLE_VALIDATION_LAYERS: bool = false;
 #[cfg(not(debug_assertions))]
 pub const ENABLE_VALIDATION_LAYERS: bool = false;
 
+/// Environment variable to enable (`1`) or disable (`0`) validation layers at runtime.
+pub const VALIDATION_ENV_VAR: &str = "SCOP_VALIDATION";
+
 const REQUIRED_LAYERS: [&str; 1] = ["VK_LAYER_KHRONOS_validation"];
 
+/// Returns whether validation layers and the debug messenger are used. They are
+/// requested by `VALIDATION_ENV_VAR` or else `ENABLE_VALIDATION_LAYERS` and used if
+/// the layers are installed. The result is decided and logged on the first call.
+pub fn validation_enabled(entry: &Entry) -> bool {
+    static ENABLED: OnceLock<bool> = OnceLock::new();
+    *ENABLED.get_or_init(|| {
+        let requested = match std::env::var(VALIDATION_ENV_VAR) {
+            Ok(value) => match value.as_str() {
+                "1" | "true" => true,
+                "0" | "false" => false,
+                _ => {
+                    log::warn!("Ignoring invalid {VALIDATION_ENV_VAR} value {value:?}, expected 0 or 1");
+                    ENABLE_VALIDATION_LAYERS
+                }
+            },
+            Err(_) => ENABLE_VALIDATION_LAYERS,
+        };
+        if !requested {
+            log::info!("Validation layers disabled, run with {VALIDATION_ENV_VAR}=1 to enable them");
+            return false;
+        }
+        match check_validation_layer_support(entry) {
+            Ok(()) => {
+                log::info!("Validation layers enabled");
+                true
+            }
+            Err(layer) => {
+                log::warn!("Validation layers disabled, {layer} is not installed");
+                false
+            }
+        }
+    })
+}
+
+/// Logs the messages of the debug messenger with the `log` crate, so they can be
+/// filtered with `RUST_LOG` like the rest. The target is `vulkan::<type>`, e.g.
+/// `RUST_LOG=vulkan::validation=warn`. Verbose messages are logged as trace
+/// and info messages as debug, warnings and errors as themselves.
 unsafe extern "system" fn vulkan_debug_callback(
     flag: vk::DebugUtilsMessageSeverityFlagsEXT,
     typ: vk::DebugUtilsMessageTypeFlagsEXT,
@@ -21,13 +64,49 @@ unsafe extern "system" fn vulkan_debug_callback(
     _: *mut c_void,
 ) -> vk::Bool32 {
     use vk::DebugUtilsMessageSeverityFlagsEXT as Flag;
+    use vk::DebugUtilsMessageTypeFlagsEXT as Type;
+
+    let level = match flag {
+        Flag::VERBOSE => log::Level::Trace,
+        Flag::INFO => log::Level::Debug,
+        Flag::WARNING => log::Level::Warn,
+        _ => log::Level::Error,
+    };
+    let target = if typ.contains(Type::VALIDATION) {
+        "vulkan::validation"
+    } else if typ.contains(Type::PERFORMANCE) {
+        "vulkan::performance"
+    } else if typ.contains(Type::DEVICE_ADDRESS_BINDING) {
+        "vulkan::device_address_binding"
+    } else {
+        "vulkan::general"
+    };
+    if !log::log_enabled!(target: target, level) {
+        return vk::FALSE;
+    }
 
-    let message = CStr::from_ptr((*p_callback_data).p_message);
-    match flag {
-        Flag::VERBOSE => log::debug!("{:?} - {:?}", typ, message),
-        Flag::INFO => log::info!("{:?} - {:?}", typ, message),
-        Flag::WARNING => log::warn!("{:?} - {:?}", typ, message),
-        _ => log::error!("{:?} - {:?}", typ, message),
+    let data = &*p_callback_data;
+    let to_str = |ptr: *const c_char| {
+        if ptr.is_null() { None } else { Some(CStr::from_ptr(ptr).to_string_lossy()) }
+    };
+    let message = to_str(data.p_message).unwrap_or_default();
+    let id_name = to_str(data.p_message_id_name).unwrap_or_default();
+    let objects = if data.p_objects.is_null() {
+        &[][..]
+    } else {
+        std::slice::from_raw_parts(data.p_objects, data.object_count as usize)
+    };
+    let objects = objects.iter().map(|object| {
+        match to_str(object.p_object_name) {
+            Some(name) => format!("{:?} {:#x} \"{name}\"", object.object_type, object.object_handle),
+            None => format!("{:?} {:#x}", object.object_type, object.object_handle),
+        }
+    }).collect::<Vec<_>>();
+
+    if objects.is_empty() {
+        log::log!(target: target, level, "[{id_name}] {message}");
+    } else {
+        log::log!(target: target, level, "[{id_name}] {message} (objects: {})", objects.join(", "));
     }
     vk::FALSE
 }
@@ -49,22 +128,20 @@ pub fn get_layer_names_and_pointers() -> (Vec<CString>, Vec<*const c_char>) {
 /// Check if the required validation set in `REQUIRED_LAYERS`
 /// are supported by the Vulkan instance.
 ///
-/// # Panics
-///
-/// Panic if at least one on the layer is not supported.
-pub fn check_validation_layer_support(entry: &Entry) {
-    let supported_layers = unsafe { entry.enumerate_instance_layer_properties().unwrap() };
+/// Returns the first layer that is not supported as error.
+pub fn check_validation_layer_support(entry: &Entry) -> Result<(), &'static str> {
+    let supported_layers = unsafe { entry.enumerate_instance_layer_properties().unwrap_or_default() };
     for required in REQUIRED_LAYERS.iter() {
         let found = supported_layers.iter().any(|layer| {
             let name = unsafe { CStr::from_ptr(layer.layer_name.as_ptr()) };
-            let name = name.to_str().expect("Failed to get layer name pointer");
-            required == &name
+            name.to_str() == Ok(required)
         });
 
         if !found {
-            panic!("Validation layer not supported: {}", required);
+            return Err(required);
         }
     }
+    Ok(())
 }
 
 /// Setup the debug message if validation layers are enabled.
@@ -72,7 +149,7 @@ pub fn setup_debug_messenger(
     entry: &Entry,
     instance: &Instance,
 ) -> Option<(debug_utils::Instance, vk::DebugUtilsMessengerEXT)> {
-    if !ENABLE_VALIDATION_LAYERS {
+    if !validation_enabled(entry) {
         return None;
     }
 
@@ -81,7 +158,8 @@ pub fn setup_debug_messenger(
         .message_severity(
             vk::DebugUtilsMessageSeverityFlagsEXT::ERROR
                 | vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
-                | vk::DebugUtilsMessageSeverityFlagsEXT::INFO,
+                | vk::DebugUtilsMessageSeverityFlagsEXT::INFO
+                | vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE,
         )
         .message_type(
             vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
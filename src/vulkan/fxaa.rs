@@ -0,0 +1,290 @@
+use super::app::VkApp;
+use super::context::VkContext;
+use super::pipeline::{Pipeline, PipelineState};
+use super::structs::ShaderSpv;
+use super::swapchain::SwapchainProperties;
+use super::texture::Texture;
+
+use ash::{vk, Device};
+use gpu_allocator::MemoryLocation;
+
+/// Smooths the edges of the scene with FXAA, a cheaper alternative to MSAA.
+///
+/// The main render pass renders the scene into a texture instead of the swapchain image.
+/// The render pass of this pass then draws a full screen triangle to the swapchain image
+/// that samples the texture. It has the same attachments as the main render pass without
+/// MSAA, so the swapchain framebuffers and the ui pipeline can be used with it.
+pub struct FxaaPass {
+    pub render_pass: vk::RenderPass,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    descriptor_set: vk::DescriptorSet,
+    sampler: vk::Sampler,
+    pipeline: Pipeline,
+    /// Texture the scene is rendered to and the framebuffer of the main render pass
+    /// with it, `None` while there is no swapchain.
+    target: Option<(Texture, vk::Framebuffer)>,
+}
+
+impl FxaaPass {
+    pub fn new(
+        device: &Device,
+        swapchain_properties: SwapchainProperties,
+        depth_format: vk::Format,
+        cache: vk::PipelineCache,
+        spv: ShaderSpv,
+    ) -> Self {
+        let render_pass = Self::create_render_pass(device, swapchain_properties, depth_format);
+        let descriptor_set_layout = {
+            let binding = vk::DescriptorSetLayoutBinding::default()
+                .binding(0)
+                .descriptor_count(1)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT);
+            let bindings = [binding];
+            let layout_info = vk::DescriptorSetLayoutCreateInfo::default().bindings(&bindings);
+            unsafe { device.create_descriptor_set_layout(&layout_info, None).unwrap() }
+        };
+        let descriptor_pool = {
+            let pool_sizes = [vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: 1,
+            }];
+            let pool_info = vk::DescriptorPoolCreateInfo::default()
+                .pool_sizes(&pool_sizes)
+                .max_sets(1);
+            unsafe { device.create_descriptor_pool(&pool_info, None).unwrap() }
+        };
+        let descriptor_set = {
+            let layouts = [descriptor_set_layout];
+            let allocate_info = vk::DescriptorSetAllocateInfo::default()
+                .descriptor_pool(descriptor_pool)
+                .set_layouts(&layouts);
+            unsafe { device.allocate_descriptor_sets(&allocate_info).unwrap()[0] }
+        };
+        // linear filtering blends the samples between the texels along the edges
+        let sampler = {
+            let sampler_info = vk::SamplerCreateInfo::default()
+                .mag_filter(vk::Filter::LINEAR)
+                .min_filter(vk::Filter::LINEAR)
+                .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+                .max_lod(0.0);
+            unsafe { device.create_sampler(&sampler_info, None).unwrap() }
+        };
+        // the vertices of the triangle are computed from the vertex index
+        let state = PipelineState {
+            depth: false,
+            vertex_input: false,
+            ..Default::default()
+        };
+        let pipeline = Pipeline::new(
+            device,
+            state,
+            vk::SampleCountFlags::TYPE_1,
+            render_pass,
+            descriptor_set_layout,
+            cache,
+            spv,
+        );
+
+        Self {
+            render_pass,
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_set,
+            sampler,
+            pipeline,
+            target: None,
+        }
+    }
+
+    /// Creates the render pass with the attachments of the main render pass without MSAA,
+    /// the color attachment, the depth attachment and the swapchain image. Nothing is
+    /// loaded, as the full screen triangle overwrites the whole swapchain image. Like after
+    /// the main render pass the image is transitioned for presenting by the app.
+    fn create_render_pass(
+        device: &Device,
+        swapchain_properties: SwapchainProperties,
+        depth_format: vk::Format,
+    ) -> vk::RenderPass {
+        let unused_attachment_desc = vk::AttachmentDescription::default()
+            .format(swapchain_properties.format.format)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .initial_layout(vk::ImageLayout::UNDEFINED)
+            .final_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+        let depth_attachment_desc = vk::AttachmentDescription::default()
+            .format(depth_format)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .initial_layout(vk::ImageLayout::UNDEFINED)
+            .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+        let target_attachment_desc = vk::AttachmentDescription::default()
+            .format(swapchain_properties.format.format)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .store_op(vk::AttachmentStoreOp::STORE)
+            .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .initial_layout(vk::ImageLayout::UNDEFINED)
+            .final_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+        let attachment_descs = [unused_attachment_desc, depth_attachment_desc, target_attachment_desc];
+
+        let color_attachment_refs = [vk::AttachmentReference::default()
+            .attachment(2)
+            .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)];
+        let depth_attachment_ref = vk::AttachmentReference::default()
+            .attachment(1)
+            .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+        let subpass_descs = [vk::SubpassDescription::default()
+            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+            .color_attachments(&color_attachment_refs)
+            .depth_stencil_attachment(&depth_attachment_ref)];
+
+        // waits for the main render pass, which writes the scene and the same depth attachment
+        let subpass_deps = [vk::SubpassDependency::default()
+            .src_subpass(vk::SUBPASS_EXTERNAL)
+            .dst_subpass(0)
+            .src_stage_mask(
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            )
+            .src_access_mask(
+                vk::AccessFlags::COLOR_ATTACHMENT_WRITE | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            )
+            .dst_stage_mask(
+                vk::PipelineStageFlags::FRAGMENT_SHADER
+                    | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            )
+            .dst_access_mask(
+                vk::AccessFlags::SHADER_READ
+                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE
+                    | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            )];
+
+        let render_pass_info = vk::RenderPassCreateInfo::default()
+            .attachments(&attachment_descs)
+            .subpasses(&subpass_descs)
+            .dependencies(&subpass_deps);
+
+        unsafe { device.create_render_pass(&render_pass_info, None).unwrap() }
+    }
+
+    /// Creates the texture the scene is rendered to with the size of the swapchain and
+    /// its framebuffer for the main `render_pass`, which must leave it in the
+    /// `SHADER_READ_ONLY_OPTIMAL` layout. Destroys the previous ones.
+    pub fn create_target(
+        &mut self,
+        vk_context: &VkContext,
+        render_pass: vk::RenderPass,
+        swapchain_properties: SwapchainProperties,
+        color_texture: &Texture,
+        depth_texture: &Texture,
+    ) {
+        self.destroy_target(vk_context);
+
+        let device = vk_context.device();
+        let format = swapchain_properties.format.format;
+        let extent = swapchain_properties.extent;
+        let (image, memory) = VkApp::create_image(
+            vk_context,
+            MemoryLocation::GpuOnly,
+            extent,
+            1,
+            vk::SampleCountFlags::TYPE_1,
+            format,
+            vk::ImageTiling::OPTIMAL,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+        );
+        let view = VkApp::create_image_view(device, image, 1, format, vk::ImageAspectFlags::COLOR);
+        let texture = Texture::new(image, memory, view, None, 1);
+
+        let attachments = [color_texture.view, depth_texture.view, texture.view];
+        let framebuffer_info = vk::FramebufferCreateInfo::default()
+            .render_pass(render_pass)
+            .attachments(&attachments)
+            .width(extent.width)
+            .height(extent.height)
+            .layers(1);
+        let framebuffer = unsafe { device.create_framebuffer(&framebuffer_info, None).unwrap() };
+
+        let image_infos = [vk::DescriptorImageInfo::default()
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .image_view(texture.view)
+            .sampler(self.sampler)];
+        let write = vk::WriteDescriptorSet::default()
+            .dst_set(self.descriptor_set)
+            .dst_binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(&image_infos);
+        unsafe { device.update_descriptor_sets(&[write], &[]) };
+
+        self.target = Some((texture, framebuffer));
+    }
+
+    /// Destroys the texture of the scene and its framebuffer, the GPU must be idle.
+    pub fn destroy_target(&mut self, vk_context: &VkContext) {
+        if let Some((mut texture, framebuffer)) = self.target.take() {
+            unsafe { vk_context.device().destroy_framebuffer(framebuffer, None) };
+            texture.destroy(vk_context);
+        }
+    }
+
+    /// Returns the framebuffer the main render pass renders the scene to.
+    ///
+    /// Panics if there is no target, see `create_target`.
+    pub fn scene_framebuffer(&self) -> vk::Framebuffer {
+        self.target.as_ref().expect("FXAA pass without target").1
+    }
+
+    /// Begins the render pass of this pass with `framebuffer`, one of the swapchain
+    /// framebuffers, and draws the smoothed scene. The render pass is not ended,
+    /// so more can be drawn on top like the ui.
+    pub unsafe fn record(
+        &self,
+        device: &Device,
+        buffer: vk::CommandBuffer,
+        framebuffer: vk::Framebuffer,
+        extent: vk::Extent2D,
+    ) {
+        let area = vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent,
+        };
+        let render_pass_begin_info = vk::RenderPassBeginInfo::default()
+            .render_pass(self.render_pass)
+            .framebuffer(framebuffer)
+            .render_area(area);
+        device.cmd_begin_render_pass(buffer, &render_pass_begin_info, vk::SubpassContents::INLINE);
+
+        device.cmd_bind_pipeline(buffer, vk::PipelineBindPoint::GRAPHICS, self.pipeline.pipeline);
+        self.pipeline.cmd_set_viewport(device, buffer, extent);
+        device.cmd_bind_descriptor_sets(
+            buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            self.pipeline.layout,
+            0,
+            &[self.descriptor_set],
+            &[],
+        );
+        device.cmd_draw(buffer, 3, 1, 0, 0);
+    }
+
+    /// Destroys all resources including the target, the GPU must be idle.
+    pub unsafe fn destroy(&mut self, vk_context: &VkContext) {
+        self.destroy_target(vk_context);
+        let device = vk_context.device();
+        self.pipeline.cleanup(vk_context);
+        device.destroy_sampler(self.sampler, None);
+        device.destroy_descriptor_pool(self.descriptor_pool, None);
+        device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+        device.destroy_render_pass(self.render_pass, None);
+    }
+}
@@ -0,0 +1,131 @@
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// Status of a model or texture load request.
+#[derive(Debug, Clone)]
+pub enum LoadStatus {
+    Pending,
+    Loaded,
+    /// The error is shared by all clones of the ticket.
+    Failed(Arc<anyhow::Error>),
+}
+
+/// Handle to a model or texture load request that can be used to query its status.
+#[derive(Debug, Clone)]
+pub struct LoadTicket {
+    id: u64,
+    status: Arc<Mutex<LoadStatus>>,
+}
+
+impl LoadTicket {
+    pub(crate) fn new(id: u64) -> Self {
+        Self {
+            id,
+            status: Arc::new(Mutex::new(LoadStatus::Pending)),
+        }
+    }
+
+    /// Returns the unique id of the request.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn status(&self) -> LoadStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    /// Returns true if the request was processed, successfully or not.
+    pub fn is_done(&self) -> bool {
+        !matches!(*self.status.lock().unwrap(), LoadStatus::Pending)
+    }
+
+    pub(crate) fn finish(&self, result: Result<(), anyhow::Error>) {
+        *self.status.lock().unwrap() = match result {
+            Ok(()) => LoadStatus::Loaded,
+            Err(err) => LoadStatus::Failed(Arc::new(err)),
+        };
+    }
+}
+
+#[derive(Debug, Default)]
+struct Requests {
+    next_id: u64,
+    queue: VecDeque<(PathBuf, LoadTicket)>,
+}
+
+/// A queue of model load requests that can be shared between threads.
+///
+/// Requests are only queued here, `VkApp` takes them during `draw_frame` and loads
+/// them in the background, see `VkApp::request_model_load`.
+#[derive(Debug, Clone, Default)]
+pub struct ModelLoadQueue {
+    requests: Arc<Mutex<Requests>>,
+}
+
+impl ModelLoadQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues loading the model at `path` and returns a ticket to follow the request.
+    pub fn request<P: Into<PathBuf>>(&self, path: P) -> LoadTicket {
+        let mut requests = self.requests.lock().unwrap();
+        let ticket = LoadTicket::new(requests.next_id);
+        requests.next_id += 1;
+        requests.queue.push_back((path.into(), ticket.clone()));
+        ticket
+    }
+
+    /// Returns the number of requests not yet processed.
+    pub fn len(&self) -> usize {
+        self.requests.lock().unwrap().queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub(crate) fn pop(&self) -> Option<(PathBuf, LoadTicket)> {
+        self.requests.lock().unwrap().queue.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_from_other_thread() {
+        let queue = ModelLoadQueue::new();
+        let queue_clone = queue.clone();
+        let ticket = std::thread::spawn(move || queue_clone.request("model.obj"))
+            .join()
+            .unwrap();
+        assert!(matches!(ticket.status(), LoadStatus::Pending));
+        assert_eq!(queue.len(), 1);
+
+        let (path, popped) = queue.pop().unwrap();
+        assert_eq!(path, PathBuf::from("model.obj"));
+        assert_eq!(popped.id(), ticket.id());
+        assert!(queue.is_empty());
+
+        let err = anyhow::anyhow!("bad file").context("Failed to parse model.obj");
+        popped.finish(Err(err));
+        assert!(ticket.is_done());
+        match ticket.status() {
+            // the whole error chain is kept
+            LoadStatus::Failed(err) => assert_eq!(format!("{err:#}"), "Failed to parse model.obj: bad file"),
+            status => panic!("Unexpected status {status:?}"),
+        }
+    }
+
+    #[test]
+    fn unique_ids() {
+        let queue = ModelLoadQueue::new();
+        let a = queue.request("a.obj");
+        let b = queue.request("b.obj");
+        assert_ne!(a.id(), b.id());
+        assert_eq!(queue.pop().unwrap().0, PathBuf::from("a.obj"));
+    }
+}
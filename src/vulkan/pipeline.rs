@@ -1,59 +1,166 @@
-use super::buffer;
+use super::buffer::{self, StagingBuffer};
 use super::context::VkContext;
-use super::structs::{ShaderSpv, Vertex};
-use super::swapchain::SwapchainProperties;
+use super::structs::{PushConstants, ShaderSpv, Vertex};
 
 use ash::{vk, Device};
+use gpu_allocator::{vulkan::Allocation, MemoryLocation};
 use std::{
     error::Error,
     ffi::CString,
+    fs,
     io::Cursor,
     mem::size_of_val,
+    path::{Path, PathBuf},
 };
 
-#[derive(Copy, Clone)]
+/// Fixed function state that differs between pipelines.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PipelineState {
+    pub topology: vk::PrimitiveTopology,
+    /// `LINE` and `POINT` require the `fillModeNonSolid` device feature.
+    pub polygon_mode: vk::PolygonMode,
+    pub cull_mode: vk::CullModeFlags,
+    /// Constant and slope factor. Depth bias is disabled if both are zero.
+    pub depth_bias: (f32, f32),
+    /// Compares against the depth buffer, otherwise all fragments pass. Depth is written
+    /// either way, so later depth tested pipelines like the skybox stay behind.
+    pub depth_test: bool,
+    /// Enables alpha blending and disables depth writes, so that what is behind stays visible.
+    pub blend: bool,
+    /// Uses the depth buffer, otherwise `depth_test` and `blend` do not affect it.
+    pub depth: bool,
+    /// Reads `Vertex` attributes from the vertex buffer, otherwise the vertex shader
+    /// computes the vertices from the vertex index like for full screen passes.
+    pub vertex_input: bool,
+}
+
+impl Default for PipelineState {
+    fn default() -> Self {
+        Self {
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            polygon_mode: vk::PolygonMode::FILL,
+            cull_mode: vk::CullModeFlags::NONE,
+            depth_bias: (0., 0.),
+            depth_test: true,
+            blend: false,
+            depth: true,
+            vertex_input: true,
+        }
+    }
+}
+
+/// Returns the path the pipeline cache is stored at,
+/// `$XDG_CACHE_HOME/scop/pipeline_cache.bin` or `~/.cache/scop/pipeline_cache.bin`.
+pub fn pipeline_cache_path() -> Option<PathBuf> {
+    let cache_dir = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))?;
+    Some(cache_dir.join("scop").join("pipeline_cache.bin"))
+}
+
+/// Creates a pipeline cache with the data from `path` if it exists.
+///
+/// The driver validates the header of the data and ignores or rejects data
+/// from another device or driver version, an empty cache is created then.
+pub fn create_pipeline_cache(device: &Device, path: Option<&Path>) -> vk::PipelineCache {
+    let data = path.and_then(|path| fs::read(path).ok()).unwrap_or_default();
+    if !data.is_empty() {
+        let create_info = vk::PipelineCacheCreateInfo::default().initial_data(&data);
+        match unsafe { device.create_pipeline_cache(&create_info, None) } {
+            Ok(cache) => {
+                log::debug!("Loaded pipeline cache with {} bytes", data.len());
+                return cache;
+            }
+            Err(err) => log::debug!("Failed to load pipeline cache, starting empty: {err}"),
+        }
+    }
+    let create_info = vk::PipelineCacheCreateInfo::default();
+    unsafe { device.create_pipeline_cache(&create_info, None).unwrap() }
+}
+
+/// Writes the data of `cache` to `path`, creating the parent directory if needed.
+pub fn save_pipeline_cache(device: &Device, cache: vk::PipelineCache, path: &Path) -> Result<(), Box<dyn Error>> {
+    let data = unsafe { device.get_pipeline_cache_data(cache)? };
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, data)?;
+    Ok(())
+}
+
 pub struct Pipeline {
     pub layout: vk::PipelineLayout,
     pub pipeline: vk::Pipeline,
+    /// Area of the framebuffer to render to or the whole framebuffer if `None`.
+    ///
+    /// Viewport and scissor are dynamic state, so this can change without recreating the pipeline.
+    pub viewport: Option<vk::Rect2D>,
     pub geometry: Option<Geometry>,
+    pub morph_geometry: Option<Geometry>,
 }
 
 impl Pipeline {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         device: &Device,
-        swapchain_properties: SwapchainProperties,
-        cull_mode: vk::CullModeFlags,
+        state: PipelineState,
         msaa_samples: vk::SampleCountFlags,
         render_pass: vk::RenderPass,
         descriptor_set_layout: vk::DescriptorSetLayout,
+        cache: vk::PipelineCache,
         shader_spv: ShaderSpv,
     ) -> Self {
         let (pipeline, layout) = Self::create_pipeline(
             device,
-            swapchain_properties,
-            cull_mode,
+            state,
             msaa_samples,
             render_pass,
             descriptor_set_layout,
+            cache,
             shader_spv,
         );
 
         Self {
             layout,
             pipeline,
+            viewport: None,
             geometry: None,
+            morph_geometry: None,
         }
     }
 
-    pub unsafe fn cleanup(&mut self, device: &Device) {
+    pub unsafe fn cleanup(&mut self, vk_context: &VkContext) {
+        let device = vk_context.device();
         device.destroy_pipeline(self.pipeline, None);
         device.destroy_pipeline_layout(self.layout, None);
         if let Some(g) = self.geometry.take() {
-            g.cleanup(device);
+            g.cleanup(vk_context);
+        }
+        if let Some(g) = self.morph_geometry.take() {
+            g.cleanup(vk_context);
         }
     }
 
-    fn create_shader_module(
+    /// Sets the viewport and scissor of `command_buffer` to `viewport`
+    /// or to the whole framebuffer of size `extent`.
+    pub unsafe fn cmd_set_viewport(&self, device: &Device, command_buffer: vk::CommandBuffer, extent: vk::Extent2D) {
+        let scissor = self.viewport.unwrap_or(vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent,
+        });
+        let viewport = vk::Viewport {
+            x: scissor.offset.x as _,
+            y: scissor.offset.y as _,
+            width: scissor.extent.width as _,
+            height: scissor.extent.height as _,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        };
+        device.cmd_set_viewport(command_buffer, 0, &[viewport]);
+        device.cmd_set_scissor(command_buffer, 0, &[scissor]);
+    }
+
+    pub(super) fn create_shader_module(
         device: &Device,
         bytes: &[u8],
     ) -> Result<vk::ShaderModule, Box<dyn Error>> {
@@ -65,13 +172,14 @@ impl Pipeline {
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn create_pipeline(
         device: &Device,
-        swapchain_properties: SwapchainProperties,
-        cull_mode: vk::CullModeFlags,
+        state: PipelineState,
         msaa_samples: vk::SampleCountFlags,
         render_pass: vk::RenderPass,
         descriptor_set_layout: vk::DescriptorSetLayout,
+        cache: vk::PipelineCache,
         shader_spv: ShaderSpv,
     ) -> (vk::Pipeline, vk::PipelineLayout) {
         let vertex_shader_module = Self::create_shader_module(device, shader_spv.vert)
@@ -90,45 +198,40 @@ impl Pipeline {
             .name(&entry_point_name);
         let shader_states_infos = [vertex_shader_state_info, fragment_shader_state_info];
 
-        let vertex_binding_descs = [Vertex::get_binding_description()];
+        let vertex_binding_descs = Vertex::get_binding_descriptions();
         let vertex_attribute_descs = Vertex::get_attribute_descriptions();
-        let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::default()
-            .vertex_binding_descriptions(&vertex_binding_descs)
-            .vertex_attribute_descriptions(&vertex_attribute_descs);
+        let vertex_input_info = if state.vertex_input {
+            vk::PipelineVertexInputStateCreateInfo::default()
+                .vertex_binding_descriptions(&vertex_binding_descs)
+                .vertex_attribute_descriptions(&vertex_attribute_descs)
+        } else {
+            vk::PipelineVertexInputStateCreateInfo::default()
+        };
 
         let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::default()
-            .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+            .topology(state.topology)
             .primitive_restart_enable(false);
 
-        let viewport = vk::Viewport {
-            x: 0.0,
-            y: 0.0,
-            width: swapchain_properties.extent.width as _,
-            height: swapchain_properties.extent.height as _,
-            min_depth: 0.0,
-            max_depth: 1.0,
-        };
-        let viewports = [viewport];
-        let scissor = vk::Rect2D {
-            offset: vk::Offset2D { x: 0, y: 0 },
-            extent: swapchain_properties.extent,
-        };
-        let scissors = [scissor];
+        // viewport and scissor are set when recording, see `cmd_set_viewport`
         let viewport_info = vk::PipelineViewportStateCreateInfo::default()
-            .viewports(&viewports)
-            .scissors(&scissors);
+            .viewport_count(1)
+            .scissor_count(1);
+        let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        let dynamic_state_info = vk::PipelineDynamicStateCreateInfo::default()
+            .dynamic_states(&dynamic_states);
 
         let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::default()
             .depth_clamp_enable(false)
             .rasterizer_discard_enable(false)
-            .polygon_mode(vk::PolygonMode::FILL)
+            .polygon_mode(state.polygon_mode)
             .line_width(1.0)
-            .cull_mode(cull_mode)
+            .cull_mode(state.cull_mode)
             .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
-            .depth_bias_enable(false)
-            .depth_bias_constant_factor(0.0)
+            .depth_bias_enable(state.depth_bias != (0.0, 0.0))
+            .depth_bias_constant_factor(state.depth_bias.0)
+            // a clamp of 0 disables clamping and does not need the `depthBiasClamp` feature
             .depth_bias_clamp(0.0)
-            .depth_bias_slope_factor(0.0);
+            .depth_bias_slope_factor(state.depth_bias.1);
 
         let multisampling_info = vk::PipelineMultisampleStateCreateInfo::default()
             .sample_shading_enable(false)
@@ -138,9 +241,9 @@ impl Pipeline {
             .alpha_to_one_enable(false);
 
         let depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo::default()
-            .depth_test_enable(true)
-            .depth_write_enable(true)
-            .depth_compare_op(vk::CompareOp::LESS)
+            .depth_test_enable(state.depth)
+            .depth_write_enable(state.depth && !state.blend)
+            .depth_compare_op(if state.depth_test { vk::CompareOp::LESS } else { vk::CompareOp::ALWAYS })
             .depth_bounds_test_enable(false)
             .min_depth_bounds(0.0)
             .max_depth_bounds(1.0)
@@ -150,12 +253,12 @@ impl Pipeline {
 
         let color_blend_attachment = vk::PipelineColorBlendAttachmentState::default()
             .color_write_mask(vk::ColorComponentFlags::RGBA)
-            .blend_enable(false)
-            .src_color_blend_factor(vk::BlendFactor::ONE)
-            .dst_color_blend_factor(vk::BlendFactor::ZERO)
+            .blend_enable(state.blend)
+            .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+            .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
             .color_blend_op(vk::BlendOp::ADD)
             .src_alpha_blend_factor(vk::BlendFactor::ONE)
-            .dst_alpha_blend_factor(vk::BlendFactor::ZERO)
+            .dst_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
             .alpha_blend_op(vk::BlendOp::ADD);
         let color_blend_attachments = [color_blend_attachment];
 
@@ -167,7 +270,10 @@ impl Pipeline {
 
         let layout = {
             let layouts = [descriptor_set_layout];
-            let layout_info = vk::PipelineLayoutCreateInfo::default().set_layouts(&layouts);
+            let push_constant_ranges = [PushConstants::get_push_constant_range()];
+            let layout_info = vk::PipelineLayoutCreateInfo::default()
+                .set_layouts(&layouts)
+                .push_constant_ranges(&push_constant_ranges);
             unsafe { device.create_pipeline_layout(&layout_info, None).unwrap() }
         };
 
@@ -180,13 +286,14 @@ impl Pipeline {
             .multisample_state(&multisampling_info)
             .depth_stencil_state(&depth_stencil_info)
             .color_blend_state(&color_blending_info)
+            .dynamic_state(&dynamic_state_info)
             .layout(layout)
             .render_pass(render_pass)
             .subpass(0);
         let pipeline_infos = [pipeline_info];
 
         let pipeline = unsafe {
-            device.create_graphics_pipelines(vk::PipelineCache::null(), &pipeline_infos, None)
+            device.create_graphics_pipelines(cache, &pipeline_infos, None)
                 .unwrap()[0]
         };
 
@@ -199,18 +306,21 @@ impl Pipeline {
     }
 }
 
-#[derive(Copy, Clone)]
 pub struct Geometry {
     pub vertex_buffer: vk::Buffer,
-    pub vertex_buffer_memory: vk::DeviceMemory,
+    pub vertex_buffer_memory: Allocation,
     pub index_buffer: vk::Buffer,
-    pub index_buffer_memory: vk::DeviceMemory,
+    pub index_buffer_memory: Allocation,
+    /// `UINT16` if all vertices can be addressed with it, `UINT32` otherwise.
+    pub index_type: vk::IndexType,
     pub index_count: usize,
+    pub vertex_count: usize,
 }
 
 impl Geometry {
     pub fn new(
         vk_context: &VkContext,
+        staging: &mut StagingBuffer,
         transient_command_pool: vk::CommandPool,
         graphics_queue: vk::Queue,
         vertices: &[Vertex],
@@ -218,17 +328,20 @@ impl Geometry {
     ) -> Self {
         let (vertex_buffer, vertex_buffer_memory) = Self::create_buffer_with_data::<u32, _>(
             vk_context,
+            staging,
             transient_command_pool,
             graphics_queue,
             vk::BufferUsageFlags::VERTEX_BUFFER,
             vertices,
         );
-        let (index_buffer, index_buffer_memory) = Self::create_buffer_with_data::<u16, _>(
+        let (index_type, index_data) = Self::index_data(vertices.len(), indices);
+        let (index_buffer, index_buffer_memory) = Self::create_buffer_with_data::<u8, _>(
             vk_context,
+            staging,
             transient_command_pool,
             graphics_queue,
             vk::BufferUsageFlags::INDEX_BUFFER,
-            indices,
+            &index_data,
         );
 
         Self {
@@ -236,53 +349,89 @@ impl Geometry {
             vertex_buffer_memory,
             index_buffer,
             index_buffer_memory,
+            index_type,
             index_count: indices.len(),
+            vertex_count: vertices.len(),
         }
     }
 
-    pub unsafe fn cleanup(self, device: &Device) {
-        device.free_memory(self.index_buffer_memory, None);
-        device.destroy_buffer(self.index_buffer, None);
-        device.free_memory(self.vertex_buffer_memory, None);
-        device.destroy_buffer(self.vertex_buffer, None);
+    /// Like `new` but only records the copies into `command_buffer` instead of waiting for them.
+    /// The data is written into new buffers pushed to `stagings`, they must not be destroyed
+    /// before the commands are executed.
+    pub fn record_upload(
+        vk_context: &VkContext,
+        stagings: &mut Vec<StagingBuffer>,
+        command_buffer: vk::CommandBuffer,
+        vertices: &[Vertex],
+        indices: &[u32],
+    ) -> Self {
+        let (vertex_buffer, vertex_buffer_memory) = Self::record_buffer_with_data::<u32, _>(
+            vk_context,
+            stagings,
+            command_buffer,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            vertices,
+        );
+        let (index_type, index_data) = Self::index_data(vertices.len(), indices);
+        let (index_buffer, index_buffer_memory) = Self::record_buffer_with_data::<u8, _>(
+            vk_context,
+            stagings,
+            command_buffer,
+            vk::BufferUsageFlags::INDEX_BUFFER,
+            &index_data,
+        );
+
+        Self {
+            vertex_buffer,
+            vertex_buffer_memory,
+            index_buffer,
+            index_buffer_memory,
+            index_type,
+            index_count: indices.len(),
+            vertex_count: vertices.len(),
+        }
+    }
+
+    /// Returns the index type used for `vertex_count` vertices and the bytes of `indices` in it.
+    /// 16 bit indices halve the size of the index buffer of smaller models.
+    fn index_data(vertex_count: usize, indices: &[u32]) -> (vk::IndexType, Vec<u8>) {
+        if vertex_count <= 1 << 16 {
+            let bytes = indices.iter().flat_map(|&idx| (idx as u16).to_ne_bytes()).collect();
+            (vk::IndexType::UINT16, bytes)
+        } else {
+            let bytes = indices.iter().flat_map(|&idx| idx.to_ne_bytes()).collect();
+            (vk::IndexType::UINT32, bytes)
+        }
+    }
+
+    pub unsafe fn cleanup(self, vk_context: &VkContext) {
+        buffer::destroy_buffer(vk_context, self.index_buffer, self.index_buffer_memory);
+        buffer::destroy_buffer(vk_context, self.vertex_buffer, self.vertex_buffer_memory);
     }
 
     /// Create a buffer and its gpu memory and fill it.
     ///
-    /// This function internally creates an host visible staging buffer and
-    /// a device local buffer. The data is first copied from the cpu to the
-    /// staging buffer. Then we copy the data from the staging buffer to the
-    /// final buffer using a one-time command buffer.
+    /// This function creates a device local buffer. The data is first copied
+    /// from the cpu to the host visible `staging` buffer, which is reused and
+    /// grown as needed by `StagingBuffer::write`. Then we copy the data from the
+    /// staging buffer to the final buffer using a one-time command buffer.
     fn create_buffer_with_data<A, T: Copy>(
         vk_context: &VkContext,
+        staging: &mut StagingBuffer,
         command_pool: vk::CommandPool,
         transfer_queue: vk::Queue,
         usage: vk::BufferUsageFlags,
         data: &[T],
-    ) -> (vk::Buffer, vk::DeviceMemory) {
+    ) -> (vk::Buffer, Allocation) {
         let device = vk_context.device();
         let size = size_of_val(data) as vk::DeviceSize;
-        let (staging_buffer, staging_memory, staging_mem_size) = buffer::create_buffer(
-            vk_context,
-            size,
-            vk::BufferUsageFlags::TRANSFER_SRC,
-            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
-        );
-
-        unsafe {
-            let data_ptr = device
-                .map_memory(staging_memory, 0, size, vk::MemoryMapFlags::empty())
-                .unwrap();
-            let mut align = ash::util::Align::new(data_ptr, align_of::<A>() as _, staging_mem_size);
-            align.copy_from_slice(data);
-            device.unmap_memory(staging_memory);
-        };
+        let staging_buffer = staging.write::<A, _>(vk_context, data);
 
-        let (buffer, memory, _) = buffer::create_buffer(
+        let (buffer, memory) = buffer::create_buffer(
             vk_context,
             size,
             vk::BufferUsageFlags::TRANSFER_DST | usage,
-            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            MemoryLocation::GpuOnly,
         );
 
         buffer::copy_buffer(
@@ -294,10 +443,30 @@ impl Geometry {
             size,
         );
 
-        unsafe {
-            device.destroy_buffer(staging_buffer, None);
-            device.free_memory(staging_memory, None);
-        };
+        (buffer, memory)
+    }
+
+    /// Like `create_buffer_with_data` but with its own staging buffer pushed to `stagings`
+    /// and the copy only recorded into `command_buffer`.
+    fn record_buffer_with_data<A, T: Copy>(
+        vk_context: &VkContext,
+        stagings: &mut Vec<StagingBuffer>,
+        command_buffer: vk::CommandBuffer,
+        usage: vk::BufferUsageFlags,
+        data: &[T],
+    ) -> (vk::Buffer, Allocation) {
+        let size = size_of_val(data) as vk::DeviceSize;
+        let mut staging = StagingBuffer::default();
+        let staging_buffer = staging.write::<A, _>(vk_context, data);
+        stagings.push(staging);
+
+        let (buffer, memory) = buffer::create_buffer(
+            vk_context,
+            size,
+            vk::BufferUsageFlags::TRANSFER_DST | usage,
+            MemoryLocation::GpuOnly,
+        );
+        buffer::record_copy_buffer(vk_context.device(), command_buffer, staging_buffer, buffer, size);
 
         (buffer, memory)
     }
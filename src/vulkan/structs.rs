@@ -1,7 +1,8 @@
-use crate::math::{Deg, Matrix4, Vector3};
+use crate::math::{Aabb, Deg, Matrix4, Vector3};
 
 use ash::vk;
 use std::mem::offset_of;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone, Copy)]
 pub struct ShaderSpv {
@@ -9,6 +10,51 @@ pub struct ShaderSpv {
     pub frag: &'static [u8],
 }
 
+/// Paths of the six face images of a cubemap. All images must have the same size.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CubemapPaths {
+    pub right: PathBuf,
+    pub left: PathBuf,
+    pub top: PathBuf,
+    pub bottom: PathBuf,
+    pub back: PathBuf,
+    pub front: PathBuf,
+    /// Equirectangular `.hdr` image that is used instead of the six faces if set.
+    pub equirect: Option<PathBuf>,
+}
+
+impl CubemapPaths {
+    /// Uses `dir` as equirectangular image if it is an `.hdr` file. Otherwise uses
+    /// the `.hdr` file in `dir` if there is one, or else the files `right.png`,
+    /// `left.png`, `top.png`, `bottom.png`, `back.png` and `front.png` in `dir`.
+    pub fn from_dir<P: AsRef<Path>>(dir: P) -> Self {
+        let dir = dir.as_ref();
+        let is_hdr = |path: &Path| path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("hdr"));
+        let equirect = if is_hdr(dir) {
+            Some(dir.to_path_buf())
+        } else {
+            std::fs::read_dir(dir).into_iter().flatten()
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| is_hdr(path) && path.is_file())
+                .min()
+        };
+        Self {
+            right: dir.join("right.png"),
+            left: dir.join("left.png"),
+            top: dir.join("top.png"),
+            bottom: dir.join("bottom.png"),
+            back: dir.join("back.png"),
+            front: dir.join("front.png"),
+            equirect,
+        }
+    }
+
+    /// Returns the paths in the order of the cubemap layers.
+    pub fn to_array(&self) -> [&PathBuf; 6] {
+        [&self.right, &self.left, &self.top, &self.bottom, &self.back, &self.front]
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 #[allow(dead_code)]
 #[repr(C)]
@@ -16,17 +62,27 @@ pub struct Vertex {
     pub pos: [f32; 3],
     pub color: [f32; 3],
     pub coords: [f32; 2],
+    pub normal: [f32; 3],
+    /// Tangent in the direction of increasing u with the handedness of the bitangent in w.
+    pub tangent: [f32; 4],
 }
 
 impl Vertex {
-    pub fn get_binding_description() -> vk::VertexInputBindingDescription {
-        vk::VertexInputBindingDescription::default()
+    /// Binding 0 holds the vertices of the model and binding 1 the vertices
+    /// of the morph target, which must be the same buffer if there is none.
+    pub fn get_binding_descriptions() -> [vk::VertexInputBindingDescription; 2] {
+        let binding_desc = vk::VertexInputBindingDescription::default()
             .binding(0)
             .stride(size_of::<Vertex>() as _)
-            .input_rate(vk::VertexInputRate::VERTEX)
+            .input_rate(vk::VertexInputRate::VERTEX);
+        let morph_binding_desc = vk::VertexInputBindingDescription::default()
+            .binding(1)
+            .stride(size_of::<Vertex>() as _)
+            .input_rate(vk::VertexInputRate::VERTEX);
+        [binding_desc, morph_binding_desc]
     }
 
-    pub fn get_attribute_descriptions() -> [vk::VertexInputAttributeDescription; 3] {
+    pub fn get_attribute_descriptions() -> [vk::VertexInputAttributeDescription; 6] {
         let position_desc = vk::VertexInputAttributeDescription::default()
             .binding(0)
             .location(0)
@@ -42,7 +98,51 @@ impl Vertex {
             .location(2)
             .format(vk::Format::R32G32_SFLOAT)
             .offset(offset_of!(Vertex, coords) as _);
-        [position_desc, color_desc, coords_desc]
+        let normal_desc = vk::VertexInputAttributeDescription::default()
+            .binding(0)
+            .location(3)
+            .format(vk::Format::R32G32B32_SFLOAT)
+            .offset(offset_of!(Vertex, normal) as _);
+        let morph_position_desc = vk::VertexInputAttributeDescription::default()
+            .binding(1)
+            .location(4)
+            .format(vk::Format::R32G32B32_SFLOAT)
+            .offset(offset_of!(Vertex, pos) as _);
+        let tangent_desc = vk::VertexInputAttributeDescription::default()
+            .binding(0)
+            .location(5)
+            .format(vk::Format::R32G32B32A32_SFLOAT)
+            .offset(offset_of!(Vertex, tangent) as _);
+        [position_desc, color_desc, coords_desc, normal_desc, morph_position_desc, tangent_desc]
+    }
+}
+
+/// Per draw data that is pushed into the command buffer instead of the uniform buffer.
+///
+/// At 128 bytes this is exactly the minimum `maxPushConstantsSize` guaranteed by the spec,
+/// more data must go into the uniform buffer.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct PushConstants {
+    pub model: Matrix4,
+    /// Inverse transpose of the model matrix to transform normals.
+    pub normal_matrix: Matrix4,
+}
+
+const _: () = assert!(size_of::<PushConstants>() <= 128);
+
+impl PushConstants {
+    pub fn get_push_constant_range() -> vk::PushConstantRange {
+        vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::VERTEX,
+            offset: 0,
+            size: size_of::<Self>() as _,
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        // SAFETY: the struct is repr(C) and consists only of f32s without padding
+        unsafe { std::slice::from_raw_parts((self as *const Self).cast(), size_of::<Self>()) }
     }
 }
 
@@ -50,10 +150,26 @@ impl Vertex {
 #[allow(dead_code)]
 #[repr(C)]
 pub struct UniformBufferObject {
-    pub model: Matrix4,
     pub view: Matrix4,
     pub proj: Matrix4,
+    /// Direction towards the light in world space, w is unused.
+    pub light_dir: [f32; 4],
+    /// Position of the camera in world space, w is unused.
+    pub view_pos: [f32; 4],
     pub texture_weight: f32,
+    pub morph_weight: f32,
+    /// Non zero if the morph target can not be interpolated and
+    /// both models are drawn with a crossfade instead.
+    pub morph_crossfade: u32,
+    /// How much of the skybox is reflected by the model, from 0 to 1.
+    pub reflectivity: f32,
+    /// Distances of the clip planes of `proj` from the camera.
+    pub near: f32,
+    pub far: f32,
+    /// Non zero if the model is colored by its depth, see `VkApp::set_show_depth`.
+    pub show_depth: u32,
+    /// Non zero if the model has vertex colors, which replace the random colors.
+    pub vertex_colors: u32,
 }
 
 impl UniformBufferObject {
@@ -73,11 +189,17 @@ impl UniformBufferObject {
         )
     }
 
-    pub fn model_matrix(extent_min: Vector3, extent_max: Vector3) -> Matrix4 {
-        let model_sizes = extent_max - extent_min;
-        let max_size = model_sizes.x().max(model_sizes.y()).max(model_sizes.z());
-        let scale = Matrix4::from_scale(1. / max_size);
-        let translate = Matrix4::from_translation(-extent_min - model_sizes / 2.);
+    /// Returns the position of the camera in world space. The view matrix must
+    /// only consist of rotations and translations.
+    pub fn view_position(view: Matrix4) -> Vector3 {
+        // the inverse of a rigid transformation [R | t] is [R^T | -R^T t]
+        let t = Vector3::from([view[3][0], view[3][1], view[3][2]]);
+        Vector3::from([0, 1, 2].map(|i| -Vector3::from([view[i][0], view[i][1], view[i][2]]).dot(t)))
+    }
+
+    pub fn model_matrix(aabb: Aabb) -> Matrix4 {
+        let scale = Matrix4::from_scale(1. / aabb.max_extent());
+        let translate = Matrix4::from_translation(-aabb.center());
         Matrix4::from_angle_y(Deg(-90.)) * scale * translate
     }
 }
@@ -39,9 +39,13 @@ impl SwapchainSupportDetails {
     pub fn get_ideal_swapchain_properties(
         &self,
         preferred_dimensions: [u32; 2],
+        preferred_present_mode: vk::PresentModeKHR,
     ) -> SwapchainProperties {
         let format = Self::choose_swapchain_surface_format(&self.formats);
-        let present_mode = Self::choose_swapchain_surface_present_mode(&self.present_modes);
+        let present_mode = Self::choose_swapchain_surface_present_mode(
+            &self.present_modes,
+            preferred_present_mode,
+        );
         let extent = Self::choose_swapchain_extent(self.capabilities, preferred_dimensions);
         log::debug!("Swapchain format: {format:?}, mode: {present_mode:?}, extent: {extent:?}");
         SwapchainProperties {
@@ -53,35 +57,42 @@ impl SwapchainSupportDetails {
 
     /// Choose the swapchain surface format.
     ///
-    /// Will choose B8G8R8A8_UNORM/SRGB_NONLINEAR if possible or
-    /// the first available otherwise.
+    /// Will choose B8G8R8A8_SRGB/SRGB_NONLINEAR if possible, so the hardware converts
+    /// the linear colors of the shaders to sRGB, B8G8R8A8_UNORM/SRGB_NONLINEAR next
+    /// or the first available otherwise.
     fn choose_swapchain_surface_format(
         available_formats: &[vk::SurfaceFormatKHR],
     ) -> vk::SurfaceFormatKHR {
         if available_formats.len() == 1 && available_formats[0].format == vk::Format::UNDEFINED {
             return vk::SurfaceFormatKHR {
-                format: vk::Format::B8G8R8A8_UNORM,
+                format: vk::Format::B8G8R8A8_SRGB,
                 color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
             };
         }
 
-        *available_formats.iter()
-            .find(|format| {
-                format.format == vk::Format::B8G8R8A8_UNORM
-                    && format.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
-            })
-            .unwrap_or(&available_formats[0])
+        let format = [vk::Format::B8G8R8A8_SRGB, vk::Format::B8G8R8A8_UNORM].iter()
+            .find_map(|&preferred| available_formats.iter().find(|format| {
+                format.format == preferred && format.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
+            }))
+            .copied()
+            .unwrap_or(available_formats[0]);
+        if format.format != vk::Format::B8G8R8A8_SRGB {
+            log::warn!("No sRGB swapchain format available, colors will look too dark");
+        }
+        format
     }
 
     /// Choose the swapchain present mode.
     ///
-    /// Will favor MAILBOX if present otherwise FIFO.
+    /// Will choose `preferred` if present otherwise FIFO.
     fn choose_swapchain_surface_present_mode(
         available_present_modes: &[vk::PresentModeKHR],
+        preferred: vk::PresentModeKHR,
     ) -> vk::PresentModeKHR {
-        if available_present_modes.contains(&vk::PresentModeKHR::MAILBOX) {
-            vk::PresentModeKHR::MAILBOX
+        if available_present_modes.contains(&preferred) {
+            preferred
         } else {
+            log::info!("Present mode {preferred:?} is not supported, falling back to FIFO");
             // The vulkan spec requires FIFO to be supported.
             vk::PresentModeKHR::FIFO
         }
@@ -1,36 +1,201 @@
-use ash::{vk, Device};
+use super::context::VkContext;
+
+use ash::vk;
+use gpu_allocator::vulkan::Allocation;
+use image::{DynamicImage, ImageBuffer, ImageFormat, ImageReader, ImageResult, Rgba, RgbaImage};
+use std::io::{BufRead, Seek};
+use std::path::Path;
+
+/// RGBA image with 16 bits per channel.
+pub type Rgba16Image = ImageBuffer<Rgba<u16>, Vec<u16>>;
 
-#[derive(Clone, Copy)]
 pub struct Texture {
     pub image: vk::Image,
-    pub memory: vk::DeviceMemory,
+    /// `None` after the texture was destroyed.
+    pub memory: Option<Allocation>,
     pub view: vk::ImageView,
     pub sampler: Option<vk::Sampler>,
+    /// Number of mip levels of the image, 1 if it has no mipmaps.
+    pub mip_levels: u32,
 }
 
 impl Texture {
     pub fn new(
         image: vk::Image,
-        memory: vk::DeviceMemory,
+        memory: Allocation,
         view: vk::ImageView,
         sampler: Option<vk::Sampler>,
+        mip_levels: u32,
     ) -> Self {
         Texture {
             image,
-            memory,
+            memory: Some(memory),
             view,
             sampler,
+            mip_levels,
         }
     }
 
-    pub fn destroy(&mut self, device: &Device) {
+    pub fn destroy(&mut self, vk_context: &VkContext) {
+        let device = vk_context.device();
         unsafe {
             if let Some(sampler) = self.sampler.take() {
                 device.destroy_sampler(sampler, None);
             }
             device.destroy_image_view(self.view, None);
             device.destroy_image(self.image, None);
-            device.free_memory(self.memory, None);
+        }
+        if let Some(memory) = self.memory.take() {
+            vk_context.free(memory);
+        }
+    }
+}
+
+/// Pixels of a decoded image expanded to RGBA, the first row is v = 0.
+pub enum TexturePixels {
+    Rgba8(RgbaImage),
+    /// Used for images with more than 8 bits per channel, so they keep their precision.
+    Rgba16(Rgba16Image),
+}
+
+impl TexturePixels {
+    /// Expands `image` to RGBA, grayscale images are copied to all color channels.
+    pub fn from_image(image: DynamicImage) -> Self {
+        let color = image.color();
+        if color.bytes_per_pixel() / color.channel_count() == 1 {
+            Self::Rgba8(image.into_rgba8())
+        } else {
+            Self::Rgba16(image.into_rgba16())
+        }
+    }
+
+    pub fn extent(&self) -> vk::Extent2D {
+        let (width, height) = match self {
+            Self::Rgba8(image) => image.dimensions(),
+            Self::Rgba16(image) => image.dimensions(),
+        };
+        vk::Extent2D { width, height }
+    }
+}
+
+/// Decodes the image file read by `reader`. The format is detected from the content,
+/// or if that is not possible like for TGA, from the extension of `path`.
+pub fn decode_image<R: BufRead + Seek>(reader: R, path: &Path) -> ImageResult<DynamicImage> {
+    let mut reader = ImageReader::new(reader);
+    if let Ok(format) = ImageFormat::from_path(path) {
+        reader.set_format(format);
+    }
+    let reader = reader.with_guessed_format()?;
+    log::info!("Decoding {} as {:?}", path.display(), reader.format());
+    reader.decode()
+}
+
+/// Converts the sRGB colors of `image` to linear values, the alpha channel is unchanged.
+pub fn srgb_to_linear(image: &mut Rgba16Image) {
+    for pixel in image.pixels_mut() {
+        for value in &mut pixel.0[..3] {
+            let c = *value as f32 / u16::MAX as f32;
+            let linear = if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) };
+            *value = (linear * u16::MAX as f32).round() as u16;
+        }
+    }
+}
+
+/// Creates a `size` x `size` checkerboard with `cells` squares per side to check texture coordinates.
+///
+/// The color goes from red at uv (0, 0) to blue at u = 1, yellow at v = 1 and cyan at (1, 1),
+/// so stretched, mirrored or rotated coordinates are easy to spot. The first row is v = 0.
+pub fn uv_checkerboard(size: u32, cells: u32) -> RgbaImage {
+    let cells = cells.clamp(1, size.max(1));
+    let cell_size = size / cells;
+    RgbaImage::from_fn(size, size, |x, y| {
+        // each cell has a single color
+        let (cell_x, cell_y) = ((x / cell_size).min(cells - 1), (y / cell_size).min(cells - 1));
+        let u = cell_x as f32 / cells as f32;
+        let v = cell_y as f32 / cells as f32;
+        let brightness = if (cell_x + cell_y) % 2 == 0 { 1. } else { 0.5 };
+        let color = [1. - u, v, u];
+        let [r, g, b] = color.map(|c| (c * brightness * 255.).round() as u8);
+        Rgba([r, g, b, u8::MAX])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GenericImageView, Luma};
+    use std::io::Cursor;
+
+    fn png_roundtrip(image: DynamicImage) -> DynamicImage {
+        let mut bytes = Vec::new();
+        image.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png).unwrap();
+        image::load_from_memory_with_format(&bytes, ImageFormat::Png).unwrap()
+    }
+
+    #[test]
+    fn decode_formats() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(3, 2, |x, y| Rgba([x as u8, y as u8, 7, 255])));
+        for (format, name) in [(ImageFormat::Tga, "a.tga"), (ImageFormat::Bmp, "a.bmp"), (ImageFormat::Jpeg, "a.jpeg")] {
+            let mut bytes = Vec::new();
+            image.write_to(&mut Cursor::new(&mut bytes), format).unwrap();
+            let decoded = decode_image(Cursor::new(&bytes), Path::new(name)).unwrap();
+            assert_eq!(decoded.dimensions(), (3, 2), "{name}");
+            if format != ImageFormat::Jpeg {
+                assert_eq!(decoded.into_rgba8(), image.to_rgba8(), "{name}");
+            }
+            // the content wins over a wrong extension, except for TGA which has no signature
+            let misnamed = decode_image(Cursor::new(&bytes), Path::new("a.png"));
+            assert_eq!(misnamed.is_ok(), format != ImageFormat::Tga, "{name}");
+        }
+    }
+
+    #[test]
+    fn gray16_keeps_precision() {
+        let heights = ImageBuffer::from_fn(2, 1, |x, _| Luma([1000 + x as u16]));
+        let image = png_roundtrip(DynamicImage::ImageLuma16(heights));
+        let TexturePixels::Rgba16(pixels) = TexturePixels::from_image(image) else {
+            panic!("16 bit image was truncated");
+        };
+        assert_eq!(pixels.get_pixel(0, 0).0, [1000, 1000, 1000, u16::MAX]);
+        assert_eq!(pixels.get_pixel(1, 0).0, [1001, 1001, 1001, u16::MAX]);
+    }
+
+    #[test]
+    fn rgba16_srgb_to_linear() {
+        let image = Rgba16Image::from_pixel(1, 1, Rgba([0, u16::MAX / 2, u16::MAX, 100]));
+        let TexturePixels::Rgba16(mut pixels) = TexturePixels::from_image(png_roundtrip(image.into())) else {
+            panic!("16 bit image was truncated");
+        };
+        srgb_to_linear(&mut pixels);
+        let [r, g, b, a] = pixels.get_pixel(0, 0).0;
+        assert_eq!((r, b, a), (0, u16::MAX, 100));
+        // sRGB 0.5 is about 0.214 in linear
+        assert!((g as f32 / u16::MAX as f32 - 0.214).abs() < 1e-3, "{g}");
+    }
+
+    #[test]
+    fn gray8_expands_to_rgba8() {
+        let image = png_roundtrip(DynamicImage::ImageLuma8(ImageBuffer::from_pixel(1, 1, Luma([42]))));
+        let TexturePixels::Rgba8(pixels) = TexturePixels::from_image(image) else {
+            panic!("8 bit image was widened");
+        };
+        assert_eq!(pixels.get_pixel(0, 0).0, [42, 42, 42, 255]);
+    }
+
+    #[test]
+    fn checkerboard() {
+        let image = uv_checkerboard(64, 8);
+        assert_eq!(image.dimensions(), (64, 64));
+        // neighboring cells alternate between bright and dark
+        let [r0, _, _, a0] = image.get_pixel(0, 0).0;
+        let [r1, _, _, _] = image.get_pixel(8, 0).0;
+        assert_eq!(a0, u8::MAX);
+        assert!(r1 < r0 / 2 + 1, "{r0} {r1}");
+        assert_eq!(image.get_pixel(0, 0), image.get_pixel(7, 0));
+        // the corners have different colors, so the orientation is visible
+        let corners = [(0, 0), (63, 0), (0, 63), (63, 63)].map(|(x, y)| image.get_pixel(x, y).0);
+        for (i, a) in corners.iter().enumerate() {
+            assert!(corners[i + 1..].iter().all(|b| a != b), "{corners:?}");
         }
     }
 }
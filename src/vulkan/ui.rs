@@ -0,0 +1,561 @@
+use super::app::VkApp;
+use super::buffer;
+use super::cmd;
+use super::context::VkContext;
+use super::pipeline::Pipeline;
+use super::structs::ShaderSpv;
+use super::texture::Texture;
+
+use anyhow::Context;
+use ash::{vk, Device};
+use gpu_allocator::{vulkan::Allocation, MemoryLocation};
+use egui::epaint::{ImageData, ImageDelta, Primitive, Vertex as UiVertex};
+use egui::{ClippedPrimitive, TextureFilter, TextureId, TexturesDelta};
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::mem::offset_of;
+
+/// Maximum number of egui textures, the font atlas is usually the only one.
+const MAX_TEXTURES: u32 = 64;
+/// Like the vertex colors the textures are converted from sRGB to linear for the sRGB framebuffer.
+const TEXTURE_FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
+
+/// Draws the meshes produced by egui on top of the scene.
+///
+/// The meshes are drawn at the end of the main render pass, so they are multisampled
+/// and resolved together with the rest of the frame.
+pub struct UiRenderer {
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    layout: vk::PipelineLayout,
+    pipeline: vk::Pipeline,
+    textures: HashMap<TextureId, (Texture, vk::DescriptorSet)>,
+    /// Vertex and index buffer of each frame in flight, grown when needed.
+    buffers: Vec<Option<FrameBuffers>>,
+    primitives: Vec<ClippedPrimitive>,
+    pixels_per_point: f32,
+    spv: ShaderSpv,
+}
+
+impl UiRenderer {
+    pub fn new(
+        device: &Device,
+        render_pass: vk::RenderPass,
+        msaa_samples: vk::SampleCountFlags,
+        cache: vk::PipelineCache,
+        spv: ShaderSpv,
+        frames_in_flight: usize,
+    ) -> Self {
+        let descriptor_set_layout = {
+            let binding = vk::DescriptorSetLayoutBinding::default()
+                .binding(0)
+                .descriptor_count(1)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT);
+            let bindings = [binding];
+            let layout_info = vk::DescriptorSetLayoutCreateInfo::default().bindings(&bindings);
+            unsafe { device.create_descriptor_set_layout(&layout_info, None).unwrap() }
+        };
+        let descriptor_pool = {
+            let pool_sizes = [vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: MAX_TEXTURES,
+            }];
+            let pool_info = vk::DescriptorPoolCreateInfo::default()
+                .flags(vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET)
+                .pool_sizes(&pool_sizes)
+                .max_sets(MAX_TEXTURES);
+            unsafe { device.create_descriptor_pool(&pool_info, None).unwrap() }
+        };
+        let layout = {
+            let layouts = [descriptor_set_layout];
+            let push_constant_ranges = [vk::PushConstantRange {
+                stage_flags: vk::ShaderStageFlags::VERTEX,
+                offset: 0,
+                size: size_of::<[f32; 2]>() as _,
+            }];
+            let layout_info = vk::PipelineLayoutCreateInfo::default()
+                .set_layouts(&layouts)
+                .push_constant_ranges(&push_constant_ranges);
+            unsafe { device.create_pipeline_layout(&layout_info, None).unwrap() }
+        };
+        let pipeline = Self::create_pipeline(device, render_pass, msaa_samples, layout, cache, spv);
+
+        Self {
+            descriptor_set_layout,
+            descriptor_pool,
+            layout,
+            pipeline,
+            textures: HashMap::new(),
+            buffers: (0..frames_in_flight).map(|_| None).collect(),
+            primitives: Vec::new(),
+            pixels_per_point: 1.,
+            spv,
+        }
+    }
+
+    /// Creates the pipeline for a new render pass after `cleanup_pipeline`.
+    pub fn recreate_pipeline(
+        &mut self,
+        device: &Device,
+        render_pass: vk::RenderPass,
+        msaa_samples: vk::SampleCountFlags,
+        cache: vk::PipelineCache,
+    ) {
+        self.pipeline = Self::create_pipeline(device, render_pass, msaa_samples, self.layout, cache, self.spv);
+    }
+
+    fn create_pipeline(
+        device: &Device,
+        render_pass: vk::RenderPass,
+        msaa_samples: vk::SampleCountFlags,
+        layout: vk::PipelineLayout,
+        cache: vk::PipelineCache,
+        spv: ShaderSpv,
+    ) -> vk::Pipeline {
+        let vertex_shader_module = Pipeline::create_shader_module(device, spv.vert)
+            .expect("failed to load ui vertex shader spv file");
+        let fragment_shader_module = Pipeline::create_shader_module(device, spv.frag)
+            .expect("failed to load ui fragment shader spv file");
+
+        let entry_point_name = CString::new("main").unwrap();
+        let shader_states_infos = [
+            vk::PipelineShaderStageCreateInfo::default()
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .module(vertex_shader_module)
+                .name(&entry_point_name),
+            vk::PipelineShaderStageCreateInfo::default()
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .module(fragment_shader_module)
+                .name(&entry_point_name),
+        ];
+
+        let vertex_binding_descs = [vk::VertexInputBindingDescription::default()
+            .binding(0)
+            .stride(size_of::<UiVertex>() as _)
+            .input_rate(vk::VertexInputRate::VERTEX)];
+        let vertex_attribute_descs = [
+            vk::VertexInputAttributeDescription::default()
+                .location(0)
+                .format(vk::Format::R32G32_SFLOAT)
+                .offset(offset_of!(UiVertex, pos) as _),
+            vk::VertexInputAttributeDescription::default()
+                .location(1)
+                .format(vk::Format::R32G32_SFLOAT)
+                .offset(offset_of!(UiVertex, uv) as _),
+            vk::VertexInputAttributeDescription::default()
+                .location(2)
+                .format(vk::Format::R8G8B8A8_UNORM)
+                .offset(offset_of!(UiVertex, color) as _),
+        ];
+        let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::default()
+            .vertex_binding_descriptions(&vertex_binding_descs)
+            .vertex_attribute_descriptions(&vertex_attribute_descs);
+
+        let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::default()
+            .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+
+        // viewport and scissor change with the window size and the clip rects
+        let viewport_info = vk::PipelineViewportStateCreateInfo::default()
+            .viewport_count(1)
+            .scissor_count(1);
+        let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        let dynamic_state_info = vk::PipelineDynamicStateCreateInfo::default()
+            .dynamic_states(&dynamic_states);
+
+        let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::default()
+            .polygon_mode(vk::PolygonMode::FILL)
+            .line_width(1.0)
+            .cull_mode(vk::CullModeFlags::NONE);
+
+        let multisampling_info = vk::PipelineMultisampleStateCreateInfo::default()
+            .rasterization_samples(msaa_samples)
+            .min_sample_shading(1.0);
+
+        // the ui is always on top
+        let depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo::default()
+            .depth_test_enable(false)
+            .depth_write_enable(false);
+
+        // egui outputs premultiplied alpha
+        let color_blend_attachments = [vk::PipelineColorBlendAttachmentState::default()
+            .color_write_mask(vk::ColorComponentFlags::RGBA)
+            .blend_enable(true)
+            .src_color_blend_factor(vk::BlendFactor::ONE)
+            .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+            .color_blend_op(vk::BlendOp::ADD)
+            .src_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_DST_ALPHA)
+            .dst_alpha_blend_factor(vk::BlendFactor::ONE)
+            .alpha_blend_op(vk::BlendOp::ADD)];
+        let color_blending_info = vk::PipelineColorBlendStateCreateInfo::default()
+            .attachments(&color_blend_attachments);
+
+        let pipeline_info = vk::GraphicsPipelineCreateInfo::default()
+            .stages(&shader_states_infos)
+            .vertex_input_state(&vertex_input_info)
+            .input_assembly_state(&input_assembly_info)
+            .viewport_state(&viewport_info)
+            .rasterization_state(&rasterizer_info)
+            .multisample_state(&multisampling_info)
+            .depth_stencil_state(&depth_stencil_info)
+            .color_blend_state(&color_blending_info)
+            .dynamic_state(&dynamic_state_info)
+            .layout(layout)
+            .render_pass(render_pass)
+            .subpass(0);
+
+        let pipeline = unsafe {
+            device.create_graphics_pipelines(cache, &[pipeline_info], None).unwrap()[0]
+        };
+        unsafe {
+            device.destroy_shader_module(vertex_shader_module, None);
+            device.destroy_shader_module(fragment_shader_module, None);
+        }
+        pipeline
+    }
+
+    /// Applies the texture changes of an egui frame and stores its meshes to draw them
+    /// with the next frames. Waits for the GPU if textures are freed.
+    pub fn update(
+        &mut self,
+        vk_context: &VkContext,
+        command_pool: vk::CommandPool,
+        queue: vk::Queue,
+        textures_delta: &TexturesDelta,
+        primitives: Vec<ClippedPrimitive>,
+        pixels_per_point: f32,
+    ) -> Result<(), anyhow::Error> {
+        for (id, delta) in &textures_delta.set {
+            self.set_texture(vk_context, command_pool, queue, *id, delta)
+                .with_context(|| format!("Failed to update ui texture {id:?}"))?;
+        }
+        if !textures_delta.free.is_empty() {
+            unsafe { vk_context.device().device_wait_idle()? };
+            for id in &textures_delta.free {
+                if let Some((texture, set)) = self.textures.remove(id) {
+                    self.destroy_texture(vk_context, texture, set);
+                }
+            }
+        }
+        self.primitives = primitives;
+        self.pixels_per_point = pixels_per_point;
+        Ok(())
+    }
+
+    fn set_texture(
+        &mut self,
+        vk_context: &VkContext,
+        command_pool: vk::CommandPool,
+        queue: vk::Queue,
+        id: TextureId,
+        delta: &ImageDelta,
+    ) -> Result<(), anyhow::Error> {
+        let device = vk_context.device();
+        let pixels: Vec<u8> = match &delta.image {
+            ImageData::Color(image) => image.pixels.iter().flat_map(|color| color.to_array()).collect(),
+            ImageData::Font(image) => image.srgba_pixels(None).flat_map(|color| color.to_array()).collect(),
+        };
+        let [width, height] = delta.image.size();
+        let extent = vk::Extent2D { width: width as _, height: height as _ };
+
+        // a delta without position replaces the whole texture, possibly with a new size
+        let (image, old_layout) = match (delta.pos, self.textures.get(&id)) {
+            (Some(_), Some((texture, _))) => (texture.image, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+            (Some(_), None) => anyhow::bail!("Partial update of an unknown texture"),
+            (None, old) => {
+                if old.is_some() {
+                    unsafe { device.device_wait_idle()? };
+                    let (texture, set) = self.textures.remove(&id).unwrap();
+                    self.destroy_texture(vk_context, texture, set);
+                }
+                let texture = Self::create_texture(vk_context, extent, delta)?;
+                let set = self.allocate_descriptor_set(device, &texture)?;
+                let image = texture.image;
+                self.textures.insert(id, (texture, set));
+                (image, vk::ImageLayout::UNDEFINED)
+            }
+        };
+
+        let (staging_buffer, staging_memory) = buffer::create_buffer(
+            vk_context,
+            pixels.len() as vk::DeviceSize,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            MemoryLocation::CpuToGpu,
+        );
+        buffer::write_mapped::<u8, _>(&staging_memory, &pixels);
+
+        let [x, y] = delta.pos.unwrap_or([0, 0]);
+        cmd::execute_one_time_commands(device, command_pool, queue, |buffer| {
+            let range = vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: 1,
+            };
+            // previous frames may still read the texture
+            let to_transfer = vk::ImageMemoryBarrier::default()
+                .old_layout(old_layout)
+                .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(image)
+                .subresource_range(range)
+                .src_access_mask(vk::AccessFlags::SHADER_READ)
+                .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE);
+            let to_shader = vk::ImageMemoryBarrier::default()
+                .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                .new_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(image)
+                .subresource_range(range)
+                .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                .dst_access_mask(vk::AccessFlags::SHADER_READ);
+            let region = vk::BufferImageCopy::default()
+                .image_subresource(vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: 0,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                })
+                .image_offset(vk::Offset3D { x: x as _, y: y as _, z: 0 })
+                .image_extent(vk::Extent3D { width: extent.width, height: extent.height, depth: 1 });
+            unsafe {
+                device.cmd_pipeline_barrier(
+                    buffer,
+                    vk::PipelineStageFlags::FRAGMENT_SHADER,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[],
+                    &[to_transfer],
+                );
+                device.cmd_copy_buffer_to_image(
+                    buffer,
+                    staging_buffer,
+                    image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &[region],
+                );
+                device.cmd_pipeline_barrier(
+                    buffer,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::PipelineStageFlags::FRAGMENT_SHADER,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[],
+                    &[to_shader],
+                );
+            }
+        });
+
+        unsafe { buffer::destroy_buffer(vk_context, staging_buffer, staging_memory) };
+        Ok(())
+    }
+
+    fn create_texture(
+        vk_context: &VkContext,
+        extent: vk::Extent2D,
+        delta: &ImageDelta,
+    ) -> Result<Texture, anyhow::Error> {
+        let device = vk_context.device();
+        let (image, memory) = VkApp::create_image(
+            vk_context,
+            MemoryLocation::GpuOnly,
+            extent,
+            1,
+            vk::SampleCountFlags::TYPE_1,
+            TEXTURE_FORMAT,
+            vk::ImageTiling::OPTIMAL,
+            vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
+        );
+        let view = VkApp::create_image_view(device, image, 1, TEXTURE_FORMAT, vk::ImageAspectFlags::COLOR);
+
+        let filter = |filter| match filter {
+            TextureFilter::Nearest => vk::Filter::NEAREST,
+            TextureFilter::Linear => vk::Filter::LINEAR,
+        };
+        let sampler_info = vk::SamplerCreateInfo::default()
+            .mag_filter(filter(delta.options.magnification))
+            .min_filter(filter(delta.options.minification))
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+            .max_lod(0.0);
+        let sampler = unsafe {
+            device.create_sampler(&sampler_info, None)
+                .context("Failed to create sampler for ui texture")?
+        };
+        Ok(Texture::new(image, memory, view, Some(sampler), 1))
+    }
+
+    fn allocate_descriptor_set(&self, device: &Device, texture: &Texture) -> Result<vk::DescriptorSet, anyhow::Error> {
+        let layouts = [self.descriptor_set_layout];
+        let allocate_info = vk::DescriptorSetAllocateInfo::default()
+            .descriptor_pool(self.descriptor_pool)
+            .set_layouts(&layouts);
+        let set = unsafe {
+            device.allocate_descriptor_sets(&allocate_info)
+                .context("Failed to allocate descriptor set, too many ui textures")?[0]
+        };
+        let image_info = [vk::DescriptorImageInfo::default()
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .image_view(texture.view)
+            .sampler(texture.sampler.unwrap())];
+        let write = vk::WriteDescriptorSet::default()
+            .dst_set(set)
+            .dst_binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(&image_info);
+        unsafe { device.update_descriptor_sets(&[write], &[]) };
+        Ok(set)
+    }
+
+    fn destroy_texture(&self, vk_context: &VkContext, mut texture: Texture, set: vk::DescriptorSet) {
+        unsafe {
+            let _ = vk_context.device().free_descriptor_sets(self.descriptor_pool, &[set]);
+        }
+        texture.destroy(vk_context);
+    }
+
+    /// Copies the meshes into the buffers of `frame`, whose previous commands must be finished.
+    pub fn prepare(&mut self, vk_context: &VkContext, frame: usize) {
+        let meshes = self.primitives.iter().filter_map(|primitive| match &primitive.primitive {
+            Primitive::Mesh(mesh) => Some(mesh),
+            Primitive::Callback(_) => None,
+        });
+        let (vertex_count, index_count) = meshes.clone()
+            .fold((0, 0), |(v, i), mesh| (v + mesh.vertices.len(), i + mesh.indices.len()));
+        if index_count == 0 {
+            return;
+        }
+
+        let buffers = &mut self.buffers[frame];
+        let vertex_size = (vertex_count * size_of::<UiVertex>()) as vk::DeviceSize;
+        let index_size = (index_count * size_of::<u32>()) as vk::DeviceSize;
+        if buffers.as_ref().is_some_and(|b| b.vertex.1.size() < vertex_size || b.index.1.size() < index_size) {
+            buffers.take().unwrap().destroy(vk_context);
+        }
+        let buffers = buffers.get_or_insert_with(|| FrameBuffers::new(vk_context, vertex_size, index_size));
+
+        let vertex_ptr = buffers.vertex.1.mapped_ptr().unwrap().cast::<UiVertex>().as_ptr();
+        let index_ptr = buffers.index.1.mapped_ptr().unwrap().cast::<u32>().as_ptr();
+        unsafe {
+            let (mut vertex_offset, mut index_offset) = (0, 0);
+            for mesh in meshes {
+                std::ptr::copy_nonoverlapping(mesh.vertices.as_ptr(), vertex_ptr.add(vertex_offset), mesh.vertices.len());
+                std::ptr::copy_nonoverlapping(mesh.indices.as_ptr(), index_ptr.add(index_offset), mesh.indices.len());
+                vertex_offset += mesh.vertices.len();
+                index_offset += mesh.indices.len();
+            }
+        }
+    }
+
+    /// Records the draw commands of the meshes copied by `prepare` into the current render pass.
+    pub unsafe fn record(&self, device: &Device, buffer: vk::CommandBuffer, frame: usize, extent: vk::Extent2D) {
+        let Some(buffers) = &self.buffers[frame] else { return };
+        if self.primitives.is_empty() {
+            return;
+        }
+        let ppp = self.pixels_per_point;
+        let screen_size = [extent.width as f32 / ppp, extent.height as f32 / ppp];
+        let viewport = vk::Viewport {
+            x: 0.,
+            y: 0.,
+            width: extent.width as _,
+            height: extent.height as _,
+            min_depth: 0.,
+            max_depth: 1.,
+        };
+
+        device.cmd_bind_pipeline(buffer, vk::PipelineBindPoint::GRAPHICS, self.pipeline);
+        device.cmd_set_viewport(buffer, 0, &[viewport]);
+        device.cmd_bind_vertex_buffers(buffer, 0, &[buffers.vertex.0], &[0]);
+        device.cmd_bind_index_buffer(buffer, buffers.index.0, 0, vk::IndexType::UINT32);
+        let bytes = std::slice::from_raw_parts(screen_size.as_ptr().cast::<u8>(), size_of_val(&screen_size));
+        device.cmd_push_constants(buffer, self.layout, vk::ShaderStageFlags::VERTEX, 0, bytes);
+
+        let (mut vertex_offset, mut index_offset) = (0, 0);
+        for ClippedPrimitive { clip_rect, primitive } in &self.primitives {
+            let Primitive::Mesh(mesh) = primitive else { continue };
+            let first_vertex = vertex_offset;
+            let first_index = index_offset;
+            vertex_offset += mesh.vertices.len();
+            index_offset += mesh.indices.len();
+
+            let Some(&(_, set)) = self.textures.get(&mesh.texture_id) else { continue };
+            let min_x = (clip_rect.min.x * ppp).round().clamp(0., extent.width as f32);
+            let min_y = (clip_rect.min.y * ppp).round().clamp(0., extent.height as f32);
+            let max_x = (clip_rect.max.x * ppp).round().clamp(min_x, extent.width as f32);
+            let max_y = (clip_rect.max.y * ppp).round().clamp(min_y, extent.height as f32);
+            if max_x == min_x || max_y == min_y {
+                continue;
+            }
+            let scissor = vk::Rect2D {
+                offset: vk::Offset2D { x: min_x as _, y: min_y as _ },
+                extent: vk::Extent2D { width: (max_x - min_x) as _, height: (max_y - min_y) as _ },
+            };
+            device.cmd_set_scissor(buffer, 0, &[scissor]);
+            device.cmd_bind_descriptor_sets(buffer, vk::PipelineBindPoint::GRAPHICS, self.layout, 0, &[set], &[]);
+            device.cmd_draw_indexed(
+                buffer,
+                mesh.indices.len() as _,
+                1,
+                first_index as _,
+                first_vertex as _,
+                0,
+            );
+        }
+    }
+
+    /// Destroys the pipeline, which depends on the render pass.
+    pub unsafe fn cleanup_pipeline(&mut self, device: &Device) {
+        device.destroy_pipeline(self.pipeline, None);
+        self.pipeline = vk::Pipeline::null();
+    }
+
+    pub unsafe fn destroy(&mut self, vk_context: &VkContext) {
+        let device = vk_context.device();
+        for (_, (mut texture, _)) in self.textures.drain() {
+            texture.destroy(vk_context);
+        }
+        for buffers in self.buffers.iter_mut().filter_map(Option::take) {
+            buffers.destroy(vk_context);
+        }
+        device.destroy_pipeline(self.pipeline, None);
+        device.destroy_pipeline_layout(self.layout, None);
+        device.destroy_descriptor_pool(self.descriptor_pool, None);
+        device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+    }
+}
+
+/// Host visible buffers with their mapped memory.
+struct FrameBuffers {
+    vertex: (vk::Buffer, Allocation),
+    index: (vk::Buffer, Allocation),
+}
+
+impl FrameBuffers {
+    fn new(vk_context: &VkContext, vertex_size: vk::DeviceSize, index_size: vk::DeviceSize) -> Self {
+        // leave room to grow so the buffers are not recreated every frame
+        let create = |size: vk::DeviceSize, usage| buffer::create_buffer(
+            vk_context,
+            size.next_power_of_two(),
+            usage,
+            MemoryLocation::CpuToGpu,
+        );
+        Self {
+            vertex: create(vertex_size, vk::BufferUsageFlags::VERTEX_BUFFER),
+            index: create(index_size, vk::BufferUsageFlags::INDEX_BUFFER),
+        }
+    }
+
+    fn destroy(self, vk_context: &VkContext) {
+        unsafe {
+            buffer::destroy_buffer(vk_context, self.vertex.0, self.vertex.1);
+            buffer::destroy_buffer(vk_context, self.index.0, self.index.1);
+        }
+    }
+}
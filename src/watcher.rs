@@ -0,0 +1,103 @@
+use notify::{EventKind, RecursiveMode, Watcher};
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, Instant};
+
+/// Time without further events after which a change is reported.
+/// A single save often causes several events, e.g. truncate and write.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Watches a set of files for modifications.
+///
+/// The parent directories are watched instead of the files themselves, so files
+/// that are replaced by renaming a temporary file, as many editors do, are detected too.
+pub struct FileWatcher {
+    watcher: notify::RecommendedWatcher,
+    events: Receiver<notify::Result<notify::Event>>,
+    /// Watched files as given to `set_files`.
+    files: Vec<PathBuf>,
+    /// Canonical paths of the watched files and the files as given.
+    canonical: HashMap<PathBuf, PathBuf>,
+    watched_dirs: Vec<PathBuf>,
+    /// Files with changes that are not reported yet and the time of their last event.
+    pending: HashMap<PathBuf, Instant>,
+}
+
+impl FileWatcher {
+    pub fn new() -> notify::Result<Self> {
+        let (sender, events) = mpsc::channel();
+        let watcher = notify::recommended_watcher(sender)?;
+        Ok(Self {
+            watcher,
+            events,
+            files: Vec::new(),
+            canonical: HashMap::new(),
+            watched_dirs: Vec::new(),
+            pending: HashMap::new(),
+        })
+    }
+
+    /// Returns the watched files.
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    /// Replaces the watched files with `files`.
+    ///
+    /// On error the files after the failing one are not watched, but are
+    /// still returned by `files` so the caller does not retry every frame.
+    pub fn set_files<P: AsRef<Path>>(&mut self, files: &[P]) -> notify::Result<()> {
+        for dir in self.watched_dirs.drain(..) {
+            let _ = self.watcher.unwatch(&dir);
+        }
+        self.files = files.iter().map(|file| file.as_ref().to_path_buf()).collect();
+        self.canonical.clear();
+        self.pending.clear();
+
+        for file in files {
+            let file = file.as_ref();
+            let canonical = file.canonicalize()?;
+            let Some(dir) = canonical.parent().map(Path::to_path_buf) else { continue };
+            if !self.watched_dirs.contains(&dir) {
+                self.watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+                self.watched_dirs.push(dir);
+            }
+            self.canonical.insert(canonical, file.to_path_buf());
+        }
+        Ok(())
+    }
+
+    /// Returns the watched files that changed and had no further events for a short time.
+    /// Each change is only reported once.
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        while let Ok(event) = self.events.try_recv() {
+            let event = match event {
+                Ok(event) => event,
+                Err(err) => {
+                    log::warn!("File watcher error: {err}");
+                    continue;
+                }
+            };
+            if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                continue;
+            }
+            for path in event.paths {
+                if let Some(file) = self.canonical.get(&path) {
+                    self.pending.insert(file.clone(), Instant::now());
+                }
+            }
+        }
+
+        let mut changed = Vec::new();
+        self.pending.retain(|file, last_event| {
+            if last_event.elapsed() < DEBOUNCE {
+                return true;
+            }
+            changed.push(file.clone());
+            false
+        });
+        changed
+    }
+}